@@ -0,0 +1,331 @@
+//! Headless command line tool for evaluating workbooks, for use in scripts and CI pipelines
+
+mod repl;
+mod workbook;
+
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use rustyline::error::ReadlineError;
+use spanleaf_core::{
+    Spanleaf,
+    cell::{CellIdx, ErrorKind, Value},
+    formula::FormulaError,
+    sheet::{Sheet, SheetIdx, ValueResult},
+};
+
+use crate::{repl::Repl, workbook::WorkbookError};
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// The workbook, a .spanleaf, .csv, .tsv or .xlsx file. Only optional for `repl`, which otherwise starts
+    /// with an empty workbook
+    file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints the computed value of a cell
+    Eval {
+        sheet: String,
+        /// The cell, as `B7` or `6,1`
+        #[arg(value_parser = parse_cell)]
+        cell: CellIdx,
+    },
+    /// Prints the computed values of every sheet, or just the given one, as a grid
+    Dump {
+        sheet: Option<String>,
+        /// Print raw values and formula scripts, rather than computed values
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Writes a value or formula to a cell, and saves the workbook
+    Set {
+        sheet: String,
+        /// The cell, as `B7` or `6,1`
+        #[arg(value_parser = parse_cell)]
+        cell: CellIdx,
+        /// The new value, with formulas starting with '=', or empty to clear the cell
+        value: String,
+        /// Where to save the workbook, rather than overwriting it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Reports every cell evaluating to an error, failing if there are any
+    Check,
+    /// Starts an interactive prompt for evaluating expressions and editing cells
+    Repl,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error(transparent)]
+    Workbook(#[from] WorkbookError),
+    #[error("no sheet named `{0}`")]
+    UnknownSheet(String),
+    #[error(transparent)]
+    Formula(#[from] FormulaError),
+    #[error("no workbook given")]
+    MissingFile,
+    #[error(transparent)]
+    Readline(#[from] ReadlineError),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, CliError> {
+    if let (Command::Repl, None) = (&cli.command, &cli.file) {
+        Repl::new(Spanleaf::new(), None).run()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let file = cli.file.ok_or(CliError::MissingFile)?;
+    let mut sl = workbook::load(&file)?;
+
+    match cli.command {
+        Command::Eval { sheet, cell } => {
+            let sref = find_sheet(&sl, &sheet)?;
+            println!("{}", display(&sl, &computed(&sl, sref, cell)));
+        }
+        Command::Dump { sheet, raw } => {
+            let sheets = match sheet {
+                Some(name) => vec![find_sheet(&sl, &name)?],
+                None => sl.sheets().map(|(sref, _)| sref).collect(),
+            };
+            for (i, sref) in sheets.into_iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                print!("{}", dump(&sl, sref, raw));
+            }
+        }
+        Command::Set {
+            sheet,
+            cell,
+            value,
+            output,
+        } => {
+            let sref = find_sheet(&sl, &sheet)?;
+            sl.insert(sref, cell, value.as_str())?;
+            workbook::save(&sl, output.as_deref().unwrap_or(&file))?;
+        }
+        Command::Check => {
+            let errors = check(&sl);
+            for (sref, cell, kind) in &errors {
+                let sheet = &sl.sheet(*sref).expect("the sheet was iterated").name;
+                println!("{sheet}!{}: {kind} ({})", cell.a1(), kind.description());
+            }
+            if !errors.is_empty() {
+                let cycles = errors
+                    .iter()
+                    .filter(|(_, _, kind)| *kind == ErrorKind::Cycle)
+                    .count();
+                eprintln!("{} error cells, {cycles} in cycles", errors.len());
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Repl => Repl::new(sl, Some(file)).run()?,
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parses a cell given on the command line, either in A1 notation or as a 0-based `row,col`
+fn parse_cell(arg: &str) -> Result<CellIdx, String> {
+    let cell = CellIdx::from_a1(arg)
+        .or_else(|| {
+            let (row, col) = arg.split_once(',')?;
+            Some(CellIdx::new(
+                row.trim().parse().ok()?,
+                col.trim().parse().ok()?,
+            ))
+        })
+        .ok_or_else(|| format!("invalid cell `{arg}`, expected i.e. `B7` or `6,1`"))?;
+
+    if cell.row.max(cell.col) > Sheet::LAST_INDEX {
+        return Err(format!(
+            "cell `{arg}` is past the last row or column of the sheet"
+        ));
+    }
+    Ok(cell)
+}
+
+fn find_sheet(sl: &Spanleaf, name: &str) -> Result<SheetIdx, CliError> {
+    sl.sheet_by_name(name)
+        .ok_or_else(|| CliError::UnknownSheet(name.to_string()))
+}
+
+/// The computed value of a cell, with evaluation errors as error values
+fn computed(sl: &Spanleaf, sref: SheetIdx, cell: CellIdx) -> Value {
+    sl.get(sref, cell)
+        .map(ValueResult::value)
+        .unwrap_or_else(|e| Value::Error(e.into()))
+}
+
+/// Formats a value for printing
+fn display(sl: &Spanleaf, val: &Value) -> String {
+    let sheet_name = |sref| sl.sheet(sref).map(|s| s.name.as_str()).unwrap_or_default();
+
+    match val {
+        Value::None => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Date(date) => date.to_string(),
+        Value::DateTime(dt) => dt.to_string(),
+        Value::Duration(duration) => duration.to_string(),
+        Value::Formula(f) => f.script.clone(),
+        Value::Error(kind) => kind.to_string(),
+        Value::Array(_) => val.to_string().trim_end_matches('\n').to_string(),
+        Value::Ref { sref, cref } => format!("&{}!{}", sheet_name(*sref), cref.a1()),
+        Value::Range { sref, range } => format!(
+            "{}!{}:{}",
+            sheet_name(*sref),
+            range.start.a1(),
+            range.end.a1()
+        ),
+    }
+}
+
+/// The sheet's used range as an aligned grid, with A1 headers
+fn dump(sl: &Spanleaf, sref: SheetIdx, raw: bool) -> String {
+    let Some(sheet) = sl.sheet(sref) else {
+        return String::new();
+    };
+    // computed values include the cells that arrays spill into
+    let range = if raw {
+        sheet.used_range()
+    } else {
+        sl.used_range(sref)
+    };
+
+    let mut grid = vec![];
+    if !range.is_empty() {
+        let mut header = vec![String::new()];
+        header.extend((range.start.col..=range.end.col).map(|col| {
+            let a1 = CellIdx::new(0, col).a1();
+            a1.trim_end_matches(|c: char| c.is_ascii_digit())
+                .to_string()
+        }));
+        grid.push(header);
+
+        for row in range.start.row..=range.end.row {
+            let mut line = vec![(row + 1).to_string()];
+            line.extend((range.start.col..=range.end.col).map(|col| {
+                let cell = CellIdx::new(row, col);
+                let val = if raw {
+                    sl.get_raw_value(sref, cell).value()
+                } else {
+                    computed(sl, sref, cell)
+                };
+                display(sl, &val)
+            }));
+            grid.push(line);
+        }
+    }
+
+    let cols = grid.first().map_or(0, Vec::len);
+    let widths = (0..cols)
+        .map(|i| {
+            grid.iter()
+                .map(|line| line[i].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = format!("{}\n", sheet.name);
+    for line in grid {
+        let cells = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Every cell of every sheet that evaluates to an error, including row and col defaults over the used range
+fn check(sl: &Spanleaf) -> Vec<(SheetIdx, CellIdx, ErrorKind)> {
+    let mut errors = vec![];
+    for (sref, _) in sl.sheets() {
+        let range = sl.used_range(sref);
+        // native cells are always in the used range
+        for cell in range.iter() {
+            if let Value::Error(kind) = computed(sl, sref, cell) {
+                errors.push((sref, cell, kind));
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use spanleaf_core::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind},
+        sheet::Sheet,
+    };
+
+    use crate::{check, dump, parse_cell};
+
+    #[test]
+    fn cells() {
+        assert_eq!(parse_cell("B7"), Ok(CellIdx::new(6, 1)));
+        assert_eq!(parse_cell("AA1"), Ok(CellIdx::new(0, 26)));
+        assert_eq!(parse_cell("6, 1"), Ok(CellIdx::new(6, 1)));
+        assert!(parse_cell("b7").is_err());
+        assert!(parse_cell("A0").is_err());
+        assert!(parse_cell("6").is_err());
+        // past the last row or col of the sheet
+        assert!(parse_cell("XFD99999999999").is_err());
+        assert!(parse_cell("5000000000, 0").is_err());
+        assert_eq!(
+            parse_cell("4294967295,0"),
+            Ok(CellIdx::new(Sheet::LAST_INDEX, 0))
+        );
+    }
+
+    #[test]
+    fn dump_and_check() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert_col_default(s0, 2, "=A1 * 2").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), "pear").unwrap();
+        sl.insert(s0, CellIdx::new(0, 1), "=B2").unwrap();
+        sl.insert(s0, CellIdx::new(1, 1), "=B1").unwrap();
+
+        assert_eq!(
+            dump(&sl, s0, false),
+            "Sheet1\n   A     B        C\n1  1     #CYCLE!  2\n2  pear  #CYCLE!  #VALUE!\n"
+        );
+        assert_eq!(
+            dump(&sl, s0, true),
+            "Sheet1\n   A     B    C\n1  1     =B2  =A1 * 2\n2  pear  =B1  =A1 * 2\n"
+        );
+
+        assert_eq!(
+            check(&sl),
+            [
+                (s0, CellIdx::new(0, 1), ErrorKind::Cycle),
+                (s0, CellIdx::new(1, 1), ErrorKind::Cycle),
+                (s0, CellIdx::new(1, 2), ErrorKind::Value),
+            ]
+        );
+    }
+}
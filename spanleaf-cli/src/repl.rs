@@ -0,0 +1,381 @@
+//! Interactive prompt for exploring the formula language against a live workbook
+
+use std::path::PathBuf;
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+use spanleaf_core::{
+    Dependency, Spanleaf,
+    cell::{CellIdx, Value},
+    formula::{Formula, FormulaError, ParseError},
+    sheet::SheetIdx,
+};
+
+use crate::workbook::{self, WorkbookError};
+
+const HELP: &str = "\
+<expression>            parse and evaluate an expression, as if it were in [0, 0] of the current sheet
+:set <cell> <value>     write a value to a cell, with formulas starting with '=', i.e. `:set [1, 2] = [0, 0] * 2`
+:deps <cell>            show what a cell depends on, and what depends on it
+:sheet [name]           switch to another sheet, or list the sheets
+:save [path]            save the workbook, to the file it was loaded from by default
+:help                   show this message
+:quit                   exit";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplError {
+    /// The input wasn't valid, with every problem pointed out below it
+    #[error("invalid formula\n{}", underline(.input, .errors))]
+    Parse {
+        input: String,
+        errors: Vec<ParseError>,
+    },
+    #[error("invalid cell `{0}`, expected i.e. `[6, 1]` or `B7`")]
+    InvalidCell(String),
+    #[error("no sheet named `{0}`")]
+    UnknownSheet(String),
+    #[error("unknown command `:{0}`, see `:help`")]
+    UnknownCommand(String),
+    #[error("usage: {0}")]
+    Usage(&'static str),
+    #[error("no file to save to, give a path")]
+    NoPath,
+    #[error(transparent)]
+    Workbook(#[from] WorkbookError),
+}
+impl ReplError {
+    fn parse(input: &str, err: FormulaError) -> Self {
+        let FormulaError::InvalidFormula(errors) = err;
+        Self::Parse {
+            input: input.to_string(),
+            errors,
+        }
+    }
+}
+
+/// Prints the input with each error's span marked beneath it, followed by its message
+fn underline(input: &str, errors: &[ParseError]) -> String {
+    let mut out = format!("  {input}");
+    for err in errors {
+        // spans are in bytes, but the markers line up with chars
+        let chars =
+            |range: std::ops::Range<usize>| input.get(range).map_or(0, |s| s.chars().count());
+        let start = chars(0..err.span.start.min(input.len()));
+        let width = chars(err.span.start.min(input.len())..err.span.end.min(input.len())).max(1);
+        out.push_str(&format!(
+            "\n  {}{} {}",
+            " ".repeat(start),
+            "^".repeat(width),
+            err.message
+        ));
+    }
+    out
+}
+
+/// A workbook being explored, and the sheet expressions are evaluated on
+pub struct Repl {
+    sl: Spanleaf,
+    sheet: SheetIdx,
+    /// Where `:save` writes to by default
+    path: Option<PathBuf>,
+}
+impl Repl {
+    /// Starts on the first sheet, adding one if the workbook has none
+    pub fn new(mut sl: Spanleaf, path: Option<PathBuf>) -> Self {
+        let first = sl.sheets().next().map(|(sheet, _)| sheet);
+        let sheet = match first {
+            Some(sheet) => sheet,
+            None => sl.insert_sheet("Sheet1").expect("the workbook is empty"),
+        };
+        Self { sl, sheet, path }
+    }
+
+    /// Reads and handles lines until the input ends or `:quit` is entered
+    pub fn run(&mut self) -> Result<(), ReadlineError> {
+        let mut editor = DefaultEditor::new()?;
+        println!(
+            "Spanleaf {}, `:help` for commands",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        loop {
+            let prompt = format!("{}> ", self.sheet_name(self.sheet));
+            let line = match editor.readline(&prompt) {
+                Ok(line) => line,
+                // ctrl-c abandons the current line, ctrl-d exits
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line)?;
+
+            if matches!(line, ":quit" | ":q") {
+                return Ok(());
+            }
+            match self.handle(line) {
+                Ok(out) if out.is_empty() => {}
+                Ok(out) => println!("{out}"),
+                Err(e) => eprintln!("error: {e}"),
+            }
+        }
+    }
+
+    /// Handles a single line of input, returning what to print
+    fn handle(&mut self, line: &str) -> Result<String, ReplError> {
+        let Some(command) = line.strip_prefix(':') else {
+            return self.eval(line);
+        };
+        let (command, args) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(command, args)| (command, args.trim()));
+
+        match command {
+            "set" => self.set(args),
+            "deps" => self.deps(args),
+            "sheet" => self.switch_sheet(args),
+            "save" => self.save(args),
+            "help" => Ok(HELP.to_string()),
+            _ => Err(ReplError::UnknownCommand(command.to_string())),
+        }
+    }
+
+    /// Prints the AST of the expression, followed by its value
+    fn eval(&self, input: &str) -> Result<String, ReplError> {
+        let formula = Formula::parse(input).map_err(|e| ReplError::parse(input, e))?;
+        let val = self
+            .sl
+            .eval_formula(self.sheet, CellIdx::new(0, 0), &formula);
+        Ok(format!("{}= {}", formula.ast(), self.display(&val)))
+    }
+
+    fn set(&mut self, args: &str) -> Result<String, ReplError> {
+        const USAGE: &str = ":set <cell> <value>";
+        let (cell, input) = split_cell(args).ok_or(ReplError::Usage(USAGE))?;
+        let cell = parse_cell(cell)?;
+
+        // allow for spacing after the '=', as in `:set [1, 2] = 1 + 2`
+        let input = match input.strip_prefix('=') {
+            Some(script) => format!("={}", script.trim_start()),
+            None => input.to_string(),
+        };
+        self.sl
+            .insert(self.sheet, cell, input.as_str())
+            .map_err(|e| ReplError::parse(&input, e))?;
+
+        let val = self
+            .sl
+            .get(self.sheet, cell)
+            .map(|res| res.value())
+            .unwrap_or_else(|e| Value::Error(e.into()));
+        Ok(format!("{} = {}", cell.a1(), self.display(&val)))
+    }
+
+    fn deps(&self, args: &str) -> Result<String, ReplError> {
+        if args.is_empty() {
+            return Err(ReplError::Usage(":deps <cell>"));
+        }
+        let cell = parse_cell(args)?;
+
+        // dependents are only known once calculated, so calculate everything that could depend on the cell
+        for (sref, sheet) in self.sl.sheets() {
+            for cref in sheet.used_range().iter() {
+                let _ = self.sl.get(sref, cref);
+            }
+        }
+
+        let precedents = self
+            .sl
+            .precedents(self.sheet, cell)
+            .into_iter()
+            .map(|dep| match dep {
+                Dependency::Cell(sref, cref) => self.location(sref, cref),
+                Dependency::Range(sref, range) if range.is_empty() => {
+                    format!("{}!(empty range)", self.sheet_name(sref))
+                }
+                Dependency::Range(sref, range) => {
+                    format!("{}:{}", self.location(sref, range.start), range.end.a1())
+                }
+                Dependency::Function(name) => format!("{name}()"),
+                Dependency::Sheet(name) => format!("{name} (missing sheet)"),
+                Dependency::Volatile => "volatile functions".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let dependents = self
+            .sl
+            .dependents(self.sheet, cell)
+            .into_iter()
+            .map(|(sref, cref)| self.location(sref, cref))
+            .collect::<Vec<_>>();
+
+        let list = |items: Vec<String>| {
+            if items.is_empty() {
+                "nothing".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        Ok(format!(
+            "depends on: {}\nused by: {}",
+            list(precedents),
+            list(dependents)
+        ))
+    }
+
+    fn switch_sheet(&mut self, name: &str) -> Result<String, ReplError> {
+        if name.is_empty() {
+            let sheets = self
+                .sl
+                .sheets()
+                .map(|(sref, sheet)| {
+                    let marker = if sref == self.sheet { '*' } else { ' ' };
+                    format!("{marker} {}", sheet.name)
+                })
+                .collect::<Vec<_>>();
+            return Ok(sheets.join("\n"));
+        }
+
+        self.sheet = self
+            .sl
+            .sheet_by_name(name)
+            .ok_or_else(|| ReplError::UnknownSheet(name.to_string()))?;
+        Ok(String::new())
+    }
+
+    fn save(&mut self, path: &str) -> Result<String, ReplError> {
+        if !path.is_empty() {
+            self.path = Some(PathBuf::from(path));
+        }
+        let path = self.path.as_ref().ok_or(ReplError::NoPath)?;
+        workbook::save(&self.sl, path)?;
+        Ok(format!("saved to {}", path.display()))
+    }
+
+    fn sheet_name(&self, sref: SheetIdx) -> &str {
+        self.sl
+            .sheet(sref)
+            .map(|s| s.name.as_str())
+            .unwrap_or_default()
+    }
+
+    /// A cell, as `Sheet1!B7`
+    fn location(&self, sref: SheetIdx, cref: CellIdx) -> String {
+        format!("{}!{}", self.sheet_name(sref), cref.a1())
+    }
+
+    fn display(&self, val: &Value) -> String {
+        match val {
+            Value::None => "(empty)".to_string(),
+            Value::Error(kind) => format!("{kind} ({})", kind.description()),
+            val => crate::display(&self.sl, val),
+        }
+    }
+}
+
+/// Splits the cell off the front of `:set`'s arguments, where it may be bracketed and contain spaces
+fn split_cell(args: &str) -> Option<(&str, &str)> {
+    let end = if args.starts_with('[') {
+        args.find(']')? + 1
+    } else {
+        args.find(char::is_whitespace).unwrap_or(args.len())
+    };
+    let (cell, rest) = args.split_at(end);
+    (!cell.is_empty()).then(|| (cell, rest.trim_start()))
+}
+
+/// Parses a cell given as `[6, 1]`, `6,1` or `B7`
+fn parse_cell(arg: &str) -> Result<CellIdx, ReplError> {
+    let inner = arg
+        .strip_prefix('[')
+        .and_then(|arg| arg.strip_suffix(']'))
+        .unwrap_or(arg);
+    crate::parse_cell(inner).map_err(|_| ReplError::InvalidCell(arg.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use spanleaf_core::{
+        Spanleaf,
+        cell::{CellIdx, Value},
+    };
+
+    use crate::repl::{Repl, ReplError};
+
+    #[test]
+    fn session() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert_sheet("Sheet2").unwrap();
+        let mut repl = Repl::new(sl, None);
+
+        assert_eq!(
+            repl.handle("1 + 2 * 3").unwrap(),
+            "Add\n  1\n  Mul\n    2\n    3\n= 7"
+        );
+        assert_eq!(repl.handle(":set [0, 1] 4").unwrap(), "B1 = 4");
+        assert_eq!(repl.handle(":set [1,2] = [0, 1] * 2").unwrap(), "C2 = 8");
+        assert_eq!(
+            repl.handle(":set A2 = sum([0..1, 1..2])").unwrap(),
+            "A2 = 12"
+        );
+        assert_eq!(
+            repl.handle("[1, 2] / 0").unwrap().lines().last(),
+            Some("= #DIV/0! (Division by zero)")
+        );
+        assert_eq!(
+            repl.handle(":deps C2").unwrap(),
+            "depends on: Sheet1!B1\nused by: Sheet1!A2"
+        );
+        assert_eq!(
+            repl.handle(":deps B1").unwrap(),
+            "depends on: nothing\nused by: Sheet1!A2, Sheet1!C2"
+        );
+
+        assert_eq!(repl.handle(":sheet Sheet2").unwrap(), "");
+        assert_eq!(repl.handle(":sheet").unwrap(), "  Sheet1\n* Sheet2");
+        assert_eq!(
+            repl.handle("Sheet1[1, 0]").unwrap().lines().last(),
+            Some("= 12")
+        );
+        assert!(matches!(
+            repl.handle(":sheet Sheet3"),
+            Err(ReplError::UnknownSheet(_))
+        ));
+        assert!(matches!(
+            repl.sl.get_raw_value(s0, CellIdx::new(1, 2)).value(),
+            Value::Formula(f) if f.script == "=[0, 1] * 2"
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let mut repl = Repl::new(Spanleaf::new(), None);
+
+        let err = repl.handle("1 + )").unwrap_err();
+        let printed = err.to_string();
+        let mut lines = printed.lines();
+        assert_eq!(lines.next(), Some("invalid formula"));
+        assert_eq!(lines.next(), Some("  1 + )"));
+        assert!(lines.next().unwrap().starts_with("      ^ unexpected ')'"));
+
+        let err = repl.handle(":set [0, 0] =sum(1,").unwrap_err();
+        assert!(matches!(&err, ReplError::Parse { input, .. } if input == "=sum(1,"));
+
+        assert!(matches!(
+            repl.handle(":set [0] 1"),
+            Err(ReplError::InvalidCell(_))
+        ));
+        assert!(matches!(
+            repl.handle(":set [5000000000,0] 1"),
+            Err(ReplError::InvalidCell(_))
+        ));
+        assert!(matches!(repl.handle(":set"), Err(ReplError::Usage(_))));
+        assert!(matches!(
+            repl.handle(":frobnicate"),
+            Err(ReplError::UnknownCommand(_))
+        ));
+        assert!(matches!(repl.handle(":save"), Err(ReplError::NoPath)));
+    }
+}
@@ -0,0 +1,132 @@
+//! Loading and saving workbooks, in the format picked by the file extension
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use spanleaf_core::{
+    Spanleaf,
+    csv::{CsvContents, CsvError, CsvExportOptions, CsvImportOptions},
+    file::LoadError,
+    xlsx::{ExportOptions, UntranslatedFormula, XlsxError},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkbookError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Csv(#[from] CsvError),
+    #[error(transparent)]
+    Xlsx(#[from] XlsxError),
+    #[error("unsupported file type, expected .spanleaf, .csv, .tsv or .xlsx")]
+    UnsupportedFormat,
+}
+
+/// The file formats a workbook can be loaded from and saved to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Spanleaf,
+    Csv,
+    Tsv,
+    Xlsx,
+}
+impl Format {
+    fn of(path: &Path) -> Result<Self, WorkbookError> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("spanleaf") => Ok(Self::Spanleaf),
+            Some("csv") => Ok(Self::Csv),
+            Some("tsv") => Ok(Self::Tsv),
+            Some("xlsx") => Ok(Self::Xlsx),
+            _ => Err(WorkbookError::UnsupportedFormat),
+        }
+    }
+}
+
+/// Loads a workbook.
+///
+/// CSV and TSV files become a single sheet named `Sheet1`, with fields starting with '=' parsed as formulas.
+/// Excel formulas that couldn't be translated are reported to stderr, and keep their cached value
+pub fn load(path: &Path) -> Result<Spanleaf, WorkbookError> {
+    let format = Format::of(path)?;
+    let file = BufReader::new(File::open(path)?);
+
+    match format {
+        Format::Spanleaf => Ok(Spanleaf::load(file)?),
+        Format::Csv | Format::Tsv => {
+            let mut sl = Spanleaf::new();
+            let sheet = sl.insert_sheet("Sheet1").expect("the workbook is empty");
+            let options = CsvImportOptions {
+                formulas: true,
+                ..if format == Format::Tsv {
+                    CsvImportOptions::tsv()
+                } else {
+                    CsvImportOptions::default()
+                }
+            };
+            sl.import_csv(sheet, file, &options)?;
+            Ok(sl)
+        }
+        Format::Xlsx => {
+            let import = Spanleaf::import_xlsx(file)?;
+            for untranslated in &import.untranslated {
+                warn_untranslated(&import.spanleaf, untranslated);
+            }
+            Ok(import.spanleaf)
+        }
+    }
+}
+
+/// Saves a workbook.
+///
+/// CSV and TSV files can only hold a single sheet, so only the first is saved, with formulas as their scripts
+pub fn save(sl: &Spanleaf, path: &Path) -> Result<(), WorkbookError> {
+    let format = Format::of(path)?;
+    let file = BufWriter::new(File::create(path)?);
+
+    match format {
+        Format::Spanleaf => sl.save(file)?,
+        Format::Csv | Format::Tsv => {
+            let Some((sheet, _)) = sl.sheets().next() else {
+                return Ok(());
+            };
+            let options = CsvExportOptions {
+                contents: CsvContents::Raw,
+                ..if format == Format::Tsv {
+                    CsvExportOptions::tsv()
+                } else {
+                    CsvExportOptions::default()
+                }
+            };
+            sl.export_csv(sheet, file, &options)?;
+        }
+        Format::Xlsx => {
+            for untranslated in sl.export_xlsx(file, &ExportOptions::default())? {
+                warn_untranslated(sl, &untranslated);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warns about a formula that couldn't be translated, so only its value was kept
+fn warn_untranslated(sl: &Spanleaf, untranslated: &UntranslatedFormula) {
+    let sheet = sl
+        .sheet(untranslated.sheet)
+        .map(|s| s.name.as_str())
+        .unwrap_or_default();
+    eprintln!(
+        "warning: {sheet}!{}: only kept the value of `{}`, {}",
+        untranslated.cell.a1(),
+        untranslated.formula,
+        untranslated.reason
+    );
+}
@@ -1,7 +1,8 @@
-use std::{fmt::Display, ops};
+use std::{cmp::Ordering, fmt::Display, ops};
 
 use crate::{
     Error,
+    datetime::{Date, DateTime, Duration},
     formula::{Formula, FormulaError},
     sheet::SheetIdx,
 };
@@ -15,6 +16,237 @@ impl CellIdx {
     pub fn new(row: u64, col: u64) -> Self {
         Self { row, col }
     }
+    /// Parses the A1 name of a cell, i.e. `B7`, with an uppercase col of up to 3 letters
+    pub fn from_a1(name: &str) -> Option<Self> {
+        let digits = name.trim_start_matches(|c: char| c.is_ascii_uppercase());
+        let letters = &name[..name.len() - digits.len()];
+        if !(1..=3).contains(&letters.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let col = letters
+            .bytes()
+            .fold(0, |acc, b| acc * 26 + u64::from(b - b'A' + 1));
+        let row = digits.parse::<u64>().ok()?.checked_sub(1)?;
+        Some(Self::new(row, col - 1))
+    }
+    /// The A1 name of the cell, i.e. `B7`
+    pub fn a1(&self) -> String {
+        let mut letters = String::new();
+        let mut col = self.col + 1;
+        while col > 0 {
+            letters.insert(0, (b'A' + ((col - 1) % 26) as u8) as char);
+            col = (col - 1) / 26;
+        }
+        format!("{letters}{}", self.row + 1)
+    }
+}
+
+/// A rectangular range of cells, inclusive on both ends
+///
+/// A range whose start is past its end in either direction is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CellRange {
+    pub start: CellIdx,
+    pub end: CellIdx,
+}
+impl CellRange {
+    pub fn new(start: CellIdx, end: CellIdx) -> Self {
+        Self { start, end }
+    }
+    /// A range that contains no cells
+    pub fn empty() -> Self {
+        Self::new(CellIdx::new(1, 1), CellIdx::new(0, 0))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.start.row > self.end.row || self.start.col > self.end.col
+    }
+    pub fn contains(&self, cref: CellIdx) -> bool {
+        self.contains_row(cref.row) && self.contains_col(cref.col)
+    }
+    pub fn contains_row(&self, row: u64) -> bool {
+        !self.is_empty() && (self.start.row..=self.end.row).contains(&row)
+    }
+    pub fn contains_col(&self, col: u64) -> bool {
+        !self.is_empty() && (self.start.col..=self.end.col).contains(&col)
+    }
+    /// Whether any cell is in both ranges
+    pub fn intersects(&self, other: &CellRange) -> bool {
+        !self.is_empty()
+            && !other.is_empty()
+            && self.start.row <= other.end.row
+            && other.start.row <= self.end.row
+            && self.start.col <= other.end.col
+            && other.start.col <= self.end.col
+    }
+    /// Iterates over every cell in the range, in row major order
+    pub fn iter(&self) -> impl Iterator<Item = CellIdx> {
+        let cols = self.start.col..=self.end.col;
+        (self.start.row..=self.end.row)
+            .flat_map(move |row| cols.clone().map(move |col| CellIdx::new(row, col)))
+    }
+}
+
+/// The kind of error held by a [Value::Error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorKind {
+    /// Division by zero
+    DivByZero,
+    /// A reference to a cell or sheet that doesn't exist
+    Ref,
+    /// A cyclic dependency between cells
+    Cycle,
+    /// A function or variable that doesn't exist
+    Name,
+    /// An operation on the wrong type of value
+    Value,
+    /// A numeric result that isn't valid
+    Num,
+    /// No value is available, i.e. a lookup found no match
+    NotAvailable,
+    /// The wrong number of arguments to a function
+    Args,
+    /// Evaluation recursed too deeply
+    Recursion,
+    /// An array result that can't spill into the cells next to its formula, as they aren't empty
+    Spill,
+}
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 10] = [
+        ErrorKind::DivByZero,
+        ErrorKind::Ref,
+        ErrorKind::Cycle,
+        ErrorKind::Name,
+        ErrorKind::Value,
+        ErrorKind::Num,
+        ErrorKind::NotAvailable,
+        ErrorKind::Args,
+        ErrorKind::Recursion,
+        ErrorKind::Spill,
+    ];
+
+    /// The short code shown in place of a value, which is also the literal syntax for the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::DivByZero => "#DIV/0!",
+            ErrorKind::Ref => "#REF!",
+            ErrorKind::Cycle => "#CYCLE!",
+            ErrorKind::Name => "#NAME?",
+            ErrorKind::Value => "#VALUE!",
+            ErrorKind::Num => "#NUM!",
+            ErrorKind::NotAvailable => "#N/A",
+            ErrorKind::Args => "#ARGS!",
+            ErrorKind::Recursion => "#RECURSION!",
+            ErrorKind::Spill => "#SPILL!",
+        }
+    }
+
+    /// A human readable explanation of the error
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorKind::DivByZero => "Division by zero",
+            ErrorKind::Ref => "Reference to a cell or sheet that doesn't exist",
+            ErrorKind::Cycle => "Cyclic dependency between cells",
+            ErrorKind::Name => "Unknown function or variable",
+            ErrorKind::Value => "Operation on the wrong type of value",
+            ErrorKind::Num => "Invalid numeric result",
+            ErrorKind::NotAvailable => "No value available",
+            ErrorKind::Args => "Wrong number of arguments",
+            ErrorKind::Recursion => "Maximum recursion depth reached",
+            ErrorKind::Spill => "Array result blocked by other cells",
+        }
+    }
+}
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+impl From<Error> for ErrorKind {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::MaxRecursionReached => ErrorKind::Recursion,
+            // caching only becomes inconsistent through re-entrant evaluation, aka a cycle
+            Error::CyclicDependencyDetected | Error::InconsistentCaching => ErrorKind::Cycle,
+            Error::RefMustBeNumber | Error::OperationUnavailable => ErrorKind::Value,
+            Error::FunctionNotAvailable | Error::UnknownVariable(_) => ErrorKind::Name,
+            Error::DivideByZero => ErrorKind::DivByZero,
+            Error::InvalidNumber | Error::NoConvergence => ErrorKind::Num,
+            Error::InsufficientArgs | Error::TooManyArgs => ErrorKind::Args,
+            Error::SheetNotFound
+            | Error::NoSheetIdxLeft
+            | Error::RefOutOfBounds
+            | Error::NoRoomToInsert
+            | Error::DuplicateSheetName(_)
+            | Error::InvalidSheetName(_) => ErrorKind::Ref,
+            Error::NoMatch => ErrorKind::NotAvailable,
+            Error::ErrorValue(kind) => kind,
+        }
+    }
+}
+
+/// A rectangular grid of values, i.e. the array literal `{1, 2; 3, 4}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array {
+    rows: usize,
+    cols: usize,
+    /// In row major order
+    values: Vec<Value>,
+}
+impl Array {
+    /// An array of the given rows, or None if they aren't all the same length
+    pub fn new(rows: Vec<Vec<Value>>) -> Option<Self> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != cols) {
+            return None;
+        }
+        Some(Self {
+            rows: rows.len(),
+            cols,
+            values: rows.into_iter().flatten().collect(),
+        })
+    }
+    /// An array with each value given by its row and col
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> Value) -> Self {
+        Self {
+            rows,
+            cols,
+            values: (0..rows)
+                .flat_map(|row| (0..cols).map(move |col| (row, col)))
+                .map(|(row, col)| f(row, col))
+                .collect(),
+        }
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn get(&self, row: usize, col: usize) -> Option<&Value> {
+        if row < self.rows && col < self.cols {
+            self.values.get(row * self.cols + col)
+        } else {
+            None
+        }
+    }
+    /// Every value, in row major order
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+    /// Applies `f` to every value, keeping the shape
+    pub fn map(self, f: impl FnMut(Value) -> Value) -> Self {
+        Self {
+            values: self.values.into_iter().map(f).collect(),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -24,19 +256,135 @@ pub enum Value {
     Bool(bool),
     Number(f64),
     String(String),
-    // Date(),
-    // Array(),
-    // Range(),
+    Date(Date),
+    DateTime(DateTime),
+    Duration(Duration),
+    /// Multiple values, which spill into the cells next to the formula that results in them
+    Array(Array),
+    Range {
+        sref: SheetIdx,
+        range: CellRange,
+    },
     Ref {
         sref: SheetIdx,
         cref: CellIdx,
     },
     Formula(Formula),
+    /// The result of a failed evaluation, which propagates through any operation using it
+    Error(ErrorKind),
 }
 impl Value {
     pub fn new(val: impl Into<Value>) -> Self {
         val.into()
     }
+
+    /// The value as text, as used by the `&` operator and text functions
+    ///
+    /// Blanks are empty, and numbers and booleans are written as they would be in a formula. Errors propagate
+    pub fn text(&self) -> Result<String, Error> {
+        match self {
+            Value::None => Ok(String::new()),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Number(n) => Ok(n.to_string()),
+            Value::String(s) => Ok(s.clone()),
+            Value::Date(date) => Ok(date.to_string()),
+            Value::DateTime(dt) => Ok(dt.to_string()),
+            Value::Duration(duration) => Ok(duration.to_string()),
+            Value::Error(kind) => Err(Error::ErrorValue(*kind)),
+            Value::Array(_) | Value::Range { .. } | Value::Ref { .. } | Value::Formula(_) => {
+                Err(Error::OperationUnavailable)
+            }
+        }
+    }
+
+    /// The truthiness of the value, as used by the logical operators
+    ///
+    /// Blanks are false, and numbers are true when non-zero. Other types have no truthiness
+    pub fn truthy(&self) -> Result<bool, Error> {
+        match self {
+            Value::None => Ok(false),
+            Value::Bool(b) => Ok(*b),
+            Value::Number(n) => Ok(*n != 0.0),
+            Value::Error(kind) => Err(Error::ErrorValue(*kind)),
+            Value::String(_)
+            | Value::Date(_)
+            | Value::DateTime(_)
+            | Value::Duration(_)
+            | Value::Array(_)
+            | Value::Range { .. }
+            | Value::Ref { .. }
+            | Value::Formula(_) => Err(Error::OperationUnavailable),
+        }
+    }
+
+    /// Orders two values, as used by the comparison operators
+    ///
+    /// Values of the same type compare naturally, with strings compared case-sensitively, and dates compare
+    /// with datetimes as their midnight.
+    /// A blank compares as the empty value of the other side's type (`0`, `''`, `false`, or a zero duration).
+    /// Otherwise, differing types are ordered numbers < dates < durations < strings < bools < refs < ranges.
+    ///
+    /// Returns `None` when the values are unordered, i.e. when comparing against NaN
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, Error> {
+        /// The position of each type in the cross-type ordering
+        fn rank(val: &Value) -> Result<u8, Error> {
+            match val {
+                Value::None => Ok(0),
+                Value::Number(_) => Ok(1),
+                Value::Date(_) | Value::DateTime(_) => Ok(2),
+                Value::Duration(_) => Ok(3),
+                Value::String(_) => Ok(4),
+                Value::Bool(_) => Ok(5),
+                Value::Ref { .. } => Ok(6),
+                Value::Range { .. } => Ok(7),
+                Value::Array(_) | Value::Formula(_) => Err(Error::OperationUnavailable),
+                Value::Error(kind) => Err(Error::ErrorValue(*kind)),
+            }
+        }
+
+        match (self, other) {
+            (Value::Error(kind), _) | (_, Value::Error(kind)) => Err(Error::ErrorValue(*kind)),
+            (Value::None, Value::None) => Ok(Some(Ordering::Equal)),
+            (Value::None, Value::Number(_)) => Value::Number(0.0).compare(other),
+            (Value::None, Value::String(_)) => Value::String(String::new()).compare(other),
+            (Value::None, Value::Bool(_)) => Value::Bool(false).compare(other),
+            (Value::None, Value::Duration(_)) => {
+                Value::Duration(Duration::default()).compare(other)
+            }
+            (
+                Value::Number(_) | Value::String(_) | Value::Bool(_) | Value::Duration(_),
+                Value::None,
+            ) => Ok(other.compare(self)?.map(Ordering::reverse)),
+            (Value::Number(a), Value::Number(b)) => Ok(a.partial_cmp(b)),
+            (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+            (Value::Bool(a), Value::Bool(b)) => Ok(Some(a.cmp(b))),
+            (Value::Duration(a), Value::Duration(b)) => Ok(Some(a.cmp(b))),
+            (Value::Date(_) | Value::DateTime(_), Value::Date(_) | Value::DateTime(_)) => {
+                Ok(instant(self).partial_cmp(&instant(other)))
+            }
+            (
+                Value::Ref {
+                    sref: l_sref,
+                    cref: l_cref,
+                },
+                Value::Ref {
+                    sref: r_sref,
+                    cref: r_cref,
+                },
+            ) => Ok(Some((l_sref, l_cref).cmp(&(r_sref, r_cref)))),
+            (
+                Value::Range {
+                    sref: l_sref,
+                    range: l_range,
+                },
+                Value::Range {
+                    sref: r_sref,
+                    range: r_range,
+                },
+            ) => Ok(Some((l_sref, l_range).cmp(&(r_sref, r_range)))),
+            _ => Ok(Some(rank(self)?.cmp(&rank(other)?))),
+        }
+    }
 }
 
 impl Display for Value {
@@ -46,8 +394,29 @@ impl Display for Value {
             Value::Bool(b) => writeln!(f, "{b}"),
             Value::Number(n) => writeln!(f, "{n}"),
             Value::String(s) => writeln!(f, "{s}"),
+            Value::Date(date) => writeln!(f, "{date}"),
+            Value::DateTime(dt) => writeln!(f, "{dt}"),
+            Value::Duration(duration) => writeln!(f, "{duration}"),
+            Value::Array(array) => {
+                let rows = (0..array.rows())
+                    .map(|row| {
+                        (0..array.cols())
+                            .filter_map(|col| array.get(row, col))
+                            .map(|val| val.to_string().trim_end_matches('\n').to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(f, "{{{}}}", rows.join("; "))
+            }
+            Value::Range { sref, range } => writeln!(
+                f,
+                "{sref:?}[{}..{}, {}..{}]",
+                range.start.row, range.end.row, range.start.col, range.end.col
+            ),
             Value::Ref { sref, cref } => writeln!(f, "{sref:?}[{}, {}]", cref.row, cref.col),
             Value::Formula(formula) => writeln!(f, "{}", formula.script),
+            Value::Error(kind) => writeln!(f, "{kind}"),
         }
     }
 }
@@ -58,6 +427,10 @@ impl PartialEq for Value {
             (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
             (Self::Number(l0), Self::Number(r0)) => l0 == r0,
             (Self::String(l0), Self::String(r0)) => l0 == r0,
+            (Self::Date(l0), Self::Date(r0)) => l0 == r0,
+            (Self::DateTime(l0), Self::DateTime(r0)) => l0 == r0,
+            (Self::Duration(l0), Self::Duration(r0)) => l0 == r0,
+            (Self::Array(l0), Self::Array(r0)) => l0 == r0,
             (
                 Self::Ref {
                     sref: l_sref,
@@ -68,7 +441,18 @@ impl PartialEq for Value {
                     cref: r_cref,
                 },
             ) => l_sref == r_sref && l_cref == r_cref,
+            (
+                Self::Range {
+                    sref: l_sref,
+                    range: l_range,
+                },
+                Self::Range {
+                    sref: r_sref,
+                    range: r_range,
+                },
+            ) => l_sref == r_sref && l_range == r_range,
             (Self::Formula(_l0), Self::Formula(_r0)) => false,
+            (Self::Error(l0), Self::Error(r0)) => l0 == r0,
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
@@ -89,13 +473,17 @@ impl From<bool> for Value {
 impl TryFrom<&str> for Value {
     type Error = FormulaError;
 
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
+    fn try_from(value: &str) -> Result<Self, FormulaError> {
         if value.is_empty() {
             Ok(Self::None)
         } else if let Some('=') = value.chars().next() {
             Ok(Self::Formula(Formula::parse(value)?))
         } else if let Ok(num) = value.parse() {
             Ok(Self::Number(num))
+        } else if let Some(date) = Date::parse(value) {
+            Ok(Self::Date(date))
+        } else if let Some(dt) = DateTime::parse(value) {
+            Ok(Self::DateTime(dt))
         } else {
             Ok(Self::String(value.to_string()))
         }
@@ -105,7 +493,7 @@ impl TryFrom<&str> for Value {
 impl TryFrom<String> for Value {
     type Error = FormulaError;
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
+    fn try_from(value: String) -> Result<Self, FormulaError> {
         TryFrom::try_from(value.as_str())
     }
 }
@@ -160,6 +548,21 @@ impl From<i64> for Value {
         (value as f64).into()
     }
 }
+impl From<Date> for Value {
+    fn from(value: Date) -> Self {
+        Self::Date(value)
+    }
+}
+impl From<DateTime> for Value {
+    fn from(value: DateTime) -> Self {
+        Self::DateTime(value)
+    }
+}
+impl From<Duration> for Value {
+    fn from(value: Duration) -> Self {
+        Self::Duration(value)
+    }
+}
 impl<T: Into<Value>> From<Option<T>> for Value {
     fn from(value: Option<T>) -> Self {
         match value {
@@ -169,39 +572,146 @@ impl<T: Into<Value>> From<Option<T>> for Value {
     }
 }
 
+/// A date or datetime as a datetime, with dates at their midnight
+fn instant(val: &Value) -> Option<DateTime> {
+    match val {
+        Value::Date(date) => Some(date.midnight()),
+        Value::DateTime(dt) => Some(*dt),
+        _ => None,
+    }
+}
+
+/// A number of days, as a duration
+fn days(n: f64) -> Result<Duration, Error> {
+    Duration::from_days(n).ok_or(Error::InvalidNumber)
+}
+
+fn negate(duration: Duration) -> Result<i64, Error> {
+    duration.millis().checked_neg().ok_or(Error::InvalidNumber)
+}
+
+/// Moves a date or datetime by a duration, where a date stays a date if the duration is a whole number of
+/// days, and otherwise becomes a datetime
+fn shift(val: Value, by: Duration) -> Result<Value, Error> {
+    match (val, by.whole_days()) {
+        (Value::Date(date), Some(days)) => date.checked_add_days(days).map(Value::Date),
+        (Value::Date(date), None) => date.midnight().checked_add(by).map(Value::DateTime),
+        (Value::DateTime(dt), _) => dt.checked_add(by).map(Value::DateTime),
+        _ => return Err(Error::OperationUnavailable),
+    }
+    .ok_or(Error::InvalidNumber)
+}
+
+/// Applies a binary operation element by element when either side is an array, and directly otherwise.
+///
+/// A single value is paired with every element, and a single row or col is repeated along the other side.
+/// Past the end of the smaller side the result is `#N/A`, and an element that fails becomes an error value
+/// rather than failing the whole array
+pub(crate) fn broadcast(
+    lhs: Value,
+    rhs: Value,
+    op: impl Fn(Value, Value) -> Result<Value, Error>,
+) -> Result<Value, Error> {
+    /// The element paired with the given position of the result
+    fn element(val: &Value, row: usize, col: usize) -> Value {
+        let Value::Array(array) = val else {
+            return val.clone();
+        };
+        let row = if array.rows() == 1 { 0 } else { row };
+        let col = if array.cols() == 1 { 0 } else { col };
+        array
+            .get(row, col)
+            .cloned()
+            .unwrap_or(Value::Error(ErrorKind::NotAvailable))
+    }
+    /// The shape of a side, where a single value fits any shape
+    fn shape(val: &Value) -> (usize, usize) {
+        match val {
+            Value::Array(array) => (array.rows(), array.cols()),
+            _ => (1, 1),
+        }
+    }
+
+    if !matches!(lhs, Value::Array(_)) && !matches!(rhs, Value::Array(_)) {
+        return op(lhs, rhs);
+    }
+    let ((l_rows, l_cols), (r_rows, r_cols)) = (shape(&lhs), shape(&rhs));
+    Ok(Value::Array(Array::from_fn(
+        l_rows.max(r_rows),
+        l_cols.max(r_cols),
+        |row, col| {
+            op(element(&lhs, row, col), element(&rhs, row, col))
+                .unwrap_or_else(|e| Value::Error(e.into()))
+        },
+    )))
+}
+
 impl ops::Neg for Value {
     type Output = Result<Value, Error>;
 
     fn neg(self) -> Self::Output {
         match self {
+            Value::Error(kind) => Ok(Value::Error(kind)),
             Value::None => Ok(Value::None),
             Value::Bool(b) => Ok(Value::Bool(!b)),
             Value::Number(f) => Ok(Value::Number(-f)),
-            Value::String(_) | Value::Ref { .. } | Value::Formula(_) => {
-                Err(Error::OperationUnavailable)
+            Value::Duration(d) => Ok(Value::Duration(Duration::from_millis(negate(d)?))),
+            Value::Array(array) => {
+                Ok(Value::Array(array.map(|val| {
+                    (-val).unwrap_or_else(|e| Value::Error(e.into()))
+                })))
             }
+            Value::String(_)
+            | Value::Date(_)
+            | Value::DateTime(_)
+            | Value::Range { .. }
+            | Value::Ref { .. }
+            | Value::Formula(_) => Err(Error::OperationUnavailable),
         }
     }
 }
 
+// Numbers added to or subtracted from dates are days, as in other spreadsheets
+
 impl ops::Add for Value {
     type Output = Result<Value, Error>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
+            (lhs @ Value::Array(_), rhs) | (lhs, rhs @ Value::Array(_)) => {
+                broadcast(lhs, rhs, ops::Add::add)
+            }
+            (err @ Value::Error(_), _) | (_, err @ Value::Error(_)) => Ok(err),
             (Value::None, other) | (other, Value::None) => Ok(other),
             (Value::Bool(_), Value::Bool(_)) => Err(Error::OperationUnavailable),
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
             (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
+            (Value::Duration(a), Value::Duration(b)) => Ok(Value::Duration(Duration::from_millis(
+                a.millis()
+                    .checked_add(b.millis())
+                    .ok_or(Error::InvalidNumber)?,
+            ))),
+            (val @ (Value::Date(_) | Value::DateTime(_)), Value::Duration(by))
+            | (Value::Duration(by), val @ (Value::Date(_) | Value::DateTime(_))) => shift(val, by),
+            (val @ (Value::Date(_) | Value::DateTime(_)), Value::Number(n))
+            | (Value::Number(n), val @ (Value::Date(_) | Value::DateTime(_))) => {
+                shift(val, days(n)?)
+            }
 
             (Value::Formula(_), _)
             | (_, Value::Formula(_))
+            | (Value::Range { .. }, _)
+            | (_, Value::Range { .. })
             | (Value::Ref { .. }, _)
             | (_, Value::Ref { .. })
             | (Value::Bool(_), _)
             | (_, Value::Bool(_))
             | (Value::Number(_), _)
-            | (_, Value::Number(_)) => Err(Error::OperationUnavailable),
+            | (_, Value::Number(_))
+            | (Value::Date(_) | Value::DateTime(_) | Value::Duration(_), _)
+            | (_, Value::Date(_) | Value::DateTime(_) | Value::Duration(_)) => {
+                Err(Error::OperationUnavailable)
+            }
         }
     }
 }
@@ -211,18 +721,46 @@ impl ops::Sub for Value {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
+            (lhs @ Value::Array(_), rhs) | (lhs, rhs @ Value::Array(_)) => {
+                broadcast(lhs, rhs, ops::Sub::sub)
+            }
+            (err @ Value::Error(_), _) | (_, err @ Value::Error(_)) => Ok(err),
             (Value::None, other) | (other, Value::None) => Ok(other),
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+            (Value::Duration(a), Value::Duration(b)) => Ok(Value::Duration(Duration::from_millis(
+                a.millis()
+                    .checked_sub(b.millis())
+                    .ok_or(Error::InvalidNumber)?,
+            ))),
+            (
+                a @ (Value::Date(_) | Value::DateTime(_)),
+                b @ (Value::Date(_) | Value::DateTime(_)),
+            ) => match (instant(&a), instant(&b)) {
+                (Some(a), Some(b)) => Ok(Value::Duration(a.since(b))),
+                _ => Err(Error::OperationUnavailable),
+            },
+            (val @ (Value::Date(_) | Value::DateTime(_)), Value::Duration(by)) => {
+                shift(val, Duration::from_millis(negate(by)?))
+            }
+            (val @ (Value::Date(_) | Value::DateTime(_)), Value::Number(n)) => {
+                shift(val, days(-n)?)
+            }
             (Value::Bool(_), Value::Bool(_))
             | (Value::String(_), Value::String(_))
             | (Value::Formula(_), _)
             | (_, Value::Formula(_))
+            | (Value::Range { .. }, _)
+            | (_, Value::Range { .. })
             | (Value::Ref { .. }, _)
             | (_, Value::Ref { .. })
             | (Value::Bool(_), _)
             | (_, Value::Bool(_))
             | (Value::Number(_), _)
-            | (_, Value::Number(_)) => Err(Error::OperationUnavailable),
+            | (_, Value::Number(_))
+            | (Value::Date(_) | Value::DateTime(_) | Value::Duration(_), _)
+            | (_, Value::Date(_) | Value::DateTime(_) | Value::Duration(_)) => {
+                Err(Error::OperationUnavailable)
+            }
         }
     }
 }
@@ -232,18 +770,31 @@ impl ops::Mul for Value {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
+            (lhs @ Value::Array(_), rhs) | (lhs, rhs @ Value::Array(_)) => {
+                broadcast(lhs, rhs, ops::Mul::mul)
+            }
+            (err @ Value::Error(_), _) | (_, err @ Value::Error(_)) => Ok(err),
             (Value::None, other) | (other, Value::None) => Ok(other),
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+            (Value::Duration(d), Value::Number(n)) | (Value::Number(n), Value::Duration(d)) => {
+                Ok(Value::Duration(days(d.as_days() * n)?))
+            }
             (Value::Bool(_), Value::Bool(_))
             | (Value::String(_), Value::String(_))
             | (Value::Formula(_), _)
             | (_, Value::Formula(_))
+            | (Value::Range { .. }, _)
+            | (_, Value::Range { .. })
             | (Value::Ref { .. }, _)
             | (_, Value::Ref { .. })
             | (Value::Bool(_), _)
             | (_, Value::Bool(_))
             | (Value::Number(_), _)
-            | (_, Value::Number(_)) => Err(Error::OperationUnavailable),
+            | (_, Value::Number(_))
+            | (Value::Date(_) | Value::DateTime(_) | Value::Duration(_), _)
+            | (_, Value::Date(_) | Value::DateTime(_) | Value::Duration(_)) => {
+                Err(Error::OperationUnavailable)
+            }
         }
     }
 }
@@ -253,18 +804,36 @@ impl ops::Div for Value {
 
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::None, other) | (other, Value::None) => Ok(other),
+            (lhs @ Value::Array(_), rhs) | (lhs, rhs @ Value::Array(_)) => {
+                broadcast(lhs, rhs, ops::Div::div)
+            }
+            (err @ Value::Error(_), _) | (_, err @ Value::Error(_)) => Ok(err),
+            // a blank divisor is zero, rather than being skipped like other blanks
+            (_, Value::None) => Err(Error::DivideByZero),
+            (Value::None, other) => Ok(other),
+            (Value::Number(_) | Value::Duration(_), Value::Number(0.0)) => Err(Error::DivideByZero),
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+            (Value::Duration(d), Value::Number(n)) => Ok(Value::Duration(days(d.as_days() / n)?)),
+            (Value::Duration(a), Value::Duration(b)) => match b.millis() {
+                0 => Err(Error::DivideByZero),
+                b => Ok(Value::Number(a.millis() as f64 / b as f64)),
+            },
             (Value::Bool(_), Value::Bool(_))
             | (Value::String(_), Value::String(_))
             | (Value::Formula(_), _)
             | (_, Value::Formula(_))
+            | (Value::Range { .. }, _)
+            | (_, Value::Range { .. })
             | (Value::Ref { .. }, _)
             | (_, Value::Ref { .. })
             | (Value::Bool(_), _)
             | (_, Value::Bool(_))
             | (Value::Number(_), _)
-            | (_, Value::Number(_)) => Err(Error::OperationUnavailable),
+            | (_, Value::Number(_))
+            | (Value::Date(_) | Value::DateTime(_) | Value::Duration(_), _)
+            | (_, Value::Date(_) | Value::DateTime(_) | Value::Duration(_)) => {
+                Err(Error::OperationUnavailable)
+            }
         }
     }
 }
@@ -0,0 +1,336 @@
+//! CSV and TSV import and export
+
+use std::io::{Read, Write};
+
+use crate::{
+    Spanleaf,
+    cell::{CellIdx, CellRange, Value},
+    formula::FormulaError,
+    sheet::{Sheet, SheetIdx},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CsvError {
+    #[error(transparent)]
+    Csv(#[from] ::csv::Error),
+    #[error("sheet not found")]
+    SheetNotFound,
+    /// A field starting with '=' wasn't a valid formula
+    #[error("cell [{}, {}]: {err}", .cell.row, .cell.col)]
+    Formula { cell: CellIdx, err: FormulaError },
+    /// A field would go past the last row or col of the sheet, counting records and fields from 0
+    #[error("record {record}, field {field}: past the edge of the sheet")]
+    PastEdge { record: usize, field: usize },
+}
+
+/// How to read a CSV file into a sheet
+#[derive(Debug, Clone)]
+pub struct CsvImportOptions {
+    /// The field delimiter, i.e. `b','` for CSV or `b'\t'` for TSV
+    pub delimiter: u8,
+    /// Whether fields may be quoted. If false, quote characters are kept as part of the field
+    pub quoting: bool,
+    /// The quote character
+    pub quote: u8,
+    /// Whether the first record is a header, which is returned rather than imported
+    pub has_headers: bool,
+    /// Whether fields starting with '=' are parsed as formulas. Otherwise they're imported as strings
+    pub formulas: bool,
+    /// Where the first field of the first record goes
+    pub origin: CellIdx,
+}
+impl CsvImportOptions {
+    /// Tab separated values
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Default::default()
+        }
+    }
+}
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quoting: true,
+            quote: b'"',
+            has_headers: false,
+            formulas: false,
+            origin: CellIdx::new(0, 0),
+        }
+    }
+}
+
+/// What to write for each cell when exporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvContents {
+    /// The calculated value, as from [Spanleaf::get]
+    Computed,
+    /// The raw value, with formulas as their scripts, as from [Spanleaf::get_raw_value]
+    Raw,
+}
+
+/// How to write a sheet to a CSV file
+#[derive(Debug, Clone)]
+pub struct CsvExportOptions {
+    /// The field delimiter, i.e. `b','` for CSV or `b'\t'` for TSV
+    pub delimiter: u8,
+    /// The quote character, used for fields containing the delimiter, quotes or line breaks
+    pub quote: u8,
+    pub contents: CsvContents,
+    /// The cells to export, or None for the sheet's used range.
+    ///
+    /// The used range spans every native cell, with row and col defaults only extending it along their own
+    /// axis, as they're otherwise infinite. Computed contents also include the cells that arrays spill into
+    pub range: Option<CellRange>,
+}
+impl CsvExportOptions {
+    /// Tab separated values
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Default::default()
+        }
+    }
+}
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            contents: CsvContents::Computed,
+            range: None,
+        }
+    }
+}
+
+impl Spanleaf {
+    /// Reads CSV records into the sheet, one row per record, overwriting any values already there.
+    ///
+    /// Empty fields clear their cell, and fields that look like numbers become numbers.
+    /// Returns the header record, which is empty unless [CsvImportOptions::has_headers] is set.
+    /// Records before one that fails, i.e. by going past the last row of the sheet, are still imported
+    pub fn import_csv(
+        &mut self,
+        sheet: SheetIdx,
+        reader: impl Read,
+        options: &CsvImportOptions,
+    ) -> Result<Vec<String>, CsvError> {
+        if self.sheet(sheet).is_none() {
+            return Err(CsvError::SheetNotFound);
+        }
+
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .quoting(options.quoting)
+            .quote(options.quote)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_reader(reader);
+
+        let headers = if options.has_headers {
+            reader.headers()?.iter().map(ToString::to_string).collect()
+        } else {
+            vec![]
+        };
+
+        for (row, record) in reader.records().enumerate() {
+            for (col, field) in record?.iter().enumerate() {
+                let cell = options
+                    .origin
+                    .row
+                    .checked_add(row as u64)
+                    .zip(options.origin.col.checked_add(col as u64))
+                    .map(|(row, col)| CellIdx::new(row, col))
+                    .filter(|cell| cell.row.max(cell.col) <= Sheet::LAST_INDEX)
+                    .ok_or(CsvError::PastEdge {
+                        record: row,
+                        field: col,
+                    })?;
+                let val = if !options.formulas && field.starts_with('=') {
+                    Value::String(field.to_string())
+                } else {
+                    Value::try_from(field).map_err(|err| CsvError::Formula { cell, err })?
+                };
+                // can't fail, as the value is already converted
+                let _ = self.insert(sheet, cell, val);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    /// Writes the sheet as CSV records, one record per row
+    pub fn export_csv(
+        &self,
+        sheet: SheetIdx,
+        writer: impl Write,
+        options: &CsvExportOptions,
+    ) -> Result<(), CsvError> {
+        let s = self.sheet(sheet).ok_or(CsvError::SheetNotFound)?;
+        let range = options.range.unwrap_or_else(|| match options.contents {
+            CsvContents::Computed => self.used_range(sheet),
+            CsvContents::Raw => s.used_range(),
+        });
+
+        let mut writer = ::csv::WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .quote(options.quote)
+            .from_writer(writer);
+
+        if !range.is_empty() {
+            for row in range.start.row..=range.end.row {
+                let record = (range.start.col..=range.end.col).map(|col| {
+                    let cref = CellIdx::new(row, col);
+                    let val = match options.contents {
+                        CsvContents::Computed => self
+                            .get(sheet, cref)
+                            .map(|v| v.value())
+                            .unwrap_or_else(|e| Value::Error(e.into())),
+                        CsvContents::Raw => self.get_raw_value(sheet, cref).value(),
+                    };
+                    self.csv_field(val)
+                });
+                writer.write_record(record)?;
+            }
+        }
+
+        writer.flush().map_err(::csv::Error::from)?;
+        Ok(())
+    }
+
+    /// Formats a value as a CSV field
+    fn csv_field(&self, val: Value) -> String {
+        let sheet_name = |sref| self.sheet(sref).map(|s| s.name.clone()).unwrap_or_default();
+
+        match val {
+            Value::None => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => s,
+            Value::Date(date) => date.to_string(),
+            Value::DateTime(dt) => dt.to_string(),
+            Value::Duration(duration) => duration.to_string(),
+            Value::Formula(f) => f.script,
+            Value::Error(kind) => kind.to_string(),
+            array @ Value::Array(_) => array.to_string().trim_end_matches('\n').to_string(),
+            // references are written as the formula that would produce them
+            Value::Ref { sref, cref } => {
+                format!("=&{}[{}, {}]", sheet_name(sref), cref.row, cref.col)
+            }
+            Value::Range { sref, range } => format!(
+                "={}[{}..{}, {}..{}]",
+                sheet_name(sref),
+                range.start.row,
+                range.end.row,
+                range.start.col,
+                range.end.col
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, CellRange, Value},
+        csv::{CsvContents, CsvError, CsvExportOptions, CsvImportOptions},
+        sheet::Sheet,
+    };
+
+    #[test]
+    fn import() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+
+        let headers = sl
+            .import_csv(
+                s0,
+                "name,qty,total\napple,3,\"=[r, 1] * 2\"\n\"pear, green\",,\"=sum([1..r, 1])\"\n"
+                    .as_bytes(),
+                &CsvImportOptions {
+                    has_headers: true,
+                    formulas: true,
+                    origin: CellIdx::new(1, 0),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(headers, ["name", "qty", "total"]);
+
+        let get = |sl: &Spanleaf, row, col| sl.get(s0, CellIdx::new(row, col)).unwrap().value();
+        assert_eq!(get(&sl, 0, 0), Value::None);
+        assert_eq!(get(&sl, 1, 0), "apple".try_into().unwrap());
+        assert_eq!(get(&sl, 1, 2), 6.into());
+        assert_eq!(get(&sl, 2, 0), "pear, green".try_into().unwrap());
+        assert_eq!(get(&sl, 2, 1), Value::None);
+        assert_eq!(get(&sl, 2, 2), 3.into());
+
+        // formulas are only parsed when asked for
+        sl.import_csv(s0, "=1 + 1".as_bytes(), &Default::default())
+            .unwrap();
+        assert_eq!(get(&sl, 0, 0), Value::String("=1 + 1".to_string()));
+
+        let err = sl
+            .import_csv(
+                s0,
+                "a\tb\n1\t=1 +".as_bytes(),
+                &CsvImportOptions {
+                    formulas: true,
+                    ..CsvImportOptions::tsv()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, CsvError::Formula { cell, .. } if cell == CellIdx::new(1, 1)));
+
+        let err = sl
+            .import_csv(
+                s0,
+                "1,2\n3,4\n".as_bytes(),
+                &CsvImportOptions {
+                    origin: CellIdx::new(Sheet::LAST_INDEX, 0),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CsvError::PastEdge {
+                record: 1,
+                field: 0
+            }
+        ));
+        assert_eq!(get(&sl, Sheet::LAST_INDEX, 1), 2.into());
+    }
+
+    #[test]
+    fn export() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert_col_default(s0, 2, "=[r, 0] * 2").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), "a, \"b\"").unwrap();
+        sl.insert(s0, CellIdx::new(0, 1), "=&[0, 0]").unwrap();
+        sl.insert(s0, CellIdx::new(1, 1), "=[0, 0] / 0").unwrap();
+
+        let export = |options: &CsvExportOptions| {
+            let mut out = vec![];
+            sl.export_csv(s0, &mut out, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            export(&Default::default()),
+            "1,\"=&Sheet1[0, 0]\",2\n\"a, \"\"b\"\"\",#DIV/0!,#VALUE!\n"
+        );
+        assert_eq!(
+            export(&CsvExportOptions {
+                contents: CsvContents::Raw,
+                range: Some(CellRange::new(CellIdx::new(0, 1), CellIdx::new(2, 2))),
+                ..CsvExportOptions::tsv()
+            }),
+            "=&[0, 0]\t=[r, 0] * 2\n=[0, 0] / 0\t=[r, 0] * 2\n\t=[r, 0] * 2\n"
+        );
+    }
+}
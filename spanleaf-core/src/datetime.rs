@@ -0,0 +1,482 @@
+//! Dates, datetimes and durations
+//!
+//! None of them have a time zone. They're written as in ISO 8601, i.e. `2024-01-31` and `2024-01-31T09:30:00`,
+//! except for durations, which are written as days, hours, minutes and seconds, i.e. `1d2h30m` or `-90.5s`.
+//! In formulas and the file format they're prefixed with `@`, i.e. `@2024-01-31 + @1d`
+
+use std::fmt::Display;
+
+use crate::cell::Value;
+
+pub const MILLIS_PER_DAY: i64 = 86_400_000;
+const MILLIS_PER_HOUR: i64 = 3_600_000;
+const MILLIS_PER_MINUTE: i64 = 60_000;
+const MILLIS_PER_SECOND: i64 = 1_000;
+
+/// The years that can be represented, so that they can always be written with 4 digits
+const YEARS: std::ops::RangeInclusive<i64> = 0..=9999;
+
+/// The days since 1970-01-01 of a date in the proleptic Gregorian calendar, from Howard Hinnant's
+/// `days_from_civil`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (i64::from(month), i64::from(day));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The inverse of [days_from_civil]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses exactly `len` ascii digits
+fn digits(s: &str, len: usize) -> Option<u32> {
+    (s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+}
+
+/// A calendar date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    /// Since 1970-01-01
+    days: i64,
+}
+impl Date {
+    /// None if the date doesn't exist, or is outside of the years 0 to 9999
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Option<Self> {
+        if !YEARS.contains(&year) || !(1..=12).contains(&month) {
+            return None;
+        }
+        if !(1..=days_in_month(year, month)).contains(&day) {
+            return None;
+        }
+        Some(Self {
+            days: days_from_civil(year, month, day),
+        })
+    }
+    /// The date a number of days after 1970-01-01
+    pub fn from_days(days: i64) -> Option<Self> {
+        let date = Self { days };
+        YEARS.contains(&date.year()).then_some(date)
+    }
+    /// Parses an ISO 8601 date, i.e. `2024-01-31`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('-');
+        let year = digits(parts.next()?, 4)?;
+        let month = digits(parts.next()?, 2)?;
+        let day = digits(parts.next()?, 2)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Self::from_ymd(i64::from(year), month, day)
+    }
+
+    /// The days since 1970-01-01
+    pub fn days(&self) -> i64 {
+        self.days
+    }
+    pub fn ymd(&self) -> (i64, u32, u32) {
+        civil_from_days(self.days)
+    }
+    pub fn year(&self) -> i64 {
+        self.ymd().0
+    }
+    pub fn month(&self) -> u32 {
+        self.ymd().1
+    }
+    pub fn day(&self) -> u32 {
+        self.ymd().2
+    }
+    /// The day of the week, from 0 for Monday to 6 for Sunday
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.days + 3).rem_euclid(7) as u32
+    }
+
+    pub fn checked_add_days(&self, days: i64) -> Option<Self> {
+        Self::from_days(self.days.checked_add(days)?)
+    }
+    /// The same day of the month a number of months later, or the last day of that month if it's shorter
+    pub fn checked_add_months(&self, months: i64) -> Option<Self> {
+        let (year, month, day) = self.ymd();
+        let months = (year * 12 + i64::from(month) - 1).checked_add(months)?;
+        let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+        if !YEARS.contains(&year) {
+            return None;
+        }
+        Self::from_ymd(year, month, day.min(days_in_month(year, month)))
+    }
+    /// The last day of the date's month
+    pub fn end_of_month(&self) -> Self {
+        let (year, month, _) = self.ymd();
+        Self {
+            days: days_from_civil(year, month, days_in_month(year, month)),
+        }
+    }
+    /// The start of the day
+    pub fn midnight(&self) -> DateTime {
+        DateTime {
+            millis: self.days * MILLIS_PER_DAY,
+        }
+    }
+}
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{year:04}-{month:02}-{day:02}")
+    }
+}
+
+/// A date and a time of day, to the millisecond
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    /// Since 1970-01-01T00:00:00
+    millis: i64,
+}
+impl DateTime {
+    pub fn new(date: Date, hour: u32, minute: u32, second: u32, milli: u32) -> Option<Self> {
+        if hour >= 24 || minute >= 60 || second >= 60 || milli >= 1000 {
+            return None;
+        }
+        let time = i64::from(hour) * MILLIS_PER_HOUR
+            + i64::from(minute) * MILLIS_PER_MINUTE
+            + i64::from(second) * MILLIS_PER_SECOND
+            + i64::from(milli);
+        Some(Self {
+            millis: date.midnight().millis + time,
+        })
+    }
+    /// The datetime a number of milliseconds after 1970-01-01T00:00:00
+    pub fn from_millis(millis: i64) -> Option<Self> {
+        Date::from_days(millis.div_euclid(MILLIS_PER_DAY))?;
+        Some(Self { millis })
+    }
+    /// Parses an ISO 8601 datetime, with a `T` or a space between the date and the time, and optional seconds
+    /// and milliseconds, i.e. `2024-01-31T09:30` or `2024-01-31 09:30:15.25`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (date, time) = s.split_once(['T', ' '])?;
+        let date = Date::parse(date)?;
+
+        let (time, fraction) = match time.split_once('.') {
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (time, None),
+        };
+        let mut parts = time.split(':');
+        let hour = digits(parts.next()?, 2)?;
+        let minute = digits(parts.next()?, 2)?;
+        let second = match parts.next() {
+            Some(second) => digits(second, 2)?,
+            None if fraction.is_none() => 0,
+            None => return None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        let milli = match fraction {
+            Some(fraction) if (1..=3).contains(&fraction.len()) => {
+                digits(fraction, fraction.len())? * 10u32.pow(3 - fraction.len() as u32)
+            }
+            Some(_) => return None,
+            None => 0,
+        };
+        Self::new(date, hour, minute, second, milli)
+    }
+
+    /// The milliseconds since 1970-01-01T00:00:00
+    pub fn millis(&self) -> i64 {
+        self.millis
+    }
+    pub fn date(&self) -> Date {
+        Date {
+            days: self.millis.div_euclid(MILLIS_PER_DAY),
+        }
+    }
+    /// The time since the start of the day
+    pub fn time(&self) -> Duration {
+        Duration {
+            millis: self.millis.rem_euclid(MILLIS_PER_DAY),
+        }
+    }
+    pub fn hour(&self) -> u32 {
+        (self.time().millis / MILLIS_PER_HOUR) as u32
+    }
+    pub fn minute(&self) -> u32 {
+        (self.time().millis % MILLIS_PER_HOUR / MILLIS_PER_MINUTE) as u32
+    }
+    pub fn second(&self) -> u32 {
+        (self.time().millis % MILLIS_PER_MINUTE / MILLIS_PER_SECOND) as u32
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        Self::from_millis(self.millis.checked_add(duration.millis)?)
+    }
+    /// The duration from `earlier` until this
+    pub fn since(&self, earlier: DateTime) -> Duration {
+        Duration {
+            millis: self.millis - earlier.millis,
+        }
+    }
+}
+impl Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}",
+            self.date(),
+            self.hour(),
+            self.minute(),
+            self.second()
+        )?;
+        match self.millis.rem_euclid(MILLIS_PER_SECOND) {
+            0 => Ok(()),
+            milli => write!(f, ".{milli:03}"),
+        }
+    }
+}
+
+/// A length of time, to the millisecond, which may be negative
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration {
+    millis: i64,
+}
+impl Duration {
+    pub fn from_millis(millis: i64) -> Self {
+        Self { millis }
+    }
+    /// A number of days, rounded to the millisecond. None if it isn't finite or is too long to represent
+    pub fn from_days(days: f64) -> Option<Self> {
+        let millis = (days * MILLIS_PER_DAY as f64).round();
+        // well beyond the range of dates, but short enough to add to any of them without overflowing
+        (millis.abs() < 1e18).then_some(Self {
+            millis: millis as i64,
+        })
+    }
+    /// Parses days, hours, minutes and seconds, in that order, each of which is optional but at least one
+    /// must be present, i.e. `1d2h30m`, `90m` or `-1.5s`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (sign, mut rest) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s),
+        };
+
+        let mut millis = 0i64;
+        // the units that may still come, in order
+        let mut units = [
+            ('d', MILLIS_PER_DAY),
+            ('h', MILLIS_PER_HOUR),
+            ('m', MILLIS_PER_MINUTE),
+            ('s', MILLIS_PER_SECOND),
+        ]
+        .as_slice();
+        while !rest.is_empty() {
+            let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+            let (number, unit) = (&rest[..end], rest[end..].chars().next()?);
+            let pos = units.iter().position(|(u, _)| *u == unit)?;
+            let per_unit = units[pos].1;
+            units = &units[pos + 1..];
+
+            // only seconds may have a fraction
+            let part = if unit == 's' && number.contains('.') {
+                let n = number.parse::<f64>().ok()?;
+                (n * MILLIS_PER_SECOND as f64).round() as i64
+            } else {
+                if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                number.parse::<i64>().ok()?.checked_mul(per_unit)?
+            };
+            millis = millis.checked_add(part)?;
+            rest = &rest[end + 1..];
+        }
+        if units.len() == 4 {
+            return None;
+        }
+        Some(Self {
+            millis: sign * millis,
+        })
+    }
+
+    pub fn millis(&self) -> i64 {
+        self.millis
+    }
+    /// The length in days, including any fraction of a day
+    pub fn as_days(&self) -> f64 {
+        self.millis as f64 / MILLIS_PER_DAY as f64
+    }
+    /// The number of days, if it's a whole number of them
+    pub fn whole_days(&self) -> Option<i64> {
+        (self.millis % MILLIS_PER_DAY == 0).then_some(self.millis / MILLIS_PER_DAY)
+    }
+}
+impl Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.millis == 0 {
+            return write!(f, "0s");
+        }
+        if self.millis < 0 {
+            write!(f, "-")?;
+        }
+        let millis = self.millis.unsigned_abs();
+        let parts = [
+            (millis / MILLIS_PER_DAY as u64, 'd'),
+            (millis % MILLIS_PER_DAY as u64 / MILLIS_PER_HOUR as u64, 'h'),
+            (
+                millis % MILLIS_PER_HOUR as u64 / MILLIS_PER_MINUTE as u64,
+                'm',
+            ),
+        ];
+        for (n, unit) in parts {
+            if n != 0 {
+                write!(f, "{n}{unit}")?;
+            }
+        }
+        let seconds = millis % MILLIS_PER_MINUTE as u64;
+        match (seconds / 1000, seconds % 1000) {
+            (0, 0) => Ok(()),
+            (s, 0) => write!(f, "{s}s"),
+            (s, milli) => {
+                let fraction = format!("{milli:03}");
+                write!(f, "{s}.{}s", fraction.trim_end_matches('0'))
+            }
+        }
+    }
+}
+
+/// Parses a date, datetime or duration as written after the `@` of a literal
+pub(crate) fn literal(s: &str) -> Option<Value> {
+    Date::parse(s)
+        .map(Value::Date)
+        .or_else(|| DateTime::parse(s).map(Value::DateTime))
+        .or_else(|| Duration::parse(s).map(Value::Duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::Value,
+        datetime::{Date, DateTime, Duration, literal},
+    };
+
+    #[test]
+    fn dates() {
+        let date = Date::from_ymd(2024, 2, 29).unwrap();
+        assert_eq!(date.ymd(), (2024, 2, 29));
+        assert_eq!(date.to_string(), "2024-02-29");
+        assert_eq!(Date::parse("2024-02-29"), Some(date));
+        assert_eq!(Date::from_ymd(1970, 1, 1).unwrap().days(), 0);
+        assert_eq!(Date::from_ymd(1969, 12, 31).unwrap().days(), -1);
+        assert_eq!(Date::from_ymd(2000, 3, 1).unwrap().days(), 11_017);
+        assert_eq!(Date::from_ymd(1, 1, 1).unwrap().to_string(), "0001-01-01");
+
+        assert_eq!(Date::from_ymd(2023, 2, 29), None);
+        assert_eq!(Date::from_ymd(1900, 2, 29), None);
+        assert_eq!(Date::from_ymd(10_000, 1, 1), None);
+        assert_eq!(Date::parse("2024-1-31"), None);
+        assert_eq!(Date::parse("2024-01-31T00:00"), None);
+
+        // a Thursday
+        assert_eq!(Date::from_ymd(1970, 1, 1).unwrap().weekday(), 3);
+        assert_eq!(Date::from_ymd(2024, 1, 1).unwrap().weekday(), 0);
+
+        let jan31 = Date::from_ymd(2023, 1, 31).unwrap();
+        assert_eq!(
+            jan31.checked_add_months(1).unwrap().to_string(),
+            "2023-02-28"
+        );
+        assert_eq!(
+            jan31.checked_add_months(-2).unwrap().to_string(),
+            "2022-11-30"
+        );
+        assert_eq!(
+            jan31.checked_add_months(13).unwrap().to_string(),
+            "2024-02-29"
+        );
+        assert_eq!(date.end_of_month().to_string(), "2024-02-29");
+        assert_eq!(
+            jan31.checked_add_days(29).unwrap().to_string(),
+            "2023-03-01"
+        );
+    }
+
+    #[test]
+    fn datetimes() {
+        let dt = DateTime::parse("2024-01-31T09:05:07.25").unwrap();
+        assert_eq!(dt.to_string(), "2024-01-31T09:05:07.250");
+        assert_eq!((dt.hour(), dt.minute(), dt.second()), (9, 5, 7));
+        assert_eq!(dt.date().to_string(), "2024-01-31");
+        assert_eq!(
+            DateTime::parse("2024-01-31 09:30").unwrap().to_string(),
+            "2024-01-31T09:30:00"
+        );
+        assert_eq!(DateTime::parse("2024-01-31T24:00"), None);
+        assert_eq!(DateTime::parse("2024-01-31T09:30:00.1234"), None);
+
+        // before 1970, the time of day is still positive
+        let dt = DateTime::parse("1969-12-31T23:00").unwrap();
+        assert_eq!(dt.millis(), -3_600_000);
+        assert_eq!(dt.hour(), 23);
+    }
+
+    #[test]
+    fn durations() {
+        let cases = [
+            ("1d2h30m", 95_400_000),
+            ("90m", 5_400_000),
+            ("-1.5s", -1_500),
+            ("2h0.25s", 7_200_250),
+            ("0s", 0),
+        ];
+        for (s, millis) in cases {
+            assert_eq!(
+                Duration::parse(s),
+                Some(Duration::from_millis(millis)),
+                "{s}"
+            );
+        }
+        assert_eq!(Duration::from_millis(95_400_000).to_string(), "1d2h30m");
+        assert_eq!(Duration::from_millis(5_400_000).to_string(), "1h30m");
+        assert_eq!(Duration::from_millis(-1_500).to_string(), "-1.5s");
+        assert_eq!(Duration::from_millis(0).to_string(), "0s");
+
+        for s in ["", "-", "1", "1h1d", "1h1h", "1.5h", "d", "1x"] {
+            assert_eq!(Duration::parse(s), None, "{s}");
+        }
+
+        assert_eq!(
+            literal("2024-01-31"),
+            Some(Value::Date(Date::from_ymd(2024, 1, 31).unwrap()))
+        );
+        assert!(matches!(
+            literal("2024-01-31T12:00"),
+            Some(Value::DateTime(_))
+        ));
+        assert_eq!(
+            literal("1d"),
+            Some(Value::Duration(Duration::from_millis(86_400_000)))
+        );
+        assert_eq!(literal("tomorrow"), None);
+    }
+}
@@ -0,0 +1,1342 @@
+//! Translation between Excel's A1-style formulas and Spanleaf formulas
+//!
+//! Excel formulas are written relative to the cell they're in, but as every imported cell gets its own
+//! formula, A1 references are translated to absolute `[row, col]` references. Only the functions and
+//! operators with a Spanleaf equivalent can be translated, anything else is reported as unsupported.
+//!
+//! Exporting goes the other way, with the formula of every cell, including those of row and col defaults,
+//! generated for that cell. Coordinates that depend on the current row and col are folded into constants,
+//! while ones that depend on other cells have no A1 equivalent.
+// only exporting is shared with ODS
+#![cfg_attr(not(feature = "xlsx"), allow(dead_code))]
+
+use std::{mem, ops::Range};
+
+use logos::Logos;
+
+use crate::{
+    Spanleaf,
+    cell::{CellIdx, CellRange, ErrorKind, Value},
+    language::{self, Expr},
+    sheet::{SheetIdx, ValueResult},
+};
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TranslateError {
+    #[error("unsupported function `{0}`")]
+    UnsupportedFunction(String),
+    #[error("unsupported syntax `{0}`")]
+    UnsupportedSyntax(String),
+    #[error("unknown sheet `{0}`")]
+    UnknownSheet(String),
+    #[error("invalid formula at {}..{}", .0.start, .0.end)]
+    Invalid(Range<usize>),
+}
+
+/// A formula that couldn't be translated, so only its value was kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct UntranslatedFormula {
+    pub sheet: SheetIdx,
+    pub cell: CellIdx,
+    /// The original formula
+    pub formula: String,
+    pub reason: TranslateError,
+}
+
+/// Options for exporting to spreadsheet formats, which have no row and col defaults
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// The last cell that row and col defaults are filled into, or None to fill each sheet's used range
+    pub default_extent: Option<CellIdx>,
+}
+
+#[derive(Logos, Debug, Clone, Copy, PartialEq)]
+#[logos(skip r"[ \t\r\n]+")]
+enum Token<'s> {
+    #[regex(r"[0-9]+(\.[0-9]*)?([eE][+-]?[0-9]+)?", |lex| lex.slice())]
+    #[regex(r"\.[0-9]+([eE][+-]?[0-9]+)?", |lex| lex.slice())]
+    Number(&'s str),
+    /// Includes the quotes, and may contain escaped `""` quotes
+    #[regex(r#""([^"]|"")*""#, |lex| lex.slice())]
+    String(&'s str),
+    /// Includes the quotes and '!', and may contain escaped `''` quotes
+    #[regex(r"'([^']|'')+'!", |lex| lex.slice())]
+    QuotedSheet(&'s str),
+    #[token("#DIV/0!", |lex| lex.slice())]
+    #[token("#N/A", |lex| lex.slice())]
+    #[token("#NAME?", |lex| lex.slice())]
+    #[token("#NULL!", |lex| lex.slice())]
+    #[token("#NUM!", |lex| lex.slice())]
+    #[token("#REF!", |lex| lex.slice())]
+    #[token("#VALUE!", |lex| lex.slice())]
+    #[token("#SPILL!", |lex| lex.slice())]
+    #[token("#GETTING_DATA", |lex| lex.slice())]
+    Error(&'s str),
+    /// A cell reference, function, sheet or defined name, decided by what surrounds it
+    #[regex(r"\$?[A-Za-z_\\][A-Za-z0-9_.]*(\$[0-9]+)?", |lex| lex.slice())]
+    Name(&'s str),
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[token("{")]
+    LBrace,
+    #[token("}")]
+    RBrace,
+    #[token(",")]
+    Comma,
+    #[token(";")]
+    Semicolon,
+    #[token(":")]
+    Colon,
+    #[token("!")]
+    Bang,
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*")]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("^")]
+    Caret,
+    #[token("&")]
+    Amp,
+    #[token("%")]
+    Percent,
+    #[token("=")]
+    Eq,
+    #[token("<>")]
+    Ne,
+    #[token("<")]
+    Lt,
+    #[token("<=")]
+    Le,
+    #[token(">")]
+    Gt,
+    #[token(">=")]
+    Ge,
+}
+
+/// Translates an Excel formula, with or without its leading '='.
+///
+/// `sheet_name` maps a sheet name used in the formula to the name of the corresponding Spanleaf sheet
+pub(crate) fn translate(
+    formula: &str,
+    sheet_name: impl Fn(&str) -> Option<String>,
+) -> Result<Expr, TranslateError> {
+    let body = formula.strip_prefix('=').unwrap_or(formula);
+
+    let mut tokens = vec![];
+    for (token, span) in Token::lexer(body).spanned() {
+        match token {
+            Ok(token) => tokens.push((token, span)),
+            // i.e. external workbooks or structured table references
+            Err(()) => return Err(TranslateError::UnsupportedSyntax(body[span].to_string())),
+        }
+    }
+
+    let mut translator = Translator {
+        tokens,
+        pos: 0,
+        end: body.len(),
+        sheet_name: &sheet_name,
+    };
+    let expr = translator.comparison()?;
+    match translator.tokens.get(translator.pos) {
+        None => Ok(expr),
+        Some((_, span)) => Err(TranslateError::Invalid(span.clone())),
+    }
+}
+
+/// The Spanleaf equivalent of an Excel error literal
+pub(crate) fn error_kind(code: &str) -> Option<ErrorKind> {
+    Some(match code {
+        "#DIV/0!" => ErrorKind::DivByZero,
+        "#N/A" | "#GETTING_DATA" => ErrorKind::NotAvailable,
+        "#NAME?" => ErrorKind::Name,
+        "#NULL!" | "#VALUE!" => ErrorKind::Value,
+        "#NUM!" => ErrorKind::Num,
+        "#REF!" => ErrorKind::Ref,
+        "#SPILL!" => ErrorKind::Spill,
+        _ => return None,
+    })
+}
+
+/// The Excel error literal of an error, if it has one
+fn error_code(kind: ErrorKind) -> Option<&'static str> {
+    match kind {
+        ErrorKind::Cycle | ErrorKind::Args | ErrorKind::Recursion => None,
+        _ => Some(kind.code()),
+    }
+}
+
+/// Parses an A1 cell reference, i.e. `B7` or `$A$1`, into a 0-based row and col
+fn parse_a1(name: &str) -> Option<(u64, u64)> {
+    let name = name.strip_prefix('$').unwrap_or(name);
+    let letters = name.len()
+        - name
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .len();
+    let (col, rest) = name.split_at(letters);
+    let row = rest.strip_prefix('$').unwrap_or(rest);
+
+    if !(1..=3).contains(&col.len()) || row.is_empty() || !row.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let col = col.chars().fold(0, |acc, c| {
+        acc * 26 + (c.to_ascii_uppercase() as u64 - 'A' as u64 + 1)
+    }) - 1;
+    let row = row.parse::<u64>().ok()?.checked_sub(1)?;
+
+    // the largest sheet Excel supports is A1:XFD1048576
+    (col < 16_384 && row < 1_048_576).then_some((row, col))
+}
+
+/// Formats a 0-based row and col as an A1 cell reference, i.e. `B7`, if it's within Excel's limits
+fn format_a1(row: u64, col: u64) -> Option<String> {
+    (row < 1_048_576 && col < 16_384).then(|| CellIdx::new(row, col).a1())
+}
+
+/// A recursive descent parser over the lexed formula, building the equivalent Spanleaf expression
+struct Translator<'s, 'f> {
+    tokens: Vec<(Token<'s>, Range<usize>)>,
+    pos: usize,
+    /// The end of the formula, for errors at the end
+    end: usize,
+    sheet_name: &'f dyn Fn(&str) -> Option<String>,
+}
+impl<'s> Translator<'s, '_> {
+    fn peek(&self) -> Option<Token<'s>> {
+        self.tokens.get(self.pos).map(|(t, _)| *t)
+    }
+
+    fn next(&mut self) -> Option<Token<'s>> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if it's the given token
+    fn eat(&mut self, token: Token<'s>) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// An error at the current token
+    fn invalid(&self) -> TranslateError {
+        TranslateError::Invalid(
+            self.tokens
+                .get(self.pos)
+                .map(|(_, span)| span.clone())
+                .unwrap_or(self.end..self.end),
+        )
+    }
+
+    fn expect(&mut self, token: Token<'s>) -> Result<(), TranslateError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.invalid())
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, TranslateError> {
+        let mut lhs = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Eq) => Expr::eq,
+                Some(Token::Ne) => Expr::ne,
+                Some(Token::Lt) => Expr::lt,
+                Some(Token::Le) => Expr::le,
+                Some(Token::Gt) => Expr::gt,
+                Some(Token::Ge) => Expr::ge,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = op(lhs, self.concat()?);
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, TranslateError> {
+        let mut lhs = self.additive()?;
+        while self.eat(Token::Amp) {
+            lhs = Expr::concat(lhs, self.additive()?);
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Expr, TranslateError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => Expr::add,
+                Some(Token::Minus) => Expr::sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = op(lhs, self.multiplicative()?);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, TranslateError> {
+        let mut lhs = self.power()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => Expr::mul,
+                Some(Token::Slash) => Expr::div,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = op(lhs, self.power()?);
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, TranslateError> {
+        let lhs = self.percent()?;
+        if self.peek() == Some(Token::Caret) {
+            return Err(TranslateError::UnsupportedSyntax("^".to_string()));
+        }
+        Ok(lhs)
+    }
+
+    fn percent(&mut self) -> Result<Expr, TranslateError> {
+        let mut expr = self.unary()?;
+        while self.eat(Token::Percent) {
+            expr = Expr::div(expr, Expr::value(Value::Number(100.0)));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, TranslateError> {
+        if self.eat(Token::Minus) {
+            Ok(Expr::neg(self.unary()?))
+        } else if self.eat(Token::Plus) {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, TranslateError> {
+        let start = self.pos;
+        match self.next() {
+            Some(Token::Number(n)) => n
+                .parse()
+                .map(|n| Expr::value(Value::Number(n)))
+                .map_err(|_| TranslateError::Invalid(self.tokens[start].1.clone())),
+            Some(Token::String(s)) => {
+                let s = s[1..s.len() - 1].replace("\"\"", "\"");
+                // Spanleaf strings have no escapes, so can't contain their own quote
+                if s.contains('\'') {
+                    return Err(TranslateError::UnsupportedSyntax(format!("\"{s}\"")));
+                }
+                Ok(Expr::value(Value::String(s)))
+            }
+            Some(Token::Error(code)) => Ok(Expr::value(Value::Error(
+                error_kind(code).expect("the lexer only produces known error codes"),
+            ))),
+            Some(Token::LParen) => {
+                let expr = self.comparison()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            // an array constant, with rows separated by ';'
+            Some(Token::LBrace) => {
+                let mut rows = vec![vec![self.comparison()?]];
+                loop {
+                    match self.peek() {
+                        Some(Token::Comma) => {
+                            self.pos += 1;
+                            let element = self.comparison()?;
+                            rows.last_mut().expect("not empty").push(element);
+                        }
+                        Some(Token::Semicolon) => {
+                            self.pos += 1;
+                            rows.push(vec![self.comparison()?]);
+                        }
+                        _ => break,
+                    }
+                }
+                if rows.iter().any(|row| row.len() != rows[0].len()) {
+                    return Err(TranslateError::Invalid(self.tokens[start].1.clone()));
+                }
+                self.expect(Token::RBrace)?;
+                Ok(Expr::Array(rows))
+            }
+            Some(Token::QuotedSheet(sheet)) => {
+                let sheet = sheet[1..sheet.len() - 2].replace("''", "'");
+                self.reference(Some(sheet))
+            }
+            Some(Token::Name(name)) => match self.peek() {
+                Some(Token::LParen) => {
+                    self.pos += 1;
+                    self.call(name)
+                }
+                Some(Token::Bang) => {
+                    self.pos += 1;
+                    self.reference(Some(name.to_string()))
+                }
+                _ if name.eq_ignore_ascii_case("true") => Ok(Expr::value(Value::Bool(true))),
+                _ if name.eq_ignore_ascii_case("false") => Ok(Expr::value(Value::Bool(false))),
+                _ => {
+                    self.pos = start;
+                    self.reference(None)
+                }
+            },
+            _ => {
+                self.pos = start;
+                Err(self.invalid())
+            }
+        }
+    }
+
+    /// A cell or range reference, after any sheet prefix
+    fn reference(&mut self, sheet: Option<String>) -> Result<Expr, TranslateError> {
+        let sheet = match sheet {
+            Some(sheet) => match (self.sheet_name)(&sheet) {
+                Some(name) => Some(Expr::Sheet(name)),
+                None => return Err(TranslateError::UnknownSheet(sheet)),
+            },
+            None => None,
+        };
+
+        let start = self.cell()?;
+        if !self.eat(Token::Colon) {
+            return Ok(Expr::cell_deref(Expr::cell_ref(
+                sheet,
+                Expr::value(Value::Number(start.0 as f64)),
+                Expr::value(Value::Number(start.1 as f64)),
+            )));
+        }
+        let end = self.cell()?;
+
+        // ranges may be written from any corner
+        let coord = |a: u64, b: u64| {
+            (
+                Expr::value(Value::Number(a.min(b) as f64)),
+                Expr::value(Value::Number(a.max(b) as f64)),
+            )
+        };
+        Ok(Expr::range_ref(
+            sheet,
+            coord(start.0, end.0),
+            coord(start.1, end.1),
+        ))
+    }
+
+    /// A single A1 cell
+    fn cell(&mut self) -> Result<(u64, u64), TranslateError> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                // anything else is a defined name, or a whole row or col, which have no equivalent
+                parse_a1(name).ok_or_else(|| TranslateError::UnsupportedSyntax(name.to_string()))
+            }
+            _ => Err(self.invalid()),
+        }
+    }
+
+    /// A function call, after the opening parenthesis
+    fn call(&mut self, name: &str) -> Result<Expr, TranslateError> {
+        let mut args = vec![];
+        if !self.eat(Token::RParen) {
+            loop {
+                args.push(self.comparison()?);
+                if self.eat(Token::RParen) {
+                    break;
+                }
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        // newer functions are prefixed in the file, to stay compatible with older versions of Excel
+        let name = name
+            .trim_start_matches("_xlfn.")
+            .trim_start_matches("_xlws.")
+            .to_ascii_uppercase();
+        let has_range = args.iter().any(|arg| matches!(arg, Expr::RangeRef(..)));
+        let call = |spanleaf_name: &str, args| Ok(Expr::Call(spanleaf_name.to_string(), args));
+
+        match (name.as_str(), args.len()) {
+            ("SUM", _) => call("sum", args),
+            ("AVERAGE", _) => call("average", args),
+            ("IF", 2 | 3) => call("if", args),
+            ("IFS", _) => call("ifs", args),
+            ("SWITCH", _) => call("switch", args),
+            ("ISERROR", 1) => call("iserror", args),
+            ("ISBLANK", 1) => call("isblank", args),
+            ("POWER", 2) => call("power", args),
+            ("ABS", 1) => call("abs", args),
+            ("SIGN", 1) => call("sign", args),
+            ("ROUND", 2) => call("round", args),
+            ("INT", 1) => call("floor", args),
+            ("MOD", 2) => call("mod", args),
+            ("SQRT", 1) => call("sqrt", args),
+            ("EXP", 1) => call("exp", args),
+            ("LN", 1) => call("ln", args),
+            ("LOG", 1 | 2) => call("log", args),
+            ("LOG10", 1) => call("log10", args),
+            ("SIN", 1) => call("sin", args),
+            ("COS", 1) => call("cos", args),
+            ("TAN", 1) => call("tan", args),
+            ("ASIN", 1) => call("asin", args),
+            ("ACOS", 1) => call("acos", args),
+            ("ATAN", 1) => call("atan", args),
+            ("ATAN2", 2) => call("atan2", args),
+            ("DEGREES", 1) => call("degrees", args),
+            ("RADIANS", 1) => call("radians", args),
+            ("PI", 0) => call("pi", args),
+            ("MIN", 1..) => call("min", args),
+            ("MAX", 1..) => call("max", args),
+            ("PRODUCT", 1..) => call("product", args),
+            ("GCD", 1..) => call("gcd", args),
+            ("LCM", 1..) => call("lcm", args),
+            ("FACT", 1) => call("factorial", args),
+            ("COUNT", 1..) => call("count", args),
+            ("COUNTA", 1..) => call("counta", args),
+            ("COUNTBLANK", 1) => call("countblank", args),
+            ("MEDIAN", 1..) => call("median", args),
+            ("MODE" | "MODE.SNGL", 1..) => call("mode", args),
+            ("VAR" | "VAR.S", 1..) => call("var", args),
+            ("VARP" | "VAR.P", 1..) => call("varp", args),
+            ("STDEV" | "STDEV.S", 1..) => call("stdev", args),
+            ("STDEVP" | "STDEV.P", 1..) => call("stdevp", args),
+            ("PERCENTILE" | "PERCENTILE.INC", 2) => call("percentile", args),
+            ("QUARTILE" | "QUARTILE.INC", 2) => call("quartile", args),
+            ("RANK" | "RANK.EQ", 2 | 3) => call("rank", args),
+            ("CORREL", 2) => call("correl", args),
+            ("COVARIANCE.S", 2) => call("covar", args),
+            // Excel's COVAR is of the whole population
+            ("COVAR" | "COVARIANCE.P", 2) => call("covarp", args),
+            ("SLOPE", 2) => call("slope", args),
+            ("INTERCEPT", 2) => call("intercept", args),
+            ("RSQ", 2) => call("rsq", args),
+            ("FORECAST" | "FORECAST.LINEAR", 3) => call("forecast", args),
+            ("LEN", 1) => call("len", args),
+            ("UPPER", 1) => call("upper", args),
+            ("LOWER", 1) => call("lower", args),
+            ("PROPER", 1) => call("proper", args),
+            ("TRIM", 1) => call("trim", args),
+            ("LEFT", 1 | 2) => call("left", args),
+            ("RIGHT", 1 | 2) => call("right", args),
+            ("MID", 3) => call("mid", args),
+            ("FIND", 2 | 3) => call("find", args),
+            ("SEARCH", 2 | 3) => call("search", args),
+            ("REPLACE", 4) => call("replace", args),
+            ("SUBSTITUTE", 3 | 4) => call("substitute", args),
+            ("CONCAT" | "CONCATENATE", 1..) => call("concat", args),
+            ("TEXTJOIN", 3..) => call("textjoin", args),
+            ("REPT", 2) => call("repeat", args),
+            ("VALUE", 1) => call("value", args),
+            ("TEXT", 2) => call("text", args),
+            // CHAR and CODE are of the system's code page rather than unicode
+            ("UNICHAR", 1) => call("char", args),
+            ("UNICODE", 1) => call("code", args),
+            ("TODAY", 0) => call("today", args),
+            ("NOW", 0) => call("now", args),
+            ("DATE", 3) => call("date", args),
+            ("TIME", 3) => call("time", args),
+            ("YEAR", 1) => call("year", args),
+            ("MONTH", 1) => call("month", args),
+            ("DAY", 1) => call("day", args),
+            ("HOUR", 1) => call("hour", args),
+            ("MINUTE", 1) => call("minute", args),
+            ("SECOND", 1) => call("second", args),
+            ("WEEKDAY", 1 | 2) => call("weekday", args),
+            ("EDATE", 2) => call("edate", args),
+            ("EOMONTH", 2) => call("eomonth", args),
+            ("NETWORKDAYS", 2 | 3) => call("networkdays", args),
+            // Excel's lookups ignore case, where Spanleaf's match as `==` does. OFFSET, INDIRECT, ADDRESS, ROW
+            // and COLUMN are of A1 references and names, rather than of references as values
+            ("VLOOKUP", 3 | 4) => call("vlookup", args),
+            ("HLOOKUP", 3 | 4) => call("hlookup", args),
+            ("XLOOKUP", 3..=5) => call("xlookup", args),
+            ("INDEX", 2 | 3) => call("index", args),
+            ("MATCH", 2 | 3) => call("match", args),
+            ("ROWS", 1) => call("rows", args),
+            ("COLUMNS", 1) => call("cols", args),
+            ("PMT", 3..=5) => call("pmt", args),
+            ("IPMT", 4..=6) => call("ipmt", args),
+            ("PPMT", 4..=6) => call("ppmt", args),
+            ("FV", 3..=5) => call("fv", args),
+            ("PV", 3..=5) => call("pv", args),
+            ("NPER", 3..=5) => call("nper", args),
+            ("RATE", 3..=6) => call("rate", args),
+            ("NPV", 2..) => call("npv", args),
+            ("IRR", 1 | 2) => call("irr", args),
+            ("XNPV", 3) => call("xnpv", args),
+            ("XIRR", 2 | 3) => call("xirr", args),
+            ("SLN", 3) => call("sln", args),
+            ("DDB", 4 | 5) => call("ddb", args),
+            ("SEQUENCE" | "COM.MICROSOFT.SEQUENCE", 1..=4) => call("sequence", args),
+            ("TRANSPOSE", 1) => call("transpose", args),
+            ("IFERROR", 2) => call("iferror", args),
+            // Excel's AND and OR also take ranges, which the operators don't
+            ("AND", 1..) if !has_range => {
+                Ok(args.into_iter().reduce(Expr::and).expect("not empty"))
+            }
+            ("OR", 1..) if !has_range => Ok(args.into_iter().reduce(Expr::or).expect("not empty")),
+            ("NOT", 1) => Ok(Expr::not(args.into_iter().next().expect("one arg"))),
+            ("TRUE", 0) => Ok(Expr::value(Value::Bool(true))),
+            ("FALSE", 0) => Ok(Expr::value(Value::Bool(false))),
+            _ => Err(TranslateError::UnsupportedFunction(name)),
+        }
+    }
+}
+
+/// The flavour of A1 formulas to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dialect {
+    /// Excel, as stored in XLSX files, i.e. `SUM(Sheet2!A1:B2,3)`
+    Excel,
+    /// OpenFormula, as stored in ODS files, i.e. `SUM([Sheet2.A1:.B2];3)`
+    #[cfg_attr(not(feature = "ods"), allow(dead_code))]
+    OpenFormula,
+}
+
+/// Generates the A1 formula of an expression as evaluated in `cell`, without the leading '='.
+///
+/// `sheet_name` maps a sheet name used in the expression to the name of the corresponding sheet in the file
+pub(crate) fn generate(
+    expr: &Expr,
+    cell: CellIdx,
+    dialect: Dialect,
+    sheet_name: impl Fn(&str) -> Option<String>,
+) -> Result<String, TranslateError> {
+    let generator = Generator {
+        cell,
+        dialect,
+        sheet_name: &sheet_name,
+    };
+    generator.expr(expr).map(|(formula, _)| formula)
+}
+
+/// A cell of a sheet, ready to be written to a file
+pub(crate) struct ExportCell {
+    pub cell: CellIdx,
+    /// The computed value
+    pub value: Value,
+    /// The generated formula, without the leading '='
+    pub formula: Option<String>,
+}
+
+/// The non-empty cells of a sheet to write to a file, in row major order, with row and col defaults filled
+/// in as far as [ExportOptions::default_extent].
+///
+/// Formulas that can't be generated are reported to `untranslated`, leaving only their computed value
+pub(crate) fn export_cells(
+    sl: &Spanleaf,
+    sref: SheetIdx,
+    dialect: Dialect,
+    options: &ExportOptions,
+    untranslated: &mut Vec<UntranslatedFormula>,
+) -> Vec<ExportCell> {
+    let Some(sheet) = sl.sheet(sref) else {
+        return vec![];
+    };
+    let extent = match options.default_extent {
+        Some(end) => CellRange::new(CellIdx::new(0, 0), end),
+        None => sheet.used_range(),
+    };
+
+    // only the rows and cols with a default have anything to fill in
+    let mut cells = sheet.cells().map(|(cell, _)| cell).collect::<Vec<_>>();
+    for (row, _) in sheet.row_defaults() {
+        if extent.contains_row(row) {
+            cells.extend((extent.start.col..=extent.end.col).map(|col| CellIdx::new(row, col)));
+        }
+    }
+    for (col, _) in sheet.col_defaults() {
+        if extent.contains_col(col) {
+            cells.extend((extent.start.row..=extent.end.row).map(|row| CellIdx::new(row, col)));
+        }
+    }
+    cells.sort();
+    cells.dedup();
+
+    let sheet_name = |name: &str| sl.sheet_by_name(name).map(|_| name.to_string());
+    cells
+        .into_iter()
+        .filter_map(|cell| {
+            let formula = match sl.get_raw_value(sref, cell).value() {
+                Value::None => return None,
+                Value::Formula(f) => match generate(f.expr(), cell, dialect, sheet_name) {
+                    Ok(formula) => Some(formula),
+                    Err(reason) => {
+                        untranslated.push(UntranslatedFormula {
+                            sheet: sref,
+                            cell,
+                            formula: f.script,
+                            reason,
+                        });
+                        None
+                    }
+                },
+                _ => None,
+            };
+
+            let value = match sl.get(sref, cell).map(ValueResult::value) {
+                // files have no references as values, so the value referred to is exported instead
+                Ok(Value::Ref { sref, cref }) => sl.get(sref, cref).map(ValueResult::value),
+                val => val,
+            };
+            let value = match value {
+                Ok(Value::Ref { .. } | Value::Range { .. }) => Value::Error(ErrorKind::Value),
+                Ok(val) => val,
+                Err(e) => Value::Error(e.into()),
+            };
+
+            // error literals are formulas
+            let formula = formula.or_else(|| match value {
+                Value::Error(kind) => error_code(kind).map(ToString::to_string),
+                _ => None,
+            });
+
+            Some(ExportCell {
+                cell,
+                value,
+                formula,
+            })
+        })
+        .collect()
+}
+
+/// The name of a call to a Spanleaf function in the dialect, which may depend on how many arguments it has
+fn function_name(name: &str, args: usize, dialect: Dialect) -> Option<&'static str> {
+    Some(match (name, args, dialect) {
+        ("sum", ..) => "SUM",
+        ("average", ..) => "AVERAGE",
+        ("if", ..) => "IF",
+        // newer functions are prefixed in the file, to stay compatible with older versions of Excel
+        ("ifs", _, Dialect::Excel) => "_xlfn.IFS",
+        ("ifs", _, Dialect::OpenFormula) => "IFS",
+        ("switch", _, Dialect::Excel) => "_xlfn.SWITCH",
+        ("switch", _, Dialect::OpenFormula) => "SWITCH",
+        ("iserror", ..) => "ISERROR",
+        ("iferror", ..) => "IFERROR",
+        ("isblank", ..) => "ISBLANK",
+        ("power", ..) => "POWER",
+        ("abs", ..) => "ABS",
+        ("sign", ..) => "SIGN",
+        // the digits are required in the dialects
+        ("round", 2, _) => "ROUND",
+        ("floor", 1, _) => "INT",
+        ("mod", ..) => "MOD",
+        ("sqrt", ..) => "SQRT",
+        ("exp", ..) => "EXP",
+        ("ln", ..) => "LN",
+        ("log", ..) => "LOG",
+        ("log10", ..) => "LOG10",
+        ("sin", ..) => "SIN",
+        ("cos", ..) => "COS",
+        ("tan", ..) => "TAN",
+        ("asin", ..) => "ASIN",
+        ("acos", ..) => "ACOS",
+        ("atan", ..) => "ATAN",
+        ("atan2", ..) => "ATAN2",
+        ("degrees", ..) => "DEGREES",
+        ("radians", ..) => "RADIANS",
+        ("pi", ..) => "PI",
+        ("min", ..) => "MIN",
+        ("max", ..) => "MAX",
+        ("product", ..) => "PRODUCT",
+        ("gcd", ..) => "GCD",
+        ("lcm", ..) => "LCM",
+        ("factorial", ..) => "FACT",
+        ("count", ..) => "COUNT",
+        ("counta", ..) => "COUNTA",
+        ("countblank", 1, _) => "COUNTBLANK",
+        ("median", ..) => "MEDIAN",
+        ("mode", ..) => "MODE",
+        ("var", ..) => "VAR",
+        ("varp", ..) => "VARP",
+        ("stdev", ..) => "STDEV",
+        ("stdevp", ..) => "STDEVP",
+        ("percentile", ..) => "PERCENTILE",
+        ("quartile", ..) => "QUARTILE",
+        ("rank", ..) => "RANK",
+        ("correl", ..) => "CORREL",
+        ("covar", _, Dialect::Excel) => "_xlfn.COVARIANCE.S",
+        ("covarp", ..) => "COVAR",
+        ("slope", ..) => "SLOPE",
+        ("intercept", ..) => "INTERCEPT",
+        ("rsq", ..) => "RSQ",
+        ("forecast", ..) => "FORECAST",
+        ("len", ..) => "LEN",
+        ("upper", ..) => "UPPER",
+        ("lower", ..) => "LOWER",
+        ("proper", ..) => "PROPER",
+        ("trim", ..) => "TRIM",
+        ("left", ..) => "LEFT",
+        ("right", ..) => "RIGHT",
+        ("mid", ..) => "MID",
+        ("find", ..) => "FIND",
+        ("search", ..) => "SEARCH",
+        ("replace", ..) => "REPLACE",
+        ("substitute", ..) => "SUBSTITUTE",
+        ("concat", _, Dialect::Excel) => "_xlfn.CONCAT",
+        ("concat", _, Dialect::OpenFormula) => "CONCAT",
+        ("textjoin", _, Dialect::Excel) => "_xlfn.TEXTJOIN",
+        ("textjoin", _, Dialect::OpenFormula) => "TEXTJOIN",
+        ("repeat", ..) => "REPT",
+        ("value", ..) => "VALUE",
+        ("text", ..) => "TEXT",
+        ("char", _, Dialect::Excel) => "_xlfn.UNICHAR",
+        ("char", _, Dialect::OpenFormula) => "UNICHAR",
+        ("code", _, Dialect::Excel) => "_xlfn.UNICODE",
+        ("code", _, Dialect::OpenFormula) => "UNICODE",
+        ("today", ..) => "TODAY",
+        ("now", ..) => "NOW",
+        ("date", ..) => "DATE",
+        ("time", ..) => "TIME",
+        ("year", ..) => "YEAR",
+        ("month", ..) => "MONTH",
+        ("day", ..) => "DAY",
+        ("hour", ..) => "HOUR",
+        ("minute", ..) => "MINUTE",
+        ("second", ..) => "SECOND",
+        ("weekday", ..) => "WEEKDAY",
+        ("edate", ..) => "EDATE",
+        ("eomonth", ..) => "EOMONTH",
+        ("networkdays", ..) => "NETWORKDAYS",
+        ("vlookup", ..) => "VLOOKUP",
+        ("hlookup", ..) => "HLOOKUP",
+        ("xlookup", _, Dialect::Excel) => "_xlfn.XLOOKUP",
+        ("xlookup", _, Dialect::OpenFormula) => "XLOOKUP",
+        ("index", ..) => "INDEX",
+        ("match", ..) => "MATCH",
+        ("rows", ..) => "ROWS",
+        ("cols", ..) => "COLUMNS",
+        ("pmt", ..) => "PMT",
+        ("ipmt", ..) => "IPMT",
+        ("ppmt", ..) => "PPMT",
+        ("fv", ..) => "FV",
+        ("pv", ..) => "PV",
+        ("nper", ..) => "NPER",
+        ("rate", ..) => "RATE",
+        ("npv", ..) => "NPV",
+        ("irr", ..) => "IRR",
+        ("xnpv", ..) => "XNPV",
+        ("xirr", ..) => "XIRR",
+        ("sln", ..) => "SLN",
+        ("sequence", _, Dialect::Excel) => "_xlfn.SEQUENCE",
+        ("sequence", _, Dialect::OpenFormula) => "COM.MICROSOFT.SEQUENCE",
+        ("transpose", ..) => "TRANSPOSE",
+        ("ddb", ..) => "DDB",
+        _ => return None,
+    })
+}
+
+// How tightly generated operators bind, to know where parentheses are needed
+const COMPARISON: u8 = 1;
+const CONCAT: u8 = 2;
+const ADDITIVE: u8 = 3;
+const MULTIPLICATIVE: u8 = 4;
+const UNARY: u8 = 5;
+const ATOM: u8 = 6;
+
+/// Generates A1 formulas from Spanleaf expressions, as evaluated in a given cell
+struct Generator<'f> {
+    cell: CellIdx,
+    dialect: Dialect,
+    sheet_name: &'f dyn Fn(&str) -> Option<String>,
+}
+impl Generator<'_> {
+    /// The formula of an expression, and how tightly its outermost operator binds
+    fn expr(&self, expr: &Expr) -> Result<(String, u8), TranslateError> {
+        let unsupported = || TranslateError::UnsupportedSyntax(expr.to_string());
+
+        Ok(match expr {
+            // every exported cell gets its own formula, so the current row and col are constants
+            Expr::CurrRow => (self.cell.row.to_string(), ATOM),
+            Expr::CurrCol => (self.cell.col.to_string(), ATOM),
+            Expr::Value(val) => self.value(val).ok_or_else(unsupported)?,
+            Expr::CellDeref(cref) => match &**cref {
+                Expr::CellRef(sheet, row, col) => {
+                    let cell = CellIdx::new(self.coord(row)?, self.coord(col)?);
+                    (self.reference(sheet.as_deref(), cell, None)?, ATOM)
+                }
+                _ => return Err(unsupported()),
+            },
+            Expr::RangeRef(sheet, rows, cols) => {
+                let start = CellIdx::new(self.coord(&rows.0)?, self.coord(&cols.0)?);
+                let end = CellIdx::new(self.coord(&rows.1)?, self.coord(&cols.1)?);
+                // A1 ranges can't be empty
+                if CellRange::new(start, end).is_empty() {
+                    return Err(unsupported());
+                }
+                (self.reference(sheet.as_deref(), start, Some(end))?, ATOM)
+            }
+            // there are no references as values, sheets are only part of references, and variables are only
+            // in named functions and lambdas, which don't exist in Excel
+            Expr::CellRef(..) | Expr::Sheet(_) | Expr::Var(_) | Expr::Lambda(..) => {
+                return Err(unsupported());
+            }
+            Expr::Array(rows) => (self.array(rows).ok_or_else(unsupported)?, ATOM),
+            Expr::Neg(expr) => (format!("-{}", self.operand(expr, UNARY)?), UNARY),
+            Expr::Concat(lhs, rhs) => self.binary(lhs, "&", rhs, CONCAT)?,
+            Expr::Add(lhs, rhs) => self.binary(lhs, "+", rhs, ADDITIVE)?,
+            Expr::Sub(lhs, rhs) => self.binary(lhs, "-", rhs, ADDITIVE)?,
+            Expr::Mul(lhs, rhs) => self.binary(lhs, "*", rhs, MULTIPLICATIVE)?,
+            Expr::Div(lhs, rhs) => self.binary(lhs, "/", rhs, MULTIPLICATIVE)?,
+            Expr::Eq(lhs, rhs) => self.binary(lhs, "=", rhs, COMPARISON)?,
+            Expr::Ne(lhs, rhs) => self.binary(lhs, "<>", rhs, COMPARISON)?,
+            Expr::Lt(lhs, rhs) => self.binary(lhs, "<", rhs, COMPARISON)?,
+            Expr::Le(lhs, rhs) => self.binary(lhs, "<=", rhs, COMPARISON)?,
+            Expr::Gt(lhs, rhs) => self.binary(lhs, ">", rhs, COMPARISON)?,
+            Expr::Ge(lhs, rhs) => self.binary(lhs, ">=", rhs, COMPARISON)?,
+            Expr::And(..) => (self.call("AND", &flatten(expr))?, ATOM),
+            Expr::Or(..) => (self.call("OR", &flatten(expr))?, ATOM),
+            Expr::Not(expr) => (self.call("NOT", &[expr])?, ATOM),
+            Expr::Call(name, args) => {
+                let name = function_name(name, args.len(), self.dialect)
+                    .ok_or_else(|| TranslateError::UnsupportedFunction(name.clone()))?;
+                (self.call(name, &args.iter().collect::<Vec<_>>())?, ATOM)
+            }
+        })
+    }
+
+    /// The formula of an operand, in parentheses if it binds looser than `binding`
+    fn operand(&self, expr: &Expr, binding: u8) -> Result<String, TranslateError> {
+        let (formula, expr_binding) = self.expr(expr)?;
+        Ok(if expr_binding < binding {
+            format!("({formula})")
+        } else {
+            formula
+        })
+    }
+
+    /// A left associative binary operator
+    fn binary(
+        &self,
+        lhs: &Expr,
+        op: &str,
+        rhs: &Expr,
+        binding: u8,
+    ) -> Result<(String, u8), TranslateError> {
+        let lhs = self.operand(lhs, binding)?;
+        let rhs = self.operand(rhs, binding + 1)?;
+        Ok((format!("{lhs}{op}{rhs}"), binding))
+    }
+
+    fn separator(&self) -> &'static str {
+        match self.dialect {
+            Dialect::Excel => ",",
+            Dialect::OpenFormula => ";",
+        }
+    }
+
+    fn call(&self, name: &str, args: &[&Expr]) -> Result<String, TranslateError> {
+        let separator = self.separator();
+        let args = args
+            .iter()
+            .map(|arg| self.expr(arg).map(|(formula, _)| formula))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("{name}({})", args.join(separator)))
+    }
+
+    fn value(&self, val: &Value) -> Option<(String, u8)> {
+        Some(match val {
+            Value::Number(n) if n.is_finite() => {
+                (n.to_string(), if *n < 0.0 { UNARY } else { ATOM })
+            }
+            Value::String(s) => (format!("\"{}\"", s.replace('"', "\"\"")), ATOM),
+            Value::Bool(b) => {
+                let name = if *b { "TRUE" } else { "FALSE" };
+                match self.dialect {
+                    Dialect::Excel => (name.to_string(), ATOM),
+                    // OpenFormula only has the functions
+                    Dialect::OpenFormula => (format!("{name}()"), ATOM),
+                }
+            }
+            Value::Error(kind) => (error_code(*kind)?.to_string(), ATOM),
+            // there are no date literals, only serial day numbers
+            Value::Date(date) => {
+                let (year, month, day) = date.ymd();
+                let sep = self.separator();
+                (format!("DATE({year}{sep}{month}{sep}{day})"), ATOM)
+            }
+            _ => return None,
+        })
+    }
+
+    /// An array constant, whose elements can only be values
+    fn array(&self, rows: &[Vec<Expr>]) -> Option<String> {
+        let element = |expr: &Expr| match expr {
+            Expr::Value(val) if !matches!(**val, Value::Date(_)) => {
+                self.value(val).map(|(formula, _)| formula)
+            }
+            Expr::Neg(expr) => match &**expr {
+                Expr::Value(val) if matches!(**val, Value::Number(_)) => {
+                    self.value(val).map(|(formula, _)| format!("-{formula}"))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        // OpenFormula separates elements by ';' instead of ',', and so rows by '|'
+        let (separator, row_separator) = match self.dialect {
+            Dialect::Excel => (",", ";"),
+            Dialect::OpenFormula => (";", "|"),
+        };
+        let rows = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(element)
+                    .collect::<Option<Vec<_>>>()
+                    .map(|row| row.join(separator))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(format!("{{{}}}", rows.join(row_separator)))
+    }
+
+    /// Folds a coordinate into a constant, as it's evaluated in the exported cell
+    fn coord(&self, expr: &Expr) -> Result<u64, TranslateError> {
+        fn fold(expr: &Expr, cell: CellIdx) -> Option<f64> {
+            Some(match expr {
+                Expr::CurrRow => cell.row as f64,
+                Expr::CurrCol => cell.col as f64,
+                Expr::Value(val) => match **val {
+                    Value::Number(n) => n,
+                    _ => return None,
+                },
+                Expr::Neg(expr) => -fold(expr, cell)?,
+                Expr::Add(lhs, rhs) => fold(lhs, cell)? + fold(rhs, cell)?,
+                Expr::Sub(lhs, rhs) => fold(lhs, cell)? - fold(rhs, cell)?,
+                Expr::Mul(lhs, rhs) => fold(lhs, cell)? * fold(rhs, cell)?,
+                Expr::Div(lhs, rhs) => fold(lhs, cell)? / fold(rhs, cell)?,
+                _ => return None,
+            })
+        }
+
+        fold(expr, self.cell)
+            .filter(|n| n.is_finite())
+            // the same conversion as in evaluation
+            .map(|n| n as u64)
+            .ok_or_else(|| TranslateError::UnsupportedSyntax(expr.to_string()))
+    }
+
+    /// A cell or range reference, with its sheet prefix if it's to another sheet
+    fn reference(
+        &self,
+        sheet: Option<&Expr>,
+        start: CellIdx,
+        end: Option<CellIdx>,
+    ) -> Result<String, TranslateError> {
+        let sheet = match sheet {
+            Some(Expr::Sheet(name)) => Some(
+                (self.sheet_name)(name)
+                    .ok_or_else(|| TranslateError::UnknownSheet(name.clone()))?,
+            ),
+            Some(expr) => return Err(TranslateError::UnsupportedSyntax(expr.to_string())),
+            None => None,
+        };
+        let a1 = |cell: CellIdx| {
+            format_a1(cell.row, cell.col).ok_or_else(|| {
+                TranslateError::UnsupportedSyntax(format!("[{}, {}]", cell.row, cell.col))
+            })
+        };
+        let start = a1(start)?;
+        let end = end.map(a1).transpose()?;
+
+        // names that could be mistaken for something else are quoted
+        let sheet = sheet.map(|name| {
+            if language::is_sheet_name(&name) && parse_a1(&name).is_none() {
+                name
+            } else {
+                format!("'{}'", name.replace('\'', "''"))
+            }
+        });
+
+        Ok(match self.dialect {
+            Dialect::Excel => {
+                let sheet = sheet.map(|name| format!("{name}!")).unwrap_or_default();
+                match end {
+                    Some(end) => format!("{sheet}{start}:{end}"),
+                    None => format!("{sheet}{start}"),
+                }
+            }
+            Dialect::OpenFormula => {
+                let sheet = sheet.unwrap_or_default();
+                match end {
+                    Some(end) => format!("[{sheet}.{start}:.{end}]"),
+                    None => format!("[{sheet}.{start}]"),
+                }
+            }
+        })
+    }
+}
+
+/// The operands of a chain of the same logical operator, i.e. `a && b && c`
+fn flatten(expr: &Expr) -> Vec<&Expr> {
+    let (Expr::And(lhs, rhs) | Expr::Or(lhs, rhs)) = expr else {
+        return vec![expr];
+    };
+    [lhs, rhs]
+        .into_iter()
+        .flat_map(|side| {
+            if mem::discriminant(&**side) == mem::discriminant(expr) {
+                flatten(side)
+            } else {
+                vec![&**side]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Spanleaf,
+        cell::CellIdx,
+        excel::{
+            Dialect, ExportOptions, TranslateError, export_cells, format_a1, generate, parse_a1,
+            translate,
+        },
+        formula::Formula,
+    };
+
+    fn tr(formula: &str) -> Result<String, TranslateError> {
+        translate(formula, |sheet| match sheet {
+            "Sheet2" => Some("Sheet2".to_string()),
+            "My Sheet" => Some("My_Sheet".to_string()),
+            _ => None,
+        })
+        .map(|expr| expr.to_string())
+    }
+
+    #[test]
+    fn a1_refs() {
+        assert_eq!(parse_a1("A1"), Some((0, 0)));
+        assert_eq!(parse_a1("$B$7"), Some((6, 1)));
+        assert_eq!(parse_a1("z10"), Some((9, 25)));
+        assert_eq!(parse_a1("AA1"), Some((0, 26)));
+        assert_eq!(parse_a1("XFD1048576"), Some((1_048_575, 16_383)));
+        assert_eq!(parse_a1("XFE1"), None);
+        assert_eq!(parse_a1("A0"), None);
+        assert_eq!(parse_a1("LOG"), None);
+        assert_eq!(parse_a1("ABCD1"), None);
+
+        assert_eq!(format_a1(0, 0).as_deref(), Some("A1"));
+        assert_eq!(format_a1(6, 25).as_deref(), Some("Z7"));
+        assert_eq!(format_a1(0, 26).as_deref(), Some("AA1"));
+        assert_eq!(format_a1(1_048_575, 16_383).as_deref(), Some("XFD1048576"));
+        assert_eq!(format_a1(0, 16_384), None);
+    }
+
+    #[test]
+    fn translation() {
+        let ok = |excel: &str, spanleaf: &str| assert_eq!(tr(excel), Ok(spanleaf.to_string()));
+
+        ok("=SUM(A1:A10)", "sum([0..9, 0..0])");
+        ok("=Sheet2!B3", "Sheet2[2, 1]");
+        ok("='My Sheet'!$C$1:A2", "My_Sheet[0..1, 0..2]");
+        ok("=-A1+B2*2-(3-C3)", "-[0, 0] + [1, 1] * 2 - (3 - [2, 2])");
+        ok(
+            "=IF(A1<>0, 1/A1, \"none\")",
+            "if([0, 0] != 0, 1 / [0, 0], 'none')",
+        );
+        ok(
+            "=AND(A1>=1, NOT(B1), TRUE)",
+            "[0, 0] >= 1 && ![0, 1] && true",
+        );
+        ok("50%", "50 / 100");
+        ok("=ROUND(MOD(A1, 3), 1)", "round(mod([0, 0], 3), 1)");
+        ok("=MAX(A1:A3, FACT(3))", "max([0..2, 0..0], factorial(3))");
+        ok(
+            "=_xlfn.STDEV.S(A1:A3)/COVAR(A1:A3, B1:B3)",
+            "stdev([0..2, 0..0]) / covarp([0..2, 0..0], [0..2, 1..1])",
+        );
+        ok(
+            "=A1&\" \"&_xlfn.CONCAT(LEFT(B1, 2), MID(B1, 3, 1))=\"x\"",
+            "[0, 0] & ' ' & concat(left([0, 1], 2), mid([0, 1], 3, 1)) == 'x'",
+        );
+        ok("=1+2&3", "1 + 2 & 3");
+        ok(
+            "=_xlfn.IFS(A1=1, #N/A, TRUE(), #DIV/0!)",
+            "ifs([0, 0] == 1, #N/A, true, #DIV/0!)",
+        );
+        ok(
+            "=NETWORKDAYS(A1, EOMONTH(TODAY(), 1), B1:B3)",
+            "networkdays([0, 0], eomonth(today(), 1), [0..2, 1..1])",
+        );
+        ok(
+            "=VLOOKUP(A1, B1:C3, 2, FALSE) + INDEX(B1:C3, MATCH(A1, B1:B3, 0), COLUMNS(B1:C3))",
+            "vlookup([0, 0], [0..2, 1..2], 2, false) + index([0..2, 1..2], match([0, 0], [0..2, 1..1], 0), cols([0..2, 1..2]))",
+        );
+        ok(
+            "=PMT(B1/12, 360, -A1) - NPV(B1, C1:C5, 100) * IRR(C1:C5)",
+            "pmt([0, 1] / 12, 360, -[0, 0]) - npv([0, 1], [0..4, 2..2], 100) * irr([0..4, 2..2])",
+        );
+
+        assert_eq!(
+            tr("=INDIRECT(\"B1\")"),
+            Err(TranslateError::UnsupportedFunction("INDIRECT".to_string()))
+        );
+        assert_eq!(
+            tr("=Missing!A1"),
+            Err(TranslateError::UnknownSheet("Missing".to_string()))
+        );
+        assert_eq!(
+            tr("=SUM(my_range)"),
+            Err(TranslateError::UnsupportedSyntax("my_range".to_string()))
+        );
+        ok("={1,2;-3,\"x\"}*A1", "{1, 2; -3, 'x'} * [0, 0]");
+        ok(
+            "=TRANSPOSE(_xlfn.SEQUENCE(2,3))",
+            "transpose(sequence(2, 3))",
+        );
+        assert!(matches!(
+            tr("=SUM(Table1[Sales])"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(tr("={1,2;3}"), Err(TranslateError::Invalid(span)) if span == (0..1)));
+        assert!(matches!(tr("=A1 +"), Err(TranslateError::Invalid(span)) if span == (4..4)));
+        assert!(matches!(tr("=(A1"), Err(TranslateError::Invalid(_))));
+    }
+
+    #[test]
+    fn generation() {
+        let cell = CellIdx::new(4, 2);
+        let gen_in = |script: &str, dialect| {
+            let formula = Formula::parse(script).unwrap();
+            generate(formula.expr(), cell, dialect, |sheet| {
+                (sheet != "Missing").then(|| sheet.to_string())
+            })
+        };
+        let excel = |script: &str| gen_in(script, Dialect::Excel);
+        let ok = |script: &str, a1: &str| assert_eq!(excel(script), Ok(a1.to_string()));
+
+        ok("=sum([0..r-1, c])", "SUM(C1:C4)");
+        ok("=Sheet2[r, c + 1] * -2", "Sheet2!D5*-2");
+        ok("=My_Sheet[0, 0] + AB1[0, 0]", "My_Sheet!A1+'AB1'!A1");
+        ok("=(1 - (2 - 3)) / (4 * 5)", "(1-(2-3))/(4*5)");
+        ok("=-(r + c)", "-(4+2)");
+        ok(
+            "=if([0, 0] != 'x' && true && ![0, 1], #N/A, 1)",
+            "IF(AND(A1<>\"x\",TRUE,NOT(B1)),#N/A,1)",
+        );
+        ok("=ifs([0, 0] == 1, 'a\"b')", "_xlfn.IFS(A1=1,\"a\"\"b\")");
+        ok("=round(floor([0, 0]) / pi(), 2)", "ROUND(INT(A1)/PI(),2)");
+        ok(
+            "=(1 & 2) + len([0, 0] & 'a') & char(65)",
+            "(1&2)+LEN(A1&\"a\")&_xlfn.UNICHAR(65)",
+        );
+        ok(
+            "=covar([0..1, 0], [0..1, 1]) + median(1, 2)",
+            "_xlfn.COVARIANCE.S(A1:A2,B1:B2)+MEDIAN(1,2)",
+        );
+        ok(
+            "=weekday(@2024-01-31 + 1, 2) + year(now())",
+            "WEEKDAY(DATE(2024,1,31)+1,2)+YEAR(NOW())",
+        );
+        ok(
+            "=xlookup([0, 0], [0..2, 1], [0..2, 2], 0) / rows([0..2, 1])",
+            "_xlfn.XLOOKUP(A1,B1:B3,C1:C3,0)/ROWS(B1:B3)",
+        );
+        ok(
+            "=xirr([0..2, 0], [0..2, 1], 0.1) + ddb(1000, 100, 5, 1)",
+            "XIRR(A1:A3,B1:B3,0.1)+DDB(1000,100,5,1)",
+        );
+        ok("={1, -2; 'a', #SPILL!} + 1", "{1,-2;\"a\",#SPILL!}+1");
+        ok(
+            "=transpose(sequence(3, 1, 0))",
+            "TRANSPOSE(_xlfn.SEQUENCE(3,1,0))",
+        );
+        assert_eq!(
+            gen_in("={1, 2; 3, 4}", Dialect::OpenFormula),
+            Ok("{1;2|3;4}".to_string())
+        );
+        assert!(matches!(
+            excel("={1, [0, 0]}"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(
+            excel("=[0, 0] + @1d"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert_eq!(
+            gen_in("=sum(Sheet2[0..1, 0..c], true)", Dialect::OpenFormula),
+            Ok("SUM([Sheet2.A1:.C2];TRUE())".to_string())
+        );
+
+        assert_eq!(
+            excel("=foo(1)"),
+            Err(TranslateError::UnsupportedFunction("foo".to_string()))
+        );
+        assert_eq!(
+            excel("=round(1.5)"),
+            Err(TranslateError::UnsupportedFunction("round".to_string()))
+        );
+        assert_eq!(
+            excel("=Missing[0, 0]"),
+            Err(TranslateError::UnknownSheet("Missing".to_string()))
+        );
+        // references as values, coordinates depending on other cells and empty ranges have no A1 form
+        assert!(matches!(
+            excel("=&[0, 0]"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(
+            excel("=[[0, 0], 1]"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(
+            excel("=sum([r..r-1, c])"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(
+            excel("=sum((x) => x)"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+        assert!(matches!(
+            excel("=#CYCLE!"),
+            Err(TranslateError::UnsupportedSyntax(_))
+        ));
+    }
+
+    #[test]
+    fn export_fill() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        // far apart, so the used range is too big to visit every cell of
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(100_000, 100_000), 2).unwrap();
+        sl.insert_col_default(s0, 1, "=[r, 0] + 1").unwrap();
+
+        let mut untranslated = vec![];
+        let cells = export_cells(
+            &sl,
+            s0,
+            Dialect::Excel,
+            &ExportOptions::default(),
+            &mut untranslated,
+        );
+        assert!(untranslated.is_empty());
+        // the stored cells, and the default down every used row
+        assert_eq!(cells.len(), 100_003);
+        assert_eq!(cells[1].cell, CellIdx::new(0, 1));
+        assert_eq!(cells[1].value, 2.into());
+        assert_eq!(cells[1].formula.as_deref(), Some("A1+1"));
+        assert_eq!(cells[100_002].cell, CellIdx::new(100_000, 100_000));
+
+        // or only as far as asked
+        let cells = export_cells(
+            &sl,
+            s0,
+            Dialect::Excel,
+            &ExportOptions {
+                default_extent: Some(CellIdx::new(2, 1)),
+            },
+            &mut untranslated,
+        );
+        assert_eq!(cells.len(), 5);
+    }
+}
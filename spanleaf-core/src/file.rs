@@ -0,0 +1,760 @@
+//! The native workbook file format
+//!
+//! A line based text format, so that workbooks diff and merge nicely under version control.
+//!
+//! ```text
+//! spanleaf 4
+//!
+//! # comments take up a whole line
+//! function tax(amount, rate) = amount * rate
+//!
+//! sheet 0 "Sheet1"
+//! row 7: 1
+//! col 0: =r
+//! col 4: "apple"
+//! cell 0 0: -3
+//! cell 2 4: =[0, 0]
+//! cell 11 2: "Lorem \"Ipsum\""
+//! cell 12 0: @2024-01-31
+//! ```
+//!
+//! - The first line is the header, `spanleaf` followed by the format version
+//! - `function <definition>` is a named function, belonging to the whole workbook. A definition containing a
+//!   line break is instead written as a quoted string
+//! - `sheet <id> "<name>"` starts a new sheet, which every following entry belongs to. The id is the
+//!   sheet's [SheetIdx], so that it's stable across saving and loading
+//! - `row <row>: <value>` and `col <col>: <value>` are row and column defaults
+//! - `cell <row> <col>: <value>` is a cell's value
+//!
+//! Values are one of
+//! - a number, i.e. `-3` or `6.02e23`
+//! - `true` or `false`
+//! - a string in double quotes, with `\"`, `\\`, `\n`, `\r` and `\t` escapes
+//! - a formula script, from the `=` to the end of the line. A script containing a line break is instead
+//!   written as `=` followed by the script (sans '=') as a quoted string
+//! - an error, i.e. `#REF!`
+//! - a date, datetime or duration, as in formulas, i.e. `@2024-01-31`, `@2024-01-31T09:30:00` or `@1d12h`
+//!
+//! Blank lines, and leading and trailing whitespace, are ignored. Entries are written in row then column
+//! order so that small changes to a workbook make for small changes to the file.
+//!
+//! ## Versions
+//! 1. Initial version
+//! 2. Sheets are written with their id. Version 1 sheets are given ids in the order they appear
+//! 3. Named functions
+//! 4. Dates, datetimes and durations
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use crate::{
+    Error, Spanleaf,
+    cell::{CellIdx, ErrorKind, Value},
+    datetime,
+    formula::{Formula, FormulaError, FunctionError, NamedFunction, ParseError},
+    sheet::{Sheet, SheetIdx},
+};
+
+/// The current version of the file format
+pub const VERSION: u32 = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The file is malformed. Line and column are 1-based, with the column counted in chars
+    #[error("{line}:{column}: {kind}")]
+    Invalid {
+        line: usize,
+        column: usize,
+        kind: LoadErrorKind,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LoadErrorKind {
+    #[error("missing `spanleaf <version>` header")]
+    MissingHeader,
+    #[error("unsupported version {0}, the latest supported version is {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("expected {0}")]
+    Expected(&'static str),
+    #[error("unknown entry `{0}`")]
+    UnknownEntry(String),
+    #[error("entry before the first sheet")]
+    NoSheet,
+    #[error("duplicate sheet name `{0}`")]
+    DuplicateSheet(String),
+    #[error("`{0}` can't be a sheet name")]
+    InvalidSheetName(String),
+    #[error("duplicate sheet id {0}")]
+    DuplicateSheetId(u64),
+    #[error("sheet id {0} is too large")]
+    SheetIdTooLarge(u64),
+    #[error("unterminated string")]
+    UnterminatedString,
+    #[error("invalid escape `\\{0}`")]
+    InvalidEscape(char),
+    #[error("invalid formula: {0}")]
+    Formula(ParseError),
+    #[error("duplicate function `{0}`")]
+    DuplicateFunction(String),
+    #[error("invalid function: {0}")]
+    Function(FunctionError),
+}
+
+impl Spanleaf {
+    /// Writes the workbook in the native file format
+    pub fn save(&self, writer: impl Write) -> io::Result<()> {
+        let mut w = io::BufWriter::new(writer);
+        writeln!(w, "spanleaf {VERSION}")?;
+
+        if self.functions().next().is_some() {
+            writeln!(w)?;
+        }
+        for function in self.functions() {
+            if function.definition.contains(['\n', '\r']) {
+                writeln!(w, "function {}", Quoted(&function.definition))?;
+            } else {
+                writeln!(w, "function {}", function.definition)?;
+            }
+        }
+
+        for (sref, sheet) in self.sheets() {
+            writeln!(w)?;
+            writeln!(w, "sheet {} {}", sref.id(), Quoted(&sheet.name))?;
+
+            for (row, val) in sheet.row_defaults() {
+                writeln!(w, "row {row}: {}", FileValue::new(val)?)?;
+            }
+            for (col, val) in sheet.col_defaults() {
+                writeln!(w, "col {col}: {}", FileValue::new(val)?)?;
+            }
+
+            let mut cells = sheet.cells().collect::<Vec<_>>();
+            cells.sort_by_key(|(cref, _)| *cref);
+            for (cref, val) in cells {
+                writeln!(
+                    w,
+                    "cell {} {}: {}",
+                    cref.row,
+                    cref.col,
+                    FileValue::new(val)?
+                )?;
+            }
+        }
+
+        w.flush()
+    }
+
+    /// Reads a workbook from the native file format
+    pub fn load(reader: impl Read) -> Result<Self, LoadError> {
+        let mut sl = Spanleaf::new();
+        let mut curr_sheet: Option<SheetIdx> = None;
+        let mut version = None;
+
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let mut cursor = Cursor::new(&line, i + 1);
+
+            cursor.skip_whitespace();
+            if cursor.is_empty() || cursor.rest().starts_with('#') {
+                continue;
+            }
+
+            let Some(version) = version else {
+                cursor
+                    .keyword("spanleaf")
+                    .map_err(|_| cursor.error(LoadErrorKind::MissingHeader))?;
+                let v = cursor.number::<u32>("a version number")?;
+                if v == 0 || v > VERSION {
+                    return Err(cursor.error(LoadErrorKind::UnsupportedVersion(v)));
+                }
+                cursor.end()?;
+                version = Some(v);
+                continue;
+            };
+
+            let entry_start = cursor.pos;
+            let entry = cursor.word();
+            match entry {
+                "sheet" => {
+                    cursor.skip_whitespace();
+                    let id_start = cursor.pos;
+                    let sref = match version {
+                        1 => SheetIdx(sl.next_sheet),
+                        _ => SheetIdx(cursor.number::<u64>("a sheet id")?),
+                    };
+                    if sl.sheets.contains_key(&sref) {
+                        return Err(
+                            cursor.error_at(id_start, LoadErrorKind::DuplicateSheetId(sref.0))
+                        );
+                    }
+
+                    cursor.skip_whitespace();
+                    let name_start = cursor.pos;
+                    let name = cursor.string()?;
+                    cursor.end()?;
+                    sl.insert_sheet_with_idx(sref, name).map_err(|e| match e {
+                        Error::DuplicateSheetName(name) => {
+                            cursor.error_at(name_start, LoadErrorKind::DuplicateSheet(name))
+                        }
+                        Error::InvalidSheetName(name) => {
+                            cursor.error_at(name_start, LoadErrorKind::InvalidSheetName(name))
+                        }
+                        Error::NoSheetIdxLeft => {
+                            cursor.error_at(id_start, LoadErrorKind::SheetIdTooLarge(sref.0))
+                        }
+                        e => unreachable!(
+                            "inserting a sheet only fails on invalid names or ids, not {e:?}"
+                        ),
+                    })?;
+                    curr_sheet = Some(sref);
+                }
+                "row" | "col" | "cell" => {
+                    let Some(sref) = curr_sheet else {
+                        return Err(cursor.error_at(entry_start, LoadErrorKind::NoSheet));
+                    };
+                    let sheet = sl.sheets.get_mut(&sref).expect("inserted above");
+
+                    let idx = cursor.index("a row or column number")?;
+                    let cref = if entry == "cell" {
+                        CellIdx::new(idx, cursor.index("a column number")?)
+                    } else {
+                        CellIdx::new(idx, idx)
+                    };
+                    cursor.skip_whitespace();
+                    cursor.expect(':')?;
+                    cursor.skip_whitespace();
+                    let val = cursor.value()?;
+
+                    // can't fail, as the value is already converted
+                    let _ = match entry {
+                        "row" => sheet.insert_row_default(cref.row, val),
+                        "col" => sheet.insert_col_default(cref.col, val),
+                        _ => sheet.insert(cref, val),
+                    };
+                }
+                "function" if version >= 3 => {
+                    cursor.skip_whitespace();
+                    let function_start = cursor.pos;
+                    let function = cursor.function()?;
+                    if sl.function(&function.name).is_some() {
+                        return Err(cursor.error_at(
+                            entry_start,
+                            LoadErrorKind::DuplicateFunction(function.name),
+                        ));
+                    }
+                    sl.insert_function(function)
+                        .map_err(|e| cursor.error_at(function_start, LoadErrorKind::Function(e)))?;
+                }
+                "" => return Err(cursor.error(LoadErrorKind::Expected("an entry"))),
+                unknown => {
+                    return Err(cursor.error_at(
+                        entry_start,
+                        LoadErrorKind::UnknownEntry(unknown.to_string()),
+                    ));
+                }
+            }
+        }
+
+        if version.is_none() {
+            return Err(LoadError::Invalid {
+                line: 1,
+                column: 1,
+                kind: LoadErrorKind::MissingHeader,
+            });
+        }
+
+        Ok(sl)
+    }
+}
+
+/// Displays a string as a quoted, escaped string
+struct Quoted<'a>(&'a str);
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// Displays a value as it's written in the file
+struct FileValue<'a>(&'a Value);
+impl<'a> FileValue<'a> {
+    fn new(val: &'a Value) -> io::Result<Self> {
+        match val {
+            Value::Array(_) | Value::Range { .. } | Value::Ref { .. } => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "arrays and references can't be saved as values",
+            )),
+            val => Ok(Self(val)),
+        }
+    }
+}
+impl Display for FileValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{}", Quoted(s)),
+            Value::Date(date) => write!(f, "@{date}"),
+            Value::DateTime(dt) => write!(f, "@{dt}"),
+            Value::Duration(duration) => write!(f, "@{duration}"),
+            Value::Formula(formula) => {
+                let body = formula.script.strip_prefix('=').unwrap_or(&formula.script);
+                if body.contains(['\n', '\r']) {
+                    write!(f, "={}", Quoted(body))
+                } else {
+                    write!(f, "={body}")
+                }
+            }
+            Value::Error(kind) => write!(f, "{kind}"),
+            // None is never stored, and arrays and references are rejected when creating the FileValue
+            Value::None | Value::Array(_) | Value::Range { .. } | Value::Ref { .. } => Ok(()),
+        }
+    }
+}
+
+/// Position tracking over a single line of the file
+struct Cursor<'a> {
+    line: &'a str,
+    line_no: usize,
+    /// Byte offset into the line
+    pos: usize,
+}
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str, line_no: usize) -> Self {
+        Self {
+            line,
+            line_no,
+            pos: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.line[self.pos..]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rest().trim_end().is_empty()
+    }
+
+    fn error(&self, kind: LoadErrorKind) -> LoadError {
+        self.error_at(self.pos, kind)
+    }
+
+    fn error_at(&self, pos: usize, kind: LoadErrorKind) -> LoadError {
+        LoadError::Invalid {
+            line: self.line_no,
+            column: self.line[..pos.min(self.line.len())].chars().count() + 1,
+            kind,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.line.len() - self.rest().trim_start().len();
+    }
+
+    /// Takes the next run of non-whitespace, non-':' chars
+    fn word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == ':')
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn keyword(&mut self, keyword: &'static str) -> Result<(), LoadError> {
+        let start = self.pos;
+        if self.word() == keyword {
+            Ok(())
+        } else {
+            self.pos = start;
+            Err(self.error(LoadErrorKind::Expected(keyword)))
+        }
+    }
+
+    fn number<N: std::str::FromStr>(&mut self, expected: &'static str) -> Result<N, LoadError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        self.word()
+            .parse()
+            .map_err(|_| self.error_at(start, LoadErrorKind::Expected(expected)))
+    }
+
+    /// A row or col number, which has to be within the sheet
+    fn index(&mut self, expected: &'static str) -> Result<u64, LoadError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.number::<u64>(expected)? {
+            idx if idx > Sheet::LAST_INDEX => {
+                Err(self.error_at(start, LoadErrorKind::Expected(expected)))
+            }
+            idx => Ok(idx),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), LoadError> {
+        match self.rest().strip_prefix(c) {
+            Some(_) => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            None => Err(self.error(LoadErrorKind::Expected(match c {
+                ':' => "':'",
+                _ => "a delimiter",
+            }))),
+        }
+    }
+
+    /// Checks that nothing but whitespace remains
+    fn end(&mut self) -> Result<(), LoadError> {
+        self.skip_whitespace();
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(LoadErrorKind::Expected("the end of the line")))
+        }
+    }
+
+    /// Parses a quoted, escaped string
+    fn string(&mut self) -> Result<String, LoadError> {
+        if !self.rest().starts_with('"') {
+            return Err(self.error(LoadErrorKind::Expected("a quoted string")));
+        }
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut s = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    let escape_pos = self.pos + i;
+                    match chars.next() {
+                        Some((_, '"')) => s.push('"'),
+                        Some((_, '\\')) => s.push('\\'),
+                        Some((_, 'n')) => s.push('\n'),
+                        Some((_, 'r')) => s.push('\r'),
+                        Some((_, 't')) => s.push('\t'),
+                        Some((_, c)) => {
+                            return Err(self.error_at(escape_pos, LoadErrorKind::InvalidEscape(c)));
+                        }
+                        None => break,
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+
+        Err(self.error_at(start, LoadErrorKind::UnterminatedString))
+    }
+
+    /// Parses a named function definition, which must take up the rest of the line
+    fn function(&mut self) -> Result<NamedFunction, LoadError> {
+        let start = self.pos;
+        // where the definition starts within the line, for mapping formula error spans
+        let (definition, definition_start) = if self.rest().starts_with('"') {
+            let definition = self.string()?;
+            self.end()?;
+            // spans within an escaped definition can't be mapped exactly, so point at the string
+            (definition, Err(start))
+        } else {
+            (self.rest().trim_end().to_string(), Ok(start))
+        };
+
+        NamedFunction::parse(&definition).map_err(|e| {
+            let pos = match (&e, definition_start) {
+                (FunctionError::Formula(FormulaError::InvalidFormula(errs)), Ok(start)) => {
+                    start + errs.first().map_or(0, |err| err.span.start)
+                }
+                (_, Ok(start) | Err(start)) => start,
+            };
+            self.error_at(pos, LoadErrorKind::Function(e))
+        })
+    }
+
+    /// Parses a value, which must take up the rest of the line
+    fn value(&mut self) -> Result<Value, LoadError> {
+        let rest = self.rest();
+
+        if rest.starts_with('"') {
+            let s = self.string()?;
+            self.end()?;
+            Ok(Value::String(s))
+        } else if let Some(body) = rest.strip_prefix('=') {
+            // where the script starts within the line, for mapping formula error spans
+            let (script, script_start) = if body.starts_with('"') {
+                self.pos += 1;
+                let start = self.pos;
+                let body = self.string()?;
+                self.end()?;
+                // spans within an escaped script can't be mapped exactly, so point at the string
+                (format!("={body}"), Err(start))
+            } else {
+                (format!("={}", body.trim_end()), Ok(self.pos))
+            };
+
+            Formula::parse(&script).map(Value::Formula).map_err(|e| {
+                let FormulaError::InvalidFormula(errs) = e;
+                let err = errs
+                    .into_iter()
+                    .next()
+                    .expect("parse errors are never empty");
+                let pos = match script_start {
+                    Ok(start) => start + err.span.start,
+                    Err(start) => start,
+                };
+                self.error_at(pos, LoadErrorKind::Formula(err))
+            })
+        } else if let Some(literal) = rest.strip_prefix('@') {
+            datetime::literal(literal.trim_end())
+                .ok_or_else(|| self.error(LoadErrorKind::Expected("a date, datetime or duration")))
+        } else if rest.starts_with('#') {
+            let code = rest.trim_end();
+            ErrorKind::ALL
+                .into_iter()
+                .find(|kind| kind.code() == code)
+                .map(Value::Error)
+                .ok_or_else(|| self.error(LoadErrorKind::Expected("an error code")))
+        } else {
+            let start = self.pos;
+            let val = match self.word() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                word => match word.parse() {
+                    Ok(n) => Value::Number(n),
+                    Err(_) => return Err(self.error_at(start, LoadErrorKind::Expected("a value"))),
+                },
+            };
+            self.end()?;
+            Ok(val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind, Value},
+        file::{LoadError, LoadErrorKind},
+        formula::{FunctionError, NamedFunction},
+    };
+
+    #[test]
+    fn round_trip() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Sheet_2").unwrap();
+
+        sl.insert_row_default(s0, 7, 1).unwrap();
+        sl.insert_col_default(s0, 0, "=r").unwrap();
+        sl.insert_col_default(s0, 4, "apple").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), -3.5).unwrap();
+        sl.insert(s0, CellIdx::new(2, 4), "=[0, 0] * 2").unwrap();
+        sl.insert(s0, CellIdx::new(3, 3), "multi\nline \"string\"\\")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(4, 4), true).unwrap();
+        sl.insert(s0, CellIdx::new(5, 5), Value::Error(ErrorKind::Ref))
+            .unwrap();
+        sl.insert(s0, CellIdx::new(6, 1), "2024-01-31").unwrap();
+        sl.insert(s0, CellIdx::new(6, 2), "=[6, 1] + @1d12h30m")
+            .unwrap();
+        sl.insert(
+            s1,
+            CellIdx::new(1, 0),
+            "=if(\n  Sheet1[0, 0] < 0,\n  'neg'\n)",
+        )
+        .unwrap();
+        sl.insert_function(NamedFunction::parse("double(x) = x * 2").unwrap())
+            .unwrap();
+        sl.insert_function(NamedFunction::parse("polarity(x) =\n  if(x < 0, -1, 1)").unwrap())
+            .unwrap();
+        sl.insert(s1, CellIdx::new(2, 0), "=double(polarity(Sheet1[0, 0]))")
+            .unwrap();
+
+        let mut file = vec![];
+        sl.save(&mut file).unwrap();
+        let loaded = Spanleaf::load(file.as_slice()).unwrap();
+
+        let mut resaved = vec![];
+        loaded.save(&mut resaved).unwrap();
+        assert_eq!(
+            String::from_utf8(file).unwrap(),
+            String::from_utf8(resaved).unwrap()
+        );
+
+        // sheet ids are stable
+        assert_eq!(loaded.sheet_by_name("Sheet1"), Some(s0));
+        assert_eq!(loaded.sheet_by_name("Sheet_2"), Some(s1));
+
+        for row in 0..8 {
+            for col in 0..8 {
+                let cref = CellIdx::new(row, col);
+                assert_eq!(
+                    sl.get(s0, cref).unwrap(),
+                    loaded.get(s0, cref).unwrap(),
+                    "{cref:?}"
+                );
+            }
+        }
+        assert_eq!(
+            *loaded.get(s1, CellIdx::new(1, 0)).unwrap(),
+            "neg".try_into().unwrap()
+        );
+        assert_eq!(*loaded.get(s1, CellIdx::new(2, 0)).unwrap(), (-2).into());
+    }
+
+    #[test]
+    fn collapsed_ranges() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), 2).unwrap();
+        sl.insert(s0, CellIdx::new(2, 0), "=rows([0..1, 0])")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(2, 1), "=sum([0..1, 0])")
+            .unwrap();
+
+        // a range down to a single cell is still a range once it's loaded again
+        sl.delete_rows(s0, 1, 1);
+        let mut file = vec![];
+        sl.save(&mut file).unwrap();
+        let loaded = Spanleaf::load(file.as_slice()).unwrap();
+        for sl in [&sl, &loaded] {
+            assert_eq!(*sl.get(s0, CellIdx::new(1, 0)).unwrap(), 1.into());
+            assert_eq!(*sl.get(s0, CellIdx::new(1, 1)).unwrap(), 1.into());
+        }
+    }
+
+    #[test]
+    fn sheet_ids() {
+        let mut loaded =
+            Spanleaf::load("spanleaf 2\nsheet 4 \"a\"\nsheet 1 \"b\"".as_bytes()).unwrap();
+        assert_eq!(loaded.sheet_by_name("a").map(|s| s.id()), Some(4));
+        assert_eq!(loaded.sheet_by_name("b").map(|s| s.id()), Some(1));
+        // new sheets never reuse a loaded id
+        assert_eq!(loaded.insert_sheet("c").unwrap().id(), 5);
+        assert!(loaded.insert_sheet("a").is_err());
+
+        // version 1 files have no ids, so they're allocated in order
+        let v1 = Spanleaf::load("spanleaf 1\nsheet \"a\"\nsheet \"b\"".as_bytes()).unwrap();
+        assert_eq!(v1.sheet_by_name("a").map(|s| s.id()), Some(0));
+        assert_eq!(v1.sheet_by_name("b").map(|s| s.id()), Some(1));
+    }
+
+    #[test]
+    fn load_errors() {
+        let err = |file: &str| match Spanleaf::load(file.as_bytes()) {
+            Err(LoadError::Invalid { line, column, kind }) => (line, column, kind),
+            res => panic!("expected an error, got {res:?}"),
+        };
+
+        assert_eq!(err("").2, LoadErrorKind::MissingHeader);
+        assert_eq!(err("sheet \"a\"").2, LoadErrorKind::MissingHeader);
+        assert_eq!(err("spanleaf 99").2, LoadErrorKind::UnsupportedVersion(99));
+        assert_eq!(
+            err("spanleaf 1\ncell 0 0: 1"),
+            (2, 1, LoadErrorKind::NoSheet)
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\n  bogus 1: 2"),
+            (3, 3, LoadErrorKind::UnknownEntry("bogus".to_string()))
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\ncell x 0: 1"),
+            (3, 6, LoadErrorKind::Expected("a row or column number"))
+        );
+        // past the last row or col of the sheet
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\ncell 5000000000 0: 1"),
+            (3, 6, LoadErrorKind::Expected("a row or column number"))
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\ncol 4294967296: 1"),
+            (3, 5, LoadErrorKind::Expected("a row or column number"))
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\ncell 0 4294967296: 1"),
+            (3, 8, LoadErrorKind::Expected("a column number"))
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\ncell 0 0: \"open"),
+            (3, 11, LoadErrorKind::UnterminatedString)
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"a\"\nsheet \"a\"").2,
+            LoadErrorKind::DuplicateSheet("a".to_string())
+        );
+        assert_eq!(
+            err("spanleaf 1\nsheet \"My Sheet\""),
+            (
+                2,
+                7,
+                LoadErrorKind::InvalidSheetName("My Sheet".to_string())
+            )
+        );
+
+        assert_eq!(
+            err("spanleaf 2\nsheet 3 \"a\"\nsheet 3 \"b\""),
+            (3, 7, LoadErrorKind::DuplicateSheetId(3))
+        );
+        assert_eq!(
+            err("spanleaf 2\nsheet 18446744073709551615 \"a\""),
+            (2, 7, LoadErrorKind::SheetIdTooLarge(u64::MAX))
+        );
+        assert_eq!(
+            err("spanleaf 2\nsheet \"a\""),
+            (2, 7, LoadErrorKind::Expected("a sheet id"))
+        );
+
+        // formula errors point into the formula
+        let (line, column, kind) = err("spanleaf 1\nsheet \"a\"\ncell 0 0: =1 + )");
+        assert_eq!((line, column), (3, 16));
+        assert!(matches!(kind, LoadErrorKind::Formula(_)));
+
+        let (line, column, kind) = err("spanleaf 3\nfunction f(x) = x + )");
+        assert_eq!((line, column), (2, 21));
+        assert!(matches!(
+            kind,
+            LoadErrorKind::Function(FunctionError::Formula(_))
+        ));
+        assert_eq!(
+            err("spanleaf 3\nfunction f(x) = y"),
+            (
+                2,
+                10,
+                LoadErrorKind::Function(FunctionError::UnknownVariable("y".to_string()))
+            )
+        );
+        assert_eq!(
+            err("spanleaf 3\nfunction f() = 1\nfunction f() = 2"),
+            (3, 1, LoadErrorKind::DuplicateFunction("f".to_string()))
+        );
+        // functions are new in version 3
+        assert_eq!(
+            err("spanleaf 2\nfunction f() = 1").2,
+            LoadErrorKind::UnknownEntry("function".to_string())
+        );
+    }
+}
@@ -1,15 +1,94 @@
-use chumsky::Parser;
+use std::{fmt::Display, ops::Range};
+
+use chumsky::{Parser, error::Rich};
 
 use crate::{
-    Error, Spanleaf,
+    Dependency, Error, Spanleaf,
     cell::{CellIdx, Value},
+    functions,
     language::{self, Expr},
-    sheet::SheetIdx,
+    sheet::{Axis, SheetIdx},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub enum FormulaError {
-    InvalidFormula,
+    #[error("invalid formula: {}", join_errors(.0))]
+    InvalidFormula(Vec<ParseError>),
+}
+
+/// Why a named function definition is invalid
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FunctionError {
+    #[error(transparent)]
+    Formula(#[from] FormulaError),
+    /// The name is taken by a native function, which would be called instead
+    #[error("`{0}` is a built in function")]
+    Builtin(String),
+    /// The parameter would be parsed as something else, i.e. `r` or `B7`
+    #[error("`{0}` can't be a parameter name")]
+    ReservedParam(String),
+    #[error("duplicate parameter `{0}`")]
+    DuplicateParam(String),
+    /// The body uses a variable that isn't one of the parameters
+    #[error("unknown variable `{0}`")]
+    UnknownVariable(String),
+}
+
+fn join_errors(errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A single problem found while parsing a formula script
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The byte span of the offending part of the script, including the leading '='
+    pub span: Range<usize>,
+    /// Descriptions of what would have been valid at this point
+    pub expected: Vec<String>,
+    /// What was found instead, or None if the script ended early
+    pub found: Option<char>,
+    /// A human readable description of the problem
+    pub message: String,
+}
+impl ParseError {
+    /// Converts a chumsky error, with `offset` being where the parsed text starts within the script
+    fn from_rich(err: &Rich<'_, char>, offset: usize) -> Self {
+        let span = err.span().into_range();
+        let mut expected = err.expected().map(ToString::to_string).collect::<Vec<_>>();
+        expected.sort();
+        expected.dedup();
+        let found = err.found().copied();
+
+        let message = if expected.is_empty() {
+            err.reason().to_string()
+        } else {
+            let found = match found {
+                Some(c) => format!("unexpected '{c}'"),
+                None => "unexpected end of formula".to_string(),
+            };
+            format!("{found}, expected {}", expected.join(" or "))
+        };
+
+        Self {
+            span: span.start + offset..span.end + offset,
+            expected,
+            found,
+            message,
+        }
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
 }
 
 /// A parsed formula AST
@@ -23,20 +102,66 @@ pub struct Formula {
 impl Formula {
     /// Parses the script, returning an error if the script is invalid
     pub fn parse(script: &str) -> Result<Self, FormulaError> {
+        Self::parse_shared(script, None)
+    }
+    /// Parses the script of a formula shared by every cell along `axis`, as with row and col defaults, where
+    /// relative A1 references follow the evaluating cell along that axis
+    pub(crate) fn parse_shared(script: &str, axis: Option<Axis>) -> Result<Self, FormulaError> {
+        let body = script.strip_prefix('=').unwrap_or(script);
+        let offset = script.len() - body.len();
+
         // According to chumsky docs, this should be sufficiently quick to generate to not need to cache it
-        let expr = language::parser().parse(&script[1..]).unwrap();
+        let expr = language::parser(axis)
+            .parse(body)
+            .into_result()
+            .map_err(|errs| {
+                FormulaError::InvalidFormula(
+                    errs.iter()
+                        .map(|e| ParseError::from_rich(e, offset))
+                        .collect(),
+                )
+            })?;
         Ok(Formula {
             script: script.to_string(),
             expr,
         })
     }
+    /// Re-parses the formula as shared by every cell along `axis`
+    pub(crate) fn share_along(self, axis: Axis) -> Self {
+        // the script already parsed, so parsing it again can't fail
+        Self::parse_shared(&self.script, Some(axis)).unwrap_or(self)
+    }
+    /// Creates a formula from an already built AST, generating its script
+    #[cfg(feature = "xlsx")]
+    pub(crate) fn from_expr(expr: Expr) -> Self {
+        Formula {
+            script: format!("={expr}"),
+            expr,
+        }
+    }
+    /// The parsed AST, printed as an indented tree
+    pub fn ast(&self) -> String {
+        self.expr.tree()
+    }
+    #[cfg(any(feature = "xlsx", feature = "ods"))]
+    pub(crate) fn expr(&self) -> &Expr {
+        &self.expr
+    }
+    /// Applies a rewrite to the AST, which returns whether anything changed.
+    ///
+    /// If it did, the script is regenerated from the rewritten AST
+    pub(crate) fn rewrite(&mut self, f: impl FnOnce(&mut Expr) -> bool) {
+        if f(&mut self.expr) {
+            self.script = format!("={}", self.expr);
+        }
+    }
     /// Evaluate the formula
     pub(crate) fn eval(
         &self,
         sl: &Spanleaf,
         curr_sheet: SheetIdx,
         curr_cell: CellIdx,
-        dependencies: &mut Vec<(SheetIdx, CellIdx)>,
+        dependencies: &mut Vec<Dependency>,
     ) -> Result<Value, Error> {
         language::eval(
             &self.expr,
@@ -45,7 +170,156 @@ impl Formula {
                 curr_sheet,
                 curr_cell,
                 dependencies,
+                locals: vec![],
+                depth: 0,
             },
         )
     }
 }
+
+/// A function defined in the formula language, i.e. `tax(amount, rate) = amount * rate`, which any formula
+/// in the workbook can call like a built in one.
+///
+/// The body is evaluated as if it were in the calling cell, so `r`, `c` and references without a sheet are
+/// relative to the caller
+#[derive(Debug, Clone)]
+pub struct NamedFunction {
+    /// The definition, as written
+    pub definition: String,
+    pub name: String,
+    pub params: Vec<String>,
+    body: Expr,
+}
+impl NamedFunction {
+    /// Parses a definition, checking that the body only uses the function's parameters
+    pub fn parse(definition: &str) -> Result<Self, FunctionError> {
+        let (name, params, body) = language::definition_parser()
+            .parse(definition)
+            .into_result()
+            .map_err(|errs| {
+                FormulaError::InvalidFormula(
+                    errs.iter().map(|e| ParseError::from_rich(e, 0)).collect(),
+                )
+            })?;
+
+        if functions::is_standard(name) {
+            return Err(FunctionError::Builtin(name.to_string()));
+        }
+        for (i, param) in params.iter().enumerate() {
+            if matches!(*param, "r" | "c" | "true" | "false") || language::is_cell_like(param) {
+                return Err(FunctionError::ReservedParam(param.to_string()));
+            }
+            if params[..i].contains(param) {
+                return Err(FunctionError::DuplicateParam(param.to_string()));
+            }
+        }
+
+        // a lambda's parameters are known within its body, so they're blanked out of a copy of it first,
+        // innermost lambdas first as they shadow the ones around them
+        let mut free = body.clone();
+        free.visit_mut(&mut |expr| {
+            if let Expr::Lambda(params, inner) = expr {
+                inner.visit_mut(&mut |expr| {
+                    if let Expr::Var(var) = expr
+                        && params.contains(var)
+                    {
+                        *expr = Expr::CurrRow;
+                    }
+                });
+            }
+        });
+        let mut unknown = None;
+        free.visit_mut(&mut |expr| {
+            if let Expr::Var(var) = expr
+                && !params.contains(&var.as_str())
+            {
+                unknown.get_or_insert_with(|| var.clone());
+            }
+        });
+        if let Some(var) = unknown {
+            return Err(FunctionError::UnknownVariable(var));
+        }
+
+        Ok(Self {
+            definition: definition.to_string(),
+            name: name.to_string(),
+            params: params.into_iter().map(ToString::to_string).collect(),
+            body,
+        })
+    }
+    pub(crate) fn body(&self) -> &Expr {
+        &self.body
+    }
+    /// Applies a rewrite to the body, which returns whether anything changed.
+    ///
+    /// If it did, the definition is regenerated from the rewritten body
+    pub(crate) fn rewrite(&mut self, f: impl FnOnce(&mut Expr) -> bool) {
+        if f(&mut self.body) {
+            self.definition = format!("{}({}) = {}", self.name, self.params.join(", "), self.body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::Value,
+        formula::{Formula, FormulaError, FunctionError, NamedFunction},
+    };
+
+    #[test]
+    fn parse_errors() {
+        let FormulaError::InvalidFormula(errs) = Formula::parse("=1 + )").unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].span, 5..6);
+        assert_eq!(errs[0].found, Some(')'));
+        assert!(!errs[0].expected.is_empty());
+
+        let FormulaError::InvalidFormula(errs) = Formula::parse("=sum(1, 2").unwrap_err();
+        assert_eq!(errs[0].found, None);
+        assert_eq!(errs[0].span.start, 9);
+
+        // used to panic
+        assert!(Formula::parse("").is_err());
+        assert!(Value::try_from("=[0, 0").is_err());
+        assert!(Value::try_from("=").is_err());
+    }
+
+    #[test]
+    fn named_functions() {
+        let function = NamedFunction::parse("tax(amount, rate) = amount * rate + [r, 0]").unwrap();
+        assert_eq!(function.name, "tax");
+        assert_eq!(function.params, ["amount", "rate"]);
+        assert_eq!(
+            NamedFunction::parse(" tau ( ) =6.28").unwrap().params,
+            Vec::<String>::new()
+        );
+
+        let err = |definition| NamedFunction::parse(definition).unwrap_err();
+        assert_eq!(err("sum(x) = x"), FunctionError::Builtin("sum".to_string()));
+        assert_eq!(
+            err("f(r) = r"),
+            FunctionError::ReservedParam("r".to_string())
+        );
+        assert_eq!(
+            err("f(B7) = 1"),
+            FunctionError::ReservedParam("B7".to_string())
+        );
+        assert_eq!(
+            err("f(x, x) = x"),
+            FunctionError::DuplicateParam("x".to_string())
+        );
+        assert_eq!(
+            err("f(x) = x + y"),
+            FunctionError::UnknownVariable("y".to_string())
+        );
+        // a lambda's parameters are only known within its body
+        assert!(NamedFunction::parse("f(xs) = map(xs, (x) => map(x, (y) => x + y))").is_ok());
+        assert_eq!(
+            err("f(xs) = map(xs, (x) => x) + x"),
+            FunctionError::UnknownVariable("x".to_string())
+        );
+        assert!(matches!(err("f(x) = x +"), FunctionError::Formula(_)));
+        assert!(matches!(err("f(x) x"), FunctionError::Formula(_)));
+    }
+}
@@ -0,0 +1,549 @@
+//! Native functions, which formulas call by name
+//!
+//! Every [Spanleaf] has a [FunctionRegistry], starting out with the standard library. Host applications can
+//! register their own functions, written in Rust, alongside it:
+//!
+//! ```
+//! use spanleaf_core::{
+//!     Spanleaf,
+//!     cell::{CellIdx, Value},
+//!     functions::{Arity, NativeFunction},
+//! };
+//!
+//! let mut sl = Spanleaf::new();
+//! let sheet = sl.insert_sheet("Sheet1").unwrap();
+//! sl.register_function(
+//!     NativeFunction::new("double", Arity::exactly(1), |args| {
+//!         args.get(0)? * Value::Number(2.0)
+//!     })
+//!     .doc("double(x): twice x"),
+//! );
+//! sl.insert(sheet, CellIdx::new(0, 0), "=double(21)").unwrap();
+//! assert_eq!(*sl.get(sheet, CellIdx::new(0, 0)).unwrap(), 42.into());
+//! ```
+
+use std::{collections::BTreeMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    Dependency, Error, Spanleaf,
+    cell::{Array, CellIdx, CellRange, Value},
+    language::{EvalCtx, Expr, array_size, call_lambda, eval, range_array, resolve_sheet},
+    sheet::{SheetIdx, ValueResult},
+};
+
+// These are the sub-groups that Sheets identifies
+mod array;
+mod date;
+mod filter;
+mod financial;
+mod info;
+mod logical;
+mod lookup;
+mod math;
+mod statistical;
+mod text;
+
+/// How many arguments a function takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    /// None if there's no limit
+    pub max: Option<usize>,
+}
+impl Arity {
+    pub fn exactly(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+    pub fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+    pub fn between(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+    /// Checks that a call with `len` arguments is allowed
+    fn check(&self, len: usize) -> Result<(), Error> {
+        if len < self.min {
+            Err(Error::InsufficientArgs)
+        } else if self.max.is_some_and(|max| len > max) {
+            Err(Error::TooManyArgs)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// When a function's arguments are evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evaluation {
+    /// Every argument is evaluated before the call, with a failing argument failing the call
+    Eager,
+    /// Arguments are only evaluated when the function asks for them, i.e. the untaken branches of `if`.
+    ///
+    /// Only the arguments that are evaluated become dependencies
+    Lazy,
+}
+
+type Body = Rc<dyn Fn(&mut Args) -> Result<Value, Error>>;
+
+/// A function written in Rust, along with what's needed to call it
+#[derive(Clone)]
+pub struct NativeFunction {
+    /// The name formulas call it by
+    pub name: String,
+    pub arity: Arity,
+    pub evaluation: Evaluation,
+    /// Whether the result can change without any of its arguments changing, i.e. a random number.
+    ///
+    /// Cells calling a volatile function are recalculated by [Spanleaf::recalculate_volatile]
+    pub volatile: bool,
+    /// A description for users, starting with its signature, i.e. `sum(value, ...): the total of the values`
+    pub doc: String,
+    body: Body,
+}
+impl NativeFunction {
+    /// An eager, non volatile function
+    pub fn new(
+        name: impl ToString,
+        arity: Arity,
+        body: impl Fn(&mut Args) -> Result<Value, Error> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            evaluation: Evaluation::Eager,
+            volatile: false,
+            doc: String::new(),
+            body: Rc::new(body),
+        }
+    }
+    /// Makes the arguments only be evaluated when asked for
+    pub fn lazy(mut self) -> Self {
+        self.evaluation = Evaluation::Lazy;
+        self
+    }
+    pub fn volatile(mut self) -> Self {
+        self.volatile = true;
+        self
+    }
+    pub fn doc(mut self, doc: impl ToString) -> Self {
+        self.doc = doc.to_string();
+        self
+    }
+    /// Checks the arguments and calls the function
+    pub(crate) fn call(&self, ctx: &mut EvalCtx<'_>, exprs: &[Expr]) -> Result<Value, Error> {
+        self.arity.check(exprs.len())?;
+        if self.volatile {
+            ctx.dependencies.push(Dependency::Volatile);
+        }
+
+        let values = match self.evaluation {
+            Evaluation::Eager => Some(
+                exprs
+                    .iter()
+                    .map(|expr| eval(expr, ctx))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Evaluation::Lazy => None,
+        };
+        (self.body)(&mut Args { ctx, exprs, values })
+    }
+}
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("evaluation", &self.evaluation)
+            .field("volatile", &self.volatile)
+            .field("doc", &self.doc)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The values of the cells of a range in row major order. Like [range_array], fails for a range with too many
+/// cells to hold all at once
+fn range_values(sl: &Spanleaf, sref: SheetIdx, range: CellRange) -> Result<Vec<Value>, Error> {
+    array_size(range)?;
+    range
+        .iter()
+        .map(|cref| sl.get(sref, cref).map(ValueResult::value))
+        .collect()
+}
+
+/// The arguments of a call to a native function, and the context it's called in
+pub struct Args<'a, 'ctx> {
+    ctx: &'a mut EvalCtx<'ctx>,
+    exprs: &'a [Expr],
+    /// The already evaluated arguments, for eager functions
+    values: Option<Vec<Value>>,
+}
+impl Args<'_, '_> {
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+    /// The value of an argument, which for a lazy function evaluates it.
+    ///
+    /// Ranges are left as [Value::Range], see [Args::flatten] to expand them
+    pub fn get(&mut self, i: usize) -> Result<Value, Error> {
+        match &self.values {
+            Some(values) => values.get(i).cloned().ok_or(Error::InsufficientArgs),
+            None => eval(self.exprs.get(i).ok_or(Error::InsufficientArgs)?, self.ctx),
+        }
+    }
+    /// Every argument's value, expanding any ranges into the values of their cells, and arrays into their
+    /// elements
+    pub fn flatten(&mut self) -> Result<Vec<Value>, Error> {
+        let mut vals = vec![];
+        for i in 0..self.len() {
+            match self.get(i)? {
+                Value::Range { sref, range } => {
+                    vals.extend(range_values(self.ctx.sl, sref, range)?)
+                }
+                Value::Array(array) => vals.extend(array.into_values()),
+                val => vals.push(val),
+            }
+        }
+        Ok(vals)
+    }
+    /// A numeric argument, where blank is 0 and an error value fails the call with that error
+    pub fn number(&mut self, i: usize) -> Result<f64, Error> {
+        number(self.get(i)?)
+    }
+    /// A whole number argument, truncating any fraction
+    pub fn integer(&mut self, i: usize) -> Result<i64, Error> {
+        let n = self.number(i)?;
+        if n.abs() < 1e15 {
+            Ok(n.trunc() as i64)
+        } else {
+            Err(Error::InvalidNumber)
+        }
+    }
+    /// A text argument, converted as by [Value::text]
+    pub fn text(&mut self, i: usize) -> Result<String, Error> {
+        self.get(i)?.text()
+    }
+    /// Every numeric argument, expanding any ranges and arrays.
+    ///
+    /// Arguments are converted as by [Args::number], except that blanks are skipped rather than being 0, and
+    /// so are cells of a range and elements of an array that aren't numbers, so that they can include labels
+    pub fn numbers(&mut self) -> Result<Vec<f64>, Error> {
+        let mut nums = vec![];
+        for i in 0..self.len() {
+            let vals = match self.get(i)? {
+                Value::Range { sref, range } => range_values(self.ctx.sl, sref, range)?,
+                Value::Array(array) => array.into_values(),
+                Value::None => continue,
+                val => {
+                    nums.push(number(val)?);
+                    continue;
+                }
+            };
+            for val in vals {
+                match val {
+                    Value::Number(n) => nums.push(n),
+                    Value::Error(kind) => return Err(Error::ErrorValue(kind)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(nums)
+    }
+    /// The values of an argument as a list, which for a range is its cells in row major order, and likewise
+    /// for the elements of an array
+    pub fn cells(&mut self, i: usize) -> Result<Vec<Value>, Error> {
+        match self.get(i)? {
+            Value::Range { sref, range } => range_values(self.ctx.sl, sref, range),
+            Value::Array(array) => Ok(array.into_values()),
+            val => Ok(vec![val]),
+        }
+    }
+    /// An argument as an array, where a range is its cells' values, and any other value is a 1x1 array
+    pub fn array(&mut self, i: usize) -> Result<Array, Error> {
+        Ok(match self.get(i)? {
+            Value::Range { sref, range } => range_array(self.ctx.sl, sref, range)?,
+            Value::Array(array) => array,
+            val => Array::from_fn(1, 1, |_, _| val.clone()),
+        })
+    }
+    /// Whether an argument is a lambda, i.e. `(x) => x * 2`
+    pub fn is_lambda(&self, i: usize) -> bool {
+        matches!(self.exprs.get(i), Some(Expr::Lambda(..)))
+    }
+    /// Calls the lambda that's an argument with the given values, which must match its parameters.
+    ///
+    /// Only a lazy function can take a lambda, as evaluating one on its own fails
+    pub fn apply(&mut self, i: usize, vals: Vec<Value>) -> Result<Value, Error> {
+        let lambda = self.exprs.get(i).ok_or(Error::InsufficientArgs)?;
+        call_lambda(self.ctx, lambda, vals)
+    }
+    /// A reference to a cell that the function worked out, i.e. by offsetting another, which becomes a
+    /// dependency of the calling formula as if it had been written as `&[r, c]`
+    pub fn cell_ref(&mut self, sref: SheetIdx, cref: CellIdx) -> Value {
+        self.ctx.dependencies.push(Dependency::Cell(sref, cref));
+        Value::Ref { sref, cref }
+    }
+    /// A range that the function worked out, which becomes a dependency of the calling formula as if it had
+    /// been written as `[r0..r1, c0..c1]`
+    pub fn range_ref(&mut self, sref: SheetIdx, range: CellRange) -> Value {
+        self.ctx.dependencies.push(Dependency::Range(sref, range));
+        Value::Range { sref, range }
+    }
+    /// A sheet by name, which if it doesn't exist yet becomes a dependency of the calling formula, so that
+    /// inserting it updates the formula
+    pub fn sheet_by_name(&mut self, name: &str) -> Result<SheetIdx, Error> {
+        resolve_sheet(name, self.ctx)
+    }
+    /// The workbook being calculated
+    pub fn spanleaf(&self) -> &Spanleaf {
+        self.ctx.sl
+    }
+    /// The sheet of the calling formula
+    pub fn sheet(&self) -> SheetIdx {
+        self.ctx.curr_sheet
+    }
+    /// The cell of the calling formula
+    pub fn cell(&self) -> CellIdx {
+        self.ctx.curr_cell
+    }
+}
+
+/// Converts an argument to a number, see [Args::number]
+fn number(val: Value) -> Result<f64, Error> {
+    match val {
+        Value::Number(n) => Ok(n),
+        Value::None => Ok(0.0),
+        Value::Error(kind) => Err(Error::ErrorValue(kind)),
+        _ => Err(Error::OperationUnavailable),
+    }
+}
+
+/// A numeric result, which is invalid if it isn't finite
+fn finite(n: f64) -> Result<Value, Error> {
+    if n.is_finite() {
+        Ok(Value::Number(n))
+    } else {
+        Err(Error::InvalidNumber)
+    }
+}
+
+/// Native functions, by name
+#[derive(Debug, Clone, Default)]
+pub struct FunctionRegistry {
+    functions: BTreeMap<String, NativeFunction>,
+}
+impl FunctionRegistry {
+    /// A registry without any functions, not even the standard ones
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A registry with the standard library
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        array::register(&mut registry);
+        date::register(&mut registry);
+        filter::register(&mut registry);
+        financial::register(&mut registry);
+        info::register(&mut registry);
+        logical::register(&mut registry);
+        lookup::register(&mut registry);
+        math::register(&mut registry);
+        statistical::register(&mut registry);
+        text::register(&mut registry);
+        registry
+    }
+    /// Registers a function, replacing any function with the same name, which is returned
+    pub fn register(&mut self, function: NativeFunction) -> Option<NativeFunction> {
+        self.functions.insert(function.name.clone(), function)
+    }
+    pub fn remove(&mut self, name: &str) -> Option<NativeFunction> {
+        self.functions.remove(name)
+    }
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.get(name)
+    }
+    /// Every function, in name order
+    pub fn iter(&self) -> impl Iterator<Item = &NativeFunction> {
+        self.functions.values()
+    }
+}
+
+/// Whether the name is taken by the standard library, which named functions can't shadow
+pub(crate) fn is_standard(name: &str) -> bool {
+    thread_local! {
+        static STANDARD: FunctionRegistry = FunctionRegistry::standard();
+    }
+    STANDARD.with(|standard| standard.get(name).is_some())
+}
+
+#[cfg(test)]
+mod testing {
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind, Value},
+        formula::Formula,
+        sheet::SheetIdx,
+    };
+
+    /// A sheet to test functions against, with a table of values in its top left
+    pub(super) struct TestSheet {
+        pub(super) sl: Spanleaf,
+        pub(super) s0: SheetIdx,
+    }
+    impl TestSheet {
+        /// Each value is inserted as if it was typed in, with an empty one leaving its cell blank
+        pub(super) fn new(table: &[&[&str]]) -> Self {
+            let mut sl = Spanleaf::new();
+            let s0 = sl.insert_sheet("Sheet1").unwrap();
+            for (row, vals) in table.iter().enumerate() {
+                for (col, val) in vals.iter().enumerate() {
+                    sl.insert(s0, CellIdx::new(row as u64, col as u64), *val)
+                        .unwrap();
+                }
+            }
+            Self { sl, s0 }
+        }
+
+        /// Evaluates the formula away from the table
+        pub(super) fn eval(&self, script: &str) -> Value {
+            self.sl.eval_formula(
+                self.s0,
+                CellIdx::new(10, 10),
+                &Formula::parse(script).unwrap(),
+            )
+        }
+        pub(super) fn num(&self, script: &str) -> f64 {
+            match self.eval(script) {
+                Value::Number(n) => n,
+                val => panic!("{script} is {val:?}"),
+            }
+        }
+        pub(super) fn s(&self, script: &str) -> String {
+            match self.eval(script) {
+                Value::String(s) => s,
+                val => panic!("{script} is {val:?}"),
+            }
+        }
+        pub(super) fn err(&self, script: &str) -> ErrorKind {
+            match self.eval(script) {
+                Value::Error(kind) => kind,
+                val => panic!("{script} is {val:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{
+        Error, Spanleaf,
+        cell::{CellIdx, ErrorKind, Value},
+        functions::{Arity, Evaluation, FunctionRegistry, NativeFunction},
+    };
+
+    #[test]
+    fn arity() {
+        assert!(Arity::exactly(2).check(2).is_ok());
+        assert!(matches!(
+            Arity::exactly(2).check(1),
+            Err(Error::InsufficientArgs)
+        ));
+        assert!(matches!(
+            Arity::between(1, 3).check(4),
+            Err(Error::TooManyArgs)
+        ));
+        assert!(Arity::at_least(1).check(100).is_ok());
+    }
+
+    #[test]
+    fn standard() {
+        let registry = FunctionRegistry::standard();
+        for name in [
+            "sum", "average", "if", "ifs", "switch", "iserror", "iferror",
+        ] {
+            let function = registry.get(name).unwrap();
+            assert!(function.doc.starts_with(name), "{name} is undocumented");
+        }
+        assert_eq!(registry.get("if").unwrap().evaluation, Evaluation::Lazy);
+        assert!(FunctionRegistry::new().iter().next().is_none());
+    }
+
+    #[test]
+    fn host_functions() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let get = |sl: &Spanleaf, row| sl.get(s0, CellIdx::new(row, 0)).unwrap().value();
+
+        sl.insert(s0, CellIdx::new(0, 0), 3).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), "=clip([0, 0], 0, 2)")
+            .unwrap();
+        // unknown until registered
+        assert_eq!(get(&sl, 1), Value::Error(ErrorKind::Name));
+
+        sl.register_function(NativeFunction::new(
+            "clip",
+            Arity::exactly(3),
+            |args| match (args.get(0)?, args.get(1)?, args.get(2)?) {
+                (Value::Number(x), Value::Number(lo), Value::Number(hi)) => {
+                    Ok(x.clamp(lo, hi).into())
+                }
+                _ => Err(Error::OperationUnavailable),
+            },
+        ));
+        assert_eq!(get(&sl, 1), 2.into());
+        sl.insert(s0, CellIdx::new(0, 0), -1).unwrap();
+        assert_eq!(get(&sl, 1), 0.into());
+
+        // arity is checked before calling
+        sl.insert(s0, CellIdx::new(2, 0), "=clip(1)").unwrap();
+        assert_eq!(get(&sl, 2), Value::Error(ErrorKind::Args));
+
+        // lazy functions only evaluate what they ask for
+        sl.register_function(
+            NativeFunction::new("first", Arity::at_least(1), |args| args.get(0)).lazy(),
+        );
+        sl.insert(s0, CellIdx::new(3, 0), "=first(1, 1 / 0)")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(4, 0), "=clip(1, 1 / 0, 2)")
+            .unwrap();
+        assert_eq!(get(&sl, 3), 1.into());
+        assert_eq!(get(&sl, 4), Value::Error(ErrorKind::DivByZero));
+
+        // volatile functions are recalculated on demand, along with their dependents
+        let counter = Rc::new(Cell::new(0));
+        let count = counter.clone();
+        sl.register_function(
+            NativeFunction::new("tick", Arity::exactly(0), move |_| {
+                count.set(count.get() + 1);
+                Ok(count.get().into())
+            })
+            .volatile(),
+        );
+        sl.insert(s0, CellIdx::new(5, 0), "=tick()").unwrap();
+        sl.insert(s0, CellIdx::new(6, 0), "=[5, 0] * 10").unwrap();
+        assert_eq!(get(&sl, 6), 10.into());
+        assert_eq!(get(&sl, 6), 10.into());
+        sl.recalculate_volatile();
+        assert_eq!(get(&sl, 6), 20.into());
+        assert_eq!(counter.get(), 2);
+
+        // native functions take precedence over named ones
+        sl.register_function(NativeFunction::new("sum", Arity::at_least(0), |_| {
+            Ok(Value::String("overridden".to_string()))
+        }));
+        sl.insert(s0, CellIdx::new(7, 0), "=sum(1, 2)").unwrap();
+        assert_eq!(get(&sl, 7), Value::String("overridden".to_string()));
+        assert!(sl.unregister_function("sum").is_some());
+        assert_eq!(get(&sl, 7), Value::Error(ErrorKind::Name));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    Error,
+    cell::{Array, ErrorKind, Value},
+    functions::{Arity, FunctionRegistry, NativeFunction},
+};
+
+// Functions taking a lambda are lazy, as a lambda can't be evaluated on its own. Their array arguments are
+// still evaluated before it's called, so they're always dependencies
+
+/// How many values a sequence can have, which is as many rows as other spreadsheets have
+const MAX_SEQUENCE: i64 = 1 << 20;
+
+/// An element of a resulting array, which can only be a single value
+fn element(res: Result<Value, Error>) -> Value {
+    match res {
+        Ok(Value::Array(_) | Value::Range { .. }) => Value::Error(ErrorKind::Value),
+        Ok(val) => val,
+        Err(e) => Value::Error(e.into()),
+    }
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    // a failing call only makes its element an error
+    registry.register(
+        NativeFunction::new("map", Arity::at_least(2), |args| {
+            let lambda = args.len() - 1;
+            if !args.is_lambda(lambda) {
+                return Err(Error::OperationUnavailable);
+            }
+            let arrays = (0..lambda)
+                .map(|i| args.array(i))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (rows, cols) = (arrays[0].rows(), arrays[0].cols());
+            if arrays
+                .iter()
+                .any(|array| (array.rows(), array.cols()) != (rows, cols))
+            {
+                return Err(Error::OperationUnavailable);
+            }
+
+            Ok(Value::Array(Array::from_fn(rows, cols, |row, col| {
+                let vals = arrays
+                    .iter()
+                    .map(|array| array.get(row, col).cloned().unwrap_or_default())
+                    .collect();
+                element(args.apply(lambda, vals))
+            })))
+        })
+        .lazy()
+        .doc(
+            "map(values, ..., (x, ...) => result): the result for each of the values, in the same shape, \
+             where more than one array of values are taken together, element by element",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("reduce", Arity::exactly(3), |args| {
+            let array = args.array(0)?;
+            let mut acc = args.get(1)?;
+            for val in array.into_values() {
+                acc = args.apply(2, vec![acc, val])?;
+            }
+            Ok(acc)
+        })
+        .lazy()
+        .doc(
+            "reduce(values, initial, (acc, x) => next): the initial value, combined with each of the values \
+             in turn, in row major order",
+        ),
+    );
+
+    registry.register(
+        NativeFunction::new("sequence", Arity::between(1, 4), |args| {
+            let rows = args.integer(0)?;
+            let cols = if args.len() > 1 { args.integer(1)? } else { 1 };
+            let start = if args.len() > 2 { args.number(2)? } else { 1.0 };
+            let step = if args.len() > 3 { args.number(3)? } else { 1.0 };
+            if rows < 1 || cols < 1 || rows.saturating_mul(cols) > MAX_SEQUENCE {
+                return Err(Error::InvalidNumber);
+            }
+
+            let (rows, cols) = (rows as usize, cols as usize);
+            Ok(Value::Array(Array::from_fn(rows, cols, |row, col| {
+                Value::Number(start + (row * cols + col) as f64 * step)
+            })))
+        })
+        .doc(
+            "sequence(rows, [cols], [start], [step]): the numbers counting from the start, 1 by default, by \
+             the step, along each row in turn",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("transpose", Arity::exactly(1), |args| {
+            let array = args.array(0)?;
+            Ok(Value::Array(Array::from_fn(
+                array.cols(),
+                array.rows(),
+                |row, col| array.get(col, row).cloned().unwrap_or_default(),
+            )))
+        })
+        .doc("transpose(values): the values with their rows as columns"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::{Array, CellIdx, ErrorKind, Value},
+        formula::NamedFunction,
+        functions::testing::TestSheet,
+        sheet::{ValueResult, ValueSource},
+    };
+
+    #[test]
+    fn arrays() {
+        let mut sheet = TestSheet::new(&[&["10", "0.5"], &["20"], &["30"]]);
+        for definition in [
+            "discount(x) = x * (1 - [0, 1])",
+            "scale(x, by) = map(x, (y) => y * by)",
+        ] {
+            sheet
+                .sl
+                .insert_function(NamedFunction::parse(definition).unwrap())
+                .unwrap();
+        }
+
+        let array = |rows: Vec<Vec<Value>>| Value::Array(Array::new(rows).unwrap());
+        let column = |nums: &[f64]| array(nums.iter().map(|n| vec![(*n).into()]).collect());
+
+        assert_eq!(
+            sheet.eval("=map([0..2, 0], (x) => x * 2)"),
+            column(&[20.0, 40.0, 60.0])
+        );
+        assert_eq!(
+            sheet.eval("=map({1, 2}, {3, 4}, (a, b) => a * b)"),
+            array(vec![vec![3.into(), 8.into()]])
+        );
+        // lambdas can call named functions, and see the variables around them
+        assert_eq!(
+            sheet.eval("=map([0..1, 0], (x) => discount(x))"),
+            column(&[5.0, 10.0])
+        );
+        assert_eq!(sheet.eval("=scale({1; 2}, 3)"), column(&[3.0, 6.0]));
+        assert_eq!(
+            sheet.eval("=map({1, 0}, (x) => 1 / x)"),
+            array(vec![vec![1.into(), Value::Error(ErrorKind::DivByZero)]])
+        );
+        assert_eq!(
+            sheet.err("=map({1, 2}, {3}, (a, b) => a)"),
+            ErrorKind::Value
+        );
+        assert_eq!(sheet.err("=map({1, 2}, 2)"), ErrorKind::Value);
+
+        assert_eq!(
+            sheet.eval("=reduce([0..2, 0], 0, (acc, x) => acc + x)"),
+            60.into()
+        );
+        assert_eq!(
+            sheet.eval("=reduce({'a', 'b'}, '', (acc, x) => x & acc)"),
+            "ba".try_into().unwrap()
+        );
+        assert_eq!(sheet.err("=reduce({1, 2}, 0, (x) => x)"), ErrorKind::Args);
+
+        assert_eq!(sheet.eval("=sequence(3)"), column(&[1.0, 2.0, 3.0]));
+        assert_eq!(
+            sheet.eval("=sequence(2, 2, 0, 10)"),
+            array(vec![vec![0.into(), 10.into()], vec![20.into(), 30.into()]])
+        );
+        assert_eq!(sheet.err("=sequence(0)"), ErrorKind::Num);
+        assert_eq!(
+            sheet.eval("=transpose([0..2, 0])"),
+            array(vec![vec![10.into(), 20.into(), 30.into()]])
+        );
+        // a lambda isn't a value
+        assert_eq!(sheet.err("=(x) => x"), ErrorKind::Value);
+
+        // instead of a helper column, the results spill down
+        let (sl, s0) = (&mut sheet.sl, sheet.s0);
+        sl.insert(s0, CellIdx::new(0, 2), "=map([0..2, 0], (x) => x + 1)")
+            .unwrap();
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 2)).unwrap(), 11.into());
+        assert_eq!(
+            sl.get(s0, CellIdx::new(2, 2)).unwrap(),
+            ValueResult::new(31, ValueSource::Spill)
+        );
+        sl.insert(s0, CellIdx::new(2, 0), 40).unwrap();
+        assert_eq!(*sl.get(s0, CellIdx::new(2, 2)).unwrap(), 41.into());
+    }
+}
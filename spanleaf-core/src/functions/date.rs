@@ -0,0 +1,298 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    Error,
+    cell::Value,
+    datetime::{Date, DateTime, Duration},
+    functions::{Args, Arity, FunctionRegistry, NativeFunction},
+};
+
+// There are no time zones, so the current date and time are in UTC
+
+/// A date argument, where a datetime is its date
+fn date(args: &mut Args, i: usize) -> Result<Date, Error> {
+    match args.get(i)? {
+        Value::Date(date) => Ok(date),
+        Value::DateTime(dt) => Ok(dt.date()),
+        Value::Error(kind) => Err(Error::ErrorValue(kind)),
+        _ => Err(Error::OperationUnavailable),
+    }
+}
+
+/// A datetime argument, where a date is its midnight
+fn datetime(args: &mut Args, i: usize) -> Result<DateTime, Error> {
+    match args.get(i)? {
+        Value::Date(date) => Ok(date.midnight()),
+        Value::DateTime(dt) => Ok(dt),
+        Value::Error(kind) => Err(Error::ErrorValue(kind)),
+        _ => Err(Error::OperationUnavailable),
+    }
+}
+
+fn now() -> Result<DateTime, Error> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::InvalidNumber)?;
+    i64::try_from(since_epoch.as_millis())
+        .ok()
+        .and_then(DateTime::from_millis)
+        .ok_or(Error::InvalidNumber)
+}
+
+/// Registers a function of a date, returning one of its parts
+fn part(registry: &mut FunctionRegistry, name: &str, doc: &str, f: fn(&DateTime) -> i64) {
+    registry.register(
+        NativeFunction::new(name, Arity::exactly(1), move |args| {
+            Ok(Value::Number(f(&datetime(args, 0)?) as f64))
+        })
+        .doc(doc),
+    );
+}
+
+/// The number of weekdays from `start` up to, but not including, `end`
+fn weekdays_between(start: Date, end: Date) -> i64 {
+    let days = end.days() - start.days();
+    let (weeks, rest) = (days / 7, days % 7);
+    let rest = (0..rest)
+        .filter(|i| (start.weekday() as i64 + i) % 7 < 5)
+        .count();
+    weeks * 5 + rest as i64
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("today", Arity::exactly(0), |_| {
+            Ok(Value::Date(now()?.date()))
+        })
+        .volatile()
+        .doc("today(): the current date"),
+    );
+    registry.register(
+        NativeFunction::new("now", Arity::exactly(0), |_| Ok(Value::DateTime(now()?)))
+            .volatile()
+            .doc("now(): the current date and time"),
+    );
+
+    // months and days past the end roll over into the next year or month, as in other spreadsheets
+    registry.register(
+        NativeFunction::new("date", Arity::exactly(3), |args| {
+            let (year, month, day) = (args.integer(0)?, args.integer(1)?, args.integer(2)?);
+            Date::from_ymd(year, 1, 1)
+                .and_then(|date| date.checked_add_months(month - 1))
+                .and_then(|date| date.checked_add_days(day - 1))
+                .map(Value::Date)
+                .ok_or(Error::InvalidNumber)
+        })
+        .doc("date(year, month, day): the date, with months and days from 1"),
+    );
+    registry.register(
+        NativeFunction::new("time", Arity::exactly(3), |args| {
+            let (hours, minutes, seconds) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let days = (hours * 3600.0 + minutes * 60.0 + seconds) / 86_400.0;
+            Duration::from_days(days)
+                .map(Value::Duration)
+                .ok_or(Error::InvalidNumber)
+        })
+        .doc("time(hours, minutes, seconds): the duration, which added to a date is that time of day"),
+    );
+
+    part(registry, "year", "year(date): the year of the date", |dt| {
+        dt.date().year()
+    });
+    part(
+        registry,
+        "month",
+        "month(date): the month of the date, from 1 to 12",
+        |dt| dt.date().month().into(),
+    );
+    part(
+        registry,
+        "day",
+        "day(date): the day of the month of the date, from 1 to 31",
+        |dt| dt.date().day().into(),
+    );
+    part(
+        registry,
+        "hour",
+        "hour(datetime): the hour of the datetime, from 0 to 23",
+        |dt| dt.hour().into(),
+    );
+    part(
+        registry,
+        "minute",
+        "minute(datetime): the minute of the datetime, from 0 to 59",
+        |dt| dt.minute().into(),
+    );
+    part(
+        registry,
+        "second",
+        "second(datetime): the second of the datetime, from 0 to 59",
+        |dt| dt.second().into(),
+    );
+
+    // the numberings are other spreadsheets' return types 1 to 3
+    registry.register(
+        NativeFunction::new("weekday", Arity::between(1, 2), |args| {
+            let day = date(args, 0)?.weekday();
+            let numbering = if args.len() == 2 {
+                args.integer(1)?
+            } else {
+                1
+            };
+            let number = match numbering {
+                1 => (day + 1) % 7 + 1,
+                2 => day + 1,
+                3 => day,
+                _ => return Err(Error::InvalidNumber),
+            };
+            Ok(Value::Number(number.into()))
+        })
+        .doc(
+            "weekday(date, [numbering]): the day of the week, from 1 for Sunday to 7 for Saturday, or with \
+             numbering 2 from 1 for Monday to 7 for Sunday, or with numbering 3 from 0 for Monday to 6 for Sunday",
+        ),
+    );
+
+    registry.register(
+        NativeFunction::new("edate", Arity::exactly(2), |args| {
+            let (date, months) = (date(args, 0)?, args.integer(1)?);
+            date.checked_add_months(months)
+                .map(Value::Date)
+                .ok_or(Error::InvalidNumber)
+        })
+        .doc(
+            "edate(date, months): the same day of the month, months later, or the last day of that month if \
+             it's shorter",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("eomonth", Arity::exactly(2), |args| {
+            let (date, months) = (date(args, 0)?, args.integer(1)?);
+            date.checked_add_months(months)
+                .map(|date| Value::Date(date.end_of_month()))
+                .ok_or(Error::InvalidNumber)
+        })
+        .doc("eomonth(date, months): the last day of the month, months later"),
+    );
+
+    registry.register(
+        NativeFunction::new("networkdays", Arity::between(2, 3), |args| {
+            let (start, end) = (date(args, 0)?, date(args, 1)?);
+            let (first, last, sign) = if start <= end {
+                (start, end, 1)
+            } else {
+                (end, start, -1)
+            };
+
+            let mut holidays = vec![];
+            if args.len() == 3 {
+                for val in args.cells(2)? {
+                    match val {
+                        Value::Date(date) => holidays.push(date),
+                        Value::DateTime(dt) => holidays.push(dt.date()),
+                        Value::None => {}
+                        Value::Error(kind) => return Err(Error::ErrorValue(kind)),
+                        _ => return Err(Error::OperationUnavailable),
+                    }
+                }
+            }
+            holidays.sort();
+            holidays.dedup();
+            let holidays = holidays
+                .into_iter()
+                .filter(|day| (first..=last).contains(day) && day.weekday() < 5)
+                .count() as i64;
+
+            let days = weekdays_between(first, last) + i64::from(last.weekday() < 5) - holidays;
+            Ok(Value::Number((sign * days) as f64))
+        })
+        .doc(
+            "networkdays(start, end, [holidays]): the number of weekdays from the start to the end, including \
+             both, and excluding any of the holidays",
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::{ErrorKind, Value},
+        datetime::{Date, DateTime, Duration},
+        functions::testing::TestSheet,
+    };
+
+    #[test]
+    fn dates() {
+        // holidays, with a blank and one on a weekend, next to an error
+        let sheet = TestSheet::new(&[
+            &["2024-01-01", "=1 / 0"],
+            &[""],
+            &["2024-01-06"],
+            &["2024-01-15T12:00"],
+        ]);
+        let date = |script: &str| match sheet.eval(script) {
+            Value::Date(date) => date.to_string(),
+            val => panic!("{script} is {val:?}"),
+        };
+
+        assert!(matches!(sheet.eval("=today()"), Value::Date(_)));
+        assert!(matches!(sheet.eval("=now()"), Value::DateTime(_)));
+        assert!(sheet.sl.registry().get("now").unwrap().volatile);
+
+        assert_eq!(date("=date(2024, 2, 29)"), "2024-02-29");
+        assert_eq!(date("=date(2023, 14, 1)"), "2024-02-01");
+        assert_eq!(date("=date(2024, 3, 0)"), "2024-02-29");
+        assert_eq!(date("=date(2024, 0, 1)"), "2023-12-01");
+        assert_eq!(sheet.err("=date(10000, 1, 1)"), ErrorKind::Num);
+        assert_eq!(
+            sheet.eval("=date(2024, 1, 31) + time(9, 30, 0)"),
+            Value::DateTime(DateTime::parse("2024-01-31T09:30").unwrap())
+        );
+        assert_eq!(
+            sheet.eval("=time(36, 0, 1)"),
+            Value::Duration(Duration::parse("1d12h1s").unwrap())
+        );
+
+        assert_eq!(sheet.num("=year(@2024-01-31)"), 2024.0);
+        assert_eq!(sheet.num("=month(@2024-01-31T23:59)"), 1.0);
+        assert_eq!(sheet.num("=day([0, 0])"), 1.0);
+        assert_eq!(sheet.num("=hour(@2024-01-31T23:59:30)"), 23.0);
+        assert_eq!(sheet.num("=minute(@2024-01-31T23:59:30)"), 59.0);
+        assert_eq!(sheet.num("=second(@2024-01-31T23:59:30)"), 30.0);
+        assert_eq!(sheet.num("=hour(@2024-01-31)"), 0.0);
+
+        // a Wednesday
+        assert_eq!(sheet.num("=weekday(@2024-01-31)"), 4.0);
+        assert_eq!(sheet.num("=weekday(@2024-01-31, 2)"), 3.0);
+        assert_eq!(sheet.num("=weekday(@2024-01-31, 3)"), 2.0);
+        assert_eq!(sheet.num("=weekday(@2024-01-28)"), 1.0);
+        assert_eq!(sheet.err("=weekday(@2024-01-31, 4)"), ErrorKind::Num);
+
+        assert_eq!(date("=edate(@2024-01-31, 1)"), "2024-02-29");
+        assert_eq!(date("=edate(@2024-01-31T08:00, -13)"), "2022-12-31");
+        assert_eq!(date("=eomonth(@2024-01-15, 1)"), "2024-02-29");
+        assert_eq!(date("=eomonth(@2024-01-15, -1)"), "2023-12-31");
+
+        assert_eq!(sheet.num("=networkdays(@2024-01-01, @2024-01-31)"), 23.0);
+        assert_eq!(sheet.num("=networkdays(@2024-01-31, @2024-01-01)"), -23.0);
+        assert_eq!(
+            sheet.num("=networkdays(@2024-01-01, @2024-01-31, [0..3, 0])"),
+            21.0
+        );
+        assert_eq!(sheet.num("=networkdays(@2024-01-06, @2024-01-07)"), 0.0);
+        assert_eq!(sheet.num("=networkdays(@2024-01-05, @2024-01-05)"), 1.0);
+
+        // a date is required, and errors propagate
+        assert_eq!(sheet.err("=year(2024)"), ErrorKind::Value);
+        assert_eq!(sheet.err("=year('2024-01-31')"), ErrorKind::Value);
+        assert_eq!(sheet.err("=eomonth([0, 1], 1)"), ErrorKind::DivByZero);
+        assert_eq!(
+            sheet.err("=networkdays(@2024-01-01, @2024-01-31, [0..1, 1])"),
+            ErrorKind::DivByZero
+        );
+        assert_eq!(
+            Date::from_ymd(2024, 1, 1).map(Value::Date),
+            Some(sheet.eval("=[0, 0]"))
+        );
+    }
+}
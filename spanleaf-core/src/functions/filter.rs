@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+
+use crate::{
+    Error,
+    cell::{Array, Value},
+    functions::{Args, Arity, FunctionRegistry, NativeFunction},
+};
+
+// These work on entries: the rows of an array, or the elements of a single row, which is how a list across
+// is written. Lambdas are given an entry as its value, or as a one row array if it has more than one
+
+/// The entries of an array, as their values
+fn entries(array: &Array) -> Vec<Vec<Value>> {
+    if array.rows() == 1 {
+        return array.values().iter().map(|val| vec![val.clone()]).collect();
+    }
+    (0..array.rows())
+        .map(|row| {
+            (0..array.cols())
+                .map(|col| array.get(row, col).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect()
+}
+
+/// An entry as it's given to a lambda
+fn entry_value(entry: &[Value]) -> Value {
+    match entry {
+        [val] => val.clone(),
+        vals => Array::new(vec![vals.to_vec()]).map_or(Value::None, Value::Array),
+    }
+}
+
+/// The picked entries of an array, in the order they're picked
+fn pick(array: &Array, picked: &[usize]) -> Array {
+    if array.rows() == 1 {
+        Array::from_fn(1, picked.len(), |_, i| {
+            array.get(0, picked[i]).cloned().unwrap_or_default()
+        })
+    } else {
+        Array::from_fn(picked.len(), array.cols(), |i, col| {
+            array.get(picked[i], col).cloned().unwrap_or_default()
+        })
+    }
+}
+
+/// Orders entries by their keys, value by value, as compared by `<`
+fn order(lhs: &[Value], rhs: &[Value]) -> Result<Ordering, Error> {
+    for (lhs, rhs) in lhs.iter().zip(rhs) {
+        match lhs.compare(rhs)? {
+            Some(Ordering::Equal) | None => {}
+            Some(ord) => return Ok(ord),
+        }
+    }
+    Ok(Ordering::Equal)
+}
+
+/// What entries are sorted by
+enum Key {
+    /// Their values in turn
+    Entry,
+    /// The result of the lambda that's the second argument
+    Lambda,
+    /// One of their values, from 0
+    Position(usize),
+}
+impl Key {
+    fn new(args: &mut Args) -> Result<Self, Error> {
+        if args.len() < 2 {
+            return Ok(Key::Entry);
+        }
+        if args.is_lambda(1) {
+            return Ok(Key::Lambda);
+        }
+        match args.get(1)? {
+            Value::None => Ok(Key::Entry),
+            _ => match args.integer(1)? {
+                ..1 => Err(Error::InvalidNumber),
+                n => Ok(Key::Position(n as usize - 1)),
+            },
+        }
+    }
+    /// The key of an entry, where an error value fails the sort
+    fn of(&self, args: &mut Args, entry: &[Value]) -> Result<Vec<Value>, Error> {
+        let key = match self {
+            Key::Entry => entry.to_vec(),
+            Key::Lambda => vec![args.apply(1, vec![entry_value(entry)])?],
+            Key::Position(i) => vec![entry.get(*i).cloned().ok_or(Error::RefOutOfBounds)?],
+        };
+        match key.iter().find_map(|val| match val {
+            Value::Error(kind) => Some(*kind),
+            _ => None,
+        }) {
+            Some(kind) => Err(Error::ErrorValue(kind)),
+            None => Ok(key),
+        }
+    }
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    // nothing being kept is #N/A, as an array can't be empty
+    registry.register(
+        NativeFunction::new("filter", Arity::exactly(2), |args| {
+            let array = args.array(0)?;
+            let entries = entries(&array);
+            let keep = if args.is_lambda(1) {
+                entries
+                    .iter()
+                    .map(|entry| args.apply(1, vec![entry_value(entry)])?.truthy())
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                let conditions = args.array(1)?.into_values();
+                if conditions.len() != entries.len() {
+                    return Err(Error::OperationUnavailable);
+                }
+                conditions
+                    .iter()
+                    .map(Value::truthy)
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+
+            let picked = (0..entries.len()).filter(|i| keep[*i]).collect::<Vec<_>>();
+            if picked.is_empty() {
+                return Err(Error::NoMatch);
+            }
+            Ok(Value::Array(pick(&array, &picked)))
+        })
+        .lazy()
+        .doc(
+            "filter(values, (entry) => keep): the rows of the values for which the lambda is true, or the \
+             values of a single row, where the lambda can also be conditions for each of them, i.e. \
+             `filter([0..9, 0..1], [0..9, 1] > 3)`",
+        ),
+    );
+    // ties keep their order
+    registry.register(
+        NativeFunction::new("sort", Arity::between(1, 3), |args| {
+            let array = args.array(0)?;
+            let entries = entries(&array);
+            let key = Key::new(args)?;
+            let keys = entries
+                .iter()
+                .map(|entry| key.of(args, entry))
+                .collect::<Result<Vec<_>, _>>()?;
+            let desc = args.len() == 3 && args.get(2)?.truthy()?;
+
+            let mut picked = (0..entries.len()).collect::<Vec<_>>();
+            let mut failed = None;
+            picked.sort_by(|a, b| match order(&keys[*a], &keys[*b]) {
+                Ok(ord) if desc => ord.reverse(),
+                Ok(ord) => ord,
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    Ordering::Equal
+                }
+            });
+            if let Some(e) = failed {
+                return Err(e);
+            }
+            Ok(Value::Array(pick(&array, &picked)))
+        })
+        .lazy()
+        .doc(
+            "sort(values, [key], [descending]): the rows of the values, or the values of a single row, in \
+             ascending order of the key, which is a lambda of the row, or the position of the value within \
+             it to sort by. Without a key, rows are sorted by their first value, then their second, and so on",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("unique", Arity::exactly(1), |args| {
+            let array = args.array(0)?;
+            let entries = entries(&array);
+
+            // sorted so that repeats are next to each other, after the earliest one as ties keep their order
+            let mut sorted = (0..entries.len()).collect::<Vec<_>>();
+            let mut failed = None;
+            sorted.sort_by(|a, b| {
+                order(&entries[*a], &entries[*b]).unwrap_or_else(|e| {
+                    failed.get_or_insert(e);
+                    Ordering::Equal
+                })
+            });
+            if let Some(e) = failed {
+                return Err(e);
+            }
+
+            let mut keep = vec![false; entries.len()];
+            let mut first: Option<usize> = None;
+            for i in sorted {
+                let repeat = match first {
+                    Some(first) => order(&entries[first], &entries[i])? == Ordering::Equal,
+                    None => false,
+                };
+                if !repeat {
+                    keep[i] = true;
+                    first = Some(i);
+                }
+            }
+
+            let picked = (0..entries.len()).filter(|i| keep[*i]).collect::<Vec<_>>();
+            Ok(Value::Array(pick(&array, &picked)))
+        })
+        .doc(
+            "unique(values): the rows of the values, or the values of a single row, without any repeats of \
+             an earlier one",
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::{Array, ErrorKind, Value},
+        functions::testing::TestSheet,
+    };
+
+    #[test]
+    fn filters() {
+        let sheet = TestSheet::new(&[
+            &["pear", "3"],
+            &["apple", "5"],
+            &["fig", "1"],
+            &["apple", "5"],
+        ]);
+        let array = |rows: Vec<Vec<Value>>| Value::Array(Array::new(rows).unwrap());
+        let row = |name: &str, qty: i32| vec![name.try_into().unwrap(), qty.into()];
+
+        // rows are given as one row arrays, and a single column as values
+        assert_eq!(
+            sheet.eval("=filter([0..3, 0..1], (entry) => max(entry) > 3)"),
+            array(vec![row("apple", 5), row("apple", 5)])
+        );
+        assert_eq!(
+            sheet.eval("=filter([0..3, 0..1], [0..3, 1] < 5)"),
+            array(vec![row("pear", 3), row("fig", 1)])
+        );
+        assert_eq!(
+            sheet.eval("=filter({1, 2, 3, 4}, (x) => x / 2 == floor(x / 2))"),
+            array(vec![vec![2.into(), 4.into()]])
+        );
+        assert_eq!(
+            sheet.err("=filter([0..3, 0], (x) => x == 'kiwi')"),
+            ErrorKind::NotAvailable
+        );
+        assert_eq!(
+            sheet.err("=filter([0..3, 0], {true, false})"),
+            ErrorKind::Value
+        );
+
+        assert_eq!(
+            sheet.eval("=sort([0..2, 0..1])"),
+            array(vec![row("apple", 5), row("fig", 1), row("pear", 3)])
+        );
+        assert_eq!(
+            sheet.eval("=sort([0..2, 0..1], 2, true)"),
+            array(vec![row("apple", 5), row("pear", 3), row("fig", 1)])
+        );
+        assert_eq!(
+            sheet.eval("=sort({'bb', 'a', 'ccc'}, (x) => len(x), true)"),
+            array(vec![vec![
+                "ccc".try_into().unwrap(),
+                "bb".try_into().unwrap(),
+                "a".try_into().unwrap()
+            ]])
+        );
+        assert_eq!(sheet.err("=sort([0..2, 0..1], 3)"), ErrorKind::Ref);
+        assert_eq!(sheet.err("=sort({1, 1 / 0})"), ErrorKind::DivByZero);
+
+        assert_eq!(
+            sheet.eval("=unique([0..3, 0..1])"),
+            array(vec![row("pear", 3), row("apple", 5), row("fig", 1)])
+        );
+        assert_eq!(
+            sheet.eval("=unique({1; 2; 1; 3})"),
+            array(vec![vec![1.into()], vec![2.into()], vec![3.into()]])
+        );
+        // in the order they're first seen
+        assert_eq!(
+            sheet.eval("=unique({3, 1, 3, 2, 1})"),
+            array(vec![vec![3.into(), 1.into(), 2.into()]])
+        );
+    }
+}
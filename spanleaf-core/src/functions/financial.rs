@@ -0,0 +1,406 @@
+use crate::{
+    Error,
+    cell::Value,
+    datetime::MILLIS_PER_DAY,
+    functions::{Args, Arity, FunctionRegistry, NativeFunction, finite, statistical::numbers_of},
+};
+
+// As in other spreadsheets, money paid out is negative and money received is positive, rates are per period,
+// and payments are at the end of each period unless `due` is true, when they're at the start
+
+/// How many steps the iterative solvers take before giving up
+const MAX_ITERATIONS: usize = 100;
+
+/// An optional numeric argument
+fn number_or(args: &mut Args, i: usize, default: f64) -> Result<f64, Error> {
+    if args.len() > i {
+        args.number(i)
+    } else {
+        Ok(default)
+    }
+}
+
+/// The optional argument of whether payments are at the start of each period
+fn due(args: &mut Args, i: usize) -> Result<bool, Error> {
+    Ok(number_or(args, i, 0.0)? != 0.0)
+}
+
+/// How much the rate grows a value over the periods, less the value itself, which stays precise for rates
+/// near 0
+fn growth(rate: f64, nper: f64) -> f64 {
+    (nper * rate.ln_1p()).exp_m1()
+}
+
+/// The future value left after the periods, which with the future value added is 0 for the rate, periods and
+/// payments that fit
+fn future_value(rate: f64, nper: f64, pmt: f64, pv: f64, due: bool) -> f64 {
+    if rate == 0.0 {
+        return -(pv + pmt * nper);
+    }
+    let growth = growth(rate, nper);
+    let due = if due { 1.0 + rate } else { 1.0 };
+    -(pv * (growth + 1.0) + pmt * due * growth / rate)
+}
+
+fn present_value(rate: f64, nper: f64, pmt: f64, fv: f64, due: bool) -> f64 {
+    if rate == 0.0 {
+        return -(fv + pmt * nper);
+    }
+    let growth = growth(rate, nper);
+    let due = if due { 1.0 + rate } else { 1.0 };
+    -(fv + pmt * due * growth / rate) / (growth + 1.0)
+}
+
+fn payment(rate: f64, nper: f64, pv: f64, fv: f64, due: bool) -> Result<f64, Error> {
+    if nper == 0.0 {
+        return Err(Error::DivideByZero);
+    }
+    if rate == 0.0 {
+        return Ok(-(pv + fv) / nper);
+    }
+    let growth = growth(rate, nper);
+    let due = if due { 1.0 + rate } else { 1.0 };
+    Ok(-(fv + pv * (growth + 1.0)) * rate / (due * growth))
+}
+
+/// The interest part of the payment of a period, from 1
+fn interest(rate: f64, per: f64, nper: f64, pv: f64, fv: f64, due: bool) -> Result<f64, Error> {
+    if per < 1.0 || per > nper {
+        return Err(Error::InvalidNumber);
+    }
+    // the first payment is made before any interest when payments are due at the start
+    if due && per == 1.0 {
+        return Ok(0.0);
+    }
+    let pmt = payment(rate, nper, pv, fv, due)?;
+    // the interest on what's owed after the previous period, which is its future value
+    let owed = future_value(rate, per - 1.0, pmt, pv, due);
+    Ok(if due {
+        owed * rate / (1.0 + rate)
+    } else {
+        owed * rate
+    })
+}
+
+/// Finds where `f` is 0 by Newton's method from the guess, for a rate, which can't go down to -100%
+fn solve(guess: f64, f: impl Fn(f64) -> f64) -> Result<f64, Error> {
+    let mut rate = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let step = 1e-7 * (1.0 + rate.abs());
+        let slope = (f(rate + step) - f(rate - step)) / (2.0 * step);
+        let mut next = rate - f(rate) / slope;
+        if !next.is_finite() {
+            return Err(Error::NoConvergence);
+        }
+        if next <= -1.0 {
+            next = (rate - 1.0) / 2.0;
+        }
+        if (next - rate).abs() < 1e-10 {
+            return Ok(next);
+        }
+        rate = next;
+    }
+    Err(Error::NoConvergence)
+}
+
+/// The net present value of cash flows at the given times, in periods
+fn discounted(rate: f64, flows: &[(f64, f64)]) -> f64 {
+    flows
+        .iter()
+        .map(|(flow, time)| flow / (1.0 + rate).powf(*time))
+        .sum()
+}
+
+/// A rate of return has to turn money paid out into money received
+fn check_returns(flows: &[(f64, f64)]) -> Result<(), Error> {
+    if flows.iter().any(|(flow, _)| *flow > 0.0) && flows.iter().any(|(flow, _)| *flow < 0.0) {
+        Ok(())
+    } else {
+        Err(Error::InvalidNumber)
+    }
+}
+
+/// Cash flows one period apart, with the first at `start`
+fn periodic(flows: Vec<f64>, start: f64) -> Vec<(f64, f64)> {
+    flows
+        .into_iter()
+        .enumerate()
+        .map(|(i, flow)| (flow, start + i as f64))
+        .collect()
+}
+
+/// The cash flows of a range of amounts and an equally long range of their dates, in years after the first
+/// date, where numbers are days
+fn dated(args: &mut Args, flows: usize, dates: usize) -> Result<Vec<(f64, f64)>, Error> {
+    let (flows, dates) = (args.cells(flows)?, args.cells(dates)?);
+    if flows.len() != dates.len() || flows.is_empty() {
+        return Err(Error::InvalidNumber);
+    }
+
+    let days = |val: &Value| match val {
+        Value::Date(date) => Ok(date.days() as f64),
+        Value::DateTime(dt) => Ok(dt.millis() as f64 / MILLIS_PER_DAY as f64),
+        Value::Number(n) => Ok(*n),
+        Value::Error(kind) => Err(Error::ErrorValue(*kind)),
+        _ => Err(Error::OperationUnavailable),
+    };
+    let first = days(&dates[0])?;
+    flows
+        .into_iter()
+        .zip(&dates)
+        .map(|(flow, date)| {
+            let flow = match flow {
+                Value::Number(n) => n,
+                Value::Error(kind) => return Err(Error::ErrorValue(kind)),
+                _ => return Err(Error::OperationUnavailable),
+            };
+            match days(date)? - first {
+                // every flow is discounted back to the first
+                days if days < 0.0 => Err(Error::InvalidNumber),
+                days => Ok((flow, days / 365.0)),
+            }
+        })
+        .collect()
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("pmt", Arity::between(3, 5), |args| {
+            let (rate, nper, pv) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let (fv, due) = (number_or(args, 3, 0.0)?, due(args, 4)?);
+            finite(payment(rate, nper, pv, fv, due)?)
+        })
+        .doc(
+            "pmt(rate, nper, pv, [fv], [due]): the payment each period that pays off the present value over \
+             the number of periods, leaving the future value",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("ipmt", Arity::between(4, 6), |args| {
+            let (rate, per) = (args.number(0)?, args.number(1)?);
+            let (nper, pv) = (args.number(2)?, args.number(3)?);
+            let (fv, due) = (number_or(args, 4, 0.0)?, due(args, 5)?);
+            finite(interest(rate, per, nper, pv, fv, due)?)
+        })
+        .doc("ipmt(rate, period, nper, pv, [fv], [due]): the interest part of the payment of the period, from 1"),
+    );
+    registry.register(
+        NativeFunction::new("ppmt", Arity::between(4, 6), |args| {
+            let (rate, per) = (args.number(0)?, args.number(1)?);
+            let (nper, pv) = (args.number(2)?, args.number(3)?);
+            let (fv, due) = (number_or(args, 4, 0.0)?, due(args, 5)?);
+            let interest = interest(rate, per, nper, pv, fv, due)?;
+            finite(payment(rate, nper, pv, fv, due)? - interest)
+        })
+        .doc("ppmt(rate, period, nper, pv, [fv], [due]): the principal part of the payment of the period, from 1"),
+    );
+    registry.register(
+        NativeFunction::new("fv", Arity::between(3, 5), |args| {
+            let (rate, nper, pmt) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let (pv, due) = (number_or(args, 3, 0.0)?, due(args, 4)?);
+            finite(future_value(rate, nper, pmt, pv, due))
+        })
+        .doc("fv(rate, nper, pmt, [pv], [due]): the value after the number of periods of payments"),
+    );
+    registry.register(
+        NativeFunction::new("pv", Arity::between(3, 5), |args| {
+            let (rate, nper, pmt) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let (fv, due) = (number_or(args, 3, 0.0)?, due(args, 4)?);
+            finite(present_value(rate, nper, pmt, fv, due))
+        })
+        .doc(
+            "pv(rate, nper, pmt, [fv], [due]): the value now of the number of periods of payments",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("nper", Arity::between(3, 5), |args| {
+            let (rate, pmt, pv) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let (fv, due) = (number_or(args, 3, 0.0)?, due(args, 4)?);
+            if rate == 0.0 {
+                return match pmt {
+                    0.0 => Err(Error::DivideByZero),
+                    _ => finite(-(pv + fv) / pmt),
+                };
+            }
+            let pmt = pmt * if due { 1.0 + rate } else { 1.0 } / rate;
+            finite(((pmt - fv) / (pmt + pv)).ln() / (1.0 + rate).ln())
+        })
+        .doc("nper(rate, pmt, pv, [fv], [due]): the number of periods of payments to pay off the present value"),
+    );
+    registry.register(
+        NativeFunction::new("rate", Arity::between(3, 6), |args| {
+            let (nper, pmt, pv) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let (fv, due) = (number_or(args, 3, 0.0)?, due(args, 4)?);
+            let guess = number_or(args, 5, 0.1)?;
+            finite(solve(guess, |rate| {
+                future_value(rate, nper, pmt, pv, due) - fv
+            })?)
+        })
+        .doc(
+            "rate(nper, pmt, pv, [fv], [due], [guess]): the interest rate per period that the payments pay off \
+             the present value at, found by iterating from the guess",
+        ),
+    );
+
+    registry.register(
+        NativeFunction::new("npv", Arity::at_least(2), |args| {
+            let rate = args.number(0)?;
+            let mut flows = vec![];
+            for i in 1..args.len() {
+                flows.extend(numbers_of(args, i)?);
+            }
+            finite(discounted(rate, &periodic(flows, 1.0)))
+        })
+        .doc(
+            "npv(rate, value, ...): the net present value of cash flows at the end of each period, from the \
+             numbers of the values and of any ranges",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("irr", Arity::between(1, 2), |args| {
+            let flows = periodic(numbers_of(args, 0)?, 0.0);
+            check_returns(&flows)?;
+            let guess = number_or(args, 1, 0.1)?;
+            finite(solve(guess, |rate| discounted(rate, &flows))?)
+        })
+        .doc(
+            "irr(values, [guess]): the rate of return of cash flows one period apart, at which their net present \
+             value is 0, found by iterating from the guess",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("xnpv", Arity::exactly(3), |args| {
+            let rate = args.number(0)?;
+            finite(discounted(rate, &dated(args, 1, 2)?))
+        })
+        .doc(
+            "xnpv(rate, values, dates): the net present value of cash flows on the dates, as of the first, with \
+             an annual rate",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("xirr", Arity::between(2, 3), |args| {
+            let flows = dated(args, 0, 1)?;
+            check_returns(&flows)?;
+            let guess = number_or(args, 2, 0.1)?;
+            finite(solve(guess, |rate| discounted(rate, &flows))?)
+        })
+        .doc(
+            "xirr(values, dates, [guess]): the annual rate of return of cash flows on the dates, found by \
+             iterating from the guess",
+        ),
+    );
+
+    registry.register(
+        NativeFunction::new("sln", Arity::exactly(3), |args| {
+            let (cost, salvage, life) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            if life == 0.0 {
+                return Err(Error::DivideByZero);
+            }
+            finite((cost - salvage) / life)
+        })
+        .doc("sln(cost, salvage, life): the straight line depreciation each period"),
+    );
+    registry.register(
+        NativeFunction::new("ddb", Arity::between(4, 5), |args| {
+            let (cost, salvage, life) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            let period = args.integer(3)?;
+            let factor = number_or(args, 4, 2.0)?;
+            if cost < 0.0 || salvage < 0.0 || life <= 0.0 || factor <= 0.0 {
+                return Err(Error::InvalidNumber);
+            }
+            if period < 1 || period as f64 > life.ceil() {
+                return Err(Error::InvalidNumber);
+            }
+
+            // each period takes the factor's share of the remaining value, but never below the salvage value,
+            // so the value left before the period has shrunk by the same share every period until reaching it
+            let rate = (factor / life).min(1.0);
+            let value = (cost * (1.0 - rate).powf(period as f64 - 1.0)).max(salvage);
+            finite((value * rate).min(value - salvage))
+        })
+        .doc(
+            "ddb(cost, salvage, life, period, [factor]): the declining balance depreciation of the period, from \
+             1, at the factor times the straight line rate, which is 2 unless given",
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cell::ErrorKind, functions::testing::TestSheet};
+
+    #[test]
+    fn financial() {
+        // cash flows in col 0 on the dates in col 1, and yearly ones in col 2
+        let sheet = TestSheet::new(&[
+            &["-10000", "2008-01-01", "-70000"],
+            &["2750", "2008-03-01", "12000"],
+            &["4250", "2008-10-30", "15000"],
+            &["3250", "2009-02-15", "18000"],
+            &["2750", "2009-04-01", "21000"],
+            &["", "", "26000"],
+            &["", "", "total"],
+        ]);
+        let close = |script: &str, expected: f64| {
+            let n = sheet.num(script);
+            assert!(
+                (n - expected).abs() < 1e-6,
+                "{script} is {n}, not {expected}"
+            );
+        };
+
+        close("=pmt(0.08 / 12, 10, 10000)", -1_037.032_089);
+        close("=pmt(0.08 / 12, 10, 10000, 0, 1)", -1_030.164_327);
+        close("=pmt(0, 10, 1000, 500)", -150.0);
+        close("=ipmt(0.1 / 12, 1, 36, 8000)", -66.666_667);
+        close("=ipmt(0.1, 3, 3, 8000)", -292.447_129);
+        close("=ipmt(0.1, 1, 3, 8000, 0, 1)", 0.0);
+        close("=ppmt(0.1 / 12, 1, 24, 2000)", -75.623_186);
+        close(
+            "=ipmt(0.05, 4, 10, 1000) + ppmt(0.05, 4, 10, 1000)",
+            sheet.num("=pmt(0.05, 10, 1000)"),
+        );
+        close("=fv(0.06 / 12, 10, -200, -500, 1)", 2_581.403_374);
+        close("=pv(0.08 / 12, 240, 500)", -59_777.145_851);
+        close("=nper(0.12 / 12, -100, -1000, 10000, 1)", 59.673_866);
+        close("=nper(0, -100, 1000)", 10.0);
+        close("=rate(48, -200, 8000)", 0.007_701_472);
+        close("=rate(10, -100, 1000)", 0.0);
+
+        close("=npv(0.1, -10000, 3000, 4200, 6800)", 1_188.443_412);
+        close(
+            "=npv(0.08, [1..4, 2], -40000)",
+            sheet.num("=npv(0.08, 12000, 15000, 18000, 21000, -40000)"),
+        );
+        close("=irr([0..4, 2])", -0.021_244_848);
+        // the label is skipped
+        close("=irr([0..6, 2])", 0.086_630_948);
+        close("=xnpv(0.09, [0..4, 0], [0..4, 1])", 2_086.647_602);
+        close("=xirr([0..4, 0], [0..4, 1])", 0.373_362_535);
+
+        close("=sln(30000, 7500, 10)", 2250.0);
+        close("=ddb(2400, 300, 3650, 1)", 1.315_068);
+        close("=ddb(2400, 300, 10, 1)", 480.0);
+        close("=ddb(2400, 300, 10, 2, 1.5)", 306.0);
+        close("=ddb(2400, 300, 10, 10)", 22.122_547);
+        // long since down to the salvage value, without going through every period before
+        assert_eq!(
+            sheet.num("=ddb(1000, 500, 1000000000000, 1000000000000)"),
+            0.0
+        );
+
+        // nothing fits, or there's nothing to fit
+        assert_eq!(sheet.err("=rate(10, 100, 100)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=irr([1..4, 2])"), ErrorKind::Num);
+        assert_eq!(
+            sheet.err("=xnpv(0.1, [0..4, 0], [0..3, 1])"),
+            ErrorKind::Num
+        );
+        assert_eq!(sheet.err("=ipmt(0.1, 4, 3, 8000)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=ddb(2400, 300, 10, 11)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=sln(1, 0, 0)"), ErrorKind::DivByZero);
+        assert_eq!(sheet.err("=npv(0.1, 1, 1 / 0)"), ErrorKind::DivByZero);
+        assert_eq!(sheet.err("=pmt('x', 10, 1000)"), ErrorKind::Value);
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{
+    cell::Value,
+    functions::{Arity, FunctionRegistry, NativeFunction},
+};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("isblank", Arity::exactly(1), |args| {
+            Ok(matches!(args.get(0)?, Value::None).into())
+        })
+        .doc("isblank(value): whether the value is blank"),
+    );
+
+    // lazy, so that a failed evaluation is an error rather than failing the call
+    registry.register(
+        NativeFunction::new("iserror", Arity::exactly(1), |args| {
+            Ok(matches!(args.get(0), Err(_) | Ok(Value::Error(_))).into())
+        })
+        .lazy()
+        .doc("iserror(value): whether the value is an error"),
+    );
+}
@@ -0,0 +1,85 @@
+use std::cmp::Ordering;
+
+use crate::{
+    Error,
+    cell::Value,
+    functions::{Arity, FunctionRegistry, NativeFunction},
+};
+
+// Conditionals only evaluate the branch that is taken, so only that branch becomes a dependency
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("false", Arity::exactly(0), |_| Ok(false.into()))
+            .doc("false(): the boolean false"),
+    );
+
+    registry.register(
+        NativeFunction::new("true", Arity::exactly(0), |_| Ok(true.into()))
+            .doc("true(): the boolean true"),
+    );
+
+    registry.register(
+        NativeFunction::new("iferror", Arity::exactly(2), |args| match args.get(0) {
+            Err(_) | Ok(Value::Error(_)) => args.get(1),
+            Ok(val) => Ok(val),
+        })
+        .lazy()
+        .doc("iferror(value, fallback): the value, or the fallback if the value is an error"),
+    );
+
+    // a missing `otherwise` is blank
+    registry.register(
+        NativeFunction::new("if", Arity::between(2, 3), |args| {
+            if args.get(0)?.truthy()? {
+                args.get(1)
+            } else if args.len() == 3 {
+                args.get(2)
+            } else {
+                Ok(Value::None)
+            }
+        })
+        .lazy()
+        .doc("if(cond, then, [otherwise]): `then` if the condition is true, otherwise `otherwise`"),
+    );
+
+    registry.register(
+        NativeFunction::new("ifs", Arity::at_least(2), |args| {
+            if !args.len().is_multiple_of(2) {
+                return Err(Error::InsufficientArgs);
+            }
+
+            for i in (0..args.len()).step_by(2) {
+                if args.get(i)?.truthy()? {
+                    return args.get(i + 1);
+                }
+            }
+
+            Err(Error::NoMatch)
+        })
+        .lazy()
+        .doc("ifs(cond, val, [cond, val, ...]): the value of the first true condition"),
+    );
+
+    // a trailing unpaired case is the default
+    registry.register(
+        NativeFunction::new("switch", Arity::at_least(3), |args| {
+            let val = args.get(0)?;
+            for i in (1..args.len()).step_by(2) {
+                if i + 1 == args.len() {
+                    return args.get(i);
+                }
+                if val.compare(&args.get(i)?)? == Some(Ordering::Equal) {
+                    return args.get(i + 1);
+                }
+            }
+
+            Err(Error::NoMatch)
+        })
+        .lazy()
+        .doc(
+            "switch(expr, case, val, [case, val, ...], [default]): the value of the first case equal to \
+             `expr`, or the default",
+        ),
+    );
+}
@@ -0,0 +1,564 @@
+use std::cmp::Ordering;
+
+use crate::{
+    Error,
+    cell::{CellIdx, CellRange, Value},
+    functions::{Args, Arity, FunctionRegistry, NativeFunction, range_values},
+    sheet::{Sheet, SheetIdx, ValueResult},
+};
+
+// Positions within a range count from 1, as in other spreadsheets, while coordinates within a sheet count
+// from 0, as in `[r, c]`. Keys match as compared by `==`, so text is case-sensitive
+
+/// A reference or range argument, where a reference is a range of just its cell
+fn range(args: &mut Args, i: usize) -> Result<(SheetIdx, CellRange), Error> {
+    match args.get(i)? {
+        Value::Ref { sref, cref } => Ok((sref, CellRange::new(cref, cref))),
+        Value::Range { sref, range } => Ok((sref, range)),
+        Value::Error(kind) => Err(Error::ErrorValue(kind)),
+        _ => Err(Error::OperationUnavailable),
+    }
+}
+
+/// The number of rows and cols of a range
+fn size(range: CellRange) -> Result<(u64, u64), Error> {
+    if range.is_empty() {
+        return Ok((0, 0));
+    }
+    let len = |start: u64, end: u64| (end - start).checked_add(1).ok_or(Error::InvalidNumber);
+    Ok((
+        len(range.start.row, range.end.row)?,
+        len(range.start.col, range.end.col)?,
+    ))
+}
+
+/// A position within `len` cells, from 1, as an offset from 0
+fn position(args: &mut Args, i: usize, len: u64) -> Result<u64, Error> {
+    match args.integer(i)? {
+        ..1 => Err(Error::InvalidNumber),
+        n if n as u64 > len => Err(Error::RefOutOfBounds),
+        n => Ok(n as u64 - 1),
+    }
+}
+
+/// A coordinate within a sheet, from 0
+fn coord(args: &mut Args, i: usize) -> Result<u64, Error> {
+    u64::try_from(args.integer(i)?)
+        .ok()
+        .filter(|coord| *coord <= Sheet::LAST_INDEX)
+        .ok_or(Error::RefOutOfBounds)
+}
+
+/// Moves a coordinate by a number of rows or cols, which can't go before the first one or past the last
+fn shift(coord: u64, by: i64) -> Result<u64, Error> {
+    coord
+        .checked_add_signed(by)
+        .filter(|coord| *coord <= Sheet::LAST_INDEX)
+        .ok_or(Error::RefOutOfBounds)
+}
+
+fn value(args: &Args, sref: SheetIdx, cref: CellIdx) -> Result<Value, Error> {
+    args.spanleaf().get(sref, cref).map(ValueResult::value)
+}
+
+/// The values of the cells of a range, which must be a single row or col
+fn line(args: &Args, sref: SheetIdx, range: CellRange) -> Result<Vec<Value>, Error> {
+    match size(range)? {
+        (1, _) | (_, 1) => range_values(args.spanleaf(), sref, range),
+        _ => Err(Error::OperationUnavailable),
+    }
+}
+
+/// How a lookup matches its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Match {
+    Exact,
+    /// The last value no greater than the key, before the first one that's greater, as the values are
+    /// assumed to be sorted ascending
+    Ascending,
+    /// The last value no smaller than the key, before the first one that's smaller, as the values are
+    /// assumed to be sorted descending
+    Descending,
+    /// The key, or else the largest value smaller than it
+    NextSmaller,
+    /// The key, or else the smallest value larger than it
+    NextLarger,
+}
+impl Match {
+    /// The match type of other spreadsheets' MATCH
+    fn sorted(n: i64) -> Result<Self, Error> {
+        match n {
+            1 => Ok(Match::Ascending),
+            0 => Ok(Match::Exact),
+            -1 => Ok(Match::Descending),
+            _ => Err(Error::InvalidNumber),
+        }
+    }
+    /// The match mode of other spreadsheets' XLOOKUP, without wildcards
+    fn mode(n: i64) -> Result<Self, Error> {
+        match n {
+            0 => Ok(Match::Exact),
+            -1 => Ok(Match::NextSmaller),
+            1 => Ok(Match::NextLarger),
+            _ => Err(Error::InvalidNumber),
+        }
+    }
+}
+
+/// The index of the value that matches the key, or of the first one if several do.
+///
+/// Values that don't compare with the key, i.e. errors, never match
+fn find(key: &Value, vals: &[Value], how: Match) -> Result<usize, Error> {
+    if let Value::Error(kind) = key {
+        return Err(Error::ErrorValue(*kind));
+    }
+    let ordering = |val: &Value| val.compare(key).ok().flatten();
+    let exact = || {
+        vals.iter()
+            .position(|val| ordering(val) == Some(Ordering::Equal))
+    };
+    // of the values on the given side of the key, the nearest to it
+    let nearest = |side: Ordering| {
+        (0..vals.len())
+            .filter(|&i| ordering(&vals[i]) == Some(side))
+            .reduce(|best, i| {
+                let nearer = vals[i].compare(&vals[best]).ok().flatten() == Some(side.reverse());
+                if nearer { i } else { best }
+            })
+    };
+    // the last value before the first that's past the key
+    let sorted = |past: Ordering| {
+        vals.iter()
+            .map(ordering)
+            .take_while(|ord| *ord != Some(past))
+            .enumerate()
+            .filter(|(_, ord)| ord.is_some())
+            .last()
+            .map(|(i, _)| i)
+    };
+
+    match how {
+        Match::Exact => exact(),
+        Match::Ascending => sorted(Ordering::Greater),
+        Match::Descending => sorted(Ordering::Less),
+        Match::NextSmaller => exact().or_else(|| nearest(Ordering::Less)),
+        Match::NextLarger => exact().or_else(|| nearest(Ordering::Greater)),
+    }
+    .ok_or(Error::NoMatch)
+}
+
+/// Looks up the key in the first col of a range, or with `across` in its first row, and returns the value
+/// the given number of cols along, or rows down
+fn lookup(args: &mut Args, across: bool) -> Result<Value, Error> {
+    let key = args.get(0)?;
+    let (sref, range) = range(args, 1)?;
+    let (rows, cols) = size(range)?;
+    let offset = position(args, 2, if across { rows } else { cols })?;
+    let sorted = if args.len() == 4 {
+        args.get(3)?.truthy()?
+    } else {
+        true
+    };
+
+    let keys = if across {
+        CellRange::new(range.start, CellIdx::new(range.start.row, range.end.col))
+    } else {
+        CellRange::new(range.start, CellIdx::new(range.end.row, range.start.col))
+    };
+    let keys = range_values(args.spanleaf(), sref, keys)?;
+    let how = if sorted {
+        Match::Ascending
+    } else {
+        Match::Exact
+    };
+    let i = find(&key, &keys, how)? as u64;
+
+    let cref = if across {
+        CellIdx::new(range.start.row + offset, range.start.col + i)
+    } else {
+        CellIdx::new(range.start.row + i, range.start.col + offset)
+    };
+    value(args, sref, cref)
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("vlookup", Arity::between(3, 4), |args| lookup(args, false)).doc(
+            "vlookup(key, range, col, [sorted]): the value in the col of the range, from 1, of the last row \
+             whose first cell is no greater than the key, as in a sorted range, or unless sorted, of the first \
+             row whose first cell is the key",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("hlookup", Arity::between(3, 4), |args| lookup(args, true)).doc(
+            "hlookup(key, range, row, [sorted]): the value in the row of the range, from 1, of the last col \
+             whose first cell is no greater than the key, as in a sorted range, or unless sorted, of the first \
+             col whose first cell is the key",
+        ),
+    );
+
+    // a result range of more than one row or col gives the matching part of it, as a range
+    registry.register(
+        NativeFunction::new("xlookup", Arity::between(3, 5), |args| {
+            let key = args.get(0)?;
+            let (sref, keys) = range(args, 1)?;
+            let (results_sref, results) = range(args, 2)?;
+            let how = if args.len() == 5 {
+                Match::mode(args.integer(4)?)?
+            } else {
+                Match::Exact
+            };
+
+            let (keys_size, results_size) = (size(keys)?, size(results)?);
+            let vertical = keys_size.1 == 1;
+            let len = if vertical { keys_size.0 } else { keys_size.1 };
+            let result_len = if vertical {
+                results_size.0
+            } else {
+                results_size.1
+            };
+            if result_len != len {
+                return Err(Error::OperationUnavailable);
+            }
+
+            let i = match find(&key, &line(args, sref, keys)?, how) {
+                Ok(i) => i as u64,
+                Err(Error::NoMatch) if args.len() >= 4 => return args.get(3),
+                Err(e) => return Err(e),
+            };
+            let result = if vertical {
+                let row = results.start.row + i;
+                CellRange::new(
+                    CellIdx::new(row, results.start.col),
+                    CellIdx::new(row, results.end.col),
+                )
+            } else {
+                let col = results.start.col + i;
+                CellRange::new(
+                    CellIdx::new(results.start.row, col),
+                    CellIdx::new(results.end.row, col),
+                )
+            };
+            if result.start == result.end {
+                value(args, results_sref, result.start)
+            } else {
+                Ok(Value::Range {
+                    sref: results_sref,
+                    range: result,
+                })
+            }
+        })
+        .doc(
+            "xlookup(key, keys, results, [not_found], [mode]): the part of the results in line with the key \
+             among the keys, a single row or col, or not_found if it isn't there. Mode 0 matches exactly, -1 \
+             falls back to the next smaller key, and 1 to the next larger",
+        ),
+    );
+
+    // a row or col of 0 is all of them, as a range
+    registry.register(
+        NativeFunction::new("index", Arity::between(2, 3), |args| {
+            let (sref, range) = range(args, 0)?;
+            let (rows, cols) = size(range)?;
+            let (row, col) = match args.len() {
+                // a single row or col can be indexed along its length
+                2 if rows == 1 => (1, args.integer(1)?),
+                2 => (args.integer(1)?, 1),
+                _ => (args.integer(1)?, args.integer(2)?),
+            };
+
+            let span = |n: i64, start: u64, len: u64| match n {
+                0 => Ok((start, start + (len - 1))),
+                1.. if n as u64 <= len => Ok((start + (n as u64 - 1), start + (n as u64 - 1))),
+                1.. => Err(Error::RefOutOfBounds),
+                _ => Err(Error::InvalidNumber),
+            };
+            if range.is_empty() {
+                return Err(Error::RefOutOfBounds);
+            }
+            let (row_start, row_end) = span(row, range.start.row, rows)?;
+            let (col_start, col_end) = span(col, range.start.col, cols)?;
+
+            if (row_start, col_start) == (row_end, col_end) {
+                value(args, sref, CellIdx::new(row_start, col_start))
+            } else {
+                Ok(Value::Range {
+                    sref,
+                    range: CellRange::new(
+                        CellIdx::new(row_start, col_start),
+                        CellIdx::new(row_end, col_end),
+                    ),
+                })
+            }
+        })
+        .doc(
+            "index(range, row, [col]): the value at the row and col of the range, from 1, or along a single \
+             row or col",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("match", Arity::between(2, 3), |args| {
+            let key = args.get(0)?;
+            let (sref, range) = range(args, 1)?;
+            let how = if args.len() == 3 {
+                Match::sorted(args.integer(2)?)?
+            } else {
+                Match::Ascending
+            };
+            let i = find(&key, &line(args, sref, range)?, how)?;
+            Ok(Value::Number((i + 1) as f64))
+        })
+        .doc(
+            "match(key, range, [type]): the position of the key in the range, a single row or col, from 1. \
+             Type 0 matches exactly, 1 finds the last value no greater in an ascending range, and -1 the last \
+             value no smaller in a descending one",
+        ),
+    );
+
+    // references that are worked out are dependencies, as if they'd been written out
+    registry.register(
+        NativeFunction::new("offset", Arity::between(3, 5), |args| {
+            let arg = args.get(0)?;
+            let (sref, range) = range(args, 0)?;
+            let (rows, cols) = (args.integer(1)?, args.integer(2)?);
+            let (mut height, mut width) = size(range)?;
+            if args.len() >= 4 {
+                height = u64::try_from(args.integer(3)?).map_err(|_| Error::InvalidNumber)?;
+            }
+            if args.len() == 5 {
+                width = u64::try_from(args.integer(4)?).map_err(|_| Error::InvalidNumber)?;
+            }
+            if height == 0 || width == 0 {
+                return Err(Error::InvalidNumber);
+            }
+
+            let start = CellIdx::new(shift(range.start.row, rows)?, shift(range.start.col, cols)?);
+            let end = |start: u64, len: u64| shift(start, i64::try_from(len - 1).unwrap_or(i64::MAX));
+            let end = CellIdx::new(end(start.row, height)?, end(start.col, width)?);
+            if matches!(arg, Value::Ref { .. }) && start == end {
+                Ok(args.cell_ref(sref, start))
+            } else {
+                Ok(args.range_ref(sref, CellRange::new(start, end)))
+            }
+        })
+        .doc(
+            "offset(ref, rows, cols, [height], [width]): the reference or range moved down and right, \
+             optionally resized",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("indirect", Arity::between(2, 3), |args| {
+            let sref = if args.len() == 3 {
+                let name = args.text(0)?;
+                args.sheet_by_name(&name)?
+            } else {
+                args.sheet()
+            };
+            let (row, col) = (coord(args, args.len() - 2)?, coord(args, args.len() - 1)?);
+            Ok(args.cell_ref(sref, CellIdx::new(row, col)))
+        })
+        .doc(
+            "indirect([sheet], row, col): a reference to the cell, from 0, of the named sheet or else the \
+             current one",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("address", Arity::between(2, 3), |args| {
+            let cell = CellIdx::new(coord(args, 0)?, coord(args, 1)?);
+            Ok(Value::String(if args.len() == 3 {
+                format!("{}!{}", args.text(2)?, cell.a1())
+            } else {
+                cell.a1()
+            }))
+        })
+        .doc("address(row, col, [sheet]): the A1 name of the cell, from 0, i.e. `B7`"),
+    );
+
+    registry.register(
+        NativeFunction::new("row", Arity::between(0, 1), |args| {
+            let row = if args.is_empty() {
+                args.cell().row
+            } else {
+                range(args, 0)?.1.start.row
+            };
+            Ok(Value::Number(row as f64))
+        })
+        .doc("row([ref]): the row of the reference, or the first row of the range, or else of the current cell"),
+    );
+    registry.register(
+        NativeFunction::new("col", Arity::between(0, 1), |args| {
+            let col = if args.is_empty() {
+                args.cell().col
+            } else {
+                range(args, 0)?.1.start.col
+            };
+            Ok(Value::Number(col as f64))
+        })
+        .doc("col([ref]): the col of the reference, or the first col of the range, or else of the current cell"),
+    );
+    registry.register(
+        NativeFunction::new("rows", Arity::exactly(1), |args| {
+            Ok(Value::Number(size(range(args, 0)?.1)?.0 as f64))
+        })
+        .doc("rows(range): the number of rows of the range"),
+    );
+    registry.register(
+        NativeFunction::new("cols", Arity::exactly(1), |args| {
+            Ok(Value::Number(size(range(args, 0)?.1)?.1 as f64))
+        })
+        .doc("cols(range): the number of cols of the range"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind},
+        functions::testing::TestSheet,
+    };
+
+    #[test]
+    fn lookups() {
+        // a table sorted by its first col, and a row of labels above rows of ascending and descending numbers
+        let sheet = TestSheet::new(&[
+            &["1", "one", "10"],
+            &["2", "two", "20"],
+            &["3", "three", "30"],
+            &["5", "five", "50"],
+            &[],
+            &["x", "y", "z"],
+            &["7", "8", "9"],
+            &["9", "8", "7"],
+        ]);
+
+        assert_eq!(sheet.s("=vlookup(3, [0..3, 0..2], 2, false)"), "three");
+        assert_eq!(sheet.num("=vlookup(4, [0..3, 0..2], 3)"), 30.0);
+        assert_eq!(sheet.num("=vlookup(9, [0..3, 0..2], 3, true)"), 50.0);
+        assert_eq!(
+            sheet.err("=vlookup(0, [0..3, 0..2], 2)"),
+            ErrorKind::NotAvailable
+        );
+        assert_eq!(
+            sheet.err("=vlookup(4, [0..3, 0..2], 2, false)"),
+            ErrorKind::NotAvailable
+        );
+        assert_eq!(sheet.err("=vlookup(3, [0..3, 0..2], 4)"), ErrorKind::Ref);
+        assert_eq!(sheet.err("=vlookup(3, [0..3, 0..2], 0)"), ErrorKind::Num);
+        assert_eq!(
+            sheet.err("=vlookup(1 / 0, [0..3, 0..2], 2)"),
+            ErrorKind::DivByZero
+        );
+        assert_eq!(sheet.num("=hlookup('y', [5..6, 0..2], 2, false)"), 8.0);
+
+        assert_eq!(sheet.num("=xlookup('three', [0..3, 1], [0..3, 2])"), 30.0);
+        assert_eq!(sheet.s("=xlookup(4, [0..3, 0], [0..3, 1], 'none')"), "none");
+        assert_eq!(
+            sheet.s("=xlookup(4, [0..3, 0], [0..3, 1], 'none', 1)"),
+            "five"
+        );
+        assert_eq!(
+            sheet.s("=xlookup(4, [0..3, 0], [0..3, 1], 'none', -1)"),
+            "three"
+        );
+        assert_eq!(sheet.num("=xlookup('z', [5, 0..2], [6, 0..2])"), 9.0);
+        assert_eq!(
+            sheet.err("=xlookup(4, [0..3, 0], [0..3, 1])"),
+            ErrorKind::NotAvailable
+        );
+        // the rest of the matching row, as a range
+        assert_eq!(
+            sheet.num("=index(xlookup(2, [0..3, 0], [0..3, 1..2]), 2)"),
+            20.0
+        );
+        assert_eq!(
+            sheet.err("=xlookup(2, [0..3, 0], [0..2, 1])"),
+            ErrorKind::Value
+        );
+
+        assert_eq!(sheet.num("=index([0..3, 0..2], 2, 3)"), 20.0);
+        assert_eq!(sheet.s("=index([0..3, 1], 3)"), "three");
+        assert_eq!(sheet.s("=index([5, 0..2], 2)"), "y");
+        assert_eq!(sheet.num("=sum(index([0..3, 0..2], 0, 3))"), 110.0);
+        assert_eq!(sheet.err("=index([0..3, 0..2], 5, 1)"), ErrorKind::Ref);
+
+        assert_eq!(sheet.num("=match(3, [0..3, 0], 0)"), 3.0);
+        assert_eq!(sheet.num("=match(4, [0..3, 0])"), 3.0);
+        assert_eq!(sheet.num("=match(7.5, [7, 0..2], -1)"), 2.0);
+        assert_eq!(sheet.num("=match('y', [5, 0..2], 0)"), 2.0);
+        assert_eq!(
+            sheet.err("=match(4, [0..3, 0], 0)"),
+            ErrorKind::NotAvailable
+        );
+        assert_eq!(sheet.err("=match(1, [0..3, 0..2])"), ErrorKind::Value);
+        assert_eq!(sheet.err("=match(1, [0..4294967295, 0])"), ErrorKind::Num);
+        assert_eq!(
+            sheet.err("=vlookup(1, [0..4294967295, 0..1], 2)"),
+            ErrorKind::Num
+        );
+
+        assert_eq!(sheet.s("=*offset(&[1, 0], 1, 1)"), "three");
+        assert_eq!(sheet.num("=sum(offset([0..1, 2], 2, 0))"), 80.0);
+        assert_eq!(sheet.num("=rows(offset(&[0, 0], 0, 0, 3, 2))"), 3.0);
+        assert_eq!(sheet.err("=offset(&[0, 0], -1, 0)"), ErrorKind::Ref);
+        assert_eq!(sheet.err("=offset(&[0, 0], 0, 0, 0)"), ErrorKind::Num);
+        assert_eq!(
+            sheet.err("=offset(&[4294967295, 0], 0, 0, 2)"),
+            ErrorKind::Ref
+        );
+        assert_eq!(sheet.err("=offset(&[0, 0], 5000000000, 0)"), ErrorKind::Ref);
+        assert_eq!(
+            sheet.err("=rows(offset(&[0, 0], 0, 0, 1e14, 1))"),
+            ErrorKind::Ref
+        );
+
+        assert_eq!(sheet.num("=*indirect('Sheet1', 2, 2)"), 30.0);
+        assert_eq!(sheet.s("=*indirect(0, 1)"), "one");
+        assert_eq!(sheet.err("=indirect('Missing', 0, 0)"), ErrorKind::Ref);
+        assert_eq!(sheet.err("=indirect(-1, 0)"), ErrorKind::Ref);
+        assert_eq!(
+            sheet.err("=indirect('Sheet1', 5000000000, 0)"),
+            ErrorKind::Ref
+        );
+
+        assert_eq!(sheet.s("=address(6, 27)"), "AB7");
+        assert_eq!(sheet.s("=address(0, 0, 'Sheet2')"), "Sheet2!A1");
+        assert_eq!(sheet.s("=address(4294967295, 0)"), "A4294967296");
+        assert_eq!(sheet.err("=address(0, 4294967296)"), ErrorKind::Ref);
+
+        assert_eq!(sheet.num("=row(&[4, 7])"), 4.0);
+        assert_eq!(sheet.num("=col(&[4, 7])"), 7.0);
+        assert_eq!(sheet.num("=row([2..3, 1..2])"), 2.0);
+        assert_eq!(sheet.num("=col()"), 10.0);
+        assert_eq!(sheet.num("=rows([0..3, 0..2])"), 4.0);
+        assert_eq!(sheet.num("=cols([0..3, 0..2])"), 3.0);
+        assert_eq!(sheet.num("=cols(&[1, 1])"), 1.0);
+        // a plain reference is the cell's value
+        assert_eq!(sheet.err("=row([0, 0])"), ErrorKind::Value);
+        assert_eq!(sheet.num("=rows([0..4294967295, 0])"), 4294967296.0);
+        assert_eq!(sheet.err("=cols([0, 0..1e30])"), ErrorKind::Ref);
+    }
+
+    #[test]
+    fn dynamic_dependencies() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let get = |sl: &Spanleaf, row, col| sl.get(s0, CellIdx::new(row, col)).unwrap().value();
+
+        sl.insert(s0, CellIdx::new(0, 0), "=*offset(&[0, 1], 0, 1) + 1")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), "=*indirect('Sheet1', 1, 2) + 2")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(2, 0), "=sum(offset([0..1, 1], 0, 1))")
+            .unwrap();
+        assert_eq!(get(&sl, 0, 0), 1.into());
+        assert_eq!(get(&sl, 1, 0), 2.into());
+
+        // the cells that were only worked out are still dependencies
+        sl.insert(s0, CellIdx::new(0, 2), 5).unwrap();
+        sl.insert(s0, CellIdx::new(1, 2), 7).unwrap();
+        assert_eq!(get(&sl, 0, 0), 6.into());
+        assert_eq!(get(&sl, 1, 0), 9.into());
+        assert_eq!(get(&sl, 2, 0), 12.into());
+    }
+}
@@ -0,0 +1,389 @@
+use std::ops;
+
+use crate::{
+    Error,
+    functions::{Arity, FunctionRegistry, NativeFunction, finite},
+};
+
+/// Registers a function of a single number
+fn unary(registry: &mut FunctionRegistry, name: &str, doc: &str, f: fn(f64) -> f64) {
+    registry.register(
+        NativeFunction::new(name, Arity::exactly(1), move |args| {
+            finite(f(args.number(0)?))
+        })
+        .doc(doc),
+    );
+}
+
+/// Registers a rounding function, with an optional number of decimal digits to round to
+fn rounding(registry: &mut FunctionRegistry, name: &str, doc: &str, f: fn(f64) -> f64) {
+    registry.register(
+        NativeFunction::new(name, Arity::between(1, 2), move |args| {
+            let x = args.number(0)?;
+            let digits = if args.len() == 2 {
+                args.number(1)?
+            } else {
+                0.0
+            };
+            finite(round_to(x, digits, f)?)
+        })
+        .doc(doc),
+    );
+}
+
+/// Rounds to a number of decimal digits, which when negative rounds to tens, hundreds and so on
+pub(super) fn round_to(x: f64, digits: f64, f: fn(f64) -> f64) -> Result<f64, Error> {
+    // beyond this many digits every finite number rounds to itself, or to 0
+    let digits = digits.clamp(-400.0, 400.0).trunc() as i32;
+    // shifting the decimal point in the text avoids binary error, i.e. 2.675 * 100 being 267.4999...
+    let shifted = format!("{x}e{digits}")
+        .parse::<f64>()
+        .map_err(|_| Error::InvalidNumber)?;
+    if !shifted.is_finite() {
+        // more digits than can be represented, so there's nothing to round
+        return Ok(x);
+    }
+    format!("{}e{}", f(shifted), -digits)
+        .parse::<f64>()
+        .map_err(|_| Error::InvalidNumber)
+}
+
+/// The non-negative integers of a gcd or lcm
+fn integers(nums: Vec<f64>) -> Result<Vec<u64>, Error> {
+    nums.into_iter()
+        .map(|n| {
+            if n < 0.0 || n >= 2f64.powi(53) {
+                Err(Error::InvalidNumber)
+            } else {
+                Ok(n.trunc() as u64)
+            }
+        })
+        .collect()
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// A function of a single number, with its name and doc
+type Unary = (&'static str, &'static str, fn(f64) -> f64);
+
+const UNARY: [Unary; 13] = [
+    ("abs", "abs(x): the absolute value of x", f64::abs),
+    ("sqrt", "sqrt(x): the square root of x", f64::sqrt),
+    ("exp", "exp(x): e raised to x", f64::exp),
+    ("ln", "ln(x): the natural logarithm of x", f64::ln),
+    ("log10", "log10(x): the base 10 logarithm of x", f64::log10),
+    // angles are in radians
+    ("sin", "sin(angle): the sine of the angle", f64::sin),
+    ("cos", "cos(angle): the cosine of the angle", f64::cos),
+    ("tan", "tan(angle): the tangent of the angle", f64::tan),
+    ("asin", "asin(x): the angle whose sine is x", f64::asin),
+    ("acos", "acos(x): the angle whose cosine is x", f64::acos),
+    ("atan", "atan(x): the angle whose tangent is x", f64::atan),
+    (
+        "degrees",
+        "degrees(angle): the angle in degrees",
+        f64::to_degrees,
+    ),
+    (
+        "radians",
+        "radians(degrees): the angle in radians",
+        f64::to_radians,
+    ),
+];
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    for (name, doc, f) in UNARY {
+        unary(registry, name, doc, f);
+    }
+
+    registry.register(
+        NativeFunction::new("sign", Arity::exactly(1), |args| {
+            let x = args.number(0)?;
+            Ok(if x == 0.0 { 0.0 } else { x.signum() }.into())
+        })
+        .doc("sign(x): 1 if x is positive, -1 if negative, or 0"),
+    );
+
+    rounding(
+        registry,
+        "round",
+        "round(x, [digits]): x rounded to the nearest number with the digits after the decimal point, \
+         with halves away from zero",
+        f64::round,
+    );
+    rounding(
+        registry,
+        "floor",
+        "floor(x, [digits]): x rounded down, towards negative infinity, to the digits after the decimal point",
+        f64::floor,
+    );
+    rounding(
+        registry,
+        "ceil",
+        "ceil(x, [digits]): x rounded up, towards infinity, to the digits after the decimal point",
+        f64::ceil,
+    );
+
+    // the result has the sign of the divisor, as in other spreadsheets
+    registry.register(
+        NativeFunction::new("mod", Arity::exactly(2), |args| {
+            let (x, y) = (args.number(0)?, args.number(1)?);
+            if y == 0.0 {
+                return Err(Error::DivideByZero);
+            }
+            finite(x - y * (x / y).floor())
+        })
+        .doc("mod(x, divisor): the remainder of x divided by the divisor, with the sign of the divisor"),
+    );
+
+    registry.register(
+        NativeFunction::new("power", Arity::exactly(2), |args| {
+            finite(args.number(0)?.powf(args.number(1)?))
+        })
+        .doc("power(base, exponent): the base raised to the exponent"),
+    );
+
+    registry.register(
+        NativeFunction::new("log", Arity::between(1, 2), |args| {
+            let x = args.number(0)?;
+            let base = if args.len() == 2 {
+                args.number(1)?
+            } else {
+                10.0
+            };
+            if base <= 0.0 || base == 1.0 {
+                return Err(Error::InvalidNumber);
+            }
+            finite(x.log(base))
+        })
+        .doc("log(x, [base]): the logarithm of x, in base 10 by default"),
+    );
+
+    // x then y, as in other spreadsheets
+    registry.register(
+        NativeFunction::new("atan2", Arity::exactly(2), |args| {
+            let (x, y) = (args.number(0)?, args.number(1)?);
+            if x == 0.0 && y == 0.0 {
+                return Err(Error::DivideByZero);
+            }
+            finite(y.atan2(x))
+        })
+        .doc("atan2(x, y): the angle from the x axis to the point (x, y), in radians"),
+    );
+
+    registry.register(
+        NativeFunction::new("pi", Arity::exactly(0), |_| Ok(std::f64::consts::PI.into()))
+            .doc("pi(): the ratio of a circle's circumference to its diameter"),
+    );
+
+    // with nothing to compare, these are 0 as in other spreadsheets
+    registry.register(
+        NativeFunction::new("min", Arity::at_least(1), |args| {
+            Ok(args
+                .numbers()?
+                .into_iter()
+                .reduce(f64::min)
+                .unwrap_or(0.0)
+                .into())
+        })
+        .doc("min(x, ...): the smallest of the numbers, and of the numbers of any ranges"),
+    );
+    registry.register(
+        NativeFunction::new("max", Arity::at_least(1), |args| {
+            Ok(args
+                .numbers()?
+                .into_iter()
+                .reduce(f64::max)
+                .unwrap_or(0.0)
+                .into())
+        })
+        .doc("max(x, ...): the largest of the numbers, and of the numbers of any ranges"),
+    );
+    registry.register(
+        NativeFunction::new("product", Arity::at_least(1), |args| {
+            finite(args.numbers()?.into_iter().reduce(ops::Mul::mul).unwrap_or(0.0))
+        })
+        .doc("product(x, ...): the numbers multiplied together, along with the numbers of any ranges"),
+    );
+
+    registry.register(
+        NativeFunction::new("gcd", Arity::at_least(1), |args| {
+            let gcd = integers(args.numbers()?)?.into_iter().fold(0, gcd);
+            Ok((gcd as f64).into())
+        })
+        .doc("gcd(x, ...): the greatest common divisor of the non-negative integers"),
+    );
+    registry.register(
+        NativeFunction::new("lcm", Arity::at_least(1), |args| {
+            let lcm = integers(args.numbers()?)?
+                .into_iter()
+                .try_fold(1u64, |lcm, n| match n {
+                    0 => Some(0),
+                    n => (lcm / gcd(lcm, n).max(1)).checked_mul(n),
+                })
+                .ok_or(Error::InvalidNumber)?;
+            Ok((lcm as f64).into())
+        })
+        .doc("lcm(x, ...): the least common multiple of the non-negative integers"),
+    );
+
+    registry.register(
+        NativeFunction::new("factorial", Arity::exactly(1), |args| {
+            let n = args.number(0)?;
+            if n < 0.0 {
+                return Err(Error::InvalidNumber);
+            }
+            // anything past 170! isn't finite, so this is a short loop
+            let mut product = 1f64;
+            let mut i = 2.0;
+            while i <= n.trunc() && product.is_finite() {
+                product *= i;
+                i += 1.0;
+            }
+            finite(product)
+        })
+        .doc("factorial(n): the product of the integers from 1 to n"),
+    );
+
+    registry.register(
+        NativeFunction::new("clamp", Arity::exactly(3), |args| {
+            let (x, min, max) = (args.number(0)?, args.number(1)?, args.number(2)?);
+            if min > max {
+                return Err(Error::InvalidNumber);
+            }
+            Ok(x.clamp(min, max).into())
+        })
+        .doc("clamp(x, min, max): x, limited to between min and max"),
+    );
+
+    registry.register(
+        NativeFunction::new("sum", Arity::at_least(0), |args| {
+            finite(args.numbers()?.into_iter().fold(0.0, ops::Add::add))
+        })
+        .doc("sum(x, ...): the total of the numbers, and of the numbers of any ranges"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cell::ErrorKind, functions::testing::TestSheet};
+
+    /// Checks a result that's only close to exact, after floating point error
+    fn close(sheet: &TestSheet, script: &str, expected: f64) {
+        let n = sheet.num(script);
+        assert!(
+            (n - expected).abs() < 1e-12,
+            "{script} is {n}, not {expected}"
+        );
+    }
+
+    #[test]
+    fn rounding() {
+        let sheet = TestSheet::new(&[&["", "=1 / 0"]]);
+
+        assert_eq!(sheet.num("=abs(-2.5)"), 2.5);
+        assert_eq!(sheet.num("=sign(-0.1)"), -1.0);
+        assert_eq!(sheet.num("=sign(0)"), 0.0);
+
+        // halves round away from zero
+        assert_eq!(sheet.num("=round(2.5)"), 3.0);
+        assert_eq!(sheet.num("=round(-2.5)"), -3.0);
+        assert_eq!(sheet.num("=round(2.675, 2)"), 2.68);
+        assert_eq!(sheet.num("=round(1234.5, -2)"), 1200.0);
+        assert_eq!(sheet.num("=floor(-1.25, 1)"), -1.3);
+        assert_eq!(sheet.num("=ceil(1.21, 1)"), 1.3);
+        // any number of digits is fine
+        assert_eq!(sheet.num("=round(0.1, 400)"), 0.1);
+        assert_eq!(sheet.num("=round(5, -1e300)"), 0.0);
+        assert_eq!(sheet.num("=round(5, 1e300)"), 5.0);
+        assert_eq!(sheet.num("=clamp(15, 0, 10)"), 10.0);
+        assert_eq!(sheet.err("=clamp(1, 2, 0)"), ErrorKind::Num);
+
+        // blanks are 0, strings are the wrong type, and errors propagate
+        assert_eq!(sheet.num("=abs([0, 0])"), 0.0);
+        assert_eq!(sheet.err("=abs('x')"), ErrorKind::Value);
+        assert_eq!(sheet.err("=round([0, 1])"), ErrorKind::DivByZero);
+        assert_eq!(sheet.err("=round()"), ErrorKind::Args);
+    }
+
+    #[test]
+    fn powers_and_logs() {
+        let sheet = TestSheet::new(&[]);
+
+        assert_eq!(sheet.num("=mod(7, 3)"), 1.0);
+        // the result takes the sign of the divisor
+        assert_eq!(sheet.num("=mod(-7, 3)"), 2.0);
+        assert_eq!(sheet.num("=mod(7, -3)"), -2.0);
+        assert_eq!(sheet.err("=mod(1, 0)"), ErrorKind::DivByZero);
+
+        assert_eq!(sheet.num("=power(2, 10)"), 1024.0);
+        assert_eq!(sheet.num("=sqrt(16)"), 4.0);
+        assert_eq!(sheet.err("=sqrt(-1)"), ErrorKind::Num);
+        close(&sheet, "=ln(exp(2))", 2.0);
+        assert_eq!(sheet.err("=ln(0)"), ErrorKind::Num);
+        close(&sheet, "=log(1000)", 3.0);
+        close(&sheet, "=log(8, 2)", 3.0);
+        close(&sheet, "=log10(0.01)", -2.0);
+        assert_eq!(sheet.err("=log(8, 1)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=log(8, 0)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=log(8, -2)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=log(1, 1)"), ErrorKind::Num);
+    }
+
+    #[test]
+    fn trigonometry() {
+        let sheet = TestSheet::new(&[]);
+
+        close(&sheet, "=sin(pi() / 2)", 1.0);
+        close(&sheet, "=cos(pi())", -1.0);
+        close(&sheet, "=tan(pi() / 4)", 1.0);
+        close(&sheet, "=degrees(asin(1))", 90.0);
+        close(&sheet, "=degrees(acos(0))", 90.0);
+        close(&sheet, "=atan(1)", 45f64.to_radians());
+        // takes x then y, as in other spreadsheets
+        close(&sheet, "=atan2(0, 1)", 90f64.to_radians());
+        close(&sheet, "=atan2(-1, 0)", 180f64.to_radians());
+        close(&sheet, "=radians(180)", std::f64::consts::PI);
+        assert_eq!(sheet.err("=asin(2)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=atan2(0, 0)"), ErrorKind::DivByZero);
+    }
+
+    #[test]
+    fn aggregates() {
+        // a column of numbers, with a blank and a label, next to an error
+        let sheet = TestSheet::new(&[&["12", "=1 / 0"], &[""], &["'label'"], &["-3"], &["18"]]);
+
+        // ranges skip blanks and labels
+        assert_eq!(sheet.num("=sum([0..4, 0])"), 27.0);
+        assert_eq!(sheet.num("=sum([5..9, 0])"), 0.0);
+        assert_eq!(sheet.num("=sum()"), 0.0);
+        assert_eq!(sheet.num("=min([0..4, 0])"), -3.0);
+        assert_eq!(sheet.num("=max([0..4, 0], 20)"), 20.0);
+        assert_eq!(sheet.num("=product([0..4, 0])"), -648.0);
+        assert_eq!(sheet.num("=min([5..9, 0])"), 0.0);
+        // but not an argument that's a string
+        assert_eq!(sheet.err("=sum('1', '2')"), ErrorKind::Value);
+        assert_eq!(sheet.err("=min(1, 'x')"), ErrorKind::Value);
+        assert_eq!(sheet.err("=max([0..0, 0..1])"), ErrorKind::DivByZero);
+        // a range too large to hold all at once
+        assert_eq!(sheet.err("=max([0..4294967295, 0])"), ErrorKind::Num);
+
+        assert_eq!(sheet.num("=gcd([0..2, 0], [4, 0], 0)"), 6.0);
+        assert_eq!(sheet.err("=gcd([0..4, 0])"), ErrorKind::Num);
+        assert_eq!(sheet.err("=gcd(-2)"), ErrorKind::Num);
+        assert_eq!(sheet.num("=lcm(4, 6, 10)"), 60.0);
+        assert_eq!(sheet.num("=lcm(4, 0)"), 0.0);
+
+        // truncated to a whole number first
+        assert_eq!(sheet.num("=factorial(5)"), 120.0);
+        assert_eq!(sheet.num("=factorial(0)"), 1.0);
+        assert_eq!(sheet.num("=factorial(3.9)"), 6.0);
+        assert_eq!(sheet.err("=factorial(171)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=factorial(-1)"), ErrorKind::Num);
+    }
+}
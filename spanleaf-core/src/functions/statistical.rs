@@ -0,0 +1,391 @@
+use crate::{
+    Error,
+    cell::Value,
+    functions::{Args, Arity, FunctionRegistry, NativeFunction, finite},
+};
+
+/// The numbers of a single argument, skipping anything else, as for a range passed to [Args::numbers]
+pub(super) fn numbers_of(args: &mut Args, i: usize) -> Result<Vec<f64>, Error> {
+    args.cells(i)?
+        .into_iter()
+        .filter_map(|val| match val {
+            Value::Number(n) => Some(Ok(n)),
+            Value::Error(kind) => Some(Err(Error::ErrorValue(kind))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Counts the values of every argument, with ranges expanded, that match
+fn count(args: &mut Args, matches: fn(&Value) -> bool) -> Result<Value, Error> {
+    let mut count = 0;
+    for i in 0..args.len() {
+        count += args.cells(i)?.iter().filter(|val| matches(val)).count();
+    }
+    Ok(Value::Number(count as f64))
+}
+
+fn divide(dividend: f64, divisor: f64) -> Result<f64, Error> {
+    if divisor == 0.0 {
+        Err(Error::DivideByZero)
+    } else {
+        Ok(dividend / divisor)
+    }
+}
+
+fn mean(nums: &[f64]) -> Result<f64, Error> {
+    divide(nums.iter().sum(), nums.len() as f64)
+}
+
+/// The variance of a sample of a population, or of the whole population
+fn variance(nums: &[f64], sample: bool) -> Result<f64, Error> {
+    let mean = mean(nums)?;
+    let squares = nums.iter().map(|n| (n - mean).powi(2)).sum();
+    divide(squares, (nums.len() - usize::from(sample)) as f64)
+}
+
+/// The inclusive percentile, interpolating between the closest ranks
+fn percentile(mut nums: Vec<f64>, k: f64) -> Result<Value, Error> {
+    if nums.is_empty() || !(0.0..=1.0).contains(&k) {
+        return Err(Error::InvalidNumber);
+    }
+    nums.sort_by(f64::total_cmp);
+
+    let pos = k * (nums.len() - 1) as f64;
+    let lower = nums[pos.floor() as usize];
+    let upper = nums[pos.ceil() as usize];
+    finite(lower + (upper - lower) * pos.fract())
+}
+
+/// The sums behind correlation and linear regression, over the pairs of two equally sized arguments where
+/// both are numbers
+struct Paired {
+    len: usize,
+    mean_x: f64,
+    mean_y: f64,
+    /// The sums of the squared deviations and the products of the deviations
+    sxx: f64,
+    syy: f64,
+    sxy: f64,
+}
+impl Paired {
+    fn new(args: &mut Args, xs: usize, ys: usize) -> Result<Self, Error> {
+        let (xs, ys) = (args.cells(xs)?, args.cells(ys)?);
+        if xs.len() != ys.len() {
+            return Err(Error::NoMatch);
+        }
+
+        let mut pairs = vec![];
+        for pair in xs.into_iter().zip(ys) {
+            match pair {
+                (Value::Error(kind), _) | (_, Value::Error(kind)) => {
+                    return Err(Error::ErrorValue(kind));
+                }
+                (Value::Number(x), Value::Number(y)) => pairs.push((x, y)),
+                _ => {}
+            }
+        }
+
+        let (xs, ys): (Vec<_>, Vec<_>) = pairs.iter().copied().unzip();
+        let (mean_x, mean_y) = (mean(&xs)?, mean(&ys)?);
+        let sum =
+            |f: fn(f64, f64) -> f64| pairs.iter().map(|&(x, y)| f(x - mean_x, y - mean_y)).sum();
+        Ok(Self {
+            len: pairs.len(),
+            mean_x,
+            mean_y,
+            sxx: sum(|dx, _| dx * dx),
+            syy: sum(|_, dy| dy * dy),
+            sxy: sum(|dx, dy| dx * dy),
+        })
+    }
+    fn covariance(&self, sample: bool) -> Result<f64, Error> {
+        divide(self.sxy, (self.len - usize::from(sample)) as f64)
+    }
+    fn correlation(&self) -> Result<f64, Error> {
+        divide(self.sxy, (self.sxx * self.syy).sqrt())
+    }
+    fn slope(&self) -> Result<f64, Error> {
+        divide(self.sxy, self.sxx)
+    }
+    fn intercept(&self) -> Result<f64, Error> {
+        Ok(self.mean_y - self.slope()? * self.mean_x)
+    }
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("average", Arity::at_least(1), |args| {
+            finite(mean(&args.numbers()?)?)
+        })
+        .doc("average(x, ...): the mean of the numbers, and of the numbers of any ranges"),
+    );
+
+    registry.register(
+        NativeFunction::new("count", Arity::at_least(1), |args| {
+            count(args, |val| matches!(val, Value::Number(_)))
+        })
+        .doc("count(value, ...): how many of the values, and of the cells of any ranges, are numbers"),
+    );
+    registry.register(
+        NativeFunction::new("counta", Arity::at_least(1), |args| {
+            count(args, |val| !matches!(val, Value::None))
+        })
+        .doc("counta(value, ...): how many of the values, and of the cells of any ranges, aren't blank"),
+    );
+    registry.register(
+        NativeFunction::new("countblank", Arity::at_least(1), |args| {
+            count(args, |val| matches!(val, Value::None))
+        })
+        .doc("countblank(value, ...): how many of the values, and of the cells of any ranges, are blank"),
+    );
+
+    registry.register(
+        NativeFunction::new("median", Arity::at_least(1), |args| {
+            percentile(args.numbers()?, 0.5)
+        })
+        .doc("median(x, ...): the middle of the numbers, or the mean of the middle two"),
+    );
+
+    // the first to appear wins a tie, and nothing appearing twice is no mode at all
+    registry.register(
+        NativeFunction::new("mode", Arity::at_least(1), |args| {
+            let mut nums = args.numbers()?.into_iter().enumerate().collect::<Vec<_>>();
+            nums.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+            // the count, first appearance and value of the most common so far
+            let mut mode: Option<(usize, usize, f64)> = None;
+            for run in nums.chunk_by(|(_, a), (_, b)| a == b) {
+                let first = run.iter().map(|(i, _)| *i).min().unwrap_or_default();
+                let beats = |(most, earliest, _): (usize, usize, f64)| {
+                    run.len() > most || run.len() == most && first < earliest
+                };
+                if run.len() > 1 && mode.is_none_or(beats) {
+                    mode = Some((run.len(), first, run[0].1));
+                }
+            }
+            mode.map(|(_, _, n)| Value::Number(n)).ok_or(Error::NoMatch)
+        })
+        .doc("mode(x, ...): the most common of the numbers"),
+    );
+
+    registry.register(
+        NativeFunction::new("var", Arity::at_least(1), |args| {
+            finite(variance(&args.numbers()?, true)?)
+        })
+        .doc("var(x, ...): the variance of the numbers, as a sample of a population"),
+    );
+    registry.register(
+        NativeFunction::new("varp", Arity::at_least(1), |args| {
+            finite(variance(&args.numbers()?, false)?)
+        })
+        .doc("varp(x, ...): the variance of the numbers, as the whole population"),
+    );
+    registry.register(
+        NativeFunction::new("stdev", Arity::at_least(1), |args| {
+            finite(variance(&args.numbers()?, true)?.sqrt())
+        })
+        .doc("stdev(x, ...): the standard deviation of the numbers, as a sample of a population"),
+    );
+    registry.register(
+        NativeFunction::new("stdevp", Arity::at_least(1), |args| {
+            finite(variance(&args.numbers()?, false)?.sqrt())
+        })
+        .doc("stdevp(x, ...): the standard deviation of the numbers, as the whole population"),
+    );
+
+    registry.register(
+        NativeFunction::new("percentile", Arity::exactly(2), |args| {
+            let k = args.number(1)?;
+            percentile(numbers_of(args, 0)?, k)
+        })
+        .doc("percentile(range, k): the value k of the way through the numbers, from 0 to 1"),
+    );
+    registry.register(
+        NativeFunction::new("quartile", Arity::exactly(2), |args| {
+            let quartile = args.number(1)?.trunc();
+            if !(0.0..=4.0).contains(&quartile) {
+                return Err(Error::InvalidNumber);
+            }
+            percentile(numbers_of(args, 0)?, quartile / 4.0)
+        })
+        .doc("quartile(range, quartile): the quartile of the numbers, from 0 for the minimum to 4 for the maximum"),
+    );
+
+    // tied numbers share the highest of their ranks
+    registry.register(
+        NativeFunction::new("rank", Arity::between(2, 3), |args| {
+            let x = args.number(0)?;
+            let nums = numbers_of(args, 1)?;
+            let ascending = args.len() == 3 && args.number(2)? != 0.0;
+            if !nums.contains(&x) {
+                return Err(Error::NoMatch);
+            }
+
+            let before = nums
+                .iter()
+                .filter(|&&n| if ascending { n < x } else { n > x })
+                .count();
+            Ok(Value::Number((before + 1) as f64))
+        })
+        .doc("rank(x, range, [ascending]): the position of x in the numbers, from largest unless ascending"),
+    );
+
+    // pairs where either value isn't a number are skipped
+    registry.register(
+        NativeFunction::new("correl", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 0, 1)?.correlation()?)
+        })
+        .doc("correl(xs, ys): the correlation coefficient of the pairs of numbers"),
+    );
+    registry.register(
+        NativeFunction::new("covar", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 0, 1)?.covariance(true)?)
+        })
+        .doc("covar(xs, ys): the covariance of the pairs of numbers, as a sample of a population"),
+    );
+    registry.register(
+        NativeFunction::new("covarp", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 0, 1)?.covariance(false)?)
+        })
+        .doc("covarp(xs, ys): the covariance of the pairs of numbers, as the whole population"),
+    );
+
+    // linear regression takes the ys first, as in other spreadsheets
+    registry.register(
+        NativeFunction::new("slope", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 1, 0)?.slope()?)
+        })
+        .doc("slope(ys, xs): the slope of the line of best fit through the pairs of numbers"),
+    );
+    registry.register(
+        NativeFunction::new("intercept", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 1, 0)?.intercept()?)
+        })
+        .doc("intercept(ys, xs): where the line of best fit through the pairs of numbers crosses the y axis"),
+    );
+    registry.register(
+        NativeFunction::new("rsq", Arity::exactly(2), |args| {
+            finite(Paired::new(args, 1, 0)?.correlation()?.powi(2))
+        })
+        .doc("rsq(ys, xs): the square of the correlation coefficient of the pairs of numbers"),
+    );
+    registry.register(
+        NativeFunction::new("forecast", Arity::exactly(3), |args| {
+            let x = args.number(0)?;
+            let paired = Paired::new(args, 2, 1)?;
+            finite(paired.intercept()? + paired.slope()? * x)
+        })
+        .doc(
+            "forecast(x, ys, xs): the y at x on the line of best fit through the pairs of numbers",
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cell::ErrorKind, functions::testing::TestSheet};
+
+    /// xs in col 0 and ys = 2x + 1 in col 1, with a blank row and a label, next to an error
+    fn pairs() -> TestSheet {
+        TestSheet::new(&[
+            &["1", "3", "=1 / 0"],
+            &["2", "5"],
+            &[],
+            &["4", "9"],
+            &["4", "9"],
+            &["9", "19"],
+            &["'label'"],
+        ])
+    }
+
+    /// Checks a result that's only close to exact, after floating point error
+    fn close(sheet: &TestSheet, script: &str, expected: f64) {
+        let n = sheet.num(script);
+        assert!(
+            (n - expected).abs() < 1e-9,
+            "{script} is {n}, not {expected}"
+        );
+    }
+
+    #[test]
+    fn averages_and_counts() {
+        let sheet = pairs();
+
+        // blanks and labels don't count towards the average
+        assert_eq!(sheet.num("=average([0..6, 0])"), 4.0);
+        assert_eq!(sheet.num("=average(2, [9, 9], 4)"), 3.0);
+        assert_eq!(sheet.err("=average([9, 9])"), ErrorKind::DivByZero);
+        assert_eq!(sheet.err("=average([0..1, 0..2])"), ErrorKind::DivByZero);
+
+        assert_eq!(sheet.num("=count([0..6, 0], 'x', 1)"), 6.0);
+        assert_eq!(sheet.num("=counta([0..6, 0])"), 6.0);
+        assert_eq!(sheet.num("=countblank([0..6, 0])"), 1.0);
+        // an error isn't a number
+        assert_eq!(sheet.num("=count([0..1, 2])"), 0.0);
+    }
+
+    #[test]
+    fn ordering() {
+        let sheet = pairs();
+
+        assert_eq!(sheet.num("=median([0..6, 0])"), 4.0);
+        assert_eq!(sheet.num("=median(5, 1, 3)"), 3.0);
+        assert_eq!(sheet.err("=median()"), ErrorKind::Args);
+        assert_eq!(sheet.num("=mode([0..6, 0])"), 4.0);
+        // the first to appear wins a tie
+        assert_eq!(sheet.num("=mode(3, 1, 1, 3, 2, 2, 2, 3)"), 3.0);
+        assert_eq!(sheet.err("=mode(1, 2, 3)"), ErrorKind::NotAvailable);
+
+        // interpolated between the closest values
+        assert_eq!(sheet.num("=percentile([0..6, 0], 0.25)"), 2.0);
+        assert_eq!(sheet.num("=percentile([0..6, 0], 0.9)"), 7.0);
+        assert_eq!(sheet.num("=quartile([0..6, 0], 4)"), 9.0);
+        assert_eq!(sheet.err("=percentile([0..6, 0], 1.5)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=quartile([0..6, 0], 5)"), ErrorKind::Num);
+
+        // descending unless asked otherwise
+        assert_eq!(sheet.num("=rank(4, [0..6, 0])"), 2.0);
+        assert_eq!(sheet.num("=rank(4, [0..6, 0], 1)"), 3.0);
+        assert_eq!(sheet.err("=rank(3, [0..6, 0])"), ErrorKind::NotAvailable);
+    }
+
+    #[test]
+    fn spread() {
+        let sheet = pairs();
+
+        // of a sample, or else of the whole population
+        close(&sheet, "=var([0..6, 0])", 9.5);
+        close(&sheet, "=varp([0..6, 0])", 7.6);
+        close(&sheet, "=stdev([0..6, 0])", 9.5f64.sqrt());
+        close(&sheet, "=stdevp([0..6, 0])", 7.6f64.sqrt());
+        assert_eq!(sheet.err("=stdev(1)"), ErrorKind::DivByZero);
+    }
+
+    #[test]
+    fn regression() {
+        let sheet = pairs();
+
+        // an exact line, with the blank row and label skipped
+        close(&sheet, "=correl([0..6, 0], [0..6, 1])", 1.0);
+        close(&sheet, "=covar([0..6, 0], [0..6, 1])", 19.0);
+        close(&sheet, "=covarp([0..6, 0], [0..6, 1])", 15.2);
+        close(&sheet, "=slope([0..6, 1], [0..6, 0])", 2.0);
+        close(&sheet, "=intercept([0..6, 1], [0..6, 0])", 1.0);
+        close(&sheet, "=rsq([0..6, 1], [0..6, 0])", 1.0);
+        close(&sheet, "=forecast(10, [0..6, 1], [0..6, 0])", 21.0);
+        assert_eq!(
+            sheet.err("=slope([0..5, 1], [0..6, 0])"),
+            ErrorKind::NotAvailable
+        );
+        // a vertical line has no slope
+        assert_eq!(
+            sheet.err("=slope([0..1, 1], [3..4, 0])"),
+            ErrorKind::DivByZero
+        );
+        assert_eq!(
+            sheet.err("=correl([0..1, 1], [0..1, 2])"),
+            ErrorKind::DivByZero
+        );
+    }
+}
@@ -0,0 +1,627 @@
+use crate::{
+    Error,
+    cell::Value,
+    functions::{Args, Arity, FunctionRegistry, NativeFunction, math::round_to, number},
+};
+
+// Positions count characters from 1, as in other spreadsheets, and characters are unicode scalar values
+
+/// The longest text a function can produce, as in other spreadsheets
+const MAX_LEN: usize = 32_767;
+
+/// Registers a function of a single piece of text
+fn unary(registry: &mut FunctionRegistry, name: &str, doc: &str, f: fn(&str) -> String) {
+    registry.register(
+        NativeFunction::new(name, Arity::exactly(1), move |args| {
+            Ok(Value::String(f(&args.text(0)?)))
+        })
+        .doc(doc),
+    );
+}
+
+/// A count of characters, which can't be negative
+fn count(args: &mut Args, i: usize) -> Result<usize, Error> {
+    let n = args.number(i)?;
+    if n < 0.0 {
+        Err(Error::InvalidNumber)
+    } else {
+        Ok(n.trunc() as usize)
+    }
+}
+
+/// An optional count of characters
+fn count_or(args: &mut Args, i: usize, default: usize) -> Result<usize, Error> {
+    if args.len() > i {
+        count(args, i)
+    } else {
+        Ok(default)
+    }
+}
+
+/// A position, from 1, as an index from 0
+fn position(args: &mut Args, i: usize) -> Result<usize, Error> {
+    match count(args, i)? {
+        0 => Err(Error::InvalidNumber),
+        n => Ok(n - 1),
+    }
+}
+
+/// A text result, which is invalid if it's too long
+fn text(s: String) -> Result<Value, Error> {
+    if s.chars().count() > MAX_LEN {
+        Err(Error::InvalidNumber)
+    } else {
+        Ok(Value::String(s))
+    }
+}
+
+/// The characters from `start`, up to `len` of them
+fn substring(s: &str, start: usize, len: usize) -> String {
+    s.chars().skip(start).take(len).collect()
+}
+
+/// Capitalises the first letter of every word, and lowercases the rest
+fn proper(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_word = false;
+    for c in s.chars() {
+        if in_word {
+            out.extend(c.to_lowercase());
+        } else {
+            out.extend(c.to_uppercase());
+        }
+        in_word = c.is_alphabetic();
+    }
+    out
+}
+
+/// Removes the spaces at either end, and collapses runs of spaces between words into one
+fn trim(s: &str) -> String {
+    s.split(' ')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the pattern matches the start of the text, ignoring case.
+///
+/// `?` matches any character and `*` any run of characters, unless escaped by `~`
+fn wildcard(pattern: &[char], text: &[char]) -> bool {
+    let same = |a: char, b: char| a.to_lowercase().eq(b.to_lowercase());
+    let (mut p, mut t) = (0, 0);
+    // where the pattern after the last `*` was tried from, as only the last one ever needs to match more
+    let mut star = None;
+    loop {
+        let matched = match pattern[p..] {
+            [] => return true,
+            ['*', ..] => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            }
+            ['?', ..] => (t < text.len()).then_some(1),
+            ['~', c, ..] => text.get(t).is_some_and(|&x| same(c, x)).then_some(2),
+            [c, ..] => text.get(t).is_some_and(|&x| same(c, x)).then_some(1),
+        };
+        match (matched, star) {
+            (Some(len), _) => {
+                p += len;
+                t += 1;
+            }
+            // the `*` takes one more character, and the rest of the pattern starts over after it
+            (None, Some((after, from))) if from < text.len() => {
+                star = Some((after, from + 1));
+                (p, t) = (after, from + 1);
+            }
+            (None, _) => return false,
+        }
+    }
+}
+
+/// The position of the needle in the text, from `start`, with `search` ignoring case and allowing wildcards
+fn find(args: &mut Args, search: bool) -> Result<Value, Error> {
+    let needle = args.text(0)?.chars().collect::<Vec<_>>();
+    let haystack = args.text(1)?.chars().collect::<Vec<_>>();
+    let start = if args.len() == 3 {
+        position(args, 2)?
+    } else {
+        0
+    };
+    if start > haystack.len() {
+        return Err(Error::InvalidNumber);
+    }
+
+    (start..=haystack.len())
+        .find(|&i| {
+            if search {
+                wildcard(&needle, &haystack[i..])
+            } else {
+                haystack[i..].starts_with(&needle)
+            }
+        })
+        .map(|i| Value::Number((i + 1) as f64))
+        .ok_or(Error::NoMatch)
+}
+
+/// Every argument's text, expanding any ranges
+fn texts(args: &mut Args, from: usize) -> Result<Vec<String>, Error> {
+    let mut texts = vec![];
+    for i in from..args.len() {
+        for val in args.cells(i)? {
+            texts.push(val.text()?);
+        }
+    }
+    Ok(texts)
+}
+
+/// Converts text to a number, allowing surrounding spaces, thousands separators and a trailing percent
+fn parse_number(s: &str) -> Result<f64, Error> {
+    let s = s.trim();
+    let (s, scale) = match s.strip_suffix('%') {
+        Some(s) => (s.trim_end(), 0.01),
+        None => (s, 1.0),
+    };
+    s.replace(',', "")
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .map(|n| n * scale)
+        .ok_or(Error::OperationUnavailable)
+}
+
+/// A number format, i.e. `$#,##0.00`, `0.0%` or `0.00E+00`
+#[derive(Debug, Default)]
+struct Format {
+    /// The literal text before and after the number
+    prefix: String,
+    suffix: String,
+    /// The placeholders of the integer and fractional parts, where `0` is a digit that is always shown and
+    /// `#` one that is only shown when needed
+    int: String,
+    frac: String,
+    /// Whether the integer part is grouped in thousands
+    grouping: bool,
+    /// For scientific notation, whether positive exponents have a sign, and the minimum exponent digits
+    exponent: Option<(bool, usize)>,
+    /// How many times the number is multiplied by 100, once for every `%`
+    percent: i32,
+}
+impl Format {
+    /// Parses a format, where text in double quotes or after a `\` is literal
+    fn parse(format: &str) -> Self {
+        let mut fmt = Format::default();
+        let mut number = String::new();
+        // whether the number has been passed, so that any further placeholders are literal
+        let mut done = false;
+
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            let literal = if number.is_empty() {
+                &mut fmt.prefix
+            } else {
+                done = done || !matches!(c, '0' | '#' | ',' | '.' | 'E' | 'e');
+                &mut fmt.suffix
+            };
+            match c {
+                '"' => literal.extend(chars.by_ref().take_while(|&c| c != '"')),
+                '\\' => literal.extend(chars.next()),
+                '%' => {
+                    fmt.percent += 1;
+                    literal.push('%');
+                }
+                '0' | '#' if !done => number.push(c),
+                ',' if !done && !number.is_empty() => number.push(c),
+                '.' if !done && (!number.is_empty() || matches!(chars.peek(), Some('0' | '#'))) => {
+                    number.push(c)
+                }
+                'E' | 'e' if !done && matches!(chars.peek(), Some('+' | '-')) => {
+                    let signed = chars.next() == Some('+');
+                    let mut digits = 0;
+                    while chars.next_if(|&c| c == '0').is_some() {
+                        digits += 1;
+                    }
+                    fmt.exponent = Some((signed, digits));
+                    done = true;
+                }
+                c => literal.push(c),
+            }
+        }
+
+        let (int, frac) = number.split_once('.').unwrap_or((&number, ""));
+        fmt.grouping = int.contains(',');
+        fmt.int = int.replace(',', "");
+        fmt.frac = frac.replace(',', "");
+        fmt
+    }
+
+    fn format(&self, x: f64) -> Result<String, Error> {
+        let x = x * 100f64.powi(self.percent);
+        if self.int.is_empty() && self.frac.is_empty() {
+            // there's no number to show
+            return Ok(format!("{}{}", self.prefix, self.suffix));
+        }
+        let decimals = self.frac.len();
+        let round = |x: f64| round_to(x, decimals as f64, f64::round);
+
+        let (mantissa, exponent) = match self.exponent {
+            Some(_) if x != 0.0 => {
+                // the exponent is such that the integer part has as many digits as its placeholders
+                let digits = self.int.len().max(1) as i32;
+                let mut exponent = x.abs().log10().floor() as i32 - (digits - 1);
+                let mut mantissa = round(x / 10f64.powi(exponent))?;
+                if mantissa.abs() >= 10f64.powi(digits) {
+                    exponent += 1;
+                    mantissa = round(x / 10f64.powi(exponent))?;
+                }
+                (mantissa, Some(exponent))
+            }
+            Some(_) => (0.0, Some(0)),
+            None => (round(x)?, None),
+        };
+        if !mantissa.is_finite() {
+            return Err(Error::InvalidNumber);
+        }
+
+        let digits = format!("{:.*}", decimals, mantissa.abs());
+        let (int, frac) = digits.split_once('.').unwrap_or((&digits, ""));
+
+        let min_int = self.int.chars().filter(|&c| c == '0').count();
+        let int = if int == "0" && min_int == 0 { "" } else { int };
+        let int = format!("{int:0>min_int$}");
+        let int = if self.grouping {
+            let len = int.len();
+            int.chars()
+                .enumerate()
+                .flat_map(|(i, c)| {
+                    let sep = (i > 0 && (len - i) % 3 == 0).then_some(',');
+                    sep.into_iter().chain([c])
+                })
+                .collect()
+        } else {
+            int
+        };
+
+        let min_frac = self.frac.chars().filter(|&c| c == '0').count();
+        let mut frac = frac.to_string();
+        while frac.len() > min_frac && frac.ends_with('0') {
+            frac.pop();
+        }
+
+        let mut out = String::new();
+        if mantissa < 0.0 {
+            out.push('-');
+        }
+        out.push_str(&self.prefix);
+        out.push_str(&int);
+        if !frac.is_empty() {
+            out.push('.');
+            out.push_str(&frac);
+        }
+        if let (Some((signed, min_digits)), Some(exponent)) = (self.exponent, exponent) {
+            let sign = match exponent {
+                ..0 => "-",
+                _ if signed => "+",
+                _ => "",
+            };
+            out.push_str(&format!("E{sign}{:0>min_digits$}", exponent.abs()));
+        }
+        out.push_str(&self.suffix);
+        Ok(out)
+    }
+}
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(
+        NativeFunction::new("len", Arity::exactly(1), |args| {
+            Ok(Value::Number(args.text(0)?.chars().count() as f64))
+        })
+        .doc("len(text): the number of characters in the text"),
+    );
+
+    unary(
+        registry,
+        "upper",
+        "upper(text): the text in uppercase",
+        str::to_uppercase,
+    );
+    unary(
+        registry,
+        "lower",
+        "lower(text): the text in lowercase",
+        str::to_lowercase,
+    );
+    unary(
+        registry,
+        "proper",
+        "proper(text): the text with the first letter of every word capitalised, and the rest lowercase",
+        proper,
+    );
+    unary(
+        registry,
+        "trim",
+        "trim(text): the text without spaces at either end, and with single spaces between words",
+        trim,
+    );
+
+    registry.register(
+        NativeFunction::new("left", Arity::between(1, 2), |args| {
+            let s = args.text(0)?;
+            let len = count_or(args, 1, 1)?;
+            Ok(Value::String(substring(&s, 0, len)))
+        })
+        .doc("left(text, [count]): the first characters of the text, 1 by default"),
+    );
+    registry.register(
+        NativeFunction::new("right", Arity::between(1, 2), |args| {
+            let s = args.text(0)?;
+            let len = count_or(args, 1, 1)?;
+            let start = s.chars().count().saturating_sub(len);
+            Ok(Value::String(substring(&s, start, len)))
+        })
+        .doc("right(text, [count]): the last characters of the text, 1 by default"),
+    );
+    registry.register(
+        NativeFunction::new("mid", Arity::exactly(3), |args| {
+            let s = args.text(0)?;
+            let (start, len) = (position(args, 1)?, count(args, 2)?);
+            Ok(Value::String(substring(&s, start, len)))
+        })
+        .doc("mid(text, start, count): the characters of the text from the start position"),
+    );
+
+    registry.register(
+        NativeFunction::new("find", Arity::between(2, 3), |args| find(args, false))
+            .doc("find(needle, text, [start]): the position of the needle in the text, from the start position"),
+    );
+    registry.register(
+        NativeFunction::new("search", Arity::between(2, 3), |args| find(args, true))
+            .doc(
+                "search(needle, text, [start]): the position of the needle in the text, ignoring case, where \
+                 ? in the needle matches any character and * any characters",
+            ),
+    );
+
+    registry.register(
+        NativeFunction::new("replace", Arity::exactly(4), |args| {
+            let s = args.text(0)?;
+            let (start, len) = (position(args, 1)?, count(args, 2)?);
+            let replacement = args.text(3)?;
+            let mut out = substring(&s, 0, start);
+            out.push_str(&replacement);
+            out.extend(s.chars().skip(start.saturating_add(len)));
+            text(out)
+        })
+        .doc("replace(text, start, count, replacement): the text with the characters from the start position replaced"),
+    );
+    // every occurrence is replaced, unless one is picked
+    registry.register(
+        NativeFunction::new("substitute", Arity::between(3, 4), |args| {
+            let (s, old, new) = (args.text(0)?, args.text(1)?, args.text(2)?);
+            if old.is_empty() {
+                return Ok(Value::String(s));
+            }
+            if args.len() < 4 {
+                return text(s.replace(&old, &new));
+            }
+
+            let nth = position(args, 3)?;
+            match s.match_indices(&old).nth(nth) {
+                Some((i, _)) => text(format!("{}{new}{}", &s[..i], &s[i + old.len()..])),
+                None => Ok(Value::String(s)),
+            }
+        })
+        .doc("substitute(text, old, new, [occurrence]): the text with the old text replaced by the new"),
+    );
+
+    registry.register(
+        NativeFunction::new("concat", Arity::at_least(1), |args| {
+            text(texts(args, 0)?.concat())
+        })
+        .doc("concat(value, ...): the values joined as text, along with the cells of any ranges"),
+    );
+    registry.register(
+        NativeFunction::new("textjoin", Arity::at_least(3), |args| {
+            let delimiter = args.text(0)?;
+            let skip_blanks = args.get(1)?.truthy()?;
+            let mut texts = texts(args, 2)?;
+            if skip_blanks {
+                texts.retain(|s| !s.is_empty());
+            }
+            text(texts.join(&delimiter))
+        })
+        .doc(
+            "textjoin(delimiter, skip_blanks, value, ...): the values joined as text with the delimiter between, \
+             along with the cells of any ranges",
+        ),
+    );
+    registry.register(
+        NativeFunction::new("split", Arity::exactly(3), |args| {
+            let (s, delimiter) = (args.text(0)?, args.text(1)?);
+            if delimiter.is_empty() {
+                return Err(Error::OperationUnavailable);
+            }
+            let nth = position(args, 2)?;
+            s.split(&delimiter)
+                .nth(nth)
+                .map(|part| Value::String(part.to_string()))
+                .ok_or(Error::NoMatch)
+        })
+        .doc("split(text, delimiter, index): the part of the text at the index, when split at the delimiter"),
+    );
+    registry.register(
+        NativeFunction::new("repeat", Arity::exactly(2), |args| {
+            let s = args.text(0)?;
+            let times = count(args, 1)?;
+            if s.chars().count().saturating_mul(times) > MAX_LEN {
+                return Err(Error::InvalidNumber);
+            }
+            Ok(Value::String(s.repeat(times)))
+        })
+        .doc("repeat(text, times): the text repeated"),
+    );
+
+    registry.register(
+        NativeFunction::new("value", Arity::exactly(1), |args| match args.get(0)? {
+            Value::String(s) => parse_number(&s).map(Value::Number),
+            val => number(val).map(Value::Number),
+        })
+        .doc("value(text): the number written in the text, which may have thousands separators or a percent"),
+    );
+    registry.register(
+        NativeFunction::new("text", Arity::exactly(2), |args| {
+            let x = args.number(0)?;
+            let format = Format::parse(&args.text(1)?);
+            text(format.format(x)?)
+        })
+        .doc(
+            "text(x, format): x written in the format, where 0 is a digit, # a digit only shown when needed, \
+             and the format can also have a , for thousands separators, a % for a percentage, and E+0 for \
+             scientific notation, i.e. '$#,##0.00'",
+        ),
+    );
+
+    registry.register(
+        NativeFunction::new("char", Arity::exactly(1), |args| {
+            let code = args.number(0)?;
+            u32::try_from(code.trunc() as i64)
+                .ok()
+                .and_then(char::from_u32)
+                .filter(|_| code >= 1.0)
+                .map(|c| Value::String(c.to_string()))
+                .ok_or(Error::InvalidNumber)
+        })
+        .doc("char(code): the character with the unicode code point"),
+    );
+    registry.register(
+        NativeFunction::new("code", Arity::exactly(1), |args| {
+            args.text(0)?
+                .chars()
+                .next()
+                .map(|c| Value::Number(c as u32 as f64))
+                .ok_or(Error::OperationUnavailable)
+        })
+        .doc("code(text): the unicode code point of the first character of the text"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cell::ErrorKind, functions::testing::TestSheet};
+
+    #[test]
+    fn casing() {
+        let sheet = TestSheet::new(&[&["=1 / 0"]]);
+
+        assert_eq!(sheet.num("=len('héllo')"), 5.0);
+        assert_eq!(sheet.num("=len([9, 9])"), 0.0);
+        assert_eq!(sheet.num("=len(1.5)"), 3.0);
+        assert_eq!(sheet.s("=upper('straße')"), "STRASSE");
+        assert_eq!(sheet.s("=lower('ABC')"), "abc");
+        assert_eq!(
+            sheet.s("=proper('hello wORLD mary-jane')"),
+            "Hello World Mary-Jane"
+        );
+        assert_eq!(sheet.s("=trim('  a   b  ')"), "a b");
+        // errors propagate
+        assert_eq!(sheet.err("=upper([0, 0])"), ErrorKind::DivByZero);
+    }
+
+    #[test]
+    fn slicing() {
+        let sheet = TestSheet::new(&[]);
+
+        // positions count characters from 1
+        assert_eq!(sheet.s("=left('spanleaf', 4)"), "span");
+        assert_eq!(sheet.s("=left('spanleaf')"), "s");
+        assert_eq!(sheet.s("=right('spanleaf', 4)"), "leaf");
+        assert_eq!(sheet.s("=right('ab', 5)"), "ab");
+        assert_eq!(sheet.s("=mid('spanleaf', 3, 4)"), "anle");
+        assert_eq!(sheet.s("=mid('spanleaf', 20, 4)"), "");
+        assert_eq!(sheet.err("=mid('spanleaf', 0, 4)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=left('spanleaf', -1)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=mid('a', 1)"), ErrorKind::Args);
+
+        assert_eq!(sheet.s("=replace('spanleaf', 1, 4, 'over')"), "overleaf");
+        assert_eq!(sheet.s("=substitute('a-b-c', '-', '+')"), "a+b+c");
+        // or only the given occurrence
+        assert_eq!(sheet.s("=substitute('a-b-c', '-', '+', 2)"), "a-b+c");
+        assert_eq!(sheet.s("=substitute('a-b-c', '-', '+', 3)"), "a-b-c");
+    }
+
+    #[test]
+    fn searching() {
+        let sheet = TestSheet::new(&[]);
+
+        // find is case sensitive, search isn't
+        assert_eq!(sheet.num("=find('a', 'banana')"), 2.0);
+        assert_eq!(sheet.num("=find('a', 'banana', 3)"), 4.0);
+        assert_eq!(sheet.num("=find('', 'banana', 3)"), 3.0);
+        assert_eq!(sheet.err("=find('A', 'banana')"), ErrorKind::NotAvailable);
+        assert_eq!(sheet.num("=search('A', 'banana')"), 2.0);
+        assert_eq!(sheet.err("=search('x', 'banana')"), ErrorKind::NotAvailable);
+
+        // and takes wildcards, escaped by a ~
+        assert_eq!(sheet.num("=search('n?n', 'banana')"), 3.0);
+        assert_eq!(sheet.num("=search('b*n', 'banana')"), 1.0);
+        assert_eq!(sheet.num("=search('~?', 'why?')"), 4.0);
+        assert_eq!(sheet.num("=search('a*~*', 'a*b*')"), 1.0);
+        // without going back over every way of splitting the text between the stars
+        assert_eq!(
+            sheet.err("=search('*a*a*a*a*a*a*b', repeat('a', 60))"),
+            ErrorKind::NotAvailable
+        );
+    }
+
+    #[test]
+    fn joining() {
+        // a column of words, with a blank and a number, next to an error
+        let sheet = TestSheet::new(&[&["apple", "=1 / 0"], &[""], &["cherry"], &["4"]]);
+
+        assert_eq!(
+            sheet.s("=concat([0..3, 0], '!', true)"),
+            "applecherry4!true"
+        );
+        assert_eq!(sheet.err("=concat('a', [0..1, 1])"), ErrorKind::DivByZero);
+        // optionally skipping blanks
+        assert_eq!(
+            sheet.s("=textjoin(', ', true, [0..3, 0])"),
+            "apple, cherry, 4"
+        );
+        assert_eq!(sheet.s("=textjoin('-', false, [0..2, 0])"), "apple--cherry");
+
+        assert_eq!(sheet.s("=split('a,b,,c', ',', 2)"), "b");
+        assert_eq!(sheet.s("=split('a,b,,c', ',', 3)"), "");
+        assert_eq!(sheet.err("=split('a,b', ',', 3)"), ErrorKind::NotAvailable);
+        assert_eq!(sheet.s("=repeat('ab', 3)"), "ababab");
+        assert_eq!(sheet.err("=repeat('ab', 20000)"), ErrorKind::Num);
+    }
+
+    #[test]
+    fn conversion() {
+        let sheet = TestSheet::new(&[]);
+
+        assert_eq!(sheet.num("=value(' 1,234.5 ')"), 1234.5);
+        assert_eq!(sheet.num("=value('50%')"), 0.5);
+        assert_eq!(sheet.num("=value(3)"), 3.0);
+        assert_eq!(sheet.err("=value('abc')"), ErrorKind::Value);
+
+        assert_eq!(sheet.s("=text(1234.567, '#,##0.00')"), "1,234.57");
+        assert_eq!(sheet.s("=text(-1234.567, '$#,##0')"), "-$1,235");
+        assert_eq!(sheet.s("=text(0.125, '0.0%')"), "12.5%");
+        assert_eq!(sheet.s("=text(2.675, '0.00')"), "2.68");
+        assert_eq!(sheet.s("=text(5, '000')"), "005");
+        assert_eq!(sheet.s("=text(0.5, '#.##')"), ".5");
+        assert_eq!(sheet.s("=text(1.5, '0.0#')"), "1.5");
+        assert_eq!(sheet.s("=text(1234567, '#,##0')"), "1,234,567");
+        assert_eq!(sheet.s("=text(12345, '0.00E+00')"), "1.23E+04");
+        assert_eq!(sheet.s("=text(0.00099, '0.0E+0')"), "9.9E-4");
+        assert_eq!(sheet.s("=text(9.99, '0.0E+00')"), "1.0E+01");
+        assert_eq!(sheet.s("=text(3, '0 \"items\"')"), "3 items");
+        assert_eq!(sheet.err("=text('x', '0')"), ErrorKind::Value);
+
+        assert_eq!(sheet.s("=char(65) & char(9731)"), "A☃");
+        assert_eq!(sheet.num("=code('☃man')"), 9731.0);
+        assert_eq!(sheet.err("=char(0)"), ErrorKind::Num);
+        assert_eq!(sheet.err("=code('')"), ErrorKind::Value);
+    }
+}
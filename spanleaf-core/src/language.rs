@@ -1,15 +1,16 @@
-use std::ops;
+use std::{cmp::Ordering, fmt::Display, ops};
 
-use chumsky::{number, prelude::*};
+use chumsky::prelude::*;
 
 use crate::{
-    Error, Spanleaf,
-    cell::{CellIdx, Value},
-    sheet::{SheetIdx, ValueResult},
+    Dependency, Error, Spanleaf,
+    cell::{Array, CellIdx, CellRange, ErrorKind, Value, broadcast},
+    datetime,
+    sheet::{Axis, Sheet, SheetIdx, Shift, ValueResult},
 };
 
 /// AST expression
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub(super) enum Expr {
     /// The current row
     CurrRow,
@@ -20,12 +21,21 @@ pub(super) enum Expr {
     Value(Box<Value>),
     /// The name of a sheet
     Sheet(String),
+    /// A variable, which is a parameter of the named function being evaluated
+    Var(String),
+    /// An array literal, as its rows, i.e. `{1, 2; 3, 4}`
+    Array(Vec<Vec<Expr>>),
     /// The reference to a cell
     CellRef(Option<Box<Expr>>, Box<Expr>, Box<Expr>),
+    /// The reference to a range of cells
+    /// Sheet, then the inclusive row bounds, then the inclusive col bounds
+    RangeRef(Option<Box<Expr>>, Box<(Expr, Expr)>, Box<(Expr, Expr)>),
     /// Dereferencing of a cell reference, accessing the pointed to value
     CellDeref(Box<Expr>),
     /// Negation
     Neg(Box<Expr>),
+    /// Joining as text
+    Concat(Box<Expr>, Box<Expr>),
     /// Addition
     Add(Box<Expr>, Box<Expr>),
     /// Subtraction
@@ -34,9 +44,30 @@ pub(super) enum Expr {
     Mul(Box<Expr>, Box<Expr>),
     /// Division
     Div(Box<Expr>, Box<Expr>),
+    /// Equality
+    Eq(Box<Expr>, Box<Expr>),
+    /// Inequality
+    Ne(Box<Expr>, Box<Expr>),
+    /// Less than
+    Lt(Box<Expr>, Box<Expr>),
+    /// Less than or equal
+    Le(Box<Expr>, Box<Expr>),
+    /// Greater than
+    Gt(Box<Expr>, Box<Expr>),
+    /// Greater than or equal
+    Ge(Box<Expr>, Box<Expr>),
+    /// Short-circuiting logical and
+    And(Box<Expr>, Box<Expr>),
+    /// Short-circuiting logical or
+    Or(Box<Expr>, Box<Expr>),
+    /// Logical not
+    Not(Box<Expr>),
     /// Function call
     /// Fn name, then arguments list
     Call(String, Vec<Expr>),
+    /// An anonymous function, as its parameters and body, i.e. `(acc, x) => acc + x`, which is only an
+    /// argument of the functions that call it
+    Lambda(Vec<String>, Box<Expr>),
 }
 impl Expr {
     pub fn value(val: Value) -> Self {
@@ -45,12 +76,18 @@ impl Expr {
     pub fn cell_ref(sref: Option<Expr>, row: Expr, col: Expr) -> Self {
         Self::CellRef(sref.map(Box::new), Box::new(row), Box::new(col))
     }
+    pub fn range_ref(sref: Option<Expr>, rows: (Expr, Expr), cols: (Expr, Expr)) -> Self {
+        Self::RangeRef(sref.map(Box::new), Box::new(rows), Box::new(cols))
+    }
     pub fn cell_deref(cref: Expr) -> Self {
         Self::CellDeref(Box::new(cref))
     }
     pub fn neg(expr: Expr) -> Self {
         Self::Neg(Box::new(expr))
     }
+    pub fn concat(lhs: Expr, rhs: Expr) -> Self {
+        Self::Concat(Box::new(lhs), Box::new(rhs))
+    }
     pub fn add(lhs: Expr, rhs: Expr) -> Self {
         Self::Add(Box::new(lhs), Box::new(rhs))
     }
@@ -63,16 +100,532 @@ impl Expr {
     pub fn div(lhs: Expr, rhs: Expr) -> Self {
         Self::Div(Box::new(lhs), Box::new(rhs))
     }
+    pub fn eq(lhs: Expr, rhs: Expr) -> Self {
+        Self::Eq(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn ne(lhs: Expr, rhs: Expr) -> Self {
+        Self::Ne(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn lt(lhs: Expr, rhs: Expr) -> Self {
+        Self::Lt(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn le(lhs: Expr, rhs: Expr) -> Self {
+        Self::Le(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn gt(lhs: Expr, rhs: Expr) -> Self {
+        Self::Gt(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn ge(lhs: Expr, rhs: Expr) -> Self {
+        Self::Ge(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn and(lhs: Expr, rhs: Expr) -> Self {
+        Self::And(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn or(lhs: Expr, rhs: Expr) -> Self {
+        Self::Or(Box::new(lhs), Box::new(rhs))
+    }
+    pub fn not(expr: Expr) -> Self {
+        Self::Not(Box::new(expr))
+    }
+
+    /// Visits every expression in the tree, children before their parents
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        match self {
+            Expr::CurrRow | Expr::CurrCol | Expr::Value(_) | Expr::Sheet(_) | Expr::Var(_) => {}
+            Expr::CellRef(sheet, row, col) => {
+                if let Some(sheet) = sheet {
+                    sheet.visit_mut(f);
+                }
+                row.visit_mut(f);
+                col.visit_mut(f);
+            }
+            Expr::RangeRef(sheet, rows, cols) => {
+                if let Some(sheet) = sheet {
+                    sheet.visit_mut(f);
+                }
+                rows.0.visit_mut(f);
+                rows.1.visit_mut(f);
+                cols.0.visit_mut(f);
+                cols.1.visit_mut(f);
+            }
+            Expr::Array(rows) => {
+                for expr in rows.iter_mut().flatten() {
+                    expr.visit_mut(f);
+                }
+            }
+            Expr::CellDeref(expr) | Expr::Neg(expr) | Expr::Not(expr) | Expr::Lambda(_, expr) => {
+                expr.visit_mut(f)
+            }
+            Expr::Concat(lhs, rhs)
+            | Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Ne(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Le(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::Ge(lhs, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs) => {
+                lhs.visit_mut(f);
+                rhs.visit_mut(f);
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.visit_mut(f);
+                }
+            }
+        }
+        f(self)
+    }
+
+    /// The sheet name of a reference, or None if it refers to the current sheet
+    fn sheet_name(sheet: &Option<Box<Expr>>) -> Option<&str> {
+        match sheet.as_deref() {
+            Some(Expr::Sheet(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The literal coordinate, if the expression is an absolute coordinate
+    fn literal_coord(&self) -> Option<u64> {
+        match self {
+            Expr::Value(val) => match **val {
+                Value::Number(n) if n >= 0.0 => Some(n as u64),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// How tightly the expression binds when printed, higher binds tighter
+    fn precedence(&self) -> u8 {
+        match self {
+            // the body takes in everything after the arrow
+            Expr::Lambda(..) => 0,
+            Expr::Or(..) => 1,
+            Expr::And(..) => 2,
+            Expr::Eq(..)
+            | Expr::Ne(..)
+            | Expr::Lt(..)
+            | Expr::Le(..)
+            | Expr::Gt(..)
+            | Expr::Ge(..) => 3,
+            Expr::Concat(..) => 4,
+            Expr::Add(..) | Expr::Sub(..) => 5,
+            Expr::Mul(..) | Expr::Div(..) => 6,
+            Expr::Neg(..) | Expr::Not(..) => 7,
+            Expr::CellDeref(cref) if !matches!(**cref, Expr::CellRef(..)) => 8,
+            _ => 9,
+        }
+    }
+
+    /// Prints the expression as an indented tree, one node per line, for inspecting how a script parsed
+    pub(crate) fn tree(&self) -> String {
+        fn node(out: &mut String, depth: usize, label: &str, expr: &Expr) {
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(label);
+
+            let children: Vec<(&str, &Expr)> = match expr {
+                Expr::CurrRow | Expr::CurrCol | Expr::Value(_) | Expr::Sheet(_) | Expr::Var(_) => {
+                    out.push_str(&format!("{expr}\n"));
+                    return;
+                }
+                Expr::CellRef(sheet, row, col) => {
+                    out.push_str("CellRef\n");
+                    let sheet = sheet.as_deref().map(|sheet| ("sheet: ", sheet));
+                    sheet
+                        .into_iter()
+                        .chain([("row: ", &**row), ("col: ", &**col)])
+                        .collect()
+                }
+                Expr::RangeRef(sheet, rows, cols) => {
+                    out.push_str("RangeRef\n");
+                    let sheet = sheet.as_deref().map(|sheet| ("sheet: ", sheet));
+                    sheet
+                        .into_iter()
+                        .chain([
+                            ("rows: ", &rows.0),
+                            ("to: ", &rows.1),
+                            ("cols: ", &cols.0),
+                            ("to: ", &cols.1),
+                        ])
+                        .collect()
+                }
+                Expr::Array(rows) => {
+                    out.push_str("Array\n");
+                    rows.iter()
+                        .flat_map(|exprs| {
+                            // the first element of each row is labelled, to show where the rows start
+                            exprs
+                                .iter()
+                                .enumerate()
+                                .map(|(col, expr)| (if col == 0 { "row: " } else { "" }, expr))
+                        })
+                        .collect()
+                }
+                Expr::CellDeref(inner) | Expr::Neg(inner) | Expr::Not(inner) => {
+                    let name = match expr {
+                        Expr::CellDeref(_) => "CellDeref",
+                        Expr::Neg(_) => "Neg",
+                        _ => "Not",
+                    };
+                    out.push_str(name);
+                    out.push('\n');
+                    vec![("", inner)]
+                }
+                Expr::Concat(lhs, rhs)
+                | Expr::Add(lhs, rhs)
+                | Expr::Sub(lhs, rhs)
+                | Expr::Mul(lhs, rhs)
+                | Expr::Div(lhs, rhs)
+                | Expr::Eq(lhs, rhs)
+                | Expr::Ne(lhs, rhs)
+                | Expr::Lt(lhs, rhs)
+                | Expr::Le(lhs, rhs)
+                | Expr::Gt(lhs, rhs)
+                | Expr::Ge(lhs, rhs)
+                | Expr::And(lhs, rhs)
+                | Expr::Or(lhs, rhs) => {
+                    let name = match expr {
+                        Expr::Concat(..) => "Concat",
+                        Expr::Add(..) => "Add",
+                        Expr::Sub(..) => "Sub",
+                        Expr::Mul(..) => "Mul",
+                        Expr::Div(..) => "Div",
+                        Expr::Eq(..) => "Eq",
+                        Expr::Ne(..) => "Ne",
+                        Expr::Lt(..) => "Lt",
+                        Expr::Le(..) => "Le",
+                        Expr::Gt(..) => "Gt",
+                        Expr::Ge(..) => "Ge",
+                        Expr::And(..) => "And",
+                        _ => "Or",
+                    };
+                    out.push_str(name);
+                    out.push('\n');
+                    vec![("", lhs), ("", rhs)]
+                }
+                Expr::Call(name, args) => {
+                    out.push_str(&format!("Call {name}\n"));
+                    args.iter().map(|arg| ("", arg)).collect()
+                }
+                Expr::Lambda(params, body) => {
+                    out.push_str(&format!("Lambda {}\n", params.join(", ")));
+                    vec![("", body)]
+                }
+            };
+
+            for (label, child) in children {
+                node(out, depth + 1, label, child);
+            }
+        }
+
+        let mut out = String::new();
+        node(&mut out, 0, "", self);
+        out
+    }
+}
+
+/// Prints the expression as a script (sans '='), with only the parentheses that are needed
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        /// Prints the operand, wrapped in parentheses if it binds looser than `min`
+        fn operand(f: &mut std::fmt::Formatter<'_>, expr: &Expr, min: u8) -> std::fmt::Result {
+            if expr.precedence() < min {
+                write!(f, "({expr})")
+            } else {
+                write!(f, "{expr}")
+            }
+        }
+
+        fn binary(
+            f: &mut std::fmt::Formatter<'_>,
+            this: &Expr,
+            lhs: &Expr,
+            op: &str,
+            rhs: &Expr,
+        ) -> std::fmt::Result {
+            // everything is left associative, so the rhs needs parentheses at equal precedence
+            operand(f, lhs, this.precedence())?;
+            write!(f, " {op} ")?;
+            operand(f, rhs, this.precedence() + 1)
+        }
+
+        // always written as a range, as `[0, 0]` would be read back as a single cell
+        fn axis(f: &mut std::fmt::Formatter<'_>, (start, end): &(Expr, Expr)) -> std::fmt::Result {
+            write!(f, "{start}..{end}")
+        }
+
+        fn sheet(f: &mut std::fmt::Formatter<'_>, sheet: &Option<Box<Expr>>) -> std::fmt::Result {
+            match sheet {
+                Some(sheet) => write!(f, "{sheet}"),
+                None => Ok(()),
+            }
+        }
+
+        match self {
+            Expr::CurrRow => write!(f, "r"),
+            Expr::CurrCol => write!(f, "c"),
+            Expr::Value(val) => match &**val {
+                Value::Bool(b) => write!(f, "{b}"),
+                Value::Number(n) => write!(f, "{n}"),
+                Value::String(s) => write!(f, "'{s}'"),
+                Value::Date(date) => write!(f, "@{date}"),
+                Value::DateTime(dt) => write!(f, "@{dt}"),
+                Value::Duration(duration) => write!(f, "@{duration}"),
+                Value::Error(kind) => write!(f, "{kind}"),
+                // can't be parsed, and so shouldn't be present
+                Value::None
+                | Value::Array(_)
+                | Value::Range { .. }
+                | Value::Ref { .. }
+                | Value::Formula(_) => write!(f, "''"),
+            },
+            Expr::Sheet(name) | Expr::Var(name) => write!(f, "{name}"),
+            Expr::Array(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{{{}}}", rows.join("; "))
+            }
+            Expr::CellRef(sref, row, col) => {
+                write!(f, "&")?;
+                sheet(f, sref)?;
+                write!(f, "[{row}, {col}]")
+            }
+            Expr::RangeRef(sref, rows, cols) => {
+                sheet(f, sref)?;
+                write!(f, "[")?;
+                axis(f, rows)?;
+                write!(f, ", ")?;
+                axis(f, cols)?;
+                write!(f, "]")
+            }
+            Expr::CellDeref(cref) => match &**cref {
+                // the usual case, a plain reference
+                Expr::CellRef(sref, row, col) => {
+                    sheet(f, sref)?;
+                    write!(f, "[{row}, {col}]")
+                }
+                cref => {
+                    write!(f, "*")?;
+                    operand(f, cref, 9)
+                }
+            },
+            Expr::Neg(expr) => {
+                write!(f, "-")?;
+                operand(f, expr, self.precedence())
+            }
+            Expr::Not(expr) => {
+                write!(f, "!")?;
+                operand(f, expr, self.precedence())
+            }
+            Expr::Concat(lhs, rhs) => binary(f, self, lhs, "&", rhs),
+            Expr::Add(lhs, rhs) => binary(f, self, lhs, "+", rhs),
+            Expr::Sub(lhs, rhs) => binary(f, self, lhs, "-", rhs),
+            Expr::Mul(lhs, rhs) => binary(f, self, lhs, "*", rhs),
+            Expr::Div(lhs, rhs) => binary(f, self, lhs, "/", rhs),
+            Expr::Eq(lhs, rhs) => binary(f, self, lhs, "==", rhs),
+            Expr::Ne(lhs, rhs) => binary(f, self, lhs, "!=", rhs),
+            Expr::Lt(lhs, rhs) => binary(f, self, lhs, "<", rhs),
+            Expr::Le(lhs, rhs) => binary(f, self, lhs, "<=", rhs),
+            Expr::Gt(lhs, rhs) => binary(f, self, lhs, ">", rhs),
+            Expr::Ge(lhs, rhs) => binary(f, self, lhs, ">=", rhs),
+            Expr::And(lhs, rhs) => binary(f, self, lhs, "&&", rhs),
+            Expr::Or(lhs, rhs) => binary(f, self, lhs, "||", rhs),
+            Expr::Call(name, args) => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Expr::Lambda(params, body) => write!(f, "({}) => {body}", params.join(", ")),
+        }
+    }
 }
 
+/// Rewrites absolute references to account for rows or columns being inserted into or deleted from a sheet.
+///
+/// `targets` decides whether a reference with the given sheet name (None for the current sheet) points into
+/// the changed sheet. Only literal coordinates are moved, as anything relative to `r` or `c` moves along
+/// with the cell it's in. References to deleted cells become `#REF!` errors.
+///
+/// Returns whether anything was rewritten
+pub(crate) fn shift_refs(
+    expr: &mut Expr,
+    targets: impl Fn(Option<&str>) -> bool,
+    shift: Shift,
+) -> bool {
+    /// Moves a literal coordinate, returning false if it was deleted
+    fn shift_coord(coord: &mut Expr, shift: Shift, changed: &mut bool) -> bool {
+        let Some(idx) = coord.literal_coord() else {
+            return true;
+        };
+        match shift.apply(idx) {
+            Some(new) => {
+                if new != idx {
+                    *coord = Expr::value(Value::Number(new as f64));
+                    *changed = true;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    let mut changed = false;
+
+    expr.visit_mut(&mut |expr| {
+        let valid = match expr {
+            Expr::CellRef(sheet, row, col) if targets(Expr::sheet_name(sheet)) => {
+                shift_coord(shift.axis.select(row, col), shift, &mut changed)
+            }
+            Expr::RangeRef(sheet, rows, cols) if targets(Expr::sheet_name(sheet)) => {
+                let (start, end) = &mut **shift.axis.select(rows, cols);
+                match (start.literal_coord(), end.literal_coord()) {
+                    (Some(start_idx), Some(end_idx)) => match shift.apply_range(start_idx, end_idx)
+                    {
+                        Some((new_start, new_end)) => {
+                            if (new_start, new_end) != (start_idx, end_idx) {
+                                *start = Expr::value(Value::Number(new_start as f64));
+                                *end = Expr::value(Value::Number(new_end as f64));
+                                changed = true;
+                            }
+                            true
+                        }
+                        None => false,
+                    },
+                    // a relative bound moves with the cell, so only a literal bound can move
+                    _ => {
+                        shift_coord(start, shift, &mut changed)
+                            && shift_coord(end, shift, &mut changed)
+                    }
+                }
+            }
+            Expr::CellDeref(_) => {
+                collapse_error_deref(expr);
+                true
+            }
+            _ => true,
+        };
+
+        if !valid {
+            *expr = Expr::value(Value::Error(ErrorKind::Ref));
+            changed = true;
+        }
+    });
+
+    changed
+}
+
+/// Dereferencing an error is just the error
+fn collapse_error_deref(expr: &mut Expr) {
+    if let Expr::CellDeref(cref) = expr
+        && let Expr::Value(val) = &**cref
+        && let Value::Error(kind) = **val
+    {
+        *expr = Expr::value(Value::Error(kind));
+    }
+}
+
+/// Points references to the sheet named `old` at the sheet named `new` instead.
+///
+/// Returns whether anything was rewritten
+pub(crate) fn rename_sheet_refs(expr: &mut Expr, old: &str, new: &str) -> bool {
+    let mut changed = false;
+
+    expr.visit_mut(&mut |expr| {
+        if let Expr::CellRef(Some(sheet), ..) | Expr::RangeRef(Some(sheet), ..) = expr
+            && let Expr::Sheet(name) = &mut **sheet
+            && name == old
+        {
+            *name = new.to_string();
+            changed = true;
+        }
+    });
+
+    changed
+}
+
+/// Turns references to the sheet named `name` into `#REF!` errors, as the sheet no longer exists.
+///
+/// Returns whether anything was rewritten
+pub(crate) fn delete_sheet_refs(expr: &mut Expr, name: &str) -> bool {
+    let mut changed = false;
+
+    expr.visit_mut(&mut |expr| match expr {
+        Expr::CellRef(sheet, ..) | Expr::RangeRef(sheet, ..)
+            if Expr::sheet_name(sheet) == Some(name) =>
+        {
+            *expr = Expr::value(Value::Error(ErrorKind::Ref));
+            changed = true;
+        }
+        Expr::CellDeref(_) => collapse_error_deref(expr),
+        _ => {}
+    });
+
+    changed
+}
+
+/// Whether the name can be used to refer to a sheet from within a formula
+pub(crate) fn is_sheet_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether the name looks like an A1 cell, i.e. `B7`, `b7` or `A0`, which can't be a variable even when it
+/// isn't a valid cell
+pub(crate) fn is_cell_like(name: &str) -> bool {
+    let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    digits.len() < name.len() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The parser's extra state, which collects rich errors for reporting
+pub(crate) type Extra<'src> = extra::Err<Rich<'src, char>>;
+
 /// takes the function meat (sans '=') and parses it into an expression
-pub(crate) fn parser<'src>() -> impl Parser<'src, &'src str, Expr> {
-    let expr = recursive({
+///
+/// A1 references, i.e. `B7`, `$A$1:C10` or `Sheet2!A1`, are desugared into the same references as
+/// `[row, col]`. `shared` is the axis along which the formula is shared by a row or col default, where
+/// relative A1 coordinates are offsets from the current row or col, as if written in the default's first
+/// cell. Otherwise the formula is in a single cell, so relative coordinates are the same as absolute ones
+pub(crate) fn parser<'src>(
+    shared: Option<Axis>,
+) -> impl Parser<'src, &'src str, Expr, Extra<'src>> {
+    recursive({
         |expr| {
-            let num = number::number::<{ number::format::STANDARD }, &str, f64, extra::Default>()
+            // a '.' must be followed by digits, so that the `0.` of `0..5` isn't taken as a float
+            let digits = text::digits(10);
+            let num = digits
+                .then(just('.').then(digits).or_not())
+                .then(
+                    one_of("eE")
+                        .then(one_of("+-").or_not())
+                        .then(digits)
+                        .or_not(),
+                )
+                .to_slice()
+                .map(|s: &str| s.parse().unwrap())
                 .map(Value::Number)
                 .map(Expr::value)
-                .padded();
+                .padded()
+                .labelled("number");
 
             let string = any()
                 .filter(|c| c != &'\'')
@@ -81,13 +634,68 @@ pub(crate) fn parser<'src>() -> impl Parser<'src, &'src str, Expr> {
                 .map(Value::String)
                 .map(Expr::value);
 
-            let boolean = just("true")
-                .or(just("false"))
-                .map(|s| Value::Bool(s.parse().unwrap()))
+            // a date, datetime or duration, i.e. `@2024-01-31`, `@2024-01-31T09:30` or `@-1d12h`. A '-' inside
+            // has to be followed by a digit, so that `@1d-A1` and `@1d-[0, 0]` are subtractions
+            let chunk = any()
+                .filter(|c: &char| c.is_ascii_alphanumeric() || matches!(c, ':' | '.'))
+                .repeated()
+                .at_least(1);
+            let digit_next = any().filter(char::is_ascii_digit).rewind();
+            let temporal = just('@')
+                .ignore_then(
+                    just('-')
+                        .or_not()
+                        .then(chunk)
+                        .then(just('-').then(digit_next).then(chunk).repeated())
+                        .to_slice(),
+                )
+                .try_map(|s: &str, span| {
+                    datetime::literal(s)
+                        .map(Expr::value)
+                        .ok_or_else(|| Rich::custom(span, "not a valid date, datetime or duration"))
+                });
+
+            let error = choice(ErrorKind::ALL.map(|kind| just(kind.code()).to(kind)))
+                .map(Value::Error)
                 .map(Expr::value);
 
+            // rows are separated by ';', and must all be the same length
+            let array = expr
+                .clone()
+                .separated_by(just(','))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .separated_by(just(';'))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .delimited_by(just('{'), just('}'))
+                .try_map(|rows: Vec<Vec<Expr>>, span| {
+                    if rows.iter().all(|row| row.len() == rows[0].len()) {
+                        Ok(Expr::Array(rows))
+                    } else {
+                        Err(Rich::custom(span, "array rows must be the same length"))
+                    }
+                });
+
             let ident = text::ascii::ident().padded();
 
+            // parameters can't be keywords or cells, as those names never become variables
+            let param = ident.try_map(|name: &str, span| match name {
+                "true" | "false" | "r" | "c" => {
+                    Err(Rich::custom(span, "not a valid parameter name"))
+                }
+                name if is_cell_like(name) => Err(Rich::custom(span, "not a valid parameter name")),
+                name => Ok(name.to_string()),
+            });
+            let lambda = param
+                .separated_by(just(','))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .delimited_by(just('('), just(')'))
+                .then_ignore(just("=>").padded())
+                .then(expr.clone())
+                .map(|(params, body)| Expr::Lambda(params, Box::new(body)));
+
             let call = ident
                 .then(
                     expr.clone()
@@ -98,37 +706,138 @@ pub(crate) fn parser<'src>() -> impl Parser<'src, &'src str, Expr> {
                 )
                 .map(|(name, args): (&str, _)| Expr::Call(name.to_string(), args));
 
+            // a single coordinate, or an inclusive span of coordinates
+            let axis = expr
+                .clone()
+                .then(just("..").ignore_then(expr.clone()).or_not());
+
             let raw_ref = ident
                 .or_not()
                 .map(move |sheet_name| sheet_name.map(|sn: &str| Expr::Sheet(sn.to_string())))
                 .then(
-                    expr.clone()
-                        .then(just(','))
-                        .then(expr.clone())
+                    axis.clone()
+                        .then_ignore(just(','))
+                        .then(axis)
                         .delimited_by(just('['), just(']')),
                 );
 
+            // an A1 cell, i.e. `B7` or `$A$1`, as whether each of its row and col are absolute, and their
+            // index. Columns are uppercase to keep them apart from `r`, `c` and function names
+            let a1_col = just('$')
+                .or_not()
+                .then(
+                    one_of("ABCDEFGHIJKLMNOPQRSTUVWXYZ")
+                        .repeated()
+                        .at_least(1)
+                        .at_most(3)
+                        .to_slice(),
+                )
+                .map(|(dollar, letters): (_, &str)| {
+                    let col = letters
+                        .bytes()
+                        .fold(0, |acc, b| acc * 26 + u64::from(b - b'A' + 1));
+                    (dollar.is_some(), col - 1)
+                });
+            let a1_row = just('$').or_not().then(digits.to_slice()).try_map(
+                |(dollar, digits): (_, &str), span| match digits.parse::<u64>() {
+                    Ok(row @ 1..) => Ok((dollar.is_some(), row - 1)),
+                    _ => Err(Rich::custom(span, "A1 rows start at 1")),
+                },
+            );
+            let a1_cell = a1_col.then(a1_row).map(|(col, row)| (row, col));
+
+            let a1_coord = move |axis: Axis, (absolute, idx): (bool, u64)| {
+                let literal = Expr::value(Value::Number(idx as f64));
+                if absolute || shared != Some(axis) {
+                    return literal;
+                }
+                let curr = axis.select(Expr::CurrRow, Expr::CurrCol);
+                if idx == 0 {
+                    curr
+                } else {
+                    Expr::add(curr, literal)
+                }
+            };
+            let a1_cells =
+                a1_cell
+                    .then(just(':').ignore_then(a1_cell).or_not())
+                    .map(move |(start, end)| match end {
+                        None => (
+                            (a1_coord(Axis::Row, start.0), None),
+                            (a1_coord(Axis::Col, start.1), None),
+                        ),
+                        // ranges may be written from any corner
+                        Some(end) => {
+                            let axis = |axis, a: (bool, u64), b: (bool, u64)| {
+                                let (a, b) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+                                (a1_coord(axis, a), Some(a1_coord(axis, b)))
+                            };
+                            (
+                                axis(Axis::Row, start.0, end.0),
+                                axis(Axis::Col, start.1, end.1),
+                            )
+                        }
+                    });
+            let a1_ref = choice((
+                text::ascii::ident()
+                    .then_ignore(just('!'))
+                    .map(|sheet_name: &str| Some(Expr::Sheet(sheet_name.to_string())))
+                    .then(a1_cells),
+                a1_cells.map(|cells| (None, cells)),
+            ));
+            let raw_ref = raw_ref.or(a1_ref);
+
+            // ranges are always references, so they aren't dereferenced
+            let to_range = |sheet,
+                            (row_start, row_end): (Expr, Option<Expr>),
+                            (col_start, col_end): (Expr, Option<Expr>)| {
+                let row_end = row_end.unwrap_or_else(|| row_start.clone());
+                let col_end = col_end.unwrap_or_else(|| col_start.clone());
+                Expr::range_ref(sheet, (row_start, row_end), (col_start, col_end))
+            };
+
             let deref = raw_ref
                 .clone()
-                .map(|(sheet, ((row, _), col))| Expr::cell_deref(Expr::cell_ref(sheet, row, col)));
+                .map(move |(sheet, (row, col))| match (row, col) {
+                    ((row, None), (col, None)) => Expr::cell_deref(Expr::cell_ref(sheet, row, col)),
+                    (row, col) => to_range(sheet, row, col),
+                });
 
             let cref = just('&')
-                .then(raw_ref)
-                .map(|(_, (sheet, ((row, _), col)))| Expr::cell_ref(sheet, row, col));
-
-            let r = just('r').map(|_| Expr::CurrRow);
-            let c = just('c').map(|_| Expr::CurrCol);
+                .ignore_then(raw_ref)
+                .map(move |(sheet, (row, col))| match (row, col) {
+                    ((row, None), (col, None)) => Expr::cell_ref(sheet, row, col),
+                    (row, col) => to_range(sheet, row, col),
+                });
+
+            // a bare name, after function calls and references have had their chance at it. Keywords are only
+            // whole names, so that variables may start with them
+            let word = text::ascii::ident()
+                .try_map(|name: &str, span| match name {
+                    "true" => Ok(Expr::value(Value::Bool(true))),
+                    "false" => Ok(Expr::value(Value::Bool(false))),
+                    "r" => Ok(Expr::CurrRow),
+                    "c" => Ok(Expr::CurrCol),
+                    name if is_cell_like(name) => Err(Rich::custom(span, "not a valid cell")),
+                    name => Ok(Expr::Var(name.to_string())),
+                })
+                .labelled("name");
 
             let atom = choice((
                 num,
-                boolean,
+                temporal.labelled("date"),
+                error.labelled("error"),
+                // before parentheses, which a lambda's parameters look like
+                lambda.labelled("lambda"),
                 expr.delimited_by(just('('), just(')')),
-                string.delimited_by(just('\''), just('\'')),
-                call,
-                cref,
-                deref,
-                r,
-                c,
+                array.labelled("array"),
+                string
+                    .delimited_by(just('\''), just('\''))
+                    .labelled("string"),
+                call.labelled("function call"),
+                cref.labelled("cell reference"),
+                deref.labelled("cell reference"),
+                word,
             ))
             .padded();
 
@@ -138,35 +847,99 @@ pub(crate) fn parser<'src>() -> impl Parser<'src, &'src str, Expr> {
                 .repeated()
                 .foldr(atom, |_op, rhs| Expr::cell_deref(rhs));
 
-            let neg = op('-').repeated().foldr(deref, |_op, rhs| Expr::neg(rhs));
+            let unary = choice((
+                op('-').to(Expr::neg as fn(_) -> _),
+                op('!').to(Expr::not as fn(_) -> _),
+            ))
+            .repeated()
+            .foldr(deref, |op, rhs| op(rhs));
 
-            let product = neg.clone().foldl(
+            let product = unary.clone().foldl(
                 choice((
                     op('*').to(Expr::mul as fn(_, _) -> _),
                     op('/').to(Expr::div as fn(_, _) -> _),
                 ))
-                .then(neg)
+                .then(unary)
                 .repeated(),
                 |lhs, (op, rhs)| op(lhs, rhs),
             );
 
-            let sum = product.clone().foldl(
+            let sum = product
+                .clone()
+                .foldl(
+                    choice((
+                        op('+').to(Expr::add as fn(_, _) -> _),
+                        op('-').to(Expr::sub as fn(_, _) -> _),
+                    ))
+                    .then(product)
+                    .repeated(),
+                    |lhs, (op, rhs)| op(lhs, rhs),
+                )
+                // every level of operators nests the types of the ones below twice, so boxing keeps them from
+                // growing too large to compile
+                .boxed();
+
+            // a single '&', as `&&` is the logical and
+            let concat = sum.clone().foldl(
+                just('&')
+                    .and_is(just("&&").not())
+                    .padded()
+                    .ignore_then(sum)
+                    .repeated(),
+                Expr::concat,
+            );
+
+            let op = |s| just(s).padded();
+
+            // the two character operators need to be checked first
+            let comparison = concat.clone().foldl(
                 choice((
-                    op('+').to(Expr::add as fn(_, _) -> _),
-                    op('-').to(Expr::sub as fn(_, _) -> _),
+                    op("==").to(Expr::eq as fn(_, _) -> _),
+                    op("!=").to(Expr::ne as fn(_, _) -> _),
+                    op("<=").to(Expr::le as fn(_, _) -> _),
+                    op(">=").to(Expr::ge as fn(_, _) -> _),
+                    op("<").to(Expr::lt as fn(_, _) -> _),
+                    op(">").to(Expr::gt as fn(_, _) -> _),
                 ))
-                .then(product)
+                .then(concat)
                 .repeated(),
                 |lhs, (op, rhs)| op(lhs, rhs),
             );
 
-            sum
+            let and = comparison
+                .clone()
+                .foldl(op("&&").ignore_then(comparison).repeated(), Expr::and);
+
+            and.clone()
+                .foldl(op("||").ignore_then(and).repeated(), Expr::or)
         }
-    });
+    })
+}
 
-    expr
+/// Parses a named function definition, i.e. `tax(amount, rate) = amount * rate`, into its name, parameters
+/// and body
+pub(crate) fn definition_parser<'src>()
+-> impl Parser<'src, &'src str, (&'src str, Vec<&'src str>, Expr), Extra<'src>> {
+    let ident = text::ascii::ident().padded();
+    ident
+        .then(
+            ident
+                .separated_by(just(','))
+                .allow_trailing()
+                .collect::<Vec<_>>()
+                .delimited_by(just('(').padded(), just(')')),
+        )
+        .then_ignore(just('=').padded())
+        .then(parser(None))
+        .map(|((name, params), body)| (name, params, body))
 }
 
+/// How deeply named functions may call each other, to stop runaway recursion
+const MAX_CALL_DEPTH: usize = 100;
+
+/// The most cells a range can have to be turned into an array
+const MAX_ARRAY_CELLS: u64 = 1 << 20;
+
 /// Necessary context for the evaluation of an expression
 pub struct EvalCtx<'a> {
     /// The top level Spanleaf instance
@@ -177,7 +950,106 @@ pub struct EvalCtx<'a> {
     pub curr_sheet: SheetIdx,
     pub curr_cell: CellIdx,
     /// The dependency chain for cache invalidation
-    pub dependencies: &'a mut Vec<(SheetIdx, CellIdx)>,
+    pub(crate) dependencies: &'a mut Vec<Dependency>,
+    /// The arguments of the named function being evaluated, by parameter name
+    pub(crate) locals: Vec<(String, Value)>,
+    /// How many named function calls deep the evaluation is
+    pub(crate) depth: usize,
+}
+
+/// Evaluates an expression that must resolve to a number, for use as a coordinate
+fn eval_coord(expr: &Expr, ctx: &mut EvalCtx<'_>) -> Result<f64, Error> {
+    match eval(expr, ctx)? {
+        Value::Number(n) => Ok(n),
+        Value::Error(kind) => Err(Error::ErrorValue(kind)),
+        _ => Err(Error::RefMustBeNumber),
+    }
+}
+
+/// A coordinate of a single cell, which has to be a whole row or col within the sheet
+fn cell_coord(n: f64) -> Result<u64, Error> {
+    if n.fract() == 0.0 && (0.0..=Sheet::LAST_INDEX as f64).contains(&n) {
+        Ok(n as u64)
+    } else {
+        Err(Error::RefOutOfBounds)
+    }
+}
+
+/// Resolves an optional sheet name expression to a sheet, defaulting to the current sheet
+fn eval_sheet(sheet_ref: &Option<Box<Expr>>, ctx: &mut EvalCtx<'_>) -> Result<SheetIdx, Error> {
+    match sheet_ref {
+        Some(sheet_ref) => {
+            let Value::String(sheet_name) = eval(sheet_ref, ctx)? else {
+                return Err(Error::RefMustBeNumber);
+            };
+
+            resolve_sheet(&sheet_name, ctx)
+        }
+        None => Ok(ctx.curr_sheet),
+    }
+}
+
+/// Finds a sheet by name. A sheet that doesn't exist is still recorded, so that inserting it updates the
+/// formula
+pub(crate) fn resolve_sheet(name: &str, ctx: &mut EvalCtx<'_>) -> Result<SheetIdx, Error> {
+    ctx.sl.sheet_by_name(name).ok_or_else(|| {
+        ctx.dependencies.push(Dependency::Sheet(name.to_string()));
+        Error::SheetNotFound
+    })
+}
+
+/// The values of the cells of a range, in the same shape as it. Fails for a range of more than
+/// [MAX_ARRAY_CELLS] cells, as every one of them is held at once
+pub(crate) fn range_array(sl: &Spanleaf, sref: SheetIdx, range: CellRange) -> Result<Array, Error> {
+    let (rows, cols) = array_size(range)?;
+    Ok(Array::from_fn(rows, cols, |row, col| {
+        let cref = CellIdx::new(range.start.row + row as u64, range.start.col + col as u64);
+        sl.get(sref, cref)
+            .map(ValueResult::value)
+            .unwrap_or_else(|e| Value::Error(e.into()))
+    }))
+}
+
+/// The number of rows and cols of a range, which fails if it has too many cells to hold all at once
+pub(crate) fn array_size(range: CellRange) -> Result<(usize, usize), Error> {
+    if range.is_empty() {
+        return Ok((0, 0));
+    }
+    let len = |start: u64, end: u64| (end - start).checked_add(1);
+    let (Some(rows), Some(cols)) = (
+        len(range.start.row, range.end.row),
+        len(range.start.col, range.end.col),
+    ) else {
+        return Err(Error::InvalidNumber);
+    };
+    if rows
+        .checked_mul(cols)
+        .is_none_or(|cells| cells > MAX_ARRAY_CELLS)
+    {
+        return Err(Error::InvalidNumber);
+    }
+    Ok((rows as usize, cols as usize))
+}
+
+/// Evaluates an operand of an operator, where a range is an array of its cells' values so that the operator
+/// applies to each of them
+fn operand(expr: &Expr, ctx: &mut EvalCtx<'_>) -> Result<Value, Error> {
+    match eval(expr, ctx)? {
+        Value::Range { sref, range } => Ok(Value::Array(range_array(ctx.sl, sref, range)?)),
+        val => Ok(val),
+    }
+}
+
+/// Evaluates both sides and checks their ordering
+fn compare(
+    lhs: &Expr,
+    rhs: &Expr,
+    ctx: &mut EvalCtx<'_>,
+    check: impl Fn(Option<Ordering>) -> bool,
+) -> Result<Value, Error> {
+    let lhs = operand(lhs, ctx)?;
+    let rhs = operand(rhs, ctx)?;
+    broadcast(lhs, rhs, |lhs, rhs| Ok(check(lhs.compare(&rhs)?).into()))
 }
 
 pub fn eval(expr: &Expr, ctx: &mut EvalCtx<'_>) -> Result<Value, Error> {
@@ -186,34 +1058,56 @@ pub fn eval(expr: &Expr, ctx: &mut EvalCtx<'_>) -> Result<Value, Error> {
         Expr::CurrCol => Ok(Value::Number(ctx.curr_cell.col as f64)),
         Expr::Value(val) => Ok((**val).clone()),
         Expr::Sheet(name) => Ok(Value::String(name.clone())),
-        Expr::CellRef(sheet_ref, row, col) => {
-            let Value::Number(row) = eval(row, ctx)? else {
-                return Err(Error::RefMustBeNumber);
-            };
-            let Value::Number(col) = eval(col, ctx)? else {
-                return Err(Error::RefMustBeNumber);
+        Expr::Var(name) => ctx
+            .locals
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, val)| val.clone())
+            .ok_or_else(|| Error::UnknownVariable(name.clone())),
+        Expr::Array(rows) => {
+            // every element is a single value, and one failing only makes that element an error
+            let mut element = |expr| match eval(expr, ctx) {
+                Ok(Value::Array(_) | Value::Range { .. }) => Value::Error(ErrorKind::Value),
+                Ok(val) => val,
+                Err(e) => Value::Error(e.into()),
             };
+            let rows = rows
+                .iter()
+                .map(|row| row.iter().map(&mut element).collect())
+                .collect();
+            Array::new(rows)
+                .map(Value::Array)
+                .ok_or(Error::OperationUnavailable)
+        }
+        Expr::CellRef(sheet_ref, row, col) => {
+            let row = eval_coord(row, ctx)?;
+            let col = eval_coord(col, ctx)?;
+            let sref = eval_sheet(sheet_ref, ctx)?;
 
-            let sref = match sheet_ref {
-                Some(sheet_ref) => {
-                    let Value::String(sheet_name) = eval(sheet_ref, ctx)? else {
-                        return Err(Error::RefMustBeNumber);
-                    };
+            let cref = CellIdx::new(cell_coord(row)?, cell_coord(col)?);
 
-                    ctx.sl
-                        .sheets
-                        .iter()
-                        .find_map(|(k, v)| (v.name == sheet_name).then_some(*k))
-                        .ok_or(Error::SheetNotFound)?
-                }
-                None => ctx.curr_sheet,
-            };
+            ctx.dependencies.push(Dependency::Cell(sref, cref));
 
-            let cref = CellIdx::new(row as u64, col as u64);
+            Ok(Value::Ref { sref, cref })
+        }
+        Expr::RangeRef(sheet_ref, rows, cols) => {
+            let (row_start, row_end) = (eval_coord(&rows.0, ctx)?, eval_coord(&rows.1, ctx)?);
+            let (col_start, col_end) = (eval_coord(&cols.0, ctx)?, eval_coord(&cols.1, ctx)?);
+            let sref = eval_sheet(sheet_ref, ctx)?;
+
+            // a backwards range is empty, which allows for things like `[0..r-1, c]` on the first row
+            let range = if row_end < row_start || col_end < col_start {
+                CellRange::empty()
+            } else {
+                CellRange::new(
+                    CellIdx::new(cell_coord(row_start)?, cell_coord(col_start)?),
+                    CellIdx::new(cell_coord(row_end)?, cell_coord(col_end)?),
+                )
+            };
 
-            ctx.dependencies.push((sref, cref));
+            ctx.dependencies.push(Dependency::Range(sref, range));
 
-            Ok(Value::Ref { sref, cref })
+            Ok(Value::Range { sref, range })
         }
         Expr::CellDeref(cref) => {
             let Value::Ref { sref, cref } = eval(cref, ctx)? else {
@@ -222,135 +1116,100 @@ pub fn eval(expr: &Expr, ctx: &mut EvalCtx<'_>) -> Result<Value, Error> {
 
             ctx.sl.get(sref, cref).map(ValueResult::value)
         }
-        Expr::Neg(expr) => Ok(ops::Neg::neg(eval(expr, ctx)?)?),
-        Expr::Add(lhs, rhs) => Ok(ops::Add::add(eval(lhs, ctx)?, eval(rhs, ctx)?)?),
-        Expr::Sub(lhs, rhs) => Ok(ops::Sub::sub(eval(lhs, ctx)?, eval(rhs, ctx)?)?),
-        Expr::Mul(lhs, rhs) => Ok(ops::Mul::mul(eval(lhs, ctx)?, eval(rhs, ctx)?)?),
-        Expr::Div(lhs, rhs) => Ok(ops::Div::div(eval(lhs, ctx)?, eval(rhs, ctx)?)?),
-        Expr::Call(fn_name, args) => {
-            // I don't want to create exprs for every action, that sounds like a nightmare. So I think just an enum and associated functions? Maybe not even an enum?
-            // Can also create a HashMap<String, fn(&Expr) -> Result<Value, Error>> to make it more dynamic friendly, populate it on startup or use statics?
-            match fn_name.as_str() {
-                // This could probably be macro-tized, or trait-ified or smth. But it works, so
-                "sum" => functions::sum(ctx, args),
-                "average" => functions::average(ctx, args),
-                _ => Err(Error::FunctionNotAvailable),
-            }
+        Expr::Neg(expr) => Ok(ops::Neg::neg(operand(expr, ctx)?)?),
+        Expr::Concat(lhs, rhs) => {
+            let lhs = operand(lhs, ctx)?;
+            broadcast(lhs, operand(rhs, ctx)?, |lhs, rhs| {
+                Ok(Value::String(lhs.text()? + &rhs.text()?))
+            })
         }
+        Expr::Add(lhs, rhs) => Ok(ops::Add::add(operand(lhs, ctx)?, operand(rhs, ctx)?)?),
+        Expr::Sub(lhs, rhs) => Ok(ops::Sub::sub(operand(lhs, ctx)?, operand(rhs, ctx)?)?),
+        Expr::Mul(lhs, rhs) => Ok(ops::Mul::mul(operand(lhs, ctx)?, operand(rhs, ctx)?)?),
+        Expr::Div(lhs, rhs) => Ok(ops::Div::div(operand(lhs, ctx)?, operand(rhs, ctx)?)?),
+        Expr::Eq(lhs, rhs) => compare(lhs, rhs, ctx, |ord| ord == Some(Ordering::Equal)),
+        Expr::Ne(lhs, rhs) => compare(lhs, rhs, ctx, |ord| ord != Some(Ordering::Equal)),
+        Expr::Lt(lhs, rhs) => compare(lhs, rhs, ctx, |ord| ord == Some(Ordering::Less)),
+        Expr::Le(lhs, rhs) => compare(lhs, rhs, ctx, |ord| {
+            matches!(ord, Some(Ordering::Less | Ordering::Equal))
+        }),
+        Expr::Gt(lhs, rhs) => compare(lhs, rhs, ctx, |ord| ord == Some(Ordering::Greater)),
+        Expr::Ge(lhs, rhs) => compare(lhs, rhs, ctx, |ord| {
+            matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+        }),
+        // the rhs is only evaluated when needed, so it only becomes a dependency when it matters
+        Expr::And(lhs, rhs) => Ok((eval(lhs, ctx)?.truthy()? && eval(rhs, ctx)?.truthy()?).into()),
+        Expr::Or(lhs, rhs) => Ok((eval(lhs, ctx)?.truthy()? || eval(rhs, ctx)?.truthy()?).into()),
+        Expr::Not(expr) => Ok((!eval(expr, ctx)?.truthy()?).into()),
+        // native functions take precedence over the workbook's named functions
+        Expr::Call(fn_name, args) => match ctx.sl.registry().get(fn_name) {
+            Some(function) => function.call(ctx, args),
+            None => call_named(ctx, fn_name, args),
+        },
+        // only a function can call it
+        Expr::Lambda(..) => Err(Error::OperationUnavailable),
     }
 }
 
-/// Functions that can be called within a formula
-mod functions {
-
-    /// Helper macro to generate argument count validation for any number of argument counts
-    // we don't even have to be explicit, we can just do macro-fu to get the number of args. Yeah bay-beeeee
-    macro_rules! function {
-        ($name:ident($ctx:ident $(, $arg:ident)*) $body:expr) => {
-            pub fn $name($ctx: &mut EvalCtx, args: &[Expr]) -> Result<Value, Error> {
-                #[allow(unreachable_patterns)] // if it's a no-arg fn, there's duplication
-                match args {
-                    [$($arg),*] => $body,
-                    [] => Err(Error::InsufficientArgs),
-                    [..] => Err(Error::TooManyArgs),
-                }
-            }
-        };
-    }
-
-    // These are the sub-groups that Sheets identifies
-    pub use info::*;
-    pub use logical::*;
-    pub use math::*;
-    pub use statistical::*;
-
-    mod info {
-        use crate::{
-            Error,
-            cell::Value,
-            language::{EvalCtx, Expr, eval},
-        };
-
-        function!(
-            is_blank(ctx, arg) {
-                Ok(matches!(eval(arg, ctx)?, Value::Bool(_)).into())
-            }
-        );
-
-        function!(
-            is_formula(ctx, arg) {
-                Ok(matches!(eval(arg, ctx)?, Value::Formula(_)).into())
-            }
-        );
+/// Calls a lambda with the given arguments, whose body also sees the variables of where it was written
+pub(crate) fn call_lambda(
+    ctx: &mut EvalCtx<'_>,
+    lambda: &Expr,
+    args: Vec<Value>,
+) -> Result<Value, Error> {
+    let Expr::Lambda(params, body) = lambda else {
+        return Err(Error::OperationUnavailable);
+    };
+    match args.len().cmp(&params.len()) {
+        Ordering::Less => return Err(Error::InsufficientArgs),
+        Ordering::Greater => return Err(Error::TooManyArgs),
+        Ordering::Equal => {}
     }
 
-    mod logical {
-        use crate::{
-            Error,
-            cell::Value,
-            language::{EvalCtx, Expr},
-        };
-
-        function!(r#false(ctx) {Ok(false.into())});
+    // parameters shadow the variables around them, as the first one of a name is found
+    let mut locals = params.iter().cloned().zip(args).collect::<Vec<_>>();
+    locals.append(&mut ctx.locals);
+    ctx.locals = locals;
+    let res = eval(body, ctx);
+    ctx.locals.drain(..params.len());
+    res
+}
 
-        function!(r#true(ctx) {Ok(true.into())});
+/// Calls one of the workbook's named functions, with its arguments evaluated in the caller's scope
+fn call_named(ctx: &mut EvalCtx<'_>, name: &str, args: &[Expr]) -> Result<Value, Error> {
+    // recorded even if it doesn't exist yet, so that defining it updates the caller
+    ctx.dependencies
+        .push(Dependency::Function(name.to_string()));
+
+    let sl = ctx.sl;
+    let function = sl.function(name).ok_or(Error::FunctionNotAvailable)?;
+    match args.len().cmp(&function.params.len()) {
+        Ordering::Less => return Err(Error::InsufficientArgs),
+        Ordering::Greater => return Err(Error::TooManyArgs),
+        Ordering::Equal => {}
     }
-
-    mod math {
-        use std::ops;
-
-        use crate::{
-            Error,
-            cell::Value,
-            language::{EvalCtx, Expr, eval},
-        };
-
-        function!(abs(ctx, arg) {
-            let val = eval(arg, ctx)?;
-
-            todo!()
-        });
-
-        function!(power(ctx, base, exponent) {
-            let base = eval(base, ctx)?;
-            let exponent = eval(exponent, ctx)?;
-
-            match (base, exponent) {
-                (Value::Number(base), Value::Number(exponent)) => {
-                    Ok(base.powf(exponent).into())
-                }
-                _ => Err(Error::RefMustBeNumber),
-            }
-        });
-
-        // since it takes any number of args, we don't need the macro
-        pub fn sum(ctx: &mut EvalCtx, args: &[Expr]) -> Result<Value, Error> {
-            let mut arg_vals = vec![];
-            for arg in args {
-                arg_vals.push(eval(arg, ctx)?);
-            }
-            arg_vals.into_iter().try_fold(Value::None, ops::Add::add)
-        }
+    if ctx.depth >= MAX_CALL_DEPTH {
+        return Err(Error::MaxRecursionReached);
     }
 
-    mod statistical {
-        use crate::{
-            Error,
-            cell::Value,
-            language::{EvalCtx, Expr, functions::sum},
-        };
-
-        pub fn average(ctx: &mut EvalCtx, args: &[Expr]) -> Result<Value, Error> {
-            let len = args.len();
-            if len == 0 {
-                return Ok(Value::Number(0.0));
-            }
-
-            let sum = sum(ctx, args)?;
-
-            sum / Value::Number(len as f64)
-        }
-    }
+    // failed arguments are passed as error values, so that the body can still check for them
+    let locals = function
+        .params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| {
+            let val = eval(arg, ctx).unwrap_or_else(|e| Value::Error(e.into()));
+            (param.clone(), val)
+        })
+        .collect();
+
+    // the body only sees its own parameters
+    let caller_locals = std::mem::replace(&mut ctx.locals, locals);
+    ctx.depth += 1;
+    let res = eval(function.body(), ctx);
+    ctx.depth -= 1;
+    ctx.locals = caller_locals;
+    res
 }
 
 #[cfg(test)]
@@ -361,9 +1220,11 @@ mod tests {
 
     use crate::{
         Error, Spanleaf,
-        cell::{CellIdx, Value},
+        cell::{Array, CellIdx, ErrorKind, Value},
+        datetime::{Date, DateTime, Duration},
+        formula::Formula,
         language::{EvalCtx, Expr, eval, parser},
-        sheet::SheetIdx,
+        sheet::{Axis, SheetIdx},
     };
 
     #[test]
@@ -390,12 +1251,35 @@ mod tests {
             "bad_sheet_name[1, 2]",
             "&sheet_name[6, 6]",
             "'words are words'",
+            "[0..r-1, c]",
+            "[2, 0..5]",
+            "sheet_name[0..9, 0..3]",
+            "&[0 .. 2, 1]",
+            "sum([0..4, 0], [1, 1..2])",
+            "[r, 0] > 10",
+            "1 + 2 == 3",
+            "[0, 0] != 'words'",
+            "1 <= 2 && 2 >= 1 || !true",
+            "!![0, 0] < -1",
+            "{1, 2; 3, 4}",
+            "{[0, 0] * 2; 'a'} & 'b'",
+            "map([0..4, 0], (x) => x * 2)",
+            "reduce({1, 2}, 0, (acc, x) => acc + x)",
         ];
 
         for s in good_strings {
-            let parser = parser();
+            let parser = parser(None);
             let x = dbg!(parser.parse(s).unwrap());
         }
+
+        // lambda parameters that would never be variables
+        for s in [
+            "map([0, 0], (r) => r)",
+            "map([0, 0], (A1) => 1)",
+            "map([0, 0], () => 1)",
+        ] {
+            assert!(parser(None).parse(s).into_result().is_err(), "{s}");
+        }
     }
 
     fn evaluate_dummy(expr: &Expr) -> Result<Value, Error> {
@@ -403,9 +1287,11 @@ mod tests {
             expr,
             &mut EvalCtx {
                 sl: &mut Spanleaf::new(),
-                curr_sheet: SheetIdx::next(),
+                curr_sheet: SheetIdx(0),
                 dependencies: &mut vec![],
                 curr_cell: CellIdx { row: 0, col: 0 },
+                locals: vec![],
+                depth: 0,
             },
         )
     }
@@ -442,8 +1328,8 @@ mod tests {
     fn references() {
         let mut sl = Spanleaf::new();
 
-        let s0 = sl.insert_sheet("sheet_name");
-        let s1 = sl.insert_sheet("other_sheet");
+        let s0 = sl.insert_sheet("sheet_name").unwrap();
+        let s1 = sl.insert_sheet("other_sheet").unwrap();
 
         sl.insert(s0, CellIdx::new(0, 0), "=sum(1,2,3,4,)").unwrap();
 
@@ -459,4 +1345,374 @@ mod tests {
         dbg!(sl.get(s0, CellIdx::new(0, 0)).unwrap());
         dbg!(sl.get(s1, CellIdx::new(1, 1)).unwrap());
     }
+
+    #[test]
+    fn a1_refs() {
+        let parse = |script: &str, shared| parser(shared).parse(script).unwrap().to_string();
+
+        // in a single cell, relative and absolute refs are the same
+        assert_eq!(parse("B7 + $A$1", None), "[6, 1] + [0, 0]");
+        assert_eq!(
+            parse("SUM(C10:A1, sheet_2!AA3)", None),
+            "SUM([0..9, 0..2], sheet_2[2, 26])"
+        );
+        assert_eq!(parse("&Other!$B2:C$3", None), "Other[1..2, 1..2]");
+        assert_eq!(parse("A1!=B1", None), "[0, 0] != [0, 1]");
+        // defaults follow the current row or col along their axis, unless anchored with '$'
+        assert_eq!(parse("A1 * B$2", Some(Axis::Row)), "[r, 0] * [1, 1]");
+        assert_eq!(parse("$A2:B4", Some(Axis::Col)), "[1..3, 0..c + 1]");
+        // native refs to sheets named like cells still work
+        assert_eq!(parse("AB1[0, 0]", None), "AB1[0, 0]");
+
+        assert!(parser(None).parse("A0").into_result().is_err());
+        assert!(parser(None).parse("b7").into_result().is_err());
+        assert!(parser(None).parse("ABCD1").into_result().is_err());
+
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Sheet2").unwrap();
+        for row in 0..4 {
+            sl.insert(s0, CellIdx::new(row, 0), row + 1).unwrap();
+        }
+        // a running total, written as if in the first row
+        sl.insert_col_default(s0, 1, "=sum($A$1:A1)").unwrap();
+        sl.insert_row_default(s0, 5, "=A1 * 10").unwrap();
+        sl.insert(s1, CellIdx::new(0, 0), "=Sheet1!B4 + Sheet1!C6")
+            .unwrap();
+
+        let get = |sref, row, col| sl.get(sref, CellIdx::new(row, col)).unwrap().value();
+        assert_eq!(get(s0, 0, 1), 1.into());
+        assert_eq!(get(s0, 3, 1), 10.into());
+        assert_eq!(get(s0, 5, 2), 10.into());
+        assert_eq!(get(s1, 0, 0), 20.into());
+    }
+
+    #[test]
+    fn ranges() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("sheet_name").unwrap();
+
+        for row in 0..5 {
+            sl.insert(s0, CellIdx::new(row, 0), row).unwrap();
+        }
+        sl.insert(s0, CellIdx::new(0, 1), "=sum([0..4, 0])")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(1, 1), "=average(sheet_name[1..3, 0])")
+            .unwrap();
+        // running total, which is 0 on the first row
+        sl.insert_col_default(s0, 2, "=sum([0..r-1, 0])").unwrap();
+
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 1)).unwrap(), 10.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(1, 1)).unwrap(), 2.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 2)).unwrap(), 0.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(3, 2)).unwrap(), 3.into());
+
+        // editing anywhere inside the range invalidates the result
+        sl.insert(s0, CellIdx::new(2, 0), 12).unwrap();
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 1)).unwrap(), 20.into());
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(1, 1)).unwrap(),
+            (16.0 / 3.0).into()
+        );
+        assert_eq!(*sl.get(s0, CellIdx::new(3, 2)).unwrap(), 13.into());
+
+        // as do defaults that intersect the range
+        sl.insert_row_default(s0, 4, 1).unwrap();
+        sl.insert(s0, CellIdx::new(4, 0), ()).unwrap();
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 1)).unwrap(), 17.into());
+    }
+
+    #[test]
+    fn conditions() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("sheet_name").unwrap();
+
+        let cases: [(&str, Value); 15] = [
+            ("=1 + 2 == 3", true.into()),
+            ("=1 < 2 == 2 > 1", true.into()),
+            ("=2 * 3 >= 7", false.into()),
+            ("='apple' < 'banana'", true.into()),
+            ("='apple' != 'apple'", false.into()),
+            // blanks compare as the empty value of the other side
+            ("=[9, 9] == 0", true.into()),
+            ("=[9, 9] == ''", true.into()),
+            ("=[9, 9] == false", true.into()),
+            // numbers < strings < bools
+            ("=10 < '1'", true.into()),
+            ("='z' < false", true.into()),
+            ("=true && 1 > 0", true.into()),
+            ("=false || 0", false.into()),
+            ("=!0 && !false", true.into()),
+            ("=1 == 1 && 2 == 3 || 4 == 4", true.into()),
+            // short-circuiting skips the invalid rhs
+            ("=false && 'not a bool'", false.into()),
+        ];
+
+        for (i, (script, expected)) in cases.into_iter().enumerate() {
+            let cref = CellIdx::new(i as u64, 0);
+            sl.insert(s0, cref, script).unwrap();
+            assert_eq!(*sl.get(s0, cref).unwrap(), expected, "{script}");
+        }
+
+        sl.insert(s0, CellIdx::new(0, 1), "=true && 'not a bool'")
+            .unwrap();
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(0, 1)).unwrap(),
+            Value::Error(ErrorKind::Value)
+        );
+    }
+
+    #[test]
+    fn concatenation() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1.5).unwrap();
+
+        let cases: [(&str, Value); 6] = [
+            (
+                "='total: ' & [0, 0]",
+                Value::String("total: 1.5".to_string()),
+            ),
+            // joins after arithmetic, and before comparison
+            ("=1 + 2 & 3 * 4", Value::String("312".to_string())),
+            ("='a' & 'b' == 'ab'", true.into()),
+            // blanks are empty
+            ("=[9, 9] & true & [9, 9]", Value::String("true".to_string())),
+            // `+` is only arithmetic on mixed types
+            ("='total: ' + [0, 0]", Value::Error(ErrorKind::Value)),
+            ("='a' & 1 / 0", Value::Error(ErrorKind::DivByZero)),
+        ];
+
+        for (i, (script, expected)) in cases.into_iter().enumerate() {
+            let cref = CellIdx::new(i as u64 + 1, 0);
+            sl.insert(s0, cref, script).unwrap();
+            assert_eq!(*sl.get(s0, cref).unwrap(), expected, "{script}");
+        }
+    }
+
+    #[test]
+    fn arrays() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), 2).unwrap();
+
+        let eval = |script: &str| {
+            sl.eval_formula(s0, CellIdx::new(10, 10), &Formula::parse(script).unwrap())
+        };
+        let array = |rows: Vec<Vec<Value>>| Value::Array(Array::new(rows).unwrap());
+        let err = Value::Error;
+
+        assert!(parser(None).parse("{1, 2; 3}").into_result().is_err());
+        assert_eq!(
+            eval("={1, 2; 3, 4}"),
+            array(vec![vec![1.into(), 2.into()], vec![3.into(), 4.into()]])
+        );
+
+        // a single row or column is repeated to match the other side
+        assert_eq!(
+            eval("={1, 2} + {10; 20}"),
+            array(vec![vec![11.into(), 12.into()], vec![21.into(), 22.into()]])
+        );
+        assert_eq!(
+            eval("=[0..1, 0] * 2"),
+            array(vec![vec![2.into()], vec![4.into()]])
+        );
+        assert_eq!(
+            eval("={1, 2, 3} - {1, 1}"),
+            array(vec![vec![0.into(), 1.into(), err(ErrorKind::NotAvailable)]])
+        );
+        // failures are per element
+        assert_eq!(
+            eval("={1, 0} / 0 == 1"),
+            array(vec![vec![
+                err(ErrorKind::DivByZero),
+                err(ErrorKind::DivByZero)
+            ]])
+        );
+        assert_eq!(
+            eval("={1, 1 / 0} > 0"),
+            array(vec![vec![true.into(), err(ErrorKind::DivByZero)]])
+        );
+        assert_eq!(
+            eval("='#' & {1; 2}"),
+            array(vec![
+                vec!["#1".try_into().unwrap()],
+                vec!["#2".try_into().unwrap()]
+            ])
+        );
+        assert_eq!(
+            eval("=-{1, [0..1, 0]}"),
+            array(vec![vec![(-1).into(), err(ErrorKind::Value)]])
+        );
+
+        // functions take arrays like ranges
+        assert_eq!(eval("=sum({1, 2; 3, 4} * [0..1, 0])"), 17.into());
+        assert_eq!(eval("=if({1, 2}, 1, 0)"), err(ErrorKind::Value));
+
+        // a range too large to hold all at once can't be an array
+        assert_eq!(eval("=[0..4294967295, 0] + 1"), err(ErrorKind::Num));
+        assert_eq!(eval("=unique([0..4294967295, 0])"), err(ErrorKind::Num));
+        assert_eq!(eval("=transpose([0..1e6, 0..1e6])"), err(ErrorKind::Num));
+        // and one past the edge of the sheet isn't a range at all
+        assert_eq!(eval("=[0..1e30, 0]"), err(ErrorKind::Ref));
+        assert_eq!(eval("=[-5..2, 0]"), err(ErrorKind::Ref));
+        assert_eq!(eval("=[0..1.5, 0]"), err(ErrorKind::Ref));
+        assert_eq!(eval("=rows([2..1, 0])"), 0.into());
+    }
+
+    #[test]
+    fn dates() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), "2024-01-31").unwrap();
+
+        let date = |s| Value::Date(Date::parse(s).unwrap());
+        let datetime = |s| Value::DateTime(DateTime::parse(s).unwrap());
+        let duration = |s| Value::Duration(Duration::parse(s).unwrap());
+        let cases: [(&str, Value); 10] = [
+            ("=[0, 0] - @2024-01-01", duration("30d")),
+            ("=[0, 0] + @1d", date("2024-02-01")),
+            // numbers are days
+            ("=[0, 0] - 31", date("2023-12-31")),
+            ("=[0, 0] + @-1d12h", datetime("2024-01-29T12:00")),
+            ("=@2024-01-31T09:30 - [0, 0]", duration("9h30m")),
+            ("=@1h30m * 2 / @1h", 3.into()),
+            ("=-@90s", duration("-1m30s")),
+            ("=[0, 0] > @2024-01-30T23:59", true.into()),
+            ("=[0, 0] + [0, 0]", Value::Error(ErrorKind::Value)),
+            ("=@1d / 0", Value::Error(ErrorKind::DivByZero)),
+        ];
+
+        for (i, (script, expected)) in cases.into_iter().enumerate() {
+            let cref = CellIdx::new(i as u64 + 1, 0);
+            sl.insert(s0, cref, script).unwrap();
+            assert_eq!(*sl.get(s0, cref).unwrap(), expected, "{script}");
+        }
+        assert!(parser(None).parse("@2024-02-30").into_result().is_err());
+    }
+
+    #[test]
+    fn conditionals() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("sheet_name").unwrap();
+
+        // guarding the first rows of a fibonacci column
+        sl.insert_col_default(s0, 0, "=if(r < 2, r, [r-1, c] + [r-2, c])")
+            .unwrap();
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 0)).unwrap(), 0.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(10, 0)).unwrap(), 55.into());
+
+        sl.insert(s0, CellIdx::new(0, 1), "=if([0, 2], [0, 3], [0, 4])")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(0, 3), "then").unwrap();
+        sl.insert(s0, CellIdx::new(0, 4), "otherwise").unwrap();
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(0, 1)).unwrap(),
+            "otherwise".try_into().unwrap()
+        );
+
+        // only the taken branch is a dependency
+        let is_dependency = |sl: &Spanleaf, col| {
+            sl.dependencies
+                .borrow()
+                .get(&(s0, CellIdx::new(0, col)))
+                .is_some_and(|deps| deps.contains(&(s0, CellIdx::new(0, 1))))
+        };
+        assert!(is_dependency(&sl, 2));
+        assert!(!is_dependency(&sl, 3));
+        assert!(is_dependency(&sl, 4));
+
+        sl.insert(s0, CellIdx::new(0, 2), true).unwrap();
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(0, 1)).unwrap(),
+            "then".try_into().unwrap()
+        );
+        assert!(is_dependency(&sl, 3));
+
+        let cases: [(&str, Value); 5] = [
+            ("=if(false, 1)", ().into()),
+            ("=ifs(1 > 2, 'a', 2 > 1, 'b')", "b".try_into().unwrap()),
+            ("=switch(2, 1, 'one', 2, 'two')", "two".try_into().unwrap()),
+            (
+                "=switch(3, 1, 'one', 2, 'two', 'many')",
+                "many".try_into().unwrap(),
+            ),
+            // the invalid branch is never evaluated
+            ("=if(true, 1, 1 / 'a')", 1.into()),
+        ];
+        for (i, (script, expected)) in cases.into_iter().enumerate() {
+            let cref = CellIdx::new(i as u64 + 1, 1);
+            sl.insert(s0, cref, script).unwrap();
+            assert_eq!(*sl.get(s0, cref).unwrap(), expected, "{script}");
+        }
+    }
+
+    #[test]
+    fn printing() {
+        let cases = [
+            ("1+2*3", "1 + 2 * 3"),
+            ("(1+2)*3", "(1 + 2) * 3"),
+            ("1-(2-3)", "1 - (2 - 3)"),
+            ("(1-2)-3", "1 - 2 - 3"),
+            ("-(1+2)", "-(1 + 2)"),
+            ("!(a[0,0] && true) || false", "!(a[0, 0] && true) || false"),
+            ("sum([0..r-1,c],&[1,1])", "sum([0..r - 1, c..c], &[1, 1])"),
+            ("[2, 0..5]", "[2..2, 0..5]"),
+            ("*[1,2]", "*[1, 2]"),
+            ("'words' == #N/A", "'words' == #N/A"),
+            ("'a'&1+2&[0,0]", "'a' & 1 + 2 & [0, 0]"),
+            ("('a'&'b')+1", "('a' & 'b') + 1"),
+            ("[0,0]&&[0,1]&'x'", "[0, 0] && [0, 1] & 'x'"),
+            ("@2024-01-31+@1d2h", "@2024-01-31 + @1d2h"),
+            ("@2024-01-31T09:30-@-90s", "@2024-01-31T09:30:00 - @-1m30s"),
+            ("@1d-A1", "@1d - [0, 0]"),
+            ("@2024-01-31-@1d", "@2024-01-31 - @1d"),
+            ("{1,2;-3,'x'}*2", "{1, 2; -3, 'x'} * 2"),
+            ("map([0,0],(x)=>x*2)", "map([0, 0], (x) => x * 2)"),
+            (
+                "reduce(a[0..1,0],0,( acc , x )=>acc+x)",
+                "reduce(a[0..1, 0..0], 0, (acc, x) => acc + x)",
+            ),
+            ("((x) => x) + 1", "((x) => x) + 1"),
+        ];
+
+        for (script, printed) in cases {
+            let expr = parser(None).parse(script).unwrap();
+            assert_eq!(expr.to_string(), printed);
+            assert_eq!(parser(None).parse(printed).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn lambdas() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let eval = |script: &str| {
+            sl.eval_formula(s0, CellIdx::new(0, 0), &Formula::parse(script).unwrap())
+        };
+
+        // parameters shadow the variables around them, and are gone after the call
+        assert_eq!(
+            eval("=reduce({1, 2}, 0, (acc, x) => acc + reduce({10}, x, (acc, y) => acc * y))"),
+            30.into()
+        );
+        assert_eq!(
+            eval("=map({1}, (x) => x) + x"),
+            Value::Error(ErrorKind::Name)
+        );
+        assert_eq!(
+            parser(None).parse("map(x, (x) => -x)").unwrap().tree(),
+            "Call map\n  x\n  Lambda x\n    Neg\n      x\n"
+        );
+    }
+
+    #[test]
+    fn tree() {
+        let expr = parser(None).parse("-a[0, c] + sum([1..2, 0])").unwrap();
+        assert_eq!(
+            expr.tree(),
+            "Add\n  Neg\n    CellDeref\n      CellRef\n        sheet: a\n        row: 0\n        col: c\n  Call sum\n    RangeRef\n      rows: 1\n      to: 2\n      cols: 0\n      to: 0\n"
+        );
+    }
 }
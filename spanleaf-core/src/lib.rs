@@ -4,14 +4,26 @@ use std::{
 };
 
 use crate::{
-    cell::{CellIdx, Value},
-    sheet::{Sheet, SheetIdx, ValueResult, ValueSource},
+    cell::{Array, CellIdx, CellRange, ErrorKind, Value},
+    formula::{Formula, FunctionError, NamedFunction},
+    functions::{FunctionRegistry, NativeFunction},
+    sheet::{Axis, Sheet, SheetIdx, Shift, ValueResult, ValueSource},
 };
 
 pub mod cell;
+pub mod csv;
+pub mod datetime;
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+mod excel;
+pub mod file;
 pub mod formula;
+pub mod functions;
 mod language;
+#[cfg(feature = "ods")]
+pub mod ods;
 pub mod sheet;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
 
 // Potential configuration, used it for a bit, but nothing currently, but might still later
 #[derive(Debug)]
@@ -25,10 +37,44 @@ pub enum Error {
     RefMustBeNumber,
     OperationUnavailable,
     FunctionNotAvailable,
+    /// A variable that isn't a parameter of the named function being evaluated
+    UnknownVariable(String),
     DivideByZero,
+    /// A numeric result that isn't a finite number, i.e. the square root of a negative number
+    InvalidNumber,
+    /// An iterative calculation that didn't settle on a result, i.e. the rate of return of cash flows that have none
+    NoConvergence,
     InsufficientArgs,
     TooManyArgs,
     SheetNotFound,
+    /// Every sheet index has been used up, i.e. by a loaded file with the largest possible one
+    NoSheetIdxLeft,
+    /// A reference before the first row or col of the sheet, or outside of the range it's within
+    RefOutOfBounds,
+    /// Inserted rows or cols would push cells or defaults past the last row or col of the sheet
+    NoRoomToInsert,
+    /// A sheet with the given name already exists
+    DuplicateSheetName(String),
+    /// The name can't be referred to from formulas
+    InvalidSheetName(String),
+    NoMatch,
+    /// An error value was encountered where it couldn't be propagated as a value
+    ErrorValue(ErrorKind),
+}
+
+/// Something that the value of a formula depends on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dependency {
+    /// A single cell
+    Cell(SheetIdx, CellIdx),
+    /// Every cell within a range, recorded as a whole so that the range can be arbitrarily large
+    Range(SheetIdx, CellRange),
+    /// A named function, by name, whether or not it's currently defined
+    Function(String),
+    /// A sheet, by name, that didn't exist when the formula was calculated
+    Sheet(String),
+    /// A volatile native function, whose result can change at any time
+    Volatile,
 }
 
 #[derive(Debug)]
@@ -40,15 +86,62 @@ enum CacheEntry {
     Calculated(Value),
 }
 
+/// The cells that depend on something
+type Dependents = BTreeSet<(SheetIdx, CellIdx)>;
+
+/// The cells that an array resulting from the formula in `anchor` covers, with `anchor` as its top left, or
+/// None if it would run past the last row or col of the sheet
+fn spill_area(anchor: CellIdx, array: &Array) -> Option<CellRange> {
+    let end = |start: u64, len: usize| {
+        start
+            .checked_add(len as u64 - 1)
+            .filter(|end| *end <= Sheet::LAST_INDEX)
+    };
+    Some(CellRange::new(
+        anchor,
+        CellIdx::new(
+            end(anchor.row, array.rows())?,
+            end(anchor.col, array.cols())?,
+        ),
+    ))
+}
+
 #[derive(Debug)]
 pub struct Spanleaf {
     sheets: BTreeMap<SheetIdx, Sheet>,
+    /// The order sheets are displayed and saved in
+    order: Vec<SheetIdx>,
+    /// Index of sheet names, for resolving cross-sheet references
+    sheet_names: BTreeMap<String, SheetIdx>,
+    /// The id to give the next inserted sheet
+    next_sheet: u64,
 
     // could probably refactor into a Cache type for convenience
     /// Cache of values to reduce duplicate calculation and detect cyclic dependencies
     cache: RefCell<BTreeMap<(SheetIdx, CellIdx), CacheEntry>>,
     /// Chain of dependencies, where the key is the dependee, and the value is a set of dependents
-    dependencies: RefCell<BTreeMap<(SheetIdx, CellIdx), BTreeSet<(SheetIdx, CellIdx)>>>,
+    dependencies: RefCell<BTreeMap<(SheetIdx, CellIdx), Dependents>>,
+    /// Same as `dependencies`, but where the dependee is a whole range of cells
+    range_dependencies: RefCell<BTreeMap<(SheetIdx, CellRange), Dependents>>,
+    /// Same as `dependencies`, but where the dependee is a named function
+    function_dependencies: RefCell<BTreeMap<String, Dependents>>,
+    /// Same as `dependencies`, but where the dependee is the name of a sheet that doesn't exist yet
+    sheet_dependencies: RefCell<BTreeMap<String, Dependents>>,
+    /// Cells that called a volatile function, to be recalculated on demand
+    volatile: RefCell<BTreeSet<(SheetIdx, CellIdx)>>,
+    /// Arrays that formulas resulted in, by the formula's cell, which spill into the empty cells to the
+    /// right and below it
+    spills: RefCell<BTreeMap<(SheetIdx, CellIdx), Array>>,
+    /// The cells that arrays cover, or would if nothing was in the way, by the formula's cell. These outlive
+    /// the formula's cached value, so that reading one of them calculates it again
+    spill_areas: RefCell<BTreeMap<(SheetIdx, CellIdx), CellRange>>,
+    /// Cells that might have read an empty cell while they were being calculated, which an array has since
+    /// spilled into, so they're calculated again once they're done
+    redo: RefCell<BTreeSet<(SheetIdx, CellIdx)>>,
+    /// Workbook level functions, callable from any formula, by name
+    functions: BTreeMap<String, NamedFunction>,
+    /// Functions written in Rust, callable from any formula, by name
+    registry: FunctionRegistry,
 
     _config: Config,
 }
@@ -58,18 +151,219 @@ impl Spanleaf {
             _config: Config {},
 
             sheets: Default::default(),
+            order: Default::default(),
+            sheet_names: Default::default(),
+            next_sheet: 0,
             cache: Default::default(),
             dependencies: Default::default(),
+            range_dependencies: Default::default(),
+            function_dependencies: Default::default(),
+            sheet_dependencies: Default::default(),
+            volatile: Default::default(),
+            spills: Default::default(),
+            spill_areas: Default::default(),
+            redo: Default::default(),
+            functions: Default::default(),
+            registry: FunctionRegistry::standard(),
         }
     }
 
     /// Inserts a new sheet to the Spanleaf
     ///
-    /// Because this is the only way to get a sheet index, we can know that it'll be present
-    pub fn insert_sheet(&mut self, name: impl ToString) -> SheetIdx {
-        let sref = SheetIdx::next();
-        self.sheets.insert(sref, Sheet::new(name));
-        sref
+    /// Because this is the only way to get a sheet index, we can know that it'll be present.
+    /// Sheet names must be unique within the Spanleaf, and usable from formulas, i.e. `Sheet_2`
+    pub fn insert_sheet(&mut self, name: impl ToString) -> Result<SheetIdx, Error> {
+        let sref = SheetIdx(self.next_sheet);
+        self.insert_sheet_with_idx(sref, name.to_string())?;
+        Ok(sref)
+    }
+
+    /// Inserts a sheet with a known index, such as when loading a saved workbook
+    pub(crate) fn insert_sheet_with_idx(
+        &mut self,
+        sref: SheetIdx,
+        name: String,
+    ) -> Result<(), Error> {
+        if !language::is_sheet_name(&name) {
+            return Err(Error::InvalidSheetName(name));
+        }
+        if self.sheet_names.contains_key(&name) {
+            return Err(Error::DuplicateSheetName(name));
+        }
+        debug_assert!(!self.sheets.contains_key(&sref));
+        let next = sref.0.checked_add(1).ok_or(Error::NoSheetIdxLeft)?;
+
+        self.next_sheet = self.next_sheet.max(next);
+        self.sheet_names.insert(name.clone(), sref);
+        self.sheets.insert(sref, Sheet::new(&name));
+        self.order.push(sref);
+        // formulas may have named the sheet before it was inserted
+        self.clear_sheet_from_cache(&name);
+        Ok(())
+    }
+
+    /// Renames a sheet, updating every reference to it from formulas on any sheet
+    ///
+    /// The new name must be unique, and usable from formulas, i.e. `Sheet_2`
+    pub fn rename_sheet(&mut self, sref: SheetIdx, name: impl ToString) -> Result<(), Error> {
+        let name = name.to_string();
+        let sheet = self.sheets.get_mut(&sref).ok_or(Error::SheetNotFound)?;
+        if sheet.name == name {
+            return Ok(());
+        }
+        if !language::is_sheet_name(&name) {
+            return Err(Error::InvalidSheetName(name));
+        }
+        if self.sheet_names.contains_key(&name) {
+            return Err(Error::DuplicateSheetName(name));
+        }
+
+        let old = std::mem::replace(&mut sheet.name, name.clone());
+        self.sheet_names.remove(&old);
+        self.sheet_names.insert(name.clone(), sref);
+
+        self.rewrite_formulas(|_, expr| language::rename_sheet_refs(expr, &old, &name));
+        // formulas that referenced the new name before it existed now resolve
+        self.reset_cache();
+        Ok(())
+    }
+
+    /// Deletes a sheet, returning it.
+    ///
+    /// References to the sheet, from formulas on any other sheet, become `#REF!` errors
+    pub fn delete_sheet(&mut self, sref: SheetIdx) -> Result<Sheet, Error> {
+        let sheet = self.sheets.remove(&sref).ok_or(Error::SheetNotFound)?;
+        self.sheet_names.remove(&sheet.name);
+        self.order.retain(|s| *s != sref);
+
+        self.rewrite_formulas(|_, expr| language::delete_sheet_refs(expr, &sheet.name));
+        self.reset_cache();
+        Ok(sheet)
+    }
+
+    /// Moves a sheet to the given position in the sheet order, clamped to the last position
+    pub fn move_sheet(&mut self, sref: SheetIdx, position: usize) -> Result<(), Error> {
+        let curr = self
+            .order
+            .iter()
+            .position(|s| *s == sref)
+            .ok_or(Error::SheetNotFound)?;
+        self.order.remove(curr);
+        self.order.insert(position.min(self.order.len()), sref);
+        Ok(())
+    }
+
+    /// Copies a sheet, with all of its values and defaults, to a new sheet directly after it
+    pub fn duplicate_sheet(
+        &mut self,
+        sref: SheetIdx,
+        name: impl ToString,
+    ) -> Result<SheetIdx, Error> {
+        let mut sheet = self.sheets.get(&sref).ok_or(Error::SheetNotFound)?.clone();
+        let copy = self.insert_sheet(name)?;
+        sheet.name = self.sheets[&copy].name.clone();
+        self.sheets.insert(copy, sheet);
+
+        let position = self
+            .order
+            .iter()
+            .position(|s| *s == sref)
+            .unwrap_or_default();
+        self.move_sheet(copy, position + 1)?;
+        Ok(copy)
+    }
+
+    /// Every sheet in the Spanleaf, in order
+    pub fn sheets(&self) -> impl Iterator<Item = (SheetIdx, &Sheet)> {
+        self.order.iter().map(|sref| (*sref, &self.sheets[sref]))
+    }
+
+    /// The sheet with the given index
+    pub fn sheet(&self, sref: SheetIdx) -> Option<&Sheet> {
+        self.sheets.get(&sref)
+    }
+
+    /// Looks up a sheet by its name
+    pub fn sheet_by_name(&self, name: &str) -> Option<SheetIdx> {
+        self.sheet_names.get(name).copied()
+    }
+
+    /// Inserts a named function, replacing any function with the same name, which is returned
+    ///
+    /// Native functions take precedence when called, so a name that's registered as one, including by the
+    /// host application, is rejected
+    pub fn insert_function(
+        &mut self,
+        function: NamedFunction,
+    ) -> Result<Option<NamedFunction>, FunctionError> {
+        if self.registry.get(&function.name).is_some() {
+            return Err(FunctionError::Builtin(function.name));
+        }
+        self.clear_function_from_cache(&function.name);
+        Ok(self.functions.insert(function.name.clone(), function))
+    }
+
+    /// Removes a named function, with any formulas calling it becoming `#NAME?` errors
+    pub fn remove_function(&mut self, name: &str) -> Option<NamedFunction> {
+        self.clear_function_from_cache(name);
+        self.functions.remove(name)
+    }
+
+    /// Every named function, in name order
+    pub fn functions(&self) -> impl Iterator<Item = &NamedFunction> {
+        self.functions.values()
+    }
+
+    /// The named function with the given name
+    pub fn function(&self, name: &str) -> Option<&NamedFunction> {
+        self.functions.get(name)
+    }
+
+    /// The native functions formulas can call
+    pub fn registry(&self) -> &FunctionRegistry {
+        &self.registry
+    }
+
+    /// Registers a native function, replacing any native function with the same name, which is returned.
+    ///
+    /// Native functions take precedence over named functions with the same name
+    pub fn register_function(&mut self, function: NativeFunction) -> Option<NativeFunction> {
+        // any formula could have been calling it, by either kind of function
+        self.reset_cache();
+        self.registry.register(function)
+    }
+
+    /// Removes a native function, including a standard one
+    pub fn unregister_function(&mut self, name: &str) -> Option<NativeFunction> {
+        self.reset_cache();
+        self.registry.remove(name)
+    }
+
+    /// Recalculates every cell that called a volatile function, along with anything depending on them
+    pub fn recalculate_volatile(&self) {
+        // scope to drop the borrow
+        let cells = { std::mem::take(&mut *self.volatile.borrow_mut()) };
+        for (sref, cref) in cells {
+            self.clear_from_cache(sref, cref);
+        }
+    }
+
+    /// Clears every cell that called the named function, or tried to before it existed
+    fn clear_function_from_cache(&self, name: &str) {
+        // scope to drop the borrow
+        let deps = { self.function_dependencies.borrow_mut().remove(name) };
+        for dep in deps.into_iter().flatten() {
+            self.clear_from_cache(dep.0, dep.1);
+        }
+    }
+
+    /// Clears every cell that named the sheet before it existed
+    fn clear_sheet_from_cache(&self, name: &str) {
+        // scope to drop the borrow
+        let deps = { self.sheet_dependencies.borrow_mut().remove(name) };
+        for dep in deps.into_iter().flatten() {
+            self.clear_from_cache(dep.0, dep.1);
+        }
     }
 
     /// Inserts a row default to the specified sheet
@@ -82,8 +376,12 @@ impl Spanleaf {
         // clear cache for dependents
         let to_clear = {
             let deps = self.dependencies.borrow();
+            let range_deps = self.range_dependencies.borrow();
             deps.iter()
                 .filter_map(|((sref, cref), v)| (sref == &sheet && cref.row == row).then_some(v))
+                .chain(range_deps.iter().filter_map(|((sref, range), v)| {
+                    (sref == &sheet && range.contains_row(row)).then_some(v)
+                }))
                 .flat_map(|dependants| dependants.iter().cloned())
                 .collect::<Vec<_>>()
         };
@@ -110,8 +408,12 @@ impl Spanleaf {
         // clear cache for dependents
         let to_clear = {
             let deps = self.dependencies.borrow();
+            let range_deps = self.range_dependencies.borrow();
             deps.iter()
                 .filter_map(|((sref, cref), v)| (sref == &sheet && cref.col == col).then_some(v))
+                .chain(range_deps.iter().filter_map(|((sref, range), v)| {
+                    (sref == &sheet && range.contains_col(col)).then_some(v)
+                }))
                 .flat_map(|dependants| dependants.iter().cloned())
                 .collect::<Vec<_>>()
         };
@@ -146,9 +448,143 @@ impl Spanleaf {
             .unwrap_or_default())
     }
 
+    /// Inserts `count` empty rows before `row`, moving everything below down.
+    ///
+    /// Absolute references to the moved cells, from formulas on any sheet, are updated to follow them, and
+    /// references pushed past the last row become `#REF!` errors. Fails if any cell would be pushed past it
+    pub fn insert_rows(&mut self, sheet: SheetIdx, row: u64, count: u64) -> Result<(), Error> {
+        self.insert_shift(
+            sheet,
+            Shift {
+                axis: Axis::Row,
+                at: row,
+                count,
+                delete: false,
+            },
+        )
+    }
+
+    /// Deletes `count` rows starting at `row`, moving everything below up.
+    ///
+    /// Absolute references to the moved cells, from formulas on any sheet, are updated to follow them,
+    /// and references to the deleted cells become `#REF!` errors
+    pub fn delete_rows(&mut self, sheet: SheetIdx, row: u64, count: u64) {
+        self.shift(
+            sheet,
+            Shift {
+                axis: Axis::Row,
+                at: row,
+                count,
+                delete: true,
+            },
+        );
+    }
+
+    /// Inserts `count` empty cols before `col`, moving everything to the right over.
+    ///
+    /// Absolute references to the moved cells, from formulas on any sheet, are updated to follow them, and
+    /// references pushed past the last col become `#REF!` errors. Fails if any cell would be pushed past it
+    pub fn insert_cols(&mut self, sheet: SheetIdx, col: u64, count: u64) -> Result<(), Error> {
+        self.insert_shift(
+            sheet,
+            Shift {
+                axis: Axis::Col,
+                at: col,
+                count,
+                delete: false,
+            },
+        )
+    }
+
+    /// Deletes `count` cols starting at `col`, moving everything to the right over.
+    ///
+    /// Absolute references to the moved cells, from formulas on any sheet, are updated to follow them,
+    /// and references to the deleted cells become `#REF!` errors
+    pub fn delete_cols(&mut self, sheet: SheetIdx, col: u64, count: u64) {
+        self.shift(
+            sheet,
+            Shift {
+                axis: Axis::Col,
+                at: col,
+                count,
+                delete: true,
+            },
+        );
+    }
+
+    /// Shifts the sheet, unless that would push any of its cells or defaults past its edge
+    fn insert_shift(&mut self, sheet: SheetIdx, shift: Shift) -> Result<(), Error> {
+        if self.sheets.get(&sheet).is_some_and(|s| !s.fits(shift)) {
+            return Err(Error::NoRoomToInsert);
+        }
+        self.shift(sheet, shift);
+        Ok(())
+    }
+
+    fn shift(&mut self, sheet: SheetIdx, shift: Shift) {
+        let Some(name) = self.sheets.get_mut(&sheet).map(|s| {
+            s.shift(shift);
+            s.name.clone()
+        }) else {
+            return;
+        };
+
+        self.rewrite_formulas(|sref, expr| {
+            language::shift_refs(
+                expr,
+                |sheet_name| match sheet_name {
+                    Some(sheet_name) => sheet_name == name,
+                    None => sref == Some(sheet),
+                },
+                shift,
+            )
+        });
+
+        // everything could have moved, so start fresh
+        self.reset_cache();
+    }
+
+    /// Applies a rewrite to the AST of every formula, on every sheet, and to the body of every named
+    /// function, which belong to no sheet
+    fn rewrite_formulas(
+        &mut self,
+        mut f: impl FnMut(Option<SheetIdx>, &mut language::Expr) -> bool,
+    ) {
+        for (sref, s) in self.sheets.iter_mut() {
+            for val in s.values_mut() {
+                if let Value::Formula(formula) = val {
+                    formula.rewrite(|expr| f(Some(*sref), expr));
+                }
+            }
+        }
+        for function in self.functions.values_mut() {
+            function.rewrite(|expr| f(None, expr));
+        }
+    }
+
+    /// Throws away every cached value and dependency
+    fn reset_cache(&self) {
+        self.cache.borrow_mut().clear();
+        self.dependencies.borrow_mut().clear();
+        self.range_dependencies.borrow_mut().clear();
+        self.function_dependencies.borrow_mut().clear();
+        self.sheet_dependencies.borrow_mut().clear();
+        self.volatile.borrow_mut().clear();
+        self.spills.borrow_mut().clear();
+        self.spill_areas.borrow_mut().clear();
+        self.redo.borrow_mut().clear();
+    }
+
     pub fn clear_from_cache(&self, sref: SheetIdx, cref: CellIdx) {
         // scope to drop the borrow
-        let _maybe_e = { self.cache.borrow_mut().remove(&(sref, cref)) };
+        let _maybe_e = {
+            let mut cache = self.cache.borrow_mut();
+            // a cell that's still being calculated is calculated again once it's done, see [Spanleaf::land]
+            match cache.get(&(sref, cref)) {
+                Some(CacheEntry::Calculating) => None,
+                _ => cache.remove(&(sref, cref)),
+            }
+        };
 
         // scope to drop the borrow
         let maybe_deps = { self.dependencies.borrow_mut().remove(&(sref, cref)) };
@@ -158,18 +594,100 @@ impl Spanleaf {
                 self.clear_from_cache(dep.0, dep.1);
             }
         }
+
+        // scope to drop the borrow
+        let range_deps = {
+            let mut range_deps = self.range_dependencies.borrow_mut();
+            let ranges = range_deps
+                .keys()
+                .filter(|(s, range)| s == &sref && range.contains(cref))
+                .cloned()
+                .collect::<Vec<_>>();
+            ranges
+                .into_iter()
+                .filter_map(|k| range_deps.remove(&k))
+                .collect::<Vec<_>>()
+        };
+
+        for dep in range_deps.into_iter().flatten() {
+            self.clear_from_cache(dep.0, dep.1);
+        }
+
+        // the cells an array spilled into go along with the formula that resulted in it
+        // scope to drop the borrow
+        let spill = { self.spills.borrow_mut().remove(&(sref, cref)) };
+        if let Some(area) = spill.and_then(|array| spill_area(cref, &array)) {
+            for spilled in area.iter().filter(|c| *c != cref) {
+                self.clear_from_cache(sref, spilled);
+            }
+        }
+
+        // as do the empty cells it would have spilled into if nothing was in the way
+        let area = { self.spill_areas.borrow().get(&(sref, cref)).copied() };
+        if let Some(area) = area {
+            for (s, reader) in self.take_readers(sref, cref, area) {
+                self.clear_from_cache(s, reader);
+            }
+        }
+    }
+
+    /// Forgets which cells read any of the cells within the area that an array resulting from the formula in
+    /// `anchor` covers, other than the formula itself, returning them
+    fn take_readers(
+        &self,
+        sref: SheetIdx,
+        anchor: CellIdx,
+        area: CellRange,
+    ) -> Vec<(SheetIdx, CellIdx)> {
+        let mut readers = vec![];
+        {
+            let mut deps = self.dependencies.borrow_mut();
+            let cells = deps
+                .range((sref, area.start)..=(sref, area.end))
+                .map(|(key, _)| *key)
+                .filter(|(_, c)| area.contains(*c))
+                .collect::<Vec<_>>();
+            for cell in cells {
+                readers.extend(deps.remove(&cell).into_iter().flatten());
+            }
+        }
+        {
+            let mut range_deps = self.range_dependencies.borrow_mut();
+            let ranges = range_deps
+                .keys()
+                .filter(|(s, range)| *s == sref && range.intersects(&area))
+                .cloned()
+                .collect::<Vec<_>>();
+            for range in ranges {
+                readers.extend(range_deps.remove(&range).into_iter().flatten());
+            }
+        }
+        readers.retain(|reader| *reader != (sref, anchor));
+        readers
     }
 
     /// Gets and caches the calculated value for the given cell
+    ///
+    /// A formula resulting in an array has its first element as its value, with the rest spilling into the
+    /// cells to the right and below it, as long as they're empty. Those cells are only known to be covered
+    /// once the formula has been calculated, see [Spanleaf::used_range] to calculate every formula
     pub fn get(&self, sref: SheetIdx, cref: CellIdx) -> Result<ValueResult, Error> {
         let mut val_res = self.get_raw_value(sref, cref);
 
+        // an empty cell might be covered by an array
+        if let Value::None = val_res.as_ref() {
+            return Ok(match self.spilled(sref, cref) {
+                Some(val) => ValueResult::spill(val),
+                None => val_res,
+            });
+        }
+
         // if it's a formula, resolve it recursively to a value
         if let Value::Formula(f) = val_res.as_ref() {
             *val_res = if let Some(cached) = self.cache.borrow().get(&(sref, cref)) {
                 // check the cache
                 match cached {
-                    CacheEntry::Calculating => return Err(Error::CyclicDependencyDetected),
+                    CacheEntry::Calculating => Value::Error(ErrorKind::Cycle),
                     CacheEntry::Calculated(value) => value.clone(),
                 }
             } else {
@@ -178,16 +696,64 @@ impl Spanleaf {
                     .borrow_mut()
                     .insert((sref, cref), CacheEntry::Calculating);
 
-                let mut deps = vec![];
-                // calculate and cache
-                let res = f.eval(self, sref, cref, &mut deps)?;
+                let (res, spill, deps) = loop {
+                    let mut deps = vec![];
+                    // calculate and cache, with any failure becoming an error value
+                    let res = f
+                        .eval(self, sref, cref, &mut deps)
+                        .unwrap_or_else(|e| Value::Error(e.into()));
+                    let (res, spill) = match res {
+                        Value::Array(array) => {
+                            self.place(sref, cref, array, val_res.source.clone(), &mut deps)
+                        }
+                        res => {
+                            self.spill_areas.borrow_mut().remove(&(sref, cref));
+                            (res, None)
+                        }
+                    };
+                    if let Some(area) = spill.as_ref().and_then(|array| spill_area(cref, array)) {
+                        self.land(sref, cref, area);
+                    }
+
+                    // scope to drop the borrow
+                    let redo = { self.redo.borrow_mut().remove(&(sref, cref)) };
+                    if !redo {
+                        break (res, spill, deps);
+                    }
+                };
+
                 // establish the dependency
                 for dep in deps {
-                    self.dependencies
-                        .borrow_mut()
-                        .entry(dep)
-                        .or_default()
-                        .insert((sref, cref));
+                    match dep {
+                        Dependency::Cell(dep_sref, dep_cref) => self
+                            .dependencies
+                            .borrow_mut()
+                            .entry((dep_sref, dep_cref))
+                            .or_default()
+                            .insert((sref, cref)),
+                        Dependency::Range(dep_sref, range) => self
+                            .range_dependencies
+                            .borrow_mut()
+                            .entry((dep_sref, range))
+                            .or_default()
+                            .insert((sref, cref)),
+                        Dependency::Function(name) => self
+                            .function_dependencies
+                            .borrow_mut()
+                            .entry(name)
+                            .or_default()
+                            .insert((sref, cref)),
+                        Dependency::Sheet(name) => self
+                            .sheet_dependencies
+                            .borrow_mut()
+                            .entry(name)
+                            .or_default()
+                            .insert((sref, cref)),
+                        Dependency::Volatile => self.volatile.borrow_mut().insert((sref, cref)),
+                    };
+                }
+                if let Some(array) = spill {
+                    self.spills.borrow_mut().insert((sref, cref), array);
                 }
 
                 // clear cycle trap
@@ -207,6 +773,184 @@ impl Spanleaf {
         Ok(val_res)
     }
 
+    /// Works out where an array resulting from the formula in `cref` spills to, returning the value of `cref`
+    /// itself, and the array if it does spill.
+    ///
+    /// The formula depends on every cell the array would cover, so that it can spill once they're emptied
+    fn place(
+        &self,
+        sref: SheetIdx,
+        cref: CellIdx,
+        array: Array,
+        source: ValueSource,
+        deps: &mut Vec<Dependency>,
+    ) -> (Value, Option<Array>) {
+        self.spill_areas.borrow_mut().remove(&(sref, cref));
+        let first = match array.get(0, 0) {
+            Some(first) => first.clone(),
+            None => return (Value::Error(ErrorKind::NotAvailable), None),
+        };
+        if array.rows() == 1 && array.cols() == 1 {
+            return (first, None);
+        }
+        // a default is in every cell along its row or col, so would always block itself
+        if source != ValueSource::Native {
+            return (Value::Error(ErrorKind::Spill), None);
+        }
+
+        // nor does an array that would run past the edge of the sheet
+        let Some(area) = spill_area(cref, &array) else {
+            return (Value::Error(ErrorKind::Spill), None);
+        };
+        // the formula can't depend on what it spills
+        let cyclic = deps.iter().any(|dep| match dep {
+            Dependency::Cell(s, c) => *s == sref && area.contains(*c),
+            Dependency::Range(s, range) => *s == sref && range.intersects(&area),
+            Dependency::Function(_) | Dependency::Sheet(_) | Dependency::Volatile => false,
+        });
+        if cyclic {
+            return (Value::Error(ErrorKind::Cycle), None);
+        }
+
+        deps.push(Dependency::Range(sref, area));
+        self.spill_areas.borrow_mut().insert((sref, cref), area);
+        let occupied = area
+            .iter()
+            .any(|c| c != cref && !matches!(self.get_raw_value(sref, c).value, Value::None));
+        let overlapping = self.spills.borrow().iter().any(|((s, anchor), other)| {
+            *s == sref
+                && *anchor != cref
+                && spill_area(*anchor, other).is_some_and(|other| other.intersects(&area))
+        });
+        if occupied || overlapping {
+            (Value::Error(ErrorKind::Spill), None)
+        } else {
+            (first, Some(array))
+        }
+    }
+
+    /// Recalculates whatever read the empty cells that the array resulting from the formula in `anchor` has
+    /// just spilled into
+    fn land(&self, sref: SheetIdx, anchor: CellIdx, area: CellRange) {
+        let readers = self.take_readers(sref, anchor, area);
+        // anything still being calculated might have read them too, without having become a dependent yet
+        {
+            let cache = self.cache.borrow();
+            self.redo.borrow_mut().extend(
+                cache
+                    .iter()
+                    .filter(|(key, entry)| {
+                        matches!(entry, CacheEntry::Calculating) && **key != (sref, anchor)
+                    })
+                    .map(|(key, _)| *key),
+            );
+        }
+        for (s, reader) in readers {
+            self.clear_from_cache(s, reader);
+        }
+    }
+
+    /// The value that an array spills into an empty cell, if any does.
+    ///
+    /// Only the arrays that formulas have resulted in are known, so an empty cell reads as empty until the
+    /// formula spilling into it has been calculated, when whatever read it is recalculated
+    fn spilled(&self, sref: SheetIdx, cref: CellIdx) -> Option<Value> {
+        // arrays only spill right and down
+        let anchors = self
+            .spill_areas
+            .borrow()
+            .range((sref, CellIdx::new(0, 0))..=(sref, cref))
+            .filter(|(_, area)| area.contains(cref))
+            .map(|((_, anchor), _)| *anchor)
+            .collect::<Vec<_>>();
+
+        for anchor in anchors {
+            // the formula might have to be calculated again, since its inputs changed
+            let _ = self.get(sref, anchor);
+            let spilled = self.spills.borrow().get(&(sref, anchor)).and_then(|array| {
+                array
+                    .get(
+                        (cref.row - anchor.row) as usize,
+                        (cref.col - anchor.col) as usize,
+                    )
+                    .cloned()
+            });
+            if spilled.is_some() {
+                return spilled;
+            }
+        }
+
+        None
+    }
+
+    /// The sheet's [used range](Sheet::used_range), extended over the cells that arrays spill into.
+    ///
+    /// Every formula on the sheet is calculated to find them
+    pub fn used_range(&self, sref: SheetIdx) -> CellRange {
+        let Some(sheet) = self.sheets.get(&sref) else {
+            return CellRange::empty();
+        };
+        for (cref, val) in sheet.cells() {
+            if let Value::Formula(_) = val {
+                let _ = self.get(sref, cref);
+            }
+        }
+
+        self.spills
+            .borrow()
+            .iter()
+            .filter(|((s, _), _)| *s == sref)
+            .filter_map(|((_, anchor), array)| Some(spill_area(*anchor, array)?.end))
+            .fold(sheet.used_range(), |range, end| {
+                let end = if range.is_empty() {
+                    end
+                } else {
+                    CellIdx::new(range.end.row.max(end.row), range.end.col.max(end.col))
+                };
+                CellRange::new(CellIdx::new(0, 0), end)
+            })
+    }
+
+    /// Evaluates a formula as though it were in the given cell, without inserting it
+    ///
+    /// Useful for trying out expressions. Any cells it refers to are calculated and cached as usual
+    pub fn eval_formula(&self, sref: SheetIdx, cref: CellIdx, formula: &Formula) -> Value {
+        formula
+            .eval(self, sref, cref, &mut vec![])
+            .unwrap_or_else(|e| Value::Error(e.into()))
+    }
+
+    /// What the value of the given cell depends on, in the order its formula refers to them
+    pub fn precedents(&self, sref: SheetIdx, cref: CellIdx) -> Vec<Dependency> {
+        let mut deps = vec![];
+        if let Value::Formula(f) = self.get_raw_value(sref, cref).as_ref() {
+            // only the dependencies are wanted, so any failure doesn't matter
+            let _ = f.eval(self, sref, cref, &mut deps);
+        }
+
+        let mut seen = BTreeSet::new();
+        deps.retain(|dep| seen.insert(dep.clone()));
+        deps
+    }
+
+    /// The cells whose values depend on the given cell, directly or through a range containing it
+    ///
+    /// Dependencies are recorded during calculation, so only cells that have been calculated are known
+    pub fn dependents(&self, sref: SheetIdx, cref: CellIdx) -> BTreeSet<(SheetIdx, CellIdx)> {
+        let mut dependents = self
+            .dependencies
+            .borrow()
+            .get(&(sref, cref))
+            .cloned()
+            .unwrap_or_default();
+        for ((s, range), deps) in self.range_dependencies.borrow().iter() {
+            if *s == sref && range.contains(cref) {
+                dependents.extend(deps);
+            }
+        }
+        dependents
+    }
+
     /// Gets the uncalculated value for the given cell
     ///
     /// Useful for formula bar displaying
@@ -248,12 +992,18 @@ impl Default for Spanleaf {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Spanleaf, cell::CellIdx};
+    use crate::{
+        CacheEntry, Dependency, Error, Spanleaf,
+        cell::{CellIdx, CellRange, ErrorKind, Value},
+        formula::{Formula, FunctionError, NamedFunction},
+        functions::{Arity, NativeFunction},
+        sheet::{Sheet, ValueResult, ValueSource},
+    };
 
     #[test]
     fn big_test() {
         let mut sl = Spanleaf::new();
-        let s0 = sl.insert_sheet("Sheet1");
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
 
         sl.insert(s0, CellIdx::new(0, 0), 42.0).unwrap();
 
@@ -267,4 +1017,439 @@ mod tests {
         dbg!(sl.get(s0, CellIdx::new(0, 1)));
         dbg!(sl.get(s0, CellIdx::new(0, 2)));
     }
+
+    #[test]
+    fn error_values() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+
+        let get = |sl: &Spanleaf, row, col| sl.get(s0, CellIdx::new(row, col)).unwrap().value();
+
+        sl.insert(s0, CellIdx::new(0, 0), "=1 / 0").unwrap();
+        // propagates through arithmetic
+        sl.insert(s0, CellIdx::new(0, 1), "=[0, 0] + 1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 2), "=iserror([0, 1])")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(0, 3), "=iferror([0, 1], 'fallback')")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(0, 4), "=unknown_fn()").unwrap();
+        sl.insert(s0, CellIdx::new(0, 5), "=1 + 'one'").unwrap();
+        sl.insert(s0, CellIdx::new(0, 6), "=missing_sheet[0, 0]")
+            .unwrap();
+        // an empty cell divides by zero
+        sl.insert(s0, CellIdx::new(0, 7), "=5 / [9, 9]").unwrap();
+
+        assert_eq!(get(&sl, 0, 0), Value::Error(ErrorKind::DivByZero));
+        assert_eq!(get(&sl, 0, 1), Value::Error(ErrorKind::DivByZero));
+        assert_eq!(get(&sl, 0, 2), true.into());
+        assert_eq!(get(&sl, 0, 3), "fallback".try_into().unwrap());
+        assert_eq!(get(&sl, 0, 4), Value::Error(ErrorKind::Name));
+        assert_eq!(get(&sl, 0, 5), Value::Error(ErrorKind::Value));
+        assert_eq!(get(&sl, 0, 6), Value::Error(ErrorKind::Ref));
+        assert_eq!(get(&sl, 0, 7), Value::Error(ErrorKind::DivByZero));
+
+        // as is a reference before the first row or col, or between them
+        sl.insert(s0, CellIdx::new(2, 0), "=[r - 3, c]").unwrap();
+        sl.insert(s0, CellIdx::new(2, 1), "=[0, 0.5]").unwrap();
+        sl.insert(s0, CellIdx::new(2, 2), "=if(r > 2, [r - 3, c], 0)")
+            .unwrap();
+        assert_eq!(get(&sl, 2, 0), Value::Error(ErrorKind::Ref));
+        assert_eq!(get(&sl, 2, 1), Value::Error(ErrorKind::Ref));
+        assert_eq!(get(&sl, 2, 2), 0.into());
+
+        // errors are cached, and cleared like any other value
+        assert!(matches!(
+            sl.cache.borrow().get(&(s0, CellIdx::new(0, 1))),
+            Some(CacheEntry::Calculated(Value::Error(ErrorKind::DivByZero)))
+        ));
+        sl.insert(s0, CellIdx::new(0, 0), 4).unwrap();
+        assert_eq!(get(&sl, 0, 1), 5.into());
+        assert_eq!(get(&sl, 0, 2), false.into());
+        assert_eq!(get(&sl, 0, 3), 5.into());
+
+        // every cell in a cycle is an error
+        sl.insert(s0, CellIdx::new(1, 0), "=[1, 1]").unwrap();
+        sl.insert(s0, CellIdx::new(1, 1), "=[1, 0]").unwrap();
+        assert_eq!(get(&sl, 1, 0), Value::Error(ErrorKind::Cycle));
+        assert_eq!(get(&sl, 1, 1), Value::Error(ErrorKind::Cycle));
+
+        // breaking the cycle recovers
+        sl.insert(s0, CellIdx::new(1, 1), 3).unwrap();
+        assert_eq!(get(&sl, 1, 0), 3.into());
+    }
+
+    #[test]
+    fn insert_delete_rows_cols() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Sheet2").unwrap();
+
+        let script = |sl: &Spanleaf, sref, row, col| match sl
+            .get_raw_value(sref, CellIdx::new(row, col))
+            .value()
+        {
+            Value::Formula(f) => f.script,
+            val => panic!("not a formula: {val:?}"),
+        };
+
+        sl.insert(s0, CellIdx::new(3, 0), 3).unwrap();
+        sl.insert(s0, CellIdx::new(4, 0), 4).unwrap();
+        sl.insert(s0, CellIdx::new(0, 1), "=[3, 0] + [4,0]")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(1, 1), "=sum([2..4, 0])")
+            .unwrap();
+        // relative references are left alone
+        sl.insert(s0, CellIdx::new(2, 1), "=[r + 1, 0]").unwrap();
+        sl.insert(s1, CellIdx::new(0, 0), "=Sheet1[4, 0] * [4, 0]")
+            .unwrap();
+
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 1)).unwrap(), 7.into());
+
+        sl.insert_rows(s0, 1, 2).unwrap();
+        assert_eq!(script(&sl, s0, 0, 1), "=[5, 0] + [6, 0]");
+        assert_eq!(script(&sl, s0, 3, 1), "=sum([4..6, 0..0])");
+        assert_eq!(script(&sl, s0, 4, 1), "=[r + 1, 0]");
+        // only refs into the changed sheet are rewritten
+        assert_eq!(script(&sl, s1, 0, 0), "=Sheet1[6, 0] * [4, 0]");
+        assert_eq!(*sl.get(s0, CellIdx::new(0, 1)).unwrap(), 7.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(3, 1)).unwrap(), 7.into());
+        assert_eq!(*sl.get(s0, CellIdx::new(4, 1)).unwrap(), 3.into());
+
+        sl.delete_rows(s0, 6, 1);
+        assert_eq!(script(&sl, s0, 0, 1), "=[5, 0] + #REF!");
+        assert_eq!(script(&sl, s0, 3, 1), "=sum([4..5, 0..0])");
+        assert_eq!(script(&sl, s1, 0, 0), "=#REF! * [4, 0]");
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(0, 1)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        assert_eq!(*sl.get(s0, CellIdx::new(3, 1)).unwrap(), 3.into());
+
+        sl.delete_cols(s0, 0, 1);
+        assert_eq!(script(&sl, s0, 3, 0), "=sum(#REF!)");
+        assert_eq!(
+            *sl.get(s0, CellIdx::new(3, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+
+        sl.insert_cols(s1, 0, 1).unwrap();
+        assert_eq!(script(&sl, s1, 0, 1), "=#REF! * [4, 1]");
+
+        // however many rows are deleted, the ones before them stay put
+        sl.insert(s1, CellIdx::new(0, 2), "=sum([0..9, 1])")
+            .unwrap();
+        sl.delete_rows(s1, 1, u64::MAX);
+        assert_eq!(script(&sl, s1, 0, 1), "=#REF! * #REF!");
+        assert_eq!(script(&sl, s1, 0, 2), "=sum([0..0, 1..1])");
+
+        // cells can't be pushed past the last row or col, so nothing moves
+        assert!(matches!(
+            sl.insert_rows(s0, 0, Sheet::LAST_INDEX),
+            Err(Error::NoRoomToInsert)
+        ));
+        assert_eq!(script(&sl, s0, 3, 0), "=sum(#REF!)");
+        // but they can be pushed onto it, while references past it become errors like the ones to deleted cells
+        sl.insert_rows(s1, 0, Sheet::LAST_INDEX).unwrap();
+        assert_eq!(script(&sl, s1, Sheet::LAST_INDEX, 1), "=#REF! * #REF!");
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(Sheet::LAST_INDEX, 1)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+    }
+
+    #[test]
+    fn sheet_management() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Sheet2").unwrap();
+        let script = |sl: &Spanleaf, sref, row, col| match sl
+            .get_raw_value(sref, CellIdx::new(row, col))
+            .value()
+        {
+            Value::Formula(f) => f.script,
+            val => panic!("not a formula: {val:?}"),
+        };
+        let order = |sl: &Spanleaf| sl.sheets().map(|(s, _)| s).collect::<Vec<_>>();
+
+        sl.insert(s0, CellIdx::new(0, 0), 2).unwrap();
+        sl.insert(s1, CellIdx::new(0, 0), "=Sheet1[0, 0] * 3")
+            .unwrap();
+        sl.insert(s1, CellIdx::new(1, 0), "=Renamed[0, 0]").unwrap();
+        assert_eq!(*sl.get(s1, CellIdx::new(0, 0)).unwrap(), 6.into());
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(1, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+
+        assert!(matches!(
+            sl.rename_sheet(s0, "Sheet2"),
+            Err(Error::DuplicateSheetName(_))
+        ));
+        assert!(matches!(
+            sl.rename_sheet(s0, "not valid"),
+            Err(Error::InvalidSheetName(_))
+        ));
+        // as with every other way of naming a sheet
+        assert!(matches!(
+            sl.insert_sheet("not valid"),
+            Err(Error::InvalidSheetName(_))
+        ));
+        assert!(matches!(
+            sl.duplicate_sheet(s0, "not valid"),
+            Err(Error::InvalidSheetName(_))
+        ));
+
+        sl.rename_sheet(s0, "Renamed").unwrap();
+        assert_eq!(sl.sheet_by_name("Renamed"), Some(s0));
+        assert_eq!(sl.sheet_by_name("Sheet1"), None);
+        assert_eq!(script(&sl, s1, 0, 0), "=Renamed[0, 0] * 3");
+        assert_eq!(*sl.get(s1, CellIdx::new(0, 0)).unwrap(), 6.into());
+        // previously dangling references now resolve
+        assert_eq!(*sl.get(s1, CellIdx::new(1, 0)).unwrap(), 2.into());
+
+        sl.insert(s1, CellIdx::new(2, 0), "=Copy[0, 0]").unwrap();
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(2, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        let copy = sl.duplicate_sheet(s0, "Copy").unwrap();
+        assert_eq!(order(&sl), vec![s0, copy, s1]);
+        assert_eq!(*sl.get(copy, CellIdx::new(0, 0)).unwrap(), 2.into());
+        // as do the ones to a copy's name
+        assert_eq!(*sl.get(s1, CellIdx::new(2, 0)).unwrap(), 2.into());
+        assert_eq!(sl.sheet(copy).unwrap().name, "Copy");
+
+        sl.move_sheet(s0, 99).unwrap();
+        assert_eq!(order(&sl), vec![copy, s1, s0]);
+        sl.move_sheet(s1, 0).unwrap();
+        assert_eq!(order(&sl), vec![s1, copy, s0]);
+
+        sl.delete_sheet(s0).unwrap();
+        assert_eq!(order(&sl), vec![s1, copy]);
+        assert_eq!(script(&sl, s1, 0, 0), "=#REF! * 3");
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(0, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        // a new sheet with the old name doesn't pick the references back up
+        let s3 = sl.insert_sheet("Renamed").unwrap();
+        sl.insert(s3, CellIdx::new(0, 0), 5).unwrap();
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(1, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        assert!(matches!(sl.delete_sheet(s0), Err(Error::SheetNotFound)));
+
+        // and to a new sheet's name
+        sl.insert(s1, CellIdx::new(3, 0), "=Later[0, 0]").unwrap();
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(3, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        sl.insert(s1, CellIdx::new(4, 0), "=*indirect('Later', 0, 1)")
+            .unwrap();
+        sl.insert(s1, CellIdx::new(5, 0), "=[4, 0] + 1").unwrap();
+        assert_eq!(
+            *sl.get(s1, CellIdx::new(5, 0)).unwrap(),
+            Value::Error(ErrorKind::Ref)
+        );
+        sl.insert(s1, CellIdx::new(10, 0), 3).unwrap();
+        sl.insert(s1, CellIdx::new(11, 0), "=[10, 0] * 2").unwrap();
+        assert_eq!(*sl.get(s1, CellIdx::new(11, 0)).unwrap(), 6.into());
+        let later = sl.insert_sheet("Later").unwrap();
+        // without throwing away what's already calculated
+        assert!(
+            sl.dependents(s1, CellIdx::new(10, 0))
+                .contains(&(s1, CellIdx::new(11, 0)))
+        );
+        sl.insert(later, CellIdx::new(0, 0), 7).unwrap();
+        sl.insert(later, CellIdx::new(0, 1), 8).unwrap();
+        assert_eq!(*sl.get(s1, CellIdx::new(3, 0)).unwrap(), 7.into());
+        assert_eq!(*sl.get(s1, CellIdx::new(5, 0)).unwrap(), 9.into());
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Sheet2").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 2).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), "=[0, 0] + sum([0..1, 1]) + [0, 0]")
+            .unwrap();
+        sl.insert(s1, CellIdx::new(0, 0), "=Sheet1[1, 0] * 2")
+            .unwrap();
+
+        assert_eq!(
+            sl.precedents(s0, CellIdx::new(1, 0)),
+            [
+                Dependency::Cell(s0, CellIdx::new(0, 0)),
+                Dependency::Range(s0, CellRange::new(CellIdx::new(0, 1), CellIdx::new(1, 1))),
+            ]
+        );
+        assert!(sl.precedents(s0, CellIdx::new(0, 0)).is_empty());
+
+        // nothing is known until calculated
+        assert!(sl.dependents(s0, CellIdx::new(1, 1)).is_empty());
+        sl.get(s1, CellIdx::new(0, 0)).unwrap();
+        assert!(
+            sl.dependents(s0, CellIdx::new(1, 1))
+                .into_iter()
+                .eq([(s0, CellIdx::new(1, 0))])
+        );
+        assert!(
+            sl.dependents(s0, CellIdx::new(1, 0))
+                .into_iter()
+                .eq([(s1, CellIdx::new(0, 0))])
+        );
+
+        // trying out a formula doesn't insert it
+        let formula = Formula::parse("=[1, 0] + r").unwrap();
+        assert_eq!(sl.eval_formula(s0, CellIdx::new(3, 0), &formula), 7.into());
+        assert_eq!(*sl.get_raw_value(s0, CellIdx::new(3, 0)), Value::None);
+    }
+
+    #[test]
+    fn named_functions() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Rates").unwrap();
+        let define = |sl: &mut Spanleaf, definition| {
+            sl.insert_function(NamedFunction::parse(definition).unwrap())
+                .unwrap()
+        };
+        let get = |sl: &Spanleaf, row, col| sl.get(s0, CellIdx::new(row, col)).unwrap().value();
+
+        sl.insert(s1, CellIdx::new(0, 0), 0.5).unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 10).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), 20).unwrap();
+        // the body is evaluated as if it were in the calling cell
+        define(&mut sl, "tax(amount) = amount * Rates[0, 0] + [r, 0] * 0");
+        sl.insert_col_default(s0, 1, "=tax([r, 0])").unwrap();
+        assert_eq!(get(&sl, 0, 1), 5.into());
+        assert_eq!(get(&sl, 1, 1), 10.into());
+
+        // cells the body refers to are dependencies of the caller
+        sl.insert(s1, CellIdx::new(0, 0), 0.1).unwrap();
+        assert_eq!(get(&sl, 1, 1), 2.into());
+
+        // redefining or removing a function updates its callers
+        define(&mut sl, "tax(amount) = amount");
+        assert_eq!(get(&sl, 1, 1), 20.into());
+        sl.remove_function("tax");
+        assert_eq!(get(&sl, 1, 1), Value::Error(ErrorKind::Name));
+        // as does defining it after it was called
+        define(&mut sl, "tax(amount) = -amount");
+        assert_eq!(get(&sl, 1, 1), (-20).into());
+
+        // recursion, with a limit
+        define(&mut sl, "fact(n) = if(n <= 1, 1, n * fact(n - 1))");
+        define(&mut sl, "forever(n) = forever(n + 1)");
+        sl.insert(s0, CellIdx::new(2, 0), "=fact(5)").unwrap();
+        sl.insert(s0, CellIdx::new(3, 0), "=forever(0)").unwrap();
+        assert_eq!(get(&sl, 2, 0), 120.into());
+        assert_eq!(get(&sl, 3, 0), Value::Error(ErrorKind::Recursion));
+
+        // arguments are checked, and parameters are only visible in their own function's body
+        sl.insert(s0, CellIdx::new(4, 0), "=fact(1, 2)").unwrap();
+        sl.insert(s0, CellIdx::new(5, 0), "=n + 1").unwrap();
+        define(&mut sl, "outer(n) = inner()");
+        define(&mut sl, "inner() = 1");
+        sl.insert(
+            s0,
+            CellIdx::new(6, 0),
+            "=outer(iferror(1 / 0, 2)) + sum([0..0, 0])",
+        )
+        .unwrap();
+        assert_eq!(get(&sl, 4, 0), Value::Error(ErrorKind::Args));
+        assert_eq!(get(&sl, 5, 0), Value::Error(ErrorKind::Name));
+        assert_eq!(get(&sl, 6, 0), 11.into());
+
+        // native functions are called before named ones, so their names are taken
+        sl.register_function(NativeFunction::new("host", Arity::exactly(0), |_| {
+            Ok(1.into())
+        }));
+        assert_eq!(
+            sl.insert_function(NamedFunction::parse("host() = 2").unwrap())
+                .unwrap_err(),
+            FunctionError::Builtin("host".to_string())
+        );
+
+        // bodies follow sheet renames
+        sl.rename_sheet(s1, "Taxes").unwrap();
+        define(&mut sl, "tax_rate() = Taxes[0, 0]");
+        sl.rename_sheet(s1, "Levies").unwrap();
+        assert_eq!(
+            sl.function("tax_rate").unwrap().definition,
+            "tax_rate() = Levies[0, 0]"
+        );
+    }
+
+    #[test]
+    fn spills() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let get = |sl: &Spanleaf, row, col| sl.get(s0, CellIdx::new(row, col)).unwrap();
+
+        sl.insert(s0, CellIdx::new(0, 0), "={1, 2; 3, 4}").unwrap();
+        sl.insert(s0, CellIdx::new(0, 3), "=[1, 1] + 1").unwrap();
+        assert_eq!(get(&sl, 0, 0), ValueResult::native(1));
+        assert_eq!(get(&sl, 1, 1), ValueResult::new(4, ValueSource::Spill));
+        assert_eq!(get(&sl, 0, 3).value(), 5.into());
+        assert_eq!(get(&sl, 2, 0), ValueResult::native(()));
+        assert_eq!(
+            sl.used_range(s0),
+            CellRange::new(CellIdx::new(0, 0), CellIdx::new(1, 3))
+        );
+
+        // a value in the way blocks the whole array, until it's removed
+        sl.insert(s0, CellIdx::new(1, 0), "x").unwrap();
+        assert_eq!(get(&sl, 0, 0).value(), Value::Error(ErrorKind::Spill));
+        assert_eq!(get(&sl, 1, 1), ValueResult::native(()));
+        assert_eq!(get(&sl, 0, 3).value(), 1.into());
+        sl.insert(s0, CellIdx::new(1, 0), ()).unwrap();
+        assert_eq!(get(&sl, 0, 3).value(), 5.into());
+
+        // spills follow their formula's inputs
+        sl.insert(s0, CellIdx::new(5, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(6, 0), 2).unwrap();
+        sl.insert(s0, CellIdx::new(5, 1), "=[5..6, 0] * 2").unwrap();
+        assert_eq!(get(&sl, 5, 1).value(), 2.into());
+        assert_eq!(get(&sl, 6, 1), ValueResult::new(4, ValueSource::Spill));
+        sl.insert(s0, CellIdx::new(6, 0), 5).unwrap();
+        assert_eq!(get(&sl, 6, 1), ValueResult::new(10, ValueSource::Spill));
+        // as do the cells reading where a new array spills, once it's calculated
+        sl.insert(s0, CellIdx::new(12, 0), "=[13, 1] + 1").unwrap();
+        assert_eq!(get(&sl, 12, 0).value(), 1.into());
+        sl.insert(s0, CellIdx::new(12, 1), "={1; 2}").unwrap();
+        assert_eq!(get(&sl, 12, 1).value(), 1.into());
+        assert_eq!(get(&sl, 12, 0).value(), 3.into());
+        // even when that's while they're being calculated
+        sl.insert(s0, CellIdx::new(20, 0), "={1; 2}").unwrap();
+        sl.insert(s0, CellIdx::new(20, 1), "=[21, 0] + [20, 0]")
+            .unwrap();
+        assert_eq!(get(&sl, 20, 1).value(), 3.into());
+
+        // an array can't spill over what it depends on, or over another array that's already spilled
+        sl.insert(s0, CellIdx::new(8, 0), "={1; 2} + [9, 0]")
+            .unwrap();
+        sl.insert(s0, CellIdx::new(8, 1), "={1, 2}").unwrap();
+        sl.insert(s0, CellIdx::new(7, 2), "={1; 2}").unwrap();
+        assert_eq!(get(&sl, 7, 2).value(), 1.into());
+        assert_eq!(get(&sl, 8, 2), ValueResult::new(2, ValueSource::Spill));
+        assert_eq!(get(&sl, 8, 0).value(), Value::Error(ErrorKind::Cycle));
+        assert_eq!(get(&sl, 8, 1).value(), Value::Error(ErrorKind::Spill));
+
+        // nor from a default, as that would be every cell of its row or column
+        sl.insert_col_default(s0, 5, "={1, 2}").unwrap();
+        assert_eq!(get(&sl, 0, 5).value(), Value::Error(ErrorKind::Spill));
+        assert_eq!(get(&sl, 0, 6), ValueResult::native(()));
+
+        // nor past the edge of the sheet
+        let last = Sheet::LAST_INDEX;
+        sl.insert(s0, CellIdx::new(last, 0), "={1; 2}").unwrap();
+        sl.insert(s0, CellIdx::new(last, 2), "={1, 2}").unwrap();
+        assert_eq!(get(&sl, last, 0).value(), Value::Error(ErrorKind::Spill));
+        assert_eq!(get(&sl, last, 2).value(), 1.into());
+        assert_eq!(get(&sl, last, 3), ValueResult::new(2, ValueSource::Spill));
+    }
 }
@@ -0,0 +1,311 @@
+//! ODS export
+//!
+//! An OpenDocument spreadsheet is a zip of XML files, of which only the ones required to open it are
+//! written. As with XLSX, row and col defaults are filled into cells, and formulas are written in the
+//! OpenFormula dialect alongside their computed value, except for ones without an equivalent, which are
+//! reported and written as just their value.
+
+use std::{
+    fmt::{self, Display, Write as _},
+    io::{self, Seek, Write},
+};
+
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    Spanleaf,
+    cell::Value,
+    datetime::Duration,
+    excel::{self, Dialect, ExportCell},
+};
+
+pub use crate::excel::{ExportOptions, TranslateError, UntranslatedFormula};
+
+#[derive(Debug, thiserror::Error)]
+pub enum OdsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+}
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.3">
+ <manifest:file-entry manifest:full-path="/" manifest:version="1.3" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+const CONTENT_START: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:of="urn:oasis:names:tc:opendocument:xmlns:of:1.2" office:version="1.3"><office:body><office:spreadsheet>"#;
+
+const CONTENT_END: &str = "</office:spreadsheet></office:body></office:document-content>\n";
+
+impl Spanleaf {
+    /// Writes every sheet to an ODS spreadsheet.
+    ///
+    /// Returns the formulas that couldn't be translated, which were written as their computed value
+    pub fn export_ods(
+        &self,
+        writer: impl Write + Seek,
+        options: &ExportOptions,
+    ) -> Result<Vec<UntranslatedFormula>, OdsError> {
+        let mut untranslated = vec![];
+        let mut content = CONTENT_START.to_string();
+        for (sref, sheet) in self.sheets() {
+            let cells =
+                excel::export_cells(self, sref, Dialect::OpenFormula, options, &mut untranslated);
+            write_table(&mut content, &sheet.name, &cells);
+        }
+        content.push_str(CONTENT_END);
+
+        let mut zip = ZipWriter::new(writer);
+        // the mimetype goes first and uncompressed, so the file type can be told from its first bytes
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+        zip.start_file("META-INF/manifest.xml", SimpleFileOptions::default())?;
+        zip.write_all(MANIFEST.as_bytes())?;
+        zip.start_file("content.xml", SimpleFileOptions::default())?;
+        zip.write_all(content.as_bytes())?;
+        zip.finish()?;
+
+        Ok(untranslated)
+    }
+}
+
+/// Writes a sheet as a table, with runs of empty rows and cells collapsed into repeated ones
+fn write_table(out: &mut String, name: &str, cells: &[ExportCell]) {
+    let cols = cells.iter().map(|c| c.cell.col + 1).max().unwrap_or(1);
+
+    // writing to a string can't fail
+    let _ = write!(
+        out,
+        r#"<table:table table:name="{}"><table:table-column table:number-columns-repeated="{cols}"/>"#,
+        Escaped(name)
+    );
+
+    let mut next_row = 0;
+    for row in cells.chunk_by(|a, b| a.cell.row == b.cell.row) {
+        let row_idx = row[0].cell.row;
+        if row_idx > next_row {
+            let _ = write!(
+                out,
+                r#"<table:table-row table:number-rows-repeated="{}"><table:table-cell/></table:table-row>"#,
+                row_idx - next_row
+            );
+        }
+
+        out.push_str("<table:table-row>");
+        let mut next_col = 0;
+        for cell in row {
+            if cell.cell.col > next_col {
+                let _ = write!(
+                    out,
+                    r#"<table:table-cell table:number-columns-repeated="{}"/>"#,
+                    cell.cell.col - next_col
+                );
+            }
+            write_cell(out, cell);
+            next_col = cell.cell.col + 1;
+        }
+        out.push_str("</table:table-row>");
+
+        next_row = row_idx + 1;
+    }
+    // tables need at least one row
+    if cells.is_empty() {
+        out.push_str("<table:table-row><table:table-cell/></table:table-row>");
+    }
+
+    out.push_str("</table:table>");
+}
+
+/// Writes a cell, with its formula if it has one
+fn write_cell(out: &mut String, cell: &ExportCell) {
+    out.push_str("<table:table-cell");
+    if let Some(formula) = &cell.formula {
+        let _ = write!(out, r#" table:formula="of:={}""#, Escaped(formula));
+    }
+
+    let text = match &cell.value {
+        Value::Number(n) => {
+            let _ = write!(out, r#" office:value-type="float" office:value="{n}""#);
+            n.to_string()
+        }
+        Value::Bool(b) => {
+            let _ = write!(
+                out,
+                r#" office:value-type="boolean" office:boolean-value="{b}""#
+            );
+            b.to_string().to_uppercase()
+        }
+        Value::String(s) => {
+            out.push_str(r#" office:value-type="string""#);
+            s.clone()
+        }
+        Value::Date(date) => {
+            let _ = write!(
+                out,
+                r#" office:value-type="date" office:date-value="{date}""#
+            );
+            date.to_string()
+        }
+        Value::DateTime(dt) => {
+            let _ = write!(out, r#" office:value-type="date" office:date-value="{dt}""#);
+            dt.to_string()
+        }
+        Value::Duration(duration) => {
+            let _ = write!(
+                out,
+                r#" office:value-type="time" office:time-value="{}""#,
+                IsoDuration(*duration)
+            );
+            duration.to_string()
+        }
+        // there's no error value type, so they're only shown
+        Value::Error(kind) => kind.to_string(),
+        _ => String::new(),
+    };
+
+    out.push('>');
+    for line in text.split('\n') {
+        let _ = write!(out, "<text:p>{}</text:p>", Escaped(line));
+    }
+    out.push_str("</table:table-cell>");
+}
+
+/// Writes a duration in hours, minutes and seconds as in ISO 8601, i.e. `PT36H0M1.5S`
+struct IsoDuration(Duration);
+impl Display for IsoDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.millis();
+        if millis < 0 {
+            f.write_str("-")?;
+        }
+        let millis = millis.unsigned_abs();
+        let (hours, minutes) = (millis / 3_600_000, millis % 3_600_000 / 60_000);
+        let seconds = (millis % 60_000) as f64 / 1000.0;
+        write!(f, "PT{hours}H{minutes}M{seconds}S")
+    }
+}
+
+/// Escapes text for use in XML content and attributes
+struct Escaped<'a>(&'a str);
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&apos;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use calamine::{Data, Ods, Reader};
+
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind, Value},
+        datetime::Duration,
+        ods::ExportOptions,
+    };
+
+    #[test]
+    fn export() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Other").unwrap();
+        sl.insert_row_default(s0, 1, "=[0, c] + 1").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(0, 1), "<a & 'b'>").unwrap();
+        sl.insert(s0, CellIdx::new(3, 3), true).unwrap();
+        sl.insert(s0, CellIdx::new(4, 0), "=[[0, 0], 0]").unwrap();
+        sl.insert(s1, CellIdx::new(0, 0), "=if(Sheet1[3, 3], 2, 3)")
+            .unwrap();
+        sl.insert(
+            s1,
+            CellIdx::new(0, 1),
+            Value::Error(ErrorKind::NotAvailable),
+        )
+        .unwrap();
+        sl.insert(s1, CellIdx::new(1, 0), "2024-01-31").unwrap();
+        sl.insert(s1, CellIdx::new(1, 1), "2024-01-31T09:30")
+            .unwrap();
+        sl.insert(
+            s1,
+            CellIdx::new(1, 2),
+            Duration::parse("1d12h1.5s").unwrap(),
+        )
+        .unwrap();
+
+        let mut file = Cursor::new(vec![]);
+        let untranslated = sl
+            .export_ods(
+                &mut file,
+                &ExportOptions {
+                    default_extent: Some(CellIdx::new(5, 1)),
+                },
+            )
+            .unwrap();
+        assert_eq!(untranslated.len(), 1);
+        assert_eq!(untranslated[0].cell, CellIdx::new(4, 0));
+
+        file.set_position(0);
+        let mut workbook = Ods::new(file).unwrap();
+        assert_eq!(workbook.sheet_names(), ["Sheet1", "Other"]);
+
+        let values = workbook.worksheet_range("Sheet1").unwrap();
+        assert_eq!(values.get_value((0, 0)), Some(&Data::Float(1.0)));
+        assert_eq!(
+            values.get_value((0, 1)),
+            Some(&Data::String("<a & 'b'>".to_string()))
+        );
+        // the row default is filled in up to the extent
+        assert_eq!(values.get_value((1, 0)), Some(&Data::Float(2.0)));
+        assert_eq!(values.get_value((1, 2)), Some(&Data::Empty));
+        assert_eq!(values.get_value((3, 3)), Some(&Data::Bool(true)));
+        assert_eq!(values.get_value((4, 0)), Some(&Data::Float(2.0)));
+
+        let formulas = workbook.worksheet_formula("Sheet1").unwrap();
+        assert_eq!(
+            formulas.get_value((1, 0)).map(String::as_str),
+            Some("of:=[.A1]+1")
+        );
+        let formulas = workbook.worksheet_formula("Other").unwrap();
+        assert_eq!(
+            formulas.get_value((0, 0)).map(String::as_str),
+            Some("of:=IF([Sheet1.D4];2;3)")
+        );
+        assert_eq!(
+            formulas.get_value((0, 1)).map(String::as_str),
+            Some("of:=#N/A")
+        );
+
+        let values = workbook.worksheet_range("Other").unwrap();
+        assert_eq!(
+            values.get_value((1, 0)),
+            Some(&Data::DateTimeIso("2024-01-31".to_string()))
+        );
+        assert_eq!(
+            values.get_value((1, 1)),
+            Some(&Data::DateTimeIso("2024-01-31T09:30:00".to_string()))
+        );
+        assert_eq!(
+            values.get_value((1, 2)),
+            Some(&Data::DurationIso("PT36H0M1.5S".to_string()))
+        );
+    }
+}
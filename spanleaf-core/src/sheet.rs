@@ -1,16 +1,20 @@
 use std::{
     collections::BTreeMap,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicU64, Ordering},
 };
 
-use crate::cell::{CellIdx, Value};
+use crate::{
+    Error,
+    cell::{CellIdx, CellRange, Value},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ValueSource {
     Native,
     RowDefault,
     ColDefault,
+    /// Part of an array that a formula above or to the left resulted in, spilling into the empty cell
+    Spill,
 }
 
 /// The result of a value fetch from the sheet. Contains metadata about where the Value came from
@@ -35,6 +39,9 @@ impl ValueResult {
     pub fn col(val: impl Into<Value>) -> Self {
         Self::new(val, ValueSource::ColDefault)
     }
+    pub fn spill(val: impl Into<Value>) -> Self {
+        Self::new(val, ValueSource::Spill)
+    }
     pub fn value(self) -> Value {
         self.into()
     }
@@ -79,14 +86,86 @@ impl From<ValueResult> for Value {
     }
 }
 
-/// The internal index of a sheet. Atomically incremented when created
+/// The index of a sheet, allocated by the owning [Spanleaf](crate::Spanleaf)
+///
+/// Stable for the lifetime of the sheet, including across saving and loading, so only meaningful within
+/// the workbook it came from
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct SheetIdx(u64);
+pub struct SheetIdx(pub(crate) u64);
 impl SheetIdx {
-    pub(crate) fn next() -> Self {
-        static COUNTER: AtomicU64 = AtomicU64::new(0);
+    /// The raw id of the sheet
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A default's value, with a formula re-parsed as shared by every cell along `axis`
+fn shared(val: Value, axis: Axis) -> Value {
+    match val {
+        Value::Formula(f) => Value::Formula(f.share_along(axis)),
+        val => val,
+    }
+}
+
+/// A direction within a sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Axis {
+    Row,
+    Col,
+}
+impl Axis {
+    /// Picks whichever of the two corresponds to this axis
+    pub fn select<T>(&self, row: T, col: T) -> T {
+        match self {
+            Axis::Row => row,
+            Axis::Col => col,
+        }
+    }
+}
+
+/// A structural change to a sheet, where `count` rows or cols are inserted or deleted at `at`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Shift {
+    pub axis: Axis,
+    pub at: u64,
+    pub count: u64,
+    pub delete: bool,
+}
+impl Shift {
+    /// Where the given row or col moves to, or None if it was deleted, or pushed past the last one
+    pub fn apply(&self, idx: u64) -> Option<u64> {
+        if idx < self.at {
+            Some(idx)
+        } else if !self.delete {
+            idx.checked_add(self.count)
+                .filter(|idx| *idx <= Sheet::LAST_INDEX)
+        } else if idx - self.at < self.count {
+            None
+        } else {
+            Some(idx - self.count)
+        }
+    }
+    /// Where the inclusive bounds of a range move to, shrinking or growing the range as needed.
+    /// None if the whole range was deleted, or either bound was pushed past the last row or col
+    pub fn apply_range(&self, start: u64, end: u64) -> Option<(u64, u64)> {
+        if !self.delete {
+            return Some((self.apply(start)?, self.apply(end)?));
+        }
 
-        Self(COUNTER.fetch_add(1, Ordering::SeqCst))
+        // deleted bounds snap to the closest surviving row or col inside the range
+        let new_start = self.apply(start).unwrap_or(self.at);
+        let new_end = match self.apply(end) {
+            Some(end) => end,
+            None => self.at.checked_sub(1)?,
+        };
+        (new_start <= new_end && start <= end).then_some((new_start, new_end))
+    }
+    /// Moves the cell, or None if it was deleted
+    fn apply_cell(&self, cref: CellIdx) -> Option<CellIdx> {
+        Some(match self.axis {
+            Axis::Row => CellIdx::new(self.apply(cref.row)?, cref.col),
+            Axis::Col => CellIdx::new(cref.row, self.apply(cref.col)?),
+        })
     }
 }
 
@@ -105,6 +184,9 @@ pub struct Sheet {
     col_defaults: BTreeMap<u64, Value>,
 }
 impl Sheet {
+    /// The last row or col of a sheet, past which cells can't be stored, as their shell offset would overflow
+    pub const LAST_INDEX: u64 = u32::MAX as u64;
+
     pub fn new(name: impl ToString) -> Self {
         Self {
             name: name.to_string(),
@@ -121,7 +203,8 @@ impl Sheet {
         row: u64,
         val: T,
     ) -> Result<Value, T::Error> {
-        let val = val.try_into()?;
+        // a row default is shared by every col of the row
+        let val = shared(val.try_into()?, Axis::Col);
         if let Value::None = val {
             Ok(self.row_defaults.remove(&row).unwrap_or_default())
         } else {
@@ -136,7 +219,8 @@ impl Sheet {
         col: u64,
         val: T,
     ) -> Result<Value, T::Error> {
-        let val = val.try_into()?;
+        // a col default is shared by every row of the col
+        let val = shared(val.try_into()?, Axis::Row);
         if let Value::None = val {
             Ok(self.col_defaults.remove(&col).unwrap_or_default())
         } else {
@@ -145,10 +229,11 @@ impl Sheet {
     }
     /// Inserts a new value into the sheet
     ///
-    /// Returns the previous value
+    /// Returns the previous value. Panics if the cell is past [Sheet::LAST_INDEX]
     pub fn insert<T: TryInto<Value>>(&mut self, cref: CellIdx, val: T) -> Result<Value, T::Error> {
         let val = val.try_into()?;
-        let offset = cell_ref_to_shell_off(cref);
+        let offset =
+            cell_ref_to_shell_off(cref).expect("cell is past the last row or col of the sheet");
         if let Value::None = val {
             Ok(self.cells.remove(&offset).unwrap_or_default())
         } else {
@@ -156,8 +241,12 @@ impl Sheet {
         }
     }
     /// Returns the raw, uncalculated formula at the given index
+    ///
+    /// A cell past [Sheet::LAST_INDEX] is always empty
     pub fn get_formula(&self, cref: CellIdx) -> ValueResult {
-        let offset = cell_ref_to_shell_off(cref);
+        let Some(offset) = cell_ref_to_shell_off(cref) else {
+            return ValueResult::default();
+        };
         self.cells
             .get(&offset)
             .map(|v| ValueResult::native(v.clone()))
@@ -181,6 +270,140 @@ impl Sheet {
     pub fn get_col_default(&self, col: u64) -> Value {
         self.col_defaults.get(&col).cloned().unwrap_or_default()
     }
+
+    /// Every native (non-default) value in the sheet, in shell order
+    pub fn cells(&self) -> impl Iterator<Item = (CellIdx, &Value)> {
+        self.cells
+            .iter()
+            .map(|(offset, val)| (shell_off_to_cell_ref(*offset), val))
+    }
+
+    /// The smallest range containing every native cell, and every row and col with a default.
+    ///
+    /// Defaults only extend the range along their own axis, as they're otherwise infinite
+    pub fn used_range(&self) -> CellRange {
+        let rows = self
+            .cells()
+            .map(|(cref, _)| cref.row)
+            .chain(self.row_defaults().map(|(row, _)| row));
+        let cols = self
+            .cells()
+            .map(|(cref, _)| cref.col)
+            .chain(self.col_defaults().map(|(col, _)| col));
+
+        match (rows.max(), cols.max()) {
+            (Some(row), Some(col)) => CellRange::new(CellIdx::new(0, 0), CellIdx::new(row, col)),
+            _ => CellRange::empty(),
+        }
+    }
+
+    /// Every row default, in row order
+    pub fn row_defaults(&self) -> impl Iterator<Item = (u64, &Value)> {
+        self.row_defaults.iter().map(|(row, val)| (*row, val))
+    }
+
+    /// Every col default, in col order
+    pub fn col_defaults(&self) -> impl Iterator<Item = (u64, &Value)> {
+        self.col_defaults.iter().map(|(col, val)| (*col, val))
+    }
+
+    /// Inserts `count` empty rows before `row`, moving everything below down.
+    ///
+    /// Fails if that would push anything past the last row
+    pub fn insert_rows(&mut self, row: u64, count: u64) -> Result<(), Error> {
+        let shift = Shift {
+            axis: Axis::Row,
+            at: row,
+            count,
+            delete: false,
+        };
+        if !self.fits(shift) {
+            return Err(Error::NoRoomToInsert);
+        }
+        self.shift(shift);
+        Ok(())
+    }
+    /// Deletes `count` rows starting at `row`, moving everything below up
+    pub fn delete_rows(&mut self, row: u64, count: u64) {
+        self.shift(Shift {
+            axis: Axis::Row,
+            at: row,
+            count,
+            delete: true,
+        });
+    }
+    /// Inserts `count` empty cols before `col`, moving everything to the right over.
+    ///
+    /// Fails if that would push anything past the last col
+    pub fn insert_cols(&mut self, col: u64, count: u64) -> Result<(), Error> {
+        let shift = Shift {
+            axis: Axis::Col,
+            at: col,
+            count,
+            delete: false,
+        };
+        if !self.fits(shift) {
+            return Err(Error::NoRoomToInsert);
+        }
+        self.shift(shift);
+        Ok(())
+    }
+    /// Deletes `count` cols starting at `col`, moving everything to the right over
+    pub fn delete_cols(&mut self, col: u64, count: u64) {
+        self.shift(Shift {
+            axis: Axis::Col,
+            at: col,
+            count,
+            delete: true,
+        });
+    }
+
+    /// Whether every cell and default is still within the sheet after the shift, as inserting rows or cols
+    /// can push them past the last one
+    pub(crate) fn fits(&self, shift: Shift) -> bool {
+        let (cells, defaults) = match shift.axis {
+            Axis::Row => (
+                self.cells().map(|(cref, _)| cref.row).max(),
+                self.row_defaults.keys().last(),
+            ),
+            Axis::Col => (
+                self.cells().map(|(cref, _)| cref.col).max(),
+                self.col_defaults.keys().last(),
+            ),
+        };
+        shift.delete
+            || cells
+                .max(defaults.copied())
+                .is_none_or(|last| shift.apply(last).is_some())
+    }
+
+    /// Moves the cells and defaults of the sheet. References within formulas are not touched
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        self.cells = std::mem::take(&mut self.cells)
+            .into_iter()
+            .filter_map(|(offset, val)| {
+                let cref = shift.apply_cell(shell_off_to_cell_ref(offset))?;
+                Some((cell_ref_to_shell_off(cref)?, val))
+            })
+            .collect();
+
+        let defaults = match shift.axis {
+            Axis::Row => &mut self.row_defaults,
+            Axis::Col => &mut self.col_defaults,
+        };
+        *defaults = std::mem::take(defaults)
+            .into_iter()
+            .filter_map(|(idx, val)| Some((shift.apply(idx)?, val)))
+            .collect();
+    }
+
+    /// Every value in the sheet, including the row and col defaults
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut Value> {
+        self.cells
+            .values_mut()
+            .chain(self.row_defaults.values_mut())
+            .chain(self.col_defaults.values_mut())
+    }
 }
 
 /// Converts the row and column to a shell offset
@@ -206,20 +429,40 @@ impl Sheet {
 ///
 /// Ultimately, we're storing things in a BTree to account for probable sparsity of the matrix,
 /// with the shell offset as the index, so really this is just because I think it's interesting
-fn cell_ref_to_shell_off(CellIdx { row, col }: CellIdx) -> u64 {
+///
+/// None if the row or col is past [Sheet::LAST_INDEX], as the offset wouldn't fit
+fn cell_ref_to_shell_off(CellIdx { row, col }: CellIdx) -> Option<u64> {
     let max = row.max(col);
+    if max > Sheet::LAST_INDEX {
+        return None;
+    }
     // let rank = max + 1;
 
     // rank * rank - (rank - r) - c
     // simplifies down to
-    (max * max) + max + row - col
+    Some((max * max) + max + row - col)
+}
+
+/// The inverse of [cell_ref_to_shell_off]
+///
+/// Within shell `max`, the offset past `max * max` counts down the last col, then leftwards along the last row
+fn shell_off_to_cell_ref(offset: u64) -> CellIdx {
+    let max = offset.isqrt();
+    let rem = offset - max * max;
+
+    if rem <= max {
+        CellIdx::new(rem, max)
+    } else {
+        CellIdx::new(max, 2 * max - rem)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        Error,
         cell::{CellIdx, Value},
-        sheet::{Sheet, ValueResult},
+        sheet::{Sheet, ValueResult, cell_ref_to_shell_off, shell_off_to_cell_ref},
     };
 
     #[test]
@@ -254,4 +497,56 @@ mod tests {
         // column default takes priority
         assert_eq!(r1c1, ValueResult::col(col_1));
     }
+
+    #[test]
+    fn shell_offsets() {
+        for row in 0..20 {
+            for col in 0..20 {
+                let cref = CellIdx::new(row, col);
+                assert_eq!(
+                    shell_off_to_cell_ref(cell_ref_to_shell_off(cref).unwrap()),
+                    cref
+                );
+            }
+        }
+        // the last cell of the sheet takes the last offset
+        let last = CellIdx::new(Sheet::LAST_INDEX, 0);
+        assert_eq!(cell_ref_to_shell_off(last), Some(u64::MAX));
+        assert_eq!(shell_off_to_cell_ref(u64::MAX), last);
+        assert_eq!(
+            cell_ref_to_shell_off(CellIdx::new(0, Sheet::LAST_INDEX + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn insert_delete_rows_cols() {
+        let mut sheet = Sheet::new("");
+        sheet.insert(CellIdx::new(1, 1), 11).unwrap();
+        sheet.insert(CellIdx::new(3, 2), 32).unwrap();
+        sheet.insert_row_default(3, "row 3").unwrap();
+        sheet.insert_col_default(2, "col 2").unwrap();
+
+        sheet.insert_rows(2, 2).unwrap();
+        assert_eq!(*sheet.get_formula(CellIdx::new(1, 1)), 11.into());
+        assert_eq!(*sheet.get_formula(CellIdx::new(5, 2)), 32.into());
+        // nothing can be pushed past the last row, so nothing moves
+        assert!(matches!(
+            sheet.insert_rows(0, Sheet::LAST_INDEX - 4),
+            Err(Error::NoRoomToInsert)
+        ));
+        assert_eq!(*sheet.get_formula(CellIdx::new(5, 2)), 32.into());
+        assert_eq!(sheet.get_row_default(5), "row 3".try_into().unwrap());
+        assert_eq!(sheet.get_row_default(3), ().into());
+        assert_eq!(sheet.get_col_default(2), "col 2".try_into().unwrap());
+
+        sheet.delete_cols(0, 2);
+        assert_eq!(*sheet.get_formula(CellIdx::new(5, 0)), 32.into());
+        assert_eq!(sheet.get_col_default(0), "col 2".try_into().unwrap());
+        // deleted along with its col
+        assert_eq!(
+            sheet.get_formula(CellIdx::new(1, 1)),
+            ValueResult::native(())
+        );
+    }
 }
@@ -0,0 +1,388 @@
+//! XLSX import and export
+//!
+//! Each worksheet becomes a sheet, with literal cells imported as values and formulas translated into
+//! Spanleaf formulas. Formulas that can't be translated are reported, and their cached value from the
+//! file is imported in their place.
+//!
+//! Exporting writes each sheet as a worksheet, with row and col defaults filled into cells. Formulas are
+//! written alongside their computed value, except for ones without an Excel equivalent, which are
+//! reported and written as just their value.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+};
+
+use calamine::{Data, ExcelDateTime, Reader, Xlsx};
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+
+use crate::{
+    Spanleaf,
+    cell::{CellIdx, ErrorKind, Value},
+    datetime::{Date, DateTime, Duration, MILLIS_PER_DAY},
+    excel::{self, Dialect, ExportCell},
+    formula::Formula,
+    language,
+};
+
+pub use crate::excel::{ExportOptions, TranslateError, UntranslatedFormula};
+
+#[derive(Debug, thiserror::Error)]
+pub enum XlsxError {
+    #[error(transparent)]
+    Read(#[from] calamine::XlsxError),
+    #[error(transparent)]
+    Write(#[from] rust_xlsxwriter::XlsxError),
+}
+
+/// The result of importing an XLSX workbook
+#[derive(Debug)]
+pub struct XlsxImport {
+    pub spanleaf: Spanleaf,
+    pub untranslated: Vec<UntranslatedFormula>,
+}
+
+impl Spanleaf {
+    /// Reads an XLSX workbook into a new Spanleaf.
+    ///
+    /// Sheet names that can't be referred to from formulas, i.e. `My Sheet`, are renamed to ones that can,
+    /// i.e. `My_Sheet`
+    pub fn import_xlsx(reader: impl Read + Seek) -> Result<XlsxImport, XlsxError> {
+        let mut workbook = Xlsx::new(reader)?;
+        let mut sl = Spanleaf::new();
+
+        // Excel sheet names to Spanleaf sheet names
+        let mut names = BTreeMap::new();
+        let mut sheets = vec![];
+        for excel_name in workbook.sheet_names() {
+            let name = spanleaf_sheet_name(&excel_name, |name| sl.sheet_by_name(name).is_some());
+            let sref = sl
+                .insert_sheet(&name)
+                .expect("the name was checked to be unused");
+            names.insert(excel_name.clone(), name);
+            sheets.push((excel_name, sref));
+        }
+
+        let mut untranslated = vec![];
+        for (excel_name, sref) in sheets {
+            let values = workbook.worksheet_range(&excel_name)?;
+            let start = values.start().unwrap_or_default();
+            for (row, col, data) in values.cells() {
+                let cell = CellIdx::new(start.0 as u64 + row as u64, start.1 as u64 + col as u64);
+                // can't fail, as the value is already converted
+                let _ = sl.insert(sref, cell, data_value(data));
+            }
+
+            let formulas = workbook.worksheet_formula(&excel_name)?;
+            let start = formulas.start().unwrap_or_default();
+            for (row, col, formula) in formulas.cells() {
+                if formula.is_empty() {
+                    continue;
+                }
+                let cell = CellIdx::new(start.0 as u64 + row as u64, start.1 as u64 + col as u64);
+
+                match excel::translate(formula, |sheet| names.get(sheet).cloned()) {
+                    Ok(expr) => {
+                        let _ = sl.insert(sref, cell, Value::Formula(Formula::from_expr(expr)));
+                    }
+                    // the cached value was already imported
+                    Err(reason) => untranslated.push(UntranslatedFormula {
+                        sheet: sref,
+                        cell,
+                        formula: formula.clone(),
+                        reason,
+                    }),
+                }
+            }
+        }
+
+        Ok(XlsxImport {
+            spanleaf: sl,
+            untranslated,
+        })
+    }
+
+    /// Writes every sheet to an XLSX workbook.
+    ///
+    /// Returns the formulas that couldn't be translated, which were written as their computed value
+    pub fn export_xlsx(
+        &self,
+        writer: impl Write + Send,
+        options: &ExportOptions,
+    ) -> Result<Vec<UntranslatedFormula>, XlsxError> {
+        let mut workbook = Workbook::new();
+        let mut untranslated = vec![];
+
+        for (sref, sheet) in self.sheets() {
+            let worksheet = workbook.add_worksheet().set_name(&sheet.name)?;
+            let cells = excel::export_cells(self, sref, Dialect::Excel, options, &mut untranslated);
+            for cell in cells {
+                write_cell(worksheet, cell)?;
+            }
+        }
+
+        workbook.save_to_writer(writer)?;
+        Ok(untranslated)
+    }
+}
+
+/// Writes a cell, as a formula with its cached result if it has one
+fn write_cell(worksheet: &mut Worksheet, cell: ExportCell) -> Result<(), XlsxError> {
+    // out of range cells are left to the writer to reject
+    let row = u32::try_from(cell.cell.row).unwrap_or(u32::MAX);
+    let col = u16::try_from(cell.cell.col).unwrap_or(u16::MAX);
+
+    // dates, datetimes and durations are serial day numbers shown with a format
+    let serial = serial(&cell.value);
+    let format = serial.map(|(_, format)| Format::new().set_num_format(format));
+
+    if let Some(formula) = cell.formula {
+        let result = match cell.value {
+            Value::Bool(b) => b.to_string().to_uppercase(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => s,
+            Value::Error(kind) => kind.to_string(),
+            _ => serial.map(|(n, _)| n.to_string()).unwrap_or_default(),
+        };
+        let formula = rust_xlsxwriter::Formula::new(formula).set_result(result);
+        match &format {
+            Some(format) => worksheet.write_formula_with_format(row, col, formula, format)?,
+            None => worksheet.write_formula(row, col, formula)?,
+        };
+        return Ok(());
+    }
+
+    if let (Some((n, _)), Some(format)) = (serial, &format) {
+        worksheet.write_number_with_format(row, col, n, format)?;
+        return Ok(());
+    }
+    match cell.value {
+        Value::Number(n) => worksheet.write_number(row, col, n)?,
+        Value::String(s) => worksheet.write_string(row, col, s)?,
+        Value::Bool(b) => worksheet.write_boolean(row, col, b)?,
+        // errors without an Excel literal
+        Value::Error(kind) => worksheet.write_string(row, col, kind.code())?,
+        _ => worksheet,
+    };
+    Ok(())
+}
+
+/// The days from Excel's serial day 0 to 1970-01-01, which only count correctly from 1900-03-01, past
+/// the 1900-02-29 that Excel has but that didn't happen
+const SERIAL_EPOCH: f64 = 25_569.0;
+
+/// Excel's serial day number of a date, datetime or duration, and the number format to show it with
+fn serial(val: &Value) -> Option<(f64, &'static str)> {
+    Some(match val {
+        Value::Date(date) => (date.days() as f64 + SERIAL_EPOCH, "yyyy-mm-dd"),
+        Value::DateTime(dt) => (
+            dt.millis() as f64 / MILLIS_PER_DAY as f64 + SERIAL_EPOCH,
+            "yyyy-mm-dd hh:mm:ss",
+        ),
+        Value::Duration(duration) => (duration.as_days(), "[h]:mm:ss"),
+        _ => return None,
+    })
+}
+
+/// The date, or datetime if it has a time of day, of a cell with a date format
+fn excel_datetime(dt: &ExcelDateTime) -> Option<Value> {
+    let (year, month, day, ..) = dt.to_ymd_hms_milli();
+    let date = Date::from_ymd(year.into(), month.into(), day.into())?;
+    // the time is rounded to the millisecond, past the imprecision of a fraction of a day
+    let time = (dt.as_f64().fract() * MILLIS_PER_DAY as f64).round() as i64;
+    if time == 0 {
+        return Some(Value::Date(date));
+    }
+    DateTime::from_millis(date.midnight().millis() + time).map(Value::DateTime)
+}
+
+/// The value of a literal cell
+fn data_value(data: &Data) -> Value {
+    match data {
+        Data::Int(i) => Value::Number(*i as f64),
+        Data::Float(f) => Value::Number(*f),
+        Data::String(s) => Value::String(s.clone()),
+        Data::Bool(b) => Value::Bool(*b),
+        // dates that can't be represented, such as Excel's 1900-02-29, are kept as serial day numbers
+        Data::DateTime(dt) if dt.is_duration() => {
+            Duration::from_days(dt.as_f64()).map_or(Value::Number(dt.as_f64()), Value::Duration)
+        }
+        Data::DateTime(dt) => excel_datetime(dt).unwrap_or(Value::Number(dt.as_f64())),
+        Data::DateTimeIso(s) => Date::parse(s)
+            .map(Value::Date)
+            .or_else(|| DateTime::parse(s).map(Value::DateTime))
+            .unwrap_or_else(|| Value::String(s.clone())),
+        Data::DurationIso(s) => Value::String(s.clone()),
+        Data::Error(e) => {
+            Value::Error(excel::error_kind(&e.to_string()).unwrap_or(ErrorKind::Value))
+        }
+        Data::Empty => Value::None,
+    }
+}
+
+/// Converts an Excel sheet name to one that can be referred to from formulas, and isn't already `taken`
+fn spanleaf_sheet_name(excel_name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut name = excel_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !language::is_sheet_name(&name) {
+        name.insert(0, '_');
+    }
+
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|i| format!("{name}_{i}"))
+        .find(|name| !taken(name))
+        .expect("there's always an unused name")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use calamine::{Data, Reader, Xlsx};
+    use rust_xlsxwriter::{Formula, Workbook};
+
+    use crate::{
+        Spanleaf,
+        cell::{CellIdx, ErrorKind, Value},
+        datetime::{Date, DateTime, Duration},
+        xlsx::{ExportOptions, TranslateError, spanleaf_sheet_name},
+    };
+
+    #[test]
+    fn sheet_names() {
+        let taken = |name: &str| name == "Taken";
+        assert_eq!(spanleaf_sheet_name("Sheet1", taken), "Sheet1");
+        assert_eq!(spanleaf_sheet_name("My Sheet", taken), "My_Sheet");
+        assert_eq!(spanleaf_sheet_name("2024", taken), "_2024");
+        assert_eq!(spanleaf_sheet_name("Taken", taken), "Taken_2");
+    }
+
+    #[test]
+    fn import() {
+        let mut workbook = Workbook::new();
+        let data = workbook.add_worksheet().set_name("Data").unwrap();
+        data.write_number(0, 0, 1.5).unwrap();
+        data.write_number(1, 0, 2.5).unwrap();
+        data.write_string(2, 0, "text").unwrap();
+        data.write_boolean(3, 0, true).unwrap();
+        data.write_formula(0, 1, Formula::new("=SUM(A1:A2)").set_result("4"))
+            .unwrap();
+        data.write_formula(1, 1, Formula::new("=INDIRECT(\"A1\")").set_result("1.5"))
+            .unwrap();
+        let summary = workbook.add_worksheet().set_name("The Summary").unwrap();
+        summary
+            .write_formula(2, 3, Formula::new("=Data!B1 * 2").set_result("8"))
+            .unwrap();
+        summary
+            .write_formula(3, 3, Formula::new("=1/0").set_result("#DIV/0!"))
+            .unwrap();
+        let file = workbook.save_to_buffer().unwrap();
+
+        let import = Spanleaf::import_xlsx(Cursor::new(file)).unwrap();
+        let sl = &import.spanleaf;
+        let data = sl.sheet_by_name("Data").unwrap();
+        let summary = sl.sheet_by_name("The_Summary").unwrap();
+        let get = |sref, row, col| sl.get(sref, CellIdx::new(row, col)).unwrap().value();
+
+        assert_eq!(get(data, 0, 0), 1.5.into());
+        assert_eq!(get(data, 2, 0), "text".try_into().unwrap());
+        assert_eq!(get(data, 3, 0), Value::Bool(true));
+        assert_eq!(get(data, 0, 1), 4.into());
+        assert_eq!(get(summary, 2, 3), 8.into());
+        assert_eq!(get(summary, 3, 3), Value::Error(ErrorKind::DivByZero));
+
+        // the untranslatable formula keeps its cached value
+        assert_eq!(get(data, 1, 1), 1.5.into());
+        assert_eq!(import.untranslated.len(), 1);
+        let untranslated = &import.untranslated[0];
+        assert_eq!(
+            (untranslated.sheet, untranslated.cell),
+            (data, CellIdx::new(1, 1))
+        );
+        assert_eq!(
+            untranslated.reason,
+            TranslateError::UnsupportedFunction("INDIRECT".to_string())
+        );
+    }
+
+    #[test]
+    fn export() {
+        let mut sl = Spanleaf::new();
+        let s0 = sl.insert_sheet("Sheet1").unwrap();
+        let s1 = sl.insert_sheet("Other").unwrap();
+        sl.insert_col_default(s0, 1, "=[r, 0] * 2").unwrap();
+        sl.insert(s0, CellIdx::new(0, 0), 1).unwrap();
+        sl.insert(s0, CellIdx::new(1, 0), 2).unwrap();
+        sl.insert(s0, CellIdx::new(2, 0), "text").unwrap();
+        sl.insert(s0, CellIdx::new(0, 2), "=&[0, 0]").unwrap();
+        sl.insert(s1, CellIdx::new(1, 1), "=sum(Sheet1[0..1, 0..1])")
+            .unwrap();
+        sl.insert(s1, CellIdx::new(2, 1), Value::Error(ErrorKind::DivByZero))
+            .unwrap();
+        sl.insert(s1, CellIdx::new(3, 1), "2024-01-31").unwrap();
+        sl.insert(s1, CellIdx::new(4, 1), "2024-01-31T09:30")
+            .unwrap();
+        let duration = Duration::parse("1d12h").unwrap();
+        sl.insert(s1, CellIdx::new(5, 1), duration).unwrap();
+
+        let mut file = vec![];
+        let untranslated = sl
+            .export_xlsx(&mut file, &ExportOptions::default())
+            .unwrap();
+        assert_eq!(untranslated.len(), 1);
+        assert_eq!(
+            (untranslated[0].sheet, untranslated[0].cell),
+            (s0, CellIdx::new(0, 2))
+        );
+
+        let mut workbook = Xlsx::new(Cursor::new(&file)).unwrap();
+        assert_eq!(workbook.sheet_names(), ["Sheet1", "Other"]);
+
+        let values = workbook.worksheet_range("Sheet1").unwrap();
+        assert_eq!(values.get_value((0, 0)), Some(&Data::Float(1.0)));
+        assert_eq!(
+            values.get_value((2, 0)),
+            Some(&Data::String("text".to_string()))
+        );
+        // the reference is exported as the value it refers to
+        assert_eq!(values.get_value((0, 2)), Some(&Data::Float(1.0)));
+        // the col default is filled in over the used range
+        assert_eq!(
+            values.get_value((2, 1)),
+            Some(&Data::Error(calamine::CellErrorType::Value))
+        );
+        let formulas = workbook.worksheet_formula("Sheet1").unwrap();
+        assert_eq!(formulas.get_value((1, 1)).map(String::as_str), Some("A2*2"));
+        // dates are serial day numbers with a date format
+        let values = workbook.worksheet_range("Other").unwrap();
+        assert!(matches!(
+            values.get_value((3, 1)),
+            Some(Data::DateTime(dt)) if dt.as_f64() == 45322.0
+        ));
+
+        // it imports back into the same workbook
+        let import = Spanleaf::import_xlsx(Cursor::new(file)).unwrap();
+        assert!(import.untranslated.is_empty());
+        let other = import.spanleaf.sheet_by_name("Other").unwrap();
+        let get = |row, col| {
+            import
+                .spanleaf
+                .get(other, CellIdx::new(row, col))
+                .unwrap()
+                .value()
+        };
+        assert_eq!(get(1, 1), 9.into());
+        assert_eq!(get(2, 1), Value::Error(ErrorKind::DivByZero));
+        assert_eq!(get(3, 1), Date::parse("2024-01-31").unwrap().into());
+        assert_eq!(
+            get(4, 1),
+            DateTime::parse("2024-01-31T09:30").unwrap().into()
+        );
+        assert_eq!(get(5, 1), duration.into());
+    }
+}
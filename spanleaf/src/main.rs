@@ -1,12 +1,15 @@
 use dioxus::prelude::*;
 use spanleaf_core::{
     cell::{CellIdx, Value},
+    formula::{FormulaError, ParseError},
     sheet::{SheetIdx, ValueResult, ValueSource},
     Spanleaf,
 };
 
 const FAVICON: Asset = asset!("/assets/favicon.ico");
 const MAIN_CSS: Asset = asset!("/assets/main.css");
+/// Demo workbook, showing off the formula language
+const DEMO: &str = include_str!("../assets/demo.spanleaf");
 // const HEADER_SVG: Asset = asset!("/assets/header.svg");
 
 fn main() {
@@ -19,53 +22,8 @@ fn main() {
 fn App() -> Element {
     info!("Starting");
 
-    let mut sl = Spanleaf::new();
-    let sref = sl.insert_sheet("Sheet1");
-
-    {
-        // row and col defaults
-        sl.insert_row_default(sref, 7, 1).unwrap();
-        sl.insert_col_default(sref, 4, "apple").unwrap();
-        // use the current row or column as a value
-        sl.insert_col_default(sref, 0, "=r").unwrap();
-
-        // explicit values overwrite row and col defaults
-        sl.insert(sref, CellIdx::new(0, 0), -3).unwrap();
-
-        // reference other cells
-        sl.insert(sref, CellIdx::new(2, 4), "=[0, 0]").unwrap();
-
-        // fibonacci
-        sl.insert(sref, CellIdx::new(0, 10), 0).unwrap();
-        sl.insert(sref, CellIdx::new(1, 10), 1).unwrap();
-        // functions in formulae
-        sl.insert_col_default(sref, 10, "=sum([r-1, c], [r-2, c])")
-            .unwrap();
-
-        // golden ratio approx
-        sl.insert_col_default(sref, 11, "=[r-1, c-1] / [r, c-1]")
-            .unwrap();
-
-        // setting explicit references
-        for i in 1..10 {
-            sl.insert(sref, CellIdx::new(0, i), format!("=[0, {}] + 1", i - 1))
-                .unwrap();
-            sl.insert(sref, CellIdx::new(i, i), format!("={i} * {i}"))
-                .unwrap();
-        }
-
-        sl.insert(sref, CellIdx::new(11, 2), "Lorem Ipsum").unwrap();
-        // reference indirection
-        sl.insert(sref, CellIdx::new(12, 2), "=&[11, 2]").unwrap();
-        // value is now a reference
-        sl.insert(sref, CellIdx::new(13, 2), "=[12, 2]").unwrap();
-        // dereference the indirect cell reference
-        sl.insert(sref, CellIdx::new(14, 2), "=*[13, 2]").unwrap();
-
-        // cyclic dependency error
-        sl.insert(sref, CellIdx::new(12, 6), "=[12, 7]").unwrap();
-        sl.insert(sref, CellIdx::new(12, 7), "=[12, 6]").unwrap();
-    }
+    let sl = Spanleaf::load(DEMO.as_bytes()).expect("the demo workbook is valid");
+    let (sref, _) = sl.sheets().next().expect("the demo workbook has a sheet");
 
     let sl = use_signal(move || sl);
     let curr_sheet = use_signal(move || sref);
@@ -112,7 +70,9 @@ pub fn FormulaBar(
                 (
                     match val.source {
                         ValueSource::Native => val.value(),
-                        ValueSource::RowDefault | ValueSource::ColDefault => Value::None,
+                        ValueSource::RowDefault | ValueSource::ColDefault | ValueSource::Spill => {
+                            Value::None
+                        }
                     },
                     format!("[{}, {}]", cref.row, cref.col),
                 )
@@ -120,6 +80,9 @@ pub fn FormulaBar(
         }
     };
 
+    // the rejected script and why, so the offending part can be underlined
+    let mut parse_error = use_signal(|| None::<(String, FormulaError)>);
+
     rsx! {
         div { class: "formula-bar",
             div { class: "current-cell-display", "{curr}" }
@@ -130,22 +93,55 @@ pub fn FormulaBar(
                     evt.prevent_default();
 
                     info!("{evt:?}");
-                    match active_el {
+                    let script = evt.value();
+                    let res = match active_el {
                         ActiveElement::Row(row) => {
-                            sl.write().insert_row_default(sref, row, evt.value()).unwrap();
+                            sl.write().insert_row_default(sref, row, script.as_str())
                         }
                         ActiveElement::Col(col) => {
-                            sl.write().insert_col_default(sref, col, evt.value()).unwrap();
-                        }
-                        ActiveElement::Cell(cref) => {
-                            sl.write().insert(sref, cref, evt.value()).unwrap();
+                            sl.write().insert_col_default(sref, col, script.as_str())
                         }
+                        ActiveElement::Cell(cref) => sl.write().insert(sref, cref, script.as_str()),
                     };
-                    info!("Updated");
+                    match res {
+                        Ok(_) => {
+                            parse_error.set(None);
+                            info!("Updated");
+                        }
+                        Err(e) => {
+                            info!("{e}");
+                            parse_error.set(Some((script, e)));
+                        }
+                    }
                 },
                 value: "{raw_value}",
             }
 
+            if let Some((script, FormulaError::InvalidFormula(errs))) = parse_error() {
+                if let Some(err) = errs.first() {
+                    FormulaErrorDisplay { script, err: err.clone() }
+                }
+            }
+        }
+    }
+}
+
+/// Shows a parse error, with the offending part of the script underlined
+#[component]
+pub fn FormulaErrorDisplay(script: String, err: ParseError) -> Element {
+    // clamp to the script, as an error at the end of the script has nothing to underline
+    let start = err.span.start.min(script.len());
+    let end = err.span.end.clamp(start, script.len());
+    let (before, rest) = script.split_at(start);
+    let (offending, after) = rest.split_at(end - start);
+    let offending = if offending.is_empty() { " " } else { offending };
+
+    rsx! {
+        div { class: "formula-error", title: "{err.message}",
+            span { "{before}" }
+            span { class: "formula-error-span", "{offending}" }
+            span { "{after}" }
+            span { class: "formula-error-message", "{err.message}" }
         }
     }
 }
@@ -217,7 +213,7 @@ pub fn HeaderCell(idx: u64, val: ValueResult, curr_elem: Signal<ActiveElement>)
     let mut class = "cell".to_string();
 
     let s = match val.source {
-        ValueSource::Native => String::new(),
+        ValueSource::Native | ValueSource::Spill => String::new(),
         ValueSource::RowDefault => {
             {
                 if let ActiveElement::Row(row) = curr_elem() {
@@ -255,7 +251,8 @@ pub fn HeaderCell(idx: u64, val: ValueResult, curr_elem: Signal<ActiveElement>)
             class,
             onclick: move |_| {
                 match val.source {
-                    ValueSource::Native => todo!(),
+                    // a header only ever shows a default, so there's no cell of its own to select
+                    ValueSource::Native | ValueSource::Spill => {}
                     ValueSource::RowDefault => *curr_elem.write() = ActiveElement::Row(idx),
                     ValueSource::ColDefault => *curr_elem.write() = ActiveElement::Col(idx),
                 }
@@ -282,8 +279,14 @@ pub fn Cell(
                 ValueSource::Native => {}
                 ValueSource::RowDefault => class.push_str(" row-default"),
                 ValueSource::ColDefault => class.push_str(" col-default"),
+                ValueSource::Spill => class.push_str(" spill"),
             };
-            (val.to_string(), raw.to_string())
+            if let Value::Error(kind) = val.value {
+                class.push_str(" error-cell");
+                (kind.to_string(), format!("{}: {raw}", kind.description()))
+            } else {
+                (val.to_string(), raw.to_string())
+            }
         }
         Err(e) => {
             class.push_str(" error-cell");
@@ -311,9 +314,114 @@ pub fn Cell(
     }
 }
 
+/// Tab bar for switching between and managing sheets
+///
+/// Double click a tab to rename it
 #[component]
 pub fn Sheets(sl: Signal<Spanleaf>, curr_sheet: Signal<SheetIdx>) -> Element {
+    let sref = curr_sheet();
+    let sheets = sl
+        .read()
+        .sheets()
+        .map(|(sref, sheet)| (sref, sheet.name.clone()))
+        .collect::<Vec<_>>();
+    let position = sheets
+        .iter()
+        .position(|(s, _)| *s == sref)
+        .unwrap_or_default();
+    let count = sheets.len();
+
+    // the sheet whose tab is currently an input for renaming it
+    let mut renaming = use_signal(|| None::<SheetIdx>);
+
+    // the first unused default name
+    let unused_name = move || {
+        let sl = sl.read();
+        (1..)
+            .map(|i| format!("Sheet{i}"))
+            .find(|name| sl.sheet_by_name(name).is_none())
+            .expect("there's always an unused name")
+    };
+
     rsx! {
-        div { class: "sheet-footer" }
+        div { class: "sheet-footer",
+            for (tab, name) in sheets {
+                if renaming() == Some(tab) {
+                    input {
+                        class: "sheet-tab",
+                        value: "{name}",
+                        autofocus: true,
+                        onchange: move |evt| {
+                            if let Err(e) = sl.write().rename_sheet(tab, evt.value()) {
+                                info!("{e:?}");
+                            }
+                            renaming.set(None);
+                        },
+                    }
+                } else {
+                    div {
+                        class: if tab == sref { "sheet-tab active-sheet-tab" } else { "sheet-tab" },
+                        onclick: move |_| *curr_sheet.write() = tab,
+                        ondoubleclick: move |_| renaming.set(Some(tab)),
+                        "{name}"
+                    }
+                }
+            }
+
+            button {
+                title: "New sheet",
+                onclick: move |_| {
+                    let name = unused_name();
+                    match sl.write().insert_sheet(name) {
+                        Ok(new) => *curr_sheet.write() = new,
+                        Err(e) => info!("{e:?}"),
+                    }
+                },
+                "+"
+            }
+            button {
+                title: "Duplicate sheet",
+                onclick: move |_| {
+                    let name = unused_name();
+                    match sl.write().duplicate_sheet(sref, name) {
+                        Ok(copy) => *curr_sheet.write() = copy,
+                        Err(e) => info!("{e:?}"),
+                    }
+                },
+                "Duplicate"
+            }
+            button {
+                title: "Move sheet left",
+                disabled: position == 0,
+                onclick: move |_| {
+                    let _ = sl.write().move_sheet(sref, position.saturating_sub(1));
+                },
+                "<"
+            }
+            button {
+                title: "Move sheet right",
+                disabled: position + 1 >= count,
+                onclick: move |_| {
+                    let _ = sl.write().move_sheet(sref, position + 1);
+                },
+                ">"
+            }
+            button {
+                title: "Delete sheet",
+                // there must always be a sheet to show
+                disabled: count <= 1,
+                onclick: move |_| {
+                    let mut sl = sl.write();
+                    if sl.delete_sheet(sref).is_ok() {
+                        // show the sheet that took its place
+                        let sheets = sl.sheets().map(|(s, _)| s).collect::<Vec<_>>();
+                        if let Some(next) = sheets.get(position.min(sheets.len().saturating_sub(1))) {
+                            *curr_sheet.write() = *next;
+                        }
+                    }
+                },
+                "Delete"
+            }
+        }
     }
 }